@@ -0,0 +1,154 @@
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use crate::config::BackupConfig;
+use crate::prelude::*;
+use crate::{schema, usage};
+
+const BACKUP_USAGE: &str = "backup <path>";
+const RESTORE_USAGE: &str = "restore <path>";
+
+// names of scheduled backups, which sort by age
+const BACKUP_PREFIX: &str = "website-";
+const BACKUP_EXTENSION: &str = "sqlite";
+
+// Copies the database through sqlite, which is safe while the server is running. Copying the
+// file itself can catch a write halfway and give a broken backup.
+pub async fn backup(args: &[String]) -> Result<(), Error> {
+    let [path] = args else {
+        return usage(BACKUP_USAGE);
+    };
+
+    let config = Config::from_json_file("website.json")?;
+    let db = Database::open(&config)?;
+    db.backup(Path::new(path))?;
+    println!("backed up {} to {}", config.database_path, path);
+
+    Ok(())
+}
+
+// Replaces the database with a backup. A running server sees the restored database right away,
+// but a rebuild should still be run if the content changed since the backup.
+pub async fn restore(args: &[String]) -> Result<(), Error> {
+    let [path] = args else {
+        return usage(RESTORE_USAGE);
+    };
+    let path = Path::new(path);
+
+    check_backup(path)?;
+
+    let config = Config::from_json_file("website.json")?;
+    let mut db = Database::open(&config)?;
+    db.restore(path)?;
+    // a backup from an older version gets the tables added since
+    schema::migrate(&db)?;
+    println!("restored {} from {}", config.database_path, path.display());
+
+    Ok(())
+}
+
+// a database of this site, from this version or an older one
+fn check_backup(path: &Path) -> Result<(), Error> {
+    if !path.is_file() {
+        return Err(Error::new(format!("backup {} not found", path.display()))
+            .with_kind(ErrorKind::Validation));
+    }
+
+    let version: Option<i64> = Database::connect(&path.to_string_lossy())?
+        .query_one("SELECT MAX(version) FROM migrations;", [], |row| row.get(0))
+        .map_err(|_| {
+            Error::new(format!("{} is not a backup of the site", path.display()))
+                .with_kind(ErrorKind::Validation)
+        })?;
+
+    match version {
+        Some(version) if version > schema::SCHEMA_VERSION => Err(Error::new(format!(
+            "backup is at schema version {} but this binary only knows version {}",
+            version,
+            schema::SCHEMA_VERSION
+        ))
+        .with_kind(ErrorKind::Validation)),
+        _ => Ok(()),
+    }
+}
+
+fn backups(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut backups = fs::read_dir(dir)
+        .context("failed to read backup directory")?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == BACKUP_EXTENSION)
+                && path
+                    .file_name()
+                    .is_some_and(|name| name.to_string_lossy().starts_with(BACKUP_PREFIX))
+        })
+        .collect::<Vec<_>>();
+    backups.sort();
+    Ok(backups)
+}
+
+// takes a backup into the backup directory and deletes the ones beyond `keep`
+pub fn run_scheduled_backup(config: &Config, backup: &BackupConfig) -> Result<PathBuf, Error> {
+    let dir = Path::new(&backup.dir);
+    fs::create_dir_all(dir).context("failed to create backup directory")?;
+
+    let path = dir.join(format!(
+        "{}{}.{}",
+        BACKUP_PREFIX,
+        chrono::Utc::now().format("%Y%m%d-%H%M%S"),
+        BACKUP_EXTENSION
+    ));
+    Database::open(config)?.backup(&path)?;
+
+    let backups = backups(dir)?;
+    let old = backups.len().saturating_sub(backup.keep.max(1));
+    for path in &backups[..old] {
+        fs::remove_file(path).context("failed to delete old backup")?;
+    }
+
+    Ok(path)
+}
+
+// time until the next backup is due, counting from the newest one so restarts don't take extra
+fn next_backup_in(backup: &BackupConfig, interval: Duration) -> Duration {
+    let newest = backups(Path::new(&backup.dir))
+        .ok()
+        .and_then(|backups| backups.last().cloned())
+        .and_then(|path| {
+            fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+        });
+
+    match newest.and_then(|newest| SystemTime::now().duration_since(newest).ok()) {
+        Some(age) => interval.saturating_sub(age),
+        None => Duration::ZERO,
+    }
+}
+
+// backs up the database every `interval_hours` for as long as the server runs
+pub fn schedule_backups(config: &Config) {
+    let Some(backup) = config.backup.clone() else {
+        return;
+    };
+    let config = config.clone();
+    let interval = Duration::from_secs(backup.interval_hours.max(1) * 60 * 60);
+
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(next_backup_in(&backup, interval)).await;
+
+            let (config, backup) = (config.clone(), backup.clone());
+            let result =
+                tokio::task::spawn_blocking(move || run_scheduled_backup(&config, &backup)).await;
+            match result {
+                Ok(Ok(path)) => println!("backed up database to {}", path.display()),
+                Ok(Err(error)) => println!("backup failed: {}", error.chain_message()),
+                Err(error) => println!("backup failed: {}", error),
+            }
+            // a failing backup isn't retried right away
+            tokio::time::sleep(Duration::from_secs(60)).await;
+        }
+    });
+}
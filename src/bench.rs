@@ -0,0 +1,252 @@
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use axum::body::Body;
+use axum::http::Request;
+use tower::ServiceExt;
+
+use crate::prelude::*;
+use crate::{build_content, make_router, take_option, usage};
+
+const USAGE: &str = "bench-serve [--posts N] [--photos M] [--requests R] [--concurrency C]";
+
+// generated content, removed again once the benchmark is done
+struct Dataset(PathBuf);
+
+impl Drop for Dataset {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+pub async fn bench_serve(args: &[String]) -> Result<(), Error> {
+    let mut args = args.to_vec();
+    let post_count = parse_option(&mut args, "--posts", 200)?;
+    let photo_count = parse_option(&mut args, "--photos", 1000)?;
+    let request_count = parse_option(&mut args, "--requests", 500)?;
+    let concurrency = parse_option(&mut args, "--concurrency", 8)?.max(1);
+
+    if !args.is_empty() {
+        return usage(USAGE);
+    }
+
+    let dataset = generate_dataset(post_count, photo_count)?;
+    let config = dataset_config(&dataset.0)?;
+    let db = Database::open(&config)?;
+
+    let start = Instant::now();
+    build_content(&db, &config)?;
+    let build_time = start.elapsed();
+
+    let post_url = Post::get_all(&db)?
+        .get(post_count / 2)
+        .map(|post| post.url());
+    let photo_id = Photo::get_all(&db, None)?
+        .into_iter()
+        .find(|photo| !photo.is_private)
+        .map(|photo| photo.id);
+
+    let mut paths = vec![
+        routes::INDEX.to_string(),
+        routes::POSTS.to_string(),
+        routes::PROJECTS.to_string(),
+        routes::PHOTOS.to_string(),
+        routes::PHOTOS_FEED.to_string(),
+    ];
+    if let Some(post_url) = post_url {
+        paths.push(post_url);
+    }
+    if let Some(photo_id) = photo_id {
+        paths.push(routes::photo_sized(&photo_id, PhotoSize::Small));
+    }
+
+    let state = AppState::new(config)?;
+    let router = make_router(state);
+
+    // handlers log every request, so the results are collected first and printed together
+    let mut results = vec![];
+    for route in paths {
+        let (mut timings, errors) =
+            bench_route(&router, &route, request_count, concurrency).await?;
+        timings.sort();
+        results.push((route, timings, errors));
+    }
+
+    println!();
+    println!(
+        "{} posts, {} photos, built in {:.2}s",
+        post_count,
+        photo_count,
+        build_time.as_secs_f64()
+    );
+    println!(
+        "{} requests per route, {} concurrent",
+        request_count, concurrency
+    );
+    println!();
+    println!(
+        "{:<40} {:>10} {:>10} {:>8}",
+        "route", "p50 (ms)", "p99 (ms)", "errors"
+    );
+    for (route, timings, errors) in results {
+        println!(
+            "{:<40} {:>10.2} {:>10.2} {:>8}",
+            route,
+            percentile(&timings, 0.50).as_secs_f64() * 1000.0,
+            percentile(&timings, 0.99).as_secs_f64() * 1000.0,
+            errors
+        );
+    }
+
+    Ok(())
+}
+
+fn parse_option(args: &mut Vec<String>, name: &str, default: usize) -> Result<usize, Error> {
+    match take_option(args, name) {
+        Some(value) => value.parse().map_err(|_| {
+            Error::new(format!("Usage: {} expects a number", name)).with_kind(ErrorKind::Usage)
+        }),
+        None => Ok(default),
+    }
+}
+
+async fn bench_route(
+    router: &ax::Router,
+    route: &str,
+    request_count: usize,
+    concurrency: usize,
+) -> Result<(Vec<Duration>, usize), Error> {
+    let mut workers = vec![];
+
+    for worker in 0..concurrency {
+        let router = router.clone();
+        let route = route.to_string();
+        let count = request_count / concurrency + usize::from(worker < request_count % concurrency);
+
+        workers.push(tokio::spawn(async move {
+            let mut timings = Vec::with_capacity(count);
+            let mut errors = 0;
+
+            for _ in 0..count {
+                let request = Request::get(&route).body(Body::empty()).unwrap();
+                let start = Instant::now();
+                let response = router.clone().oneshot(request).await.unwrap();
+                let success = response.status().is_success();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX).await;
+                timings.push(start.elapsed());
+
+                if !success || body.is_err() {
+                    errors += 1;
+                }
+            }
+
+            (timings, errors)
+        }));
+    }
+
+    let mut timings = Vec::with_capacity(request_count);
+    let mut errors = 0;
+    for worker in workers {
+        let (worker_timings, worker_errors) = worker.await.context("benchmark worker failed")?;
+        timings.extend(worker_timings);
+        errors += worker_errors;
+    }
+
+    Ok((timings, errors))
+}
+
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let index = ((sorted.len() - 1) as f64 * p).round() as usize;
+    sorted[index]
+}
+
+fn generate_dataset(post_count: usize, photo_count: usize) -> Result<Dataset, Error> {
+    let dataset =
+        Dataset(std::env::temp_dir().join(format!("website-bench-{:016x}", rand::random::<u64>())));
+    println!("generating dataset in {:?}", dataset.0);
+
+    let styles_path = dataset.0.join("files/styles");
+    fs::create_dir_all(&styles_path).context("failed to create dataset directory")?;
+    fs::write(styles_path.join("page.css"), "body {}").context("failed to write style")?;
+
+    let mut jpeg = std::io::Cursor::new(vec![]);
+    image::RgbImage::from_fn(320, 240, |x, y| image::Rgb([x as u8, y as u8, 128]))
+        .write_to(&mut jpeg, image::ImageFormat::Jpeg)
+        .context("failed to encode photo")?;
+    let jpeg = jpeg.into_inner();
+
+    let first_date = chrono::NaiveDate::from_ymd_opt(2020, 1, 1).unwrap();
+
+    for i in 0..post_count {
+        let post_path = dataset.0.join(format!("posts/post-{:05}", i));
+        fs::create_dir_all(&post_path).context("failed to create post directory")?;
+
+        let date = first_date + chrono::Days::new(i as u64);
+        let tags = if i % 5 == 0 {
+            vec!["project", "bench"]
+        } else {
+            vec!["bench"]
+        };
+        let metadata = serde_json::json!({
+            "id": format!("bench{:05}", i),
+            "title": format!("Benchmark post {}", i),
+            "description": "A generated post.",
+            "date": date.format("%Y-%m-%d").to_string(),
+            "tags": tags,
+        });
+        fs::write(post_path.join("meta.json"), metadata.to_string())
+            .context("failed to write post metadata")?;
+        fs::write(post_path.join("index.md"), post_markdown(i))
+            .context("failed to write post content")?;
+
+        // spread the photos evenly over the posts, with every fourth one private
+        for j in (i..photo_count).step_by(post_count.max(1)) {
+            let folder = if j % 4 == 3 { "private" } else { "photos" };
+            let photo_path = post_path.join(folder);
+            fs::create_dir_all(&photo_path).context("failed to create photo directory")?;
+            fs::write(photo_path.join(format!("photo-{:05}.jpg", j)), &jpeg)
+                .context("failed to write photo")?;
+        }
+    }
+
+    Ok(dataset)
+}
+
+fn post_markdown(i: usize) -> String {
+    let paragraph = "Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod \
+        tempor incididunt ut labore et dolore magna aliqua. Ut enim ad minim veniam, quis \
+        nostrud exercitation ullamco laboris nisi ut aliquip ex ea commodo consequat.";
+
+    let mut markdown = String::new();
+    for section in 0..4 {
+        markdown.push_str(&format!("## Section {}\n\n", section));
+        markdown.push_str(&format!("{}\n\n{}\n\n", paragraph, paragraph));
+        markdown.push_str(&format!("```rust\nfn post_{}() {{}}\n```\n\n", i));
+    }
+    markdown
+}
+
+fn dataset_config(path: &Path) -> Result<Config, Error> {
+    Config::from_json_str(
+        &serde_json::json!({
+            "database_path": path.join("database.sqlite"),
+            "posts_path": path.join("posts"),
+            "files_path": path.join("files"),
+            "post_content_path": "index.md",
+            "post_metadata_path": "meta.json",
+            "post_assets_path": "assets",
+            "post_public_photos_path": "photos",
+            "post_private_photos_path": "private",
+            "photo_max_preview_size": 200,
+            "photo_quality": 80,
+            "server_host": "127.0.0.1",
+            "server_port": 0,
+            "site_url": "http://localhost",
+            "photos_per_page": 50,
+        })
+        .to_string(),
+    )
+}
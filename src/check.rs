@@ -0,0 +1,192 @@
+use crate::doctor::{finish, Report};
+use crate::prelude::*;
+use crate::{schema, take_flag, usage};
+
+const USAGE: &str = "check [--repair]";
+
+// Rows pointing at something that is gone, by what they are and the condition that finds them in
+// their table. Most can't be caught by foreign keys, e.g. comments are kept when a post is
+// removed from the content and only lose their post if it never comes back.
+const ORPHANS: &[(&str, &str, &str)] = &[
+    (
+        "photo links to missing posts or photos",
+        "posts_photos",
+        "post_id NOT IN (SELECT id FROM posts) OR photo_id NOT IN (SELECT id FROM photos)",
+    ),
+    (
+        "asset links to missing posts or assets",
+        "posts_assets",
+        "post_id NOT IN (SELECT id FROM posts) OR asset_id NOT IN (SELECT id FROM styles)",
+    ),
+    (
+        "tags of missing posts",
+        "posts_tags",
+        "post_id NOT IN (SELECT id FROM posts)",
+    ),
+    (
+        "aliases of missing posts",
+        "post_aliases",
+        "post_id NOT IN (SELECT id FROM posts)",
+    ),
+    (
+        "resources of missing posts",
+        "posts_resources",
+        "post_id NOT IN (SELECT id FROM posts)",
+    ),
+    (
+        "polls of missing posts",
+        "polls",
+        "post_id NOT IN (SELECT id FROM posts)",
+    ),
+    (
+        "events of missing posts",
+        "post_events",
+        "post_id NOT IN (SELECT id FROM posts)",
+    ),
+    (
+        "alt text items of missing posts",
+        "alt_text_items",
+        "post_id NOT IN (SELECT id FROM posts)",
+    ),
+    (
+        "photos without a post",
+        "photos",
+        "id NOT IN (SELECT photo_id FROM posts_photos)",
+    ),
+    (
+        "assets without a post",
+        "styles",
+        "id NOT IN (SELECT asset_id FROM posts_assets)",
+    ),
+    // only these directories below the files directory are served
+    (
+        "files with an unknown path",
+        "files",
+        "path NOT IN ('styles', 'scripts', 'files', 'assets')",
+    ),
+    (
+        "files with missing contents",
+        "files",
+        "data_hash NOT IN (SELECT hash FROM blobs)",
+    ),
+    (
+        "assets with missing contents",
+        "styles",
+        "data_hash NOT IN (SELECT hash FROM blobs)",
+    ),
+    (
+        "photos with missing contents",
+        "photos",
+        "image_large_hash NOT IN (SELECT hash FROM blobs) OR image_small_hash NOT IN (SELECT hash FROM blobs)",
+    ),
+    (
+        "unused blobs",
+        "blobs",
+        "hash NOT IN (SELECT data_hash FROM files UNION SELECT data_hash FROM styles UNION SELECT image_large_hash FROM photos UNION SELECT image_small_hash FROM photos)",
+    ),
+    (
+        "comments on missing posts",
+        "comments",
+        "post_id NOT IN (SELECT id FROM posts)",
+    ),
+    (
+        "poll votes on missing polls",
+        "poll_votes",
+        "(post_id, poll_id) NOT IN (SELECT post_id, id FROM polls)",
+    ),
+    (
+        "alt texts of missing posts",
+        "alt_texts",
+        "post_id NOT IN (SELECT id FROM posts)",
+    ),
+    (
+        "sessions of missing users",
+        "sessions",
+        "key_hash NOT IN (SELECT key_hash FROM users)",
+    ),
+    (
+        "login links of missing users",
+        "login_links",
+        "key_hash NOT IN (SELECT key_hash FROM users)",
+    ),
+];
+
+// Checks the database file itself and then looks for rows pointing at something that is gone.
+// With `--repair` those rows are deleted.
+pub async fn check(args: &[String]) -> Result<(), Error> {
+    let mut args = args.to_vec();
+    let repair = take_flag(&mut args, "--repair");
+    if !args.is_empty() {
+        return usage(USAGE);
+    }
+
+    let config = Config::from_json_file("website.json")?;
+    let db = Database::open(&config)?;
+    schema::check_version(&db)?;
+
+    let mut report = Report::new();
+    report.check("database integrity", check_integrity(&db));
+    report.check("blobs match their hashes", check_blobs(&db));
+    for (name, table, condition) in ORPHANS {
+        check_orphans(&db, &mut report, repair, name, table, condition)?;
+    }
+
+    finish(&report)
+}
+
+fn check_integrity(db: &Database) -> Result<(), Error> {
+    let problems = db
+        .query_mul("PRAGMA integrity_check;", [], |row| row.get::<_, String>(0))
+        .context("failed to check database integrity")?;
+
+    match problems.as_slice() {
+        [ok] if ok == "ok" => Ok(()),
+        problems => Err(Error::new(problems.join(", ")).with_kind(ErrorKind::Database)),
+    }
+}
+
+fn check_blobs(db: &Database) -> Result<(), Error> {
+    match Blob::find_corrupted(db)?.len() {
+        0 => Ok(()),
+        count => Err(Error::new(format!(
+            "{} blobs changed since they were written, restore a backup or delete them and rebuild",
+            count
+        ))
+        .with_kind(ErrorKind::Database)),
+    }
+}
+
+fn check_orphans(
+    db: &Database,
+    report: &mut Report,
+    repair: bool,
+    name: &str,
+    table: &str,
+    condition: &str,
+) -> Result<(), Error> {
+    let count: i64 = db
+        .query_one(
+            &format!("SELECT COUNT(*) FROM {} WHERE {};", table, condition),
+            [],
+            |row| row.get(0),
+        )
+        .context(format!("failed to count {}", name))?;
+
+    match (count, repair) {
+        (0, _) => report.check(&format!("no {}", name), Ok(())),
+        (count, true) => {
+            db.execute(&format!("DELETE FROM {} WHERE {};", table, condition), [])
+                .context(format!("failed to delete {}", name))?;
+            report.check(&format!("deleted {} {}", count, name), Ok(()))
+        }
+        (count, false) => report.check(
+            &format!("no {}", name),
+            Err(Error::new(format!(
+                "found {}, run `website check --repair` to delete them",
+                count
+            ))),
+        ),
+    };
+
+    Ok(())
+}
@@ -0,0 +1,236 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::database::SqliteError;
+use crate::prelude::*;
+use crate::{schema, take_flag, take_option, usage};
+
+const USAGE: &str = "check-links [--recheck] [--concurrency N]";
+const TIMEOUT: Duration = Duration::from_secs(10);
+const RETRIES: u32 = 2;
+const DEFAULT_CONCURRENCY: usize = 8;
+// links that worked aren't asked again for a week, broken ones on every run so fixes show up
+const CACHE_TTL: i64 = 7 * 24 * 60 * 60;
+
+// The last answer of every external url linked from a post. Only `website check-links` reads and
+// writes it, the cache is state so a rebuild doesn't make the next run ask every site again.
+pub struct LinkCheck {
+    pub url: String,
+    // the http status, none if the request failed before there was one
+    pub status: Option<u16>,
+    pub error: Option<String>,
+}
+
+impl LinkCheck {
+    pub fn setup(db: &Database) -> Result<(), Error> {
+        db.execute_batch(
+            r#"
+                CREATE TABLE IF NOT EXISTS link_checks (
+                    url TEXT PRIMARY KEY NOT NULL,
+                    status INTEGER NULL,
+                    error TEXT NULL,
+                    checked_at INTEGER NOT NULL
+                );
+            "#,
+        )
+        .context("failed to create link_checks table")
+    }
+
+    fn from_row(row: &Row) -> Result<Self, SqliteError> {
+        Ok(Self {
+            url: row.get(0)?,
+            status: row.get(1)?,
+            error: row.get(2)?,
+        })
+    }
+
+    // working links checked within `CACHE_TTL`
+    fn get_fresh(db: &Database) -> Result<Vec<Self>, Error> {
+        db.query_mul(
+            r#"
+                SELECT url, status, error FROM link_checks
+                WHERE status < 400 AND checked_at > unixepoch() - ?;
+            "#,
+            [CACHE_TTL],
+            LinkCheck::from_row,
+        )
+        .context("failed to query link checks from database")
+    }
+
+    fn save(&self, db: &Database) -> Result<(), Error> {
+        db.execute(
+            r#"
+                INSERT INTO link_checks (url, status, error, checked_at)
+                VALUES (?, ?, ?, unixepoch())
+                ON CONFLICT (url) DO UPDATE SET
+                    status = excluded.status,
+                    error = excluded.error,
+                    checked_at = excluded.checked_at;
+            "#,
+            (&self.url, self.status, &self.error),
+        )
+        .context("failed to save link check into database")
+    }
+
+    pub fn is_broken(&self) -> bool {
+        self.status.is_none_or(|status| status >= 400)
+    }
+
+    pub fn problem(&self) -> String {
+        match (self.status, &self.error) {
+            (Some(status), _) => status.to_string(),
+            (None, Some(error)) => error.clone(),
+            (None, None) => "unknown error".to_string(),
+        }
+    }
+}
+
+// Checks every external link of every post, hidden ones too since they are fixed the same way,
+// and prints the broken ones with the posts that link to them. Fails if any are broken, so it can
+// run from cron and mail the report.
+pub async fn check_links(args: &[String]) -> Result<(), Error> {
+    let mut args = args.to_vec();
+    let recheck = take_flag(&mut args, "--recheck");
+    let concurrency = match take_option(&mut args, "--concurrency") {
+        Some(concurrency) => match concurrency.parse::<usize>() {
+            Ok(concurrency) if concurrency > 0 => concurrency,
+            _ => return usage(USAGE),
+        },
+        None => DEFAULT_CONCURRENCY,
+    };
+    if !args.is_empty() {
+        return usage(USAGE);
+    }
+
+    let config = Config::from_json_file("website.json")?;
+    let db = Database::open(&config)?;
+    schema::check_version(&db)?;
+
+    let broken = check_links_site(&db, &config, recheck, concurrency)?;
+    for (check, slugs) in &broken {
+        println!(
+            "{} {} (in {})",
+            check.problem(),
+            check.url,
+            slugs.join(", ")
+        );
+    }
+
+    match broken.len() {
+        0 => {
+            println!("no broken links");
+            Ok(())
+        }
+        count => Err(Error::new(format!("{} links are broken", count))),
+    }
+}
+
+// the broken links with the slugs of the posts linking to them
+pub(crate) fn check_links_site(
+    db: &Database,
+    cfg: &Config,
+    recheck: bool,
+    concurrency: usize,
+) -> Result<Vec<(LinkCheck, Vec<String>)>, Error> {
+    let site_url = cfg.site_url.trim_end_matches('/');
+    let mut links: Vec<(String, Vec<String>)> = vec![];
+    for post in Post::get_all(db)? {
+        let source = post.get_source(db)?;
+        let urls = markdown_links(&source)
+            .into_iter()
+            .chain(markdown_images(&source).into_iter().map(|(url, _)| url));
+        for url in urls {
+            if !(url.starts_with("http://") || url.starts_with("https://"))
+                || url.starts_with(site_url)
+            {
+                continue;
+            }
+            match links.iter_mut().find(|(other, _)| *other == url) {
+                Some((_, slugs)) if !slugs.contains(&post.slug) => slugs.push(post.slug.clone()),
+                Some(_) => {}
+                None => links.push((url, vec![post.slug.clone()])),
+            }
+        }
+    }
+
+    let fresh = match recheck {
+        true => vec![],
+        false => LinkCheck::get_fresh(db)?,
+    };
+    let pending = links
+        .iter()
+        .map(|(url, _)| url.as_str())
+        .filter(|url| !fresh.iter().any(|check| check.url == *url))
+        .collect::<Vec<_>>();
+    println!(
+        "checking {} links, {} cached",
+        pending.len(),
+        links.len() - pending.len()
+    );
+
+    let agent = ureq::AgentBuilder::new()
+        .timeout(TIMEOUT)
+        .user_agent(concat!(
+            "website/",
+            env!("CARGO_PKG_VERSION"),
+            " (link checker)"
+        ))
+        .build();
+    let queue = Mutex::new(pending.into_iter());
+    let checks = Mutex::new(vec![]);
+    std::thread::scope(|scope| {
+        for _ in 0..concurrency {
+            scope.spawn(|| loop {
+                let Some(url) = queue.lock().unwrap().next() else {
+                    break;
+                };
+                let check = check_url(&agent, url);
+                checks.lock().unwrap().push(check);
+            });
+        }
+    });
+
+    let mut broken = vec![];
+    for check in checks.into_inner().unwrap() {
+        check.save(db)?;
+        if check.is_broken() {
+            let slugs = links
+                .iter()
+                .find(|(url, _)| *url == check.url)
+                .map(|(_, slugs)| slugs.clone())
+                .unwrap_or_default();
+            broken.push((check, slugs));
+        }
+    }
+    broken.sort_by(|(a, _), (b, _)| a.url.cmp(&b.url));
+
+    Ok(broken)
+}
+
+// HEAD first, some servers only answer GET properly. Timeouts and server errors are retried,
+// they are often gone a moment later.
+fn check_url(agent: &ureq::Agent, url: &str) -> LinkCheck {
+    let mut attempt = 0;
+    loop {
+        let result = match agent.head(url).call() {
+            Err(ureq::Error::Status(405 | 403 | 404 | 501, _)) => agent.get(url).call(),
+            result => result,
+        };
+        let (status, error) = match result {
+            Ok(response) => (Some(response.status()), None),
+            Err(ureq::Error::Status(status, _)) => (Some(status), None),
+            Err(ureq::Error::Transport(transport)) => (None, Some(transport.to_string())),
+        };
+
+        let is_temporary = status.is_none_or(|status| status == 429 || status >= 500);
+        if !is_temporary || attempt >= RETRIES {
+            return LinkCheck {
+                url: url.to_string(),
+                status,
+                error,
+            };
+        }
+        attempt += 1;
+        std::thread::sleep(Duration::from_secs(attempt as u64));
+    }
+}
@@ -0,0 +1,898 @@
+use std::io::Read;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::time::Duration;
+
+use axum::extract::Request;
+use base64::Engine;
+use rsa::pkcs1v15::{Signature, SigningKey, VerifyingKey};
+use rsa::pkcs8::{DecodePrivateKey, DecodePublicKey, EncodePublicKey, LineEnding};
+use rsa::signature::{SignatureEncoding, Signer, Verifier};
+use rsa::{RsaPrivateKey, RsaPublicKey};
+use sha2::{Digest, Sha256};
+
+use crate::config::ActivityPubConfig;
+use crate::prelude::*;
+use crate::time;
+
+pub const ACTIVITY_JSON: &str = "application/activity+json";
+const JRD_JSON: &str = "application/jrd+json";
+const ACTIVITY_STREAMS: &str = "https://www.w3.org/ns/activitystreams";
+const SECURITY: &str = "https://w3id.org/security/v1";
+const PUBLIC: &str = "https://www.w3.org/ns/activitystreams#Public";
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+// activities are small, actors with a long bio are the largest documents fetched
+const MAX_INBOX_SIZE: usize = 256 * 1024;
+const MAX_ACTOR_SIZE: u64 = 1024 * 1024;
+// older signatures could be replayed
+const MAX_SIGNATURE_AGE: i64 = 12 * 60 * 60;
+
+// Fediverse accounts that follow the blog. New and changed public posts are delivered to their
+// inbox, or to the shared inbox of their server so a server with many followers gets one copy.
+pub struct Follower;
+
+impl Follower {
+    pub fn setup(db: &Database) -> Result<(), Error> {
+        db.execute_batch(
+            r#"
+                CREATE TABLE IF NOT EXISTS activitypub_followers (
+                    actor TEXT PRIMARY KEY NOT NULL,
+                    inbox TEXT NOT NULL,
+                    followed_at INTEGER NOT NULL
+                );
+
+                CREATE TABLE IF NOT EXISTS activitypub_deliveries (
+                    post_id TEXT PRIMARY KEY NOT NULL,
+                    hash TEXT NOT NULL
+                );
+            "#,
+        )
+        .context("failed to create activitypub tables")
+    }
+
+    fn add(db: &Database, actor: &str, inbox: &str) -> Result<(), Error> {
+        db.execute(
+            r#"
+                INSERT INTO activitypub_followers (actor, inbox, followed_at)
+                VALUES (?, ?, unixepoch())
+                ON CONFLICT (actor) DO UPDATE SET inbox = excluded.inbox;
+            "#,
+            (actor, inbox),
+        )
+        .context("failed to insert follower into database")
+    }
+
+    fn remove(db: &Database, actor: &str) -> Result<(), Error> {
+        db.execute(
+            "DELETE FROM activitypub_followers WHERE actor = ?;",
+            [actor],
+        )
+        .context("failed to delete follower from database")
+    }
+
+    pub fn count(db: &Database) -> Result<u32, Error> {
+        db.query_one("SELECT COUNT(*) FROM activitypub_followers;", [], |row| {
+            row.get(0)
+        })
+        .context("failed to count followers in database")
+    }
+
+    // every inbox once
+    fn inboxes(db: &Database) -> Result<Vec<String>, Error> {
+        db.query_mul(
+            "SELECT DISTINCT inbox FROM activitypub_followers ORDER BY inbox;",
+            [],
+            |row| row.get(0),
+        )
+        .context("failed to query follower inboxes from database")
+    }
+}
+
+fn actor_id(cfg: &Config) -> String {
+    format!("{}{}", cfg.site_url.trim_end_matches('/'), routes::ACTOR)
+}
+
+fn key_id(cfg: &Config) -> String {
+    format!("{}#main-key", actor_id(cfg))
+}
+
+fn absolute(cfg: &Config, path: &str) -> String {
+    format!("{}{}", cfg.site_url.trim_end_matches('/'), path)
+}
+
+pub(crate) fn private_key(activitypub: &ActivityPubConfig) -> Result<RsaPrivateKey, Error> {
+    let pem = fs::read_to_string(&activitypub.private_key_path)
+        .context("failed to read activitypub private key")?;
+    RsaPrivateKey::from_pkcs8_pem(&pem)
+        .map_err(|error| Error::new(error.to_string()))
+        .context("invalid activitypub private key, it must be a PKCS#8 PEM file")
+}
+
+fn activity_response(json: serde_json::Value) -> ax::Response {
+    (
+        [(ax::header::CONTENT_TYPE, ACTIVITY_JSON)],
+        json.to_string(),
+    )
+        .into_response()
+}
+
+#[derive(Deserialize)]
+pub struct WebfingerQuery {
+    resource: Option<String>,
+}
+
+pub async fn get_webfinger(
+    ax::State(state): ax::State<Arc<AppState>>,
+    ax::Query(query): ax::Query<WebfingerQuery>,
+) -> impl IntoResponse {
+    println!("GET webfinger, resource = {:?}", query.resource);
+
+    let cfg = &state.config.lock().unwrap();
+    let Some(activitypub) = &cfg.activitypub else {
+        return make_error(404, "Page not found").into_response();
+    };
+
+    let host = url::Url::parse(&cfg.site_url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_default();
+    let account = format!("acct:{}@{}", activitypub.username, host);
+    if query.resource.as_deref() != Some(&account)
+        && query.resource.as_deref() != Some(&actor_id(cfg))
+    {
+        return make_error(404, "Unknown resource").into_response();
+    }
+
+    let json = serde_json::json!({
+        "subject": account,
+        "aliases": [actor_id(cfg)],
+        "links": [
+            {"rel": "self", "type": ACTIVITY_JSON, "href": actor_id(cfg)},
+            {"rel": "http://webfinger.net/rel/profile-page", "type": "text/html", "href": absolute(cfg, routes::INDEX)},
+        ],
+    });
+    ([(ax::header::CONTENT_TYPE, JRD_JSON)], json.to_string()).into_response()
+}
+
+pub async fn get_actor(ax::State(state): ax::State<Arc<AppState>>) -> impl IntoResponse {
+    println!("GET activitypub actor");
+
+    let cfg = &state.config.lock().unwrap();
+    let Some(activitypub) = &cfg.activitypub else {
+        return make_error(404, "Page not found").into_response();
+    };
+
+    let public_key = private_key(activitypub).and_then(|key| {
+        RsaPublicKey::from(&key)
+            .to_public_key_pem(LineEnding::LF)
+            .map_err(|error| Error::new(error.to_string()))
+    });
+    let public_key = match public_key {
+        Ok(public_key) => public_key,
+        Err(error) => {
+            println!("error: {}", error.chain_message());
+            return make_error(500, "Failed to load key").into_response();
+        }
+    };
+
+    activity_response(serde_json::json!({
+        "@context": [ACTIVITY_STREAMS, SECURITY],
+        "id": actor_id(cfg),
+        "type": "Person",
+        "preferredUsername": activitypub.username,
+        "name": activitypub.name,
+        "summary": activitypub.summary,
+        "url": absolute(cfg, routes::INDEX),
+        "inbox": absolute(cfg, routes::INBOX),
+        "outbox": absolute(cfg, routes::OUTBOX),
+        "followers": absolute(cfg, routes::FOLLOWERS),
+        "manuallyApprovesFollowers": false,
+        "publicKey": {
+            "id": key_id(cfg),
+            "owner": actor_id(cfg),
+            "publicKeyPem": public_key,
+        },
+    }))
+}
+
+// A post as the fediverse shows it: the title, description and a link, like in the feed. Posts
+// are pages rather than notes, so the page is where they are read.
+fn note(cfg: &Config, post: &Post, tags: &[String]) -> serde_json::Value {
+    let url = absolute(cfg, &post.url());
+    let mut content = html!(
+        p { a href=(url) { (post.title) } }
+        @if let Some(description) = &post.description {
+            p { (description) }
+        }
+    )
+    .into_string();
+    if !tags.is_empty() {
+        content.push_str(
+            &html!(p {
+                @for tag in tags {
+                    a href=(absolute(cfg, &routes::posts_tagged(tag))) rel="tag" { "#" (tag) } " "
+                }
+            })
+            .into_string(),
+        );
+    }
+
+    let published = time::parse_date(&post.date, cfg.timezone())
+        .map(|date| date.with_timezone(&chrono::Utc).to_rfc3339());
+    serde_json::json!({
+        "id": absolute(cfg, &routes::note(&post.id)),
+        "type": "Note",
+        "attributedTo": actor_id(cfg),
+        "name": post.title,
+        "content": content,
+        "url": url,
+        "published": published,
+        "to": [PUBLIC],
+        "cc": [absolute(cfg, routes::FOLLOWERS)],
+        "tag": tags.iter().map(|tag| serde_json::json!({
+            "type": "Hashtag",
+            "name": format!("#{}", tag),
+            "href": absolute(cfg, &routes::posts_tagged(tag)),
+        })).collect::<Vec<_>>(),
+    })
+}
+
+fn activity(cfg: &Config, kind: &str, note: serde_json::Value) -> serde_json::Value {
+    serde_json::json!({
+        "@context": ACTIVITY_STREAMS,
+        "id": format!("{}#{}", note["id"].as_str().unwrap_or_default(), kind.to_lowercase()),
+        "type": kind,
+        "actor": actor_id(cfg),
+        "published": note["published"],
+        "to": note["to"],
+        "cc": note["cc"],
+        "object": note,
+    })
+}
+
+pub async fn get_outbox(ax::State(state): ax::State<Arc<AppState>>) -> impl IntoResponse {
+    println!("GET activitypub outbox");
+
+    let db = &match state.lock_db().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let cfg = &state.config.lock().unwrap();
+    if cfg.activitypub.is_none() {
+        return make_error(404, "Page not found").into_response();
+    }
+
+    let posts = match Post::get_all(db) {
+        Ok(posts) => posts,
+        Err(_) => return make_error(500, "Failed to load posts").into_response(),
+    };
+    let mut items = vec![];
+    for post in posts
+        .iter()
+        .filter(|post| post.visible_to(None, cfg.timezone()))
+        .take(cfg.feed_length as usize)
+    {
+        let tags = post.get_tags(db).unwrap_or_default();
+        items.push(activity(cfg, "Create", note(cfg, post, &tags)));
+    }
+
+    activity_response(serde_json::json!({
+        "@context": ACTIVITY_STREAMS,
+        "id": absolute(cfg, routes::OUTBOX),
+        "type": "OrderedCollection",
+        "totalItems": items.len(),
+        "orderedItems": items,
+    }))
+}
+
+pub async fn get_note(
+    ax::State(state): ax::State<Arc<AppState>>,
+    ax::Path(id): ax::Path<String>,
+) -> impl IntoResponse {
+    println!("GET activitypub note {}", id);
+
+    let db = &match state.lock_db().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let cfg = &state.config.lock().unwrap();
+    if cfg.activitypub.is_none() {
+        return make_error(404, "Page not found").into_response();
+    }
+
+    match Post::by_id(db, &id) {
+        Ok(post) if post.visible_to(None, cfg.timezone()) => {
+            let tags = post.get_tags(db).unwrap_or_default();
+            let mut note = note(cfg, &post, &tags);
+            note["@context"] = ACTIVITY_STREAMS.into();
+            activity_response(note)
+        }
+        _ => make_error(404, "Post not found").into_response(),
+    }
+}
+
+// only the number, who follows the blog is their business
+pub async fn get_followers(ax::State(state): ax::State<Arc<AppState>>) -> impl IntoResponse {
+    println!("GET activitypub followers");
+
+    let db = &match state.lock_db().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let cfg = &state.config.lock().unwrap();
+    if cfg.activitypub.is_none() {
+        return make_error(404, "Page not found").into_response();
+    }
+
+    let count = match Follower::count(db) {
+        Ok(count) => count,
+        Err(_) => return make_error(500, "Failed to count followers").into_response(),
+    };
+    activity_response(serde_json::json!({
+        "@context": ACTIVITY_STREAMS,
+        "id": absolute(cfg, routes::FOLLOWERS),
+        "type": "OrderedCollection",
+        "totalItems": count,
+    }))
+}
+
+// Follows and unfollows, everything else is accepted and ignored. Every activity has to be signed
+// by its actor, whose key is fetched from their server.
+pub async fn post_inbox(
+    ax::State(state): ax::State<Arc<AppState>>,
+    request: Request,
+) -> impl IntoResponse {
+    println!("POST activitypub inbox");
+
+    let Some(activitypub) = state.config.lock().unwrap().activitypub.clone() else {
+        return make_error(404, "Page not found").into_response();
+    };
+
+    let (parts, body) = request.into_parts();
+    let Ok(body) = axum::body::to_bytes(body, MAX_INBOX_SIZE).await else {
+        return make_error(413, "Activity too large").into_response();
+    };
+    let Ok(activity) = serde_json::from_slice::<serde_json::Value>(&body) else {
+        return make_error(400, "Invalid activity").into_response();
+    };
+    let Some(actor) = activity["actor"].as_str().map(str::to_string) else {
+        return make_error(400, "Activity has no actor").into_response();
+    };
+
+    let signed = SignedRequest {
+        method: parts.method.as_str().to_lowercase(),
+        path: parts
+            .uri
+            .path_and_query()
+            .map_or(routes::INBOX, |path| path.as_str())
+            .to_string(),
+        headers: parts.headers,
+        body: body.to_vec(),
+    };
+    if let Err(error) = signed.check() {
+        println!(
+            "rejecting activity from {}: {}",
+            actor,
+            error.chain_message()
+        );
+        return make_error(401, "Invalid signature").into_response();
+    }
+    let fetched = {
+        let actor = actor.clone();
+        tokio::task::spawn_blocking(move || {
+            let document = fetch_actor(&actor)?;
+            signed.verify(&document)?;
+            Ok::<_, Error>(document)
+        })
+        .await
+    };
+    let document = match fetched {
+        Ok(Ok(document)) => document,
+        Ok(Err(error)) => {
+            println!(
+                "rejecting activity from {}: {}",
+                actor,
+                error.chain_message()
+            );
+            return make_error(401, "Invalid signature").into_response();
+        }
+        Err(_) => return make_error(500, "Failed to check signature").into_response(),
+    };
+
+    let cfg = state.config.lock().unwrap().clone();
+    let object = &activity["object"];
+    let is_us = |value: &serde_json::Value| value.as_str() == Some(&actor_id(&cfg));
+
+    match activity["type"].as_str() {
+        Some("Follow") if is_us(object) => {
+            let inbox = document["endpoints"]["sharedInbox"]
+                .as_str()
+                .or(document["inbox"].as_str());
+            let Some(inbox) = inbox.map(str::to_string) else {
+                return make_error(400, "Actor has no inbox").into_response();
+            };
+            let checked = {
+                let inbox = inbox.clone();
+                tokio::task::spawn_blocking(move || check_remote_url(&inbox, cfg!(test))).await
+            };
+            if !matches!(checked, Ok(Ok(_))) {
+                return make_error(400, "Actor has no public inbox").into_response();
+            }
+            {
+                let db = &match state.lock_writer().await {
+                    Ok(db) => db,
+                    Err(response) => return response,
+                };
+                if Follower::add(db, &actor, &inbox).is_err() {
+                    return make_error(500, "Failed to save follower").into_response();
+                }
+            }
+            println!("{} follows the blog", actor);
+
+            // the personal inbox, the follow is between the two of us
+            let inbox = document["inbox"].as_str().unwrap_or(&inbox).to_string();
+            let accept = serde_json::json!({
+                "@context": ACTIVITY_STREAMS,
+                "id": format!("{}#accept-{:016x}", actor_id(&cfg), rand::random::<u64>()),
+                "type": "Accept",
+                "actor": actor_id(&cfg),
+                "object": activity,
+            });
+            tokio::spawn(async move {
+                let sent = tokio::task::spawn_blocking(move || {
+                    deliver(&cfg, &private_key(&activitypub)?, &inbox, &accept)
+                })
+                .await;
+                if let Ok(Err(error)) = sent {
+                    println!(
+                        "warning: failed to accept follow: {}",
+                        error.chain_message()
+                    );
+                }
+            });
+        }
+        Some("Undo") if object["type"].as_str() == Some("Follow") && is_us(&object["object"]) => {
+            let db = &match state.lock_writer().await {
+                Ok(db) => db,
+                Err(response) => return response,
+            };
+            if Follower::remove(db, &actor).is_err() {
+                return make_error(500, "Failed to remove follower").into_response();
+            }
+            println!("{} unfollowed the blog", actor);
+        }
+        // the account was deleted
+        Some("Delete") if object.as_str() == Some(&actor) => {
+            let db = &match state.lock_writer().await {
+                Ok(db) => db,
+                Err(response) => return response,
+            };
+            if Follower::remove(db, &actor).is_err() {
+                return make_error(500, "Failed to remove follower").into_response();
+            }
+        }
+        _ => {}
+    }
+
+    ax::StatusCode::ACCEPTED.into_response()
+}
+
+// No redirects, they could lead from a public server to a local one. Connections only go to the
+// addresses the host was just resolved to and checked, so a second lookup can't be pointed at
+// this machine or the local network.
+fn agent(allow_local: bool) -> ureq::Agent {
+    ureq::AgentBuilder::new()
+        .timeout(TIMEOUT)
+        .redirects(0)
+        .resolver(move |netloc: &str| {
+            public_addresses(netloc, allow_local).map_err(|error| {
+                std::io::Error::new(std::io::ErrorKind::PermissionDenied, error.chain_message())
+            })
+        })
+        .user_agent(concat!(
+            "website/",
+            env!("CARGO_PKG_VERSION"),
+            " (activitypub)"
+        ))
+        .build()
+}
+
+// Actors and their inboxes are fetched on behalf of whoever posts to the inbox, so only https urls
+// of public hosts are allowed, never this machine or the local network. `allow_local` is for the
+// tests, which run the other server on localhost.
+fn check_remote_url(url: &str, allow_local: bool) -> Result<(), Error> {
+    let url = url::Url::parse(url).context("invalid url")?;
+    if allow_local {
+        return Ok(());
+    }
+    if url.scheme() != "https" {
+        return Err(Error::new(format!("{} is not https", url)));
+    }
+
+    let host = url.host_str().context("url has no host")?;
+    let port = url.port_or_known_default().unwrap_or(443);
+    public_addresses(&format!("{}:{}", host, port), false).map(|_| ())
+}
+
+// the addresses of `host:port`, as long as all of them are public
+fn public_addresses(netloc: &str, allow_local: bool) -> Result<Vec<SocketAddr>, Error> {
+    let addresses = netloc
+        .to_socket_addrs()
+        .context(format!("failed to resolve {}", netloc))?
+        .collect::<Vec<_>>();
+    let is_public =
+        !addresses.is_empty() && addresses.iter().all(|address| is_public_ip(address.ip()));
+    match allow_local || is_public {
+        true => Ok(addresses),
+        false => Err(Error::new(format!("{} is not a public host", netloc))),
+    }
+}
+
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                // shared address space of carrier-grade NAT
+                || (a == 100 && (64..128).contains(&b))
+                || a == 0)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_ip(IpAddr::V4(ip)),
+            None => {
+                let first = ip.segments()[0];
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    // unique local and link local
+                    || (first & 0xfe00) == 0xfc00
+                    || (first & 0xffc0) == 0xfe80)
+            }
+        },
+    }
+}
+
+fn fetch_actor(actor: &str) -> Result<serde_json::Value, Error> {
+    check_remote_url(actor, cfg!(test))?;
+    let response = agent(cfg!(test))
+        .get(actor)
+        .set("accept", ACTIVITY_JSON)
+        .call()
+        .context(format!("failed to fetch actor {}", actor))?;
+    let mut body = vec![];
+    response
+        .into_reader()
+        .take(MAX_ACTOR_SIZE)
+        .read_to_end(&mut body)
+        .context("failed to read actor")?;
+    serde_json::from_slice(&body).context("invalid actor")
+}
+
+// An incoming request with an http signature
+// (https://datatracker.ietf.org/doc/html/draft-cavage-http-signatures), as mastodon sends them.
+pub(crate) struct SignedRequest {
+    pub method: String,
+    // with the query, as in `(request-target)`
+    pub path: String,
+    pub headers: ax::HeaderMap,
+    pub body: Vec<u8>,
+}
+
+// the parts of a signature header that passed the checks which need no key
+struct SignatureHeader {
+    key_id: String,
+    signature: Vec<u8>,
+    signed_headers: Vec<String>,
+}
+
+impl SignedRequest {
+    fn header(&self, name: &str) -> Result<&str, Error> {
+        self.headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .context(format!("missing {} header", name))
+    }
+
+    // Everything that can be checked without the key of the actor: the signed headers, the
+    // digest and the date. Runs before the actor is fetched, so a forged request costs nothing.
+    pub fn check(&self) -> Result<(), Error> {
+        self.parse().map(|_| ())
+    }
+
+    fn parse(&self) -> Result<SignatureHeader, Error> {
+        let mut params = HashMap::new();
+        for param in split_params(self.header("signature")?) {
+            if let Some((key, value)) = param.split_once('=') {
+                params.insert(key.trim(), value.trim().trim_matches('"'));
+            }
+        }
+        let key_id = params
+            .get("keyId")
+            .context("signature has no keyId")?
+            .to_string();
+        let signature = base64::engine::general_purpose::STANDARD
+            .decode(params.get("signature").context("signature is empty")?)
+            .context("invalid signature")?;
+        let signed_headers = params.get("headers").copied().unwrap_or("date");
+
+        // without the digest and date a signature could be reused for another body or replayed
+        let signed_headers = signed_headers.split_whitespace().collect::<Vec<_>>();
+        for required in ["(request-target)", "date", "digest"] {
+            if !signed_headers.contains(&required) {
+                return Err(Error::new(format!("{} is not signed", required)));
+            }
+        }
+        let digest = format!(
+            "SHA-256={}",
+            base64::engine::general_purpose::STANDARD.encode(Sha256::digest(&self.body))
+        );
+        if self.header("digest")? != digest {
+            return Err(Error::new("digest doesn't match the body"));
+        }
+        let date = chrono::DateTime::parse_from_rfc2822(self.header("date")?)
+            .context("invalid date header")?;
+        if (chrono::Utc::now().timestamp() - date.timestamp()).abs() > MAX_SIGNATURE_AGE {
+            return Err(Error::new("signature is too old"));
+        }
+
+        Ok(SignatureHeader {
+            key_id,
+            signature,
+            signed_headers: signed_headers.into_iter().map(str::to_string).collect(),
+        })
+    }
+
+    // checks the signature against the key of `actor`, the fetched actor document
+    pub fn verify(&self, actor: &serde_json::Value) -> Result<(), Error> {
+        let SignatureHeader {
+            key_id,
+            signature,
+            signed_headers,
+        } = self.parse()?;
+
+        let mut lines = vec![];
+        for name in &signed_headers {
+            let value = match name.as_str() {
+                "(request-target)" => format!("{} {}", self.method, self.path),
+                name => self.header(name)?.to_string(),
+            };
+            lines.push(format!("{}: {}", name, value));
+        }
+
+        // mastodon has one key, others may list several
+        let keys = match &actor["publicKey"] {
+            serde_json::Value::Array(keys) => keys.iter().collect::<Vec<_>>(),
+            key => vec![key],
+        };
+        let pem = keys
+            .iter()
+            .find(|key| key["id"].as_str() == Some(&key_id))
+            .and_then(|key| key["publicKeyPem"].as_str())
+            .context("actor has no key for the signature")?;
+        let key = RsaPublicKey::from_public_key_pem(pem)
+            .map_err(|error| Error::new(error.to_string()))
+            .context("invalid public key")?;
+        let signature = Signature::try_from(signature.as_slice())
+            .map_err(|error| Error::new(error.to_string()))?;
+        VerifyingKey::<Sha256>::new(key)
+            .verify(lines.join("\n").as_bytes(), &signature)
+            .map_err(|_| Error::new("signature doesn't match"))
+    }
+}
+
+// `keyId="...",headers="..."`, commas only separate outside of quotes
+fn split_params(header: &str) -> Vec<&str> {
+    let mut params = vec![];
+    let mut quoted = false;
+    let mut start = 0;
+    for (i, c) in header.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                params.push(&header[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    params.push(&header[start..]);
+    params
+}
+
+// the headers of a signed post of `body` to `url`
+pub(crate) fn sign(
+    key: &RsaPrivateKey,
+    key_id: &str,
+    url: &str,
+    body: &[u8],
+) -> Result<Vec<(&'static str, String)>, Error> {
+    let url = url::Url::parse(url).context("invalid inbox url")?;
+    let host = match url.port() {
+        Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+        None => url.host_str().unwrap_or_default().to_string(),
+    };
+    let target = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    };
+    let date = chrono::Utc::now()
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string();
+    let digest = format!(
+        "SHA-256={}",
+        base64::engine::general_purpose::STANDARD.encode(Sha256::digest(body))
+    );
+
+    let signed = format!(
+        "(request-target): post {}\nhost: {}\ndate: {}\ndigest: {}",
+        target, host, date, digest
+    );
+    let signature = SigningKey::<Sha256>::new(key.clone()).sign(signed.as_bytes());
+    let signature = format!(
+        r#"keyId="{}",algorithm="rsa-sha256",headers="(request-target) host date digest",signature="{}""#,
+        key_id,
+        base64::engine::general_purpose::STANDARD.encode(signature.to_bytes())
+    );
+
+    Ok(vec![
+        ("host", host),
+        ("date", date),
+        ("digest", digest),
+        ("signature", signature),
+    ])
+}
+
+fn deliver(
+    cfg: &Config,
+    key: &RsaPrivateKey,
+    inbox: &str,
+    activity: &serde_json::Value,
+) -> Result<(), Error> {
+    // inboxes were checked when the follow came in, but where they point may have changed since
+    check_remote_url(inbox, cfg!(test))?;
+    let body = activity.to_string();
+    let mut request = agent(cfg!(test))
+        .post(inbox)
+        .set("content-type", ACTIVITY_JSON);
+    for (name, value) in sign(key, &key_id(cfg), inbox, body.as_bytes())? {
+        request = request.set(name, &value);
+    }
+    request
+        .send_string(&body)
+        .context(format!("inbox {} rejected the activity", inbox))?;
+    Ok(())
+}
+
+// Sends new public posts to the followers as a Create and changed ones as an Update. Run after
+// every build, so scheduled posts go out with the first build after their date. The first run
+// only takes note of the existing posts, followers don't want the whole archive at once.
+// Failures are only warnings, like webmentions.
+pub fn deliver_posts(db: &Database, cfg: &Config) -> Result<(), Error> {
+    let Some(activitypub) = &cfg.activitypub else {
+        return Ok(());
+    };
+    let first_run = db
+        .query_one(
+            "SELECT NOT EXISTS (SELECT 1 FROM activitypub_deliveries);",
+            [],
+            |row| row.get::<_, bool>(0),
+        )
+        .context("failed to query deliveries from database")?;
+    let inboxes = Follower::inboxes(db)?;
+    let key = match inboxes.is_empty() {
+        true => None,
+        false => Some(private_key(activitypub)?),
+    };
+
+    for post in Post::get_all(db)? {
+        if !post.visible_to(None, cfg.timezone()) {
+            continue;
+        }
+        let tags = post.get_tags(db)?;
+        let note = note(cfg, &post, &tags);
+        let hash = hex::encode(Sha256::digest(note.to_string()));
+        let previous = db
+            .query_mul(
+                "SELECT hash FROM activitypub_deliveries WHERE post_id = ?;",
+                [&post.id],
+                |row| row.get::<_, String>(0),
+            )
+            .context("failed to query delivery from database")?
+            .pop();
+        if previous.as_ref() == Some(&hash) {
+            continue;
+        }
+
+        if let Some(key) = key.as_ref().filter(|_| !first_run) {
+            let kind = match previous {
+                Some(_) => "Update",
+                None => "Create",
+            };
+            let activity = activity(cfg, kind, note);
+            for inbox in &inboxes {
+                match deliver(cfg, key, inbox, &activity) {
+                    Ok(()) => println!("delivered {} of {} to {}", kind, post.id, inbox),
+                    Err(error) => println!(
+                        "warning: failed to deliver {} to {}: {}",
+                        post.id,
+                        inbox,
+                        error.chain_message()
+                    ),
+                }
+            }
+        }
+
+        db.execute(
+            r#"
+                INSERT INTO activitypub_deliveries (post_id, hash) VALUES (?, ?)
+                ON CONFLICT (post_id) DO UPDATE SET hash = excluded.hash;
+            "#,
+            (&post.id, &hash),
+        )
+        .context("failed to record delivery in database")?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signature_params_are_split_outside_of_quotes() {
+        assert_eq!(
+            split_params(
+                r#"keyId="https://a/actor#key",headers="(request-target) date",signature="x,y==""#
+            ),
+            vec![
+                r#"keyId="https://a/actor#key""#,
+                r#"headers="(request-target) date""#,
+                r#"signature="x,y==""#,
+            ]
+        );
+        assert_eq!(split_params(""), vec![""]);
+    }
+
+    #[test]
+    fn only_public_https_hosts_are_fetched() {
+        for url in [
+            "http://93.184.216.34/actor",
+            "https://127.0.0.1/actor",
+            "https://10.1.2.3/actor",
+            "https://192.168.0.1/actor",
+            "https://169.254.169.254/latest/meta-data",
+            "https://100.64.0.1/actor",
+            "https://0.0.0.0/actor",
+            "https://[::1]/actor",
+            "https://[fd00::1]/actor",
+            "https://[fe80::1]/actor",
+            "https://[::ffff:127.0.0.1]/actor",
+            "file:///etc/passwd",
+            "not a url",
+        ] {
+            assert!(check_remote_url(url, false).is_err(), "{}", url);
+        }
+        assert!(check_remote_url("https://93.184.216.34/actor", false).is_ok());
+        assert!(check_remote_url("https://[2606:4700::1111]/actor", false).is_ok());
+        assert!(check_remote_url("http://127.0.0.1:8080/actor", true).is_ok());
+    }
+
+    #[test]
+    fn connections_only_go_to_public_addresses() {
+        assert!(public_addresses("localhost:443", false).is_err());
+        assert!(public_addresses("localhost:443", true).is_ok());
+
+        // refused before connecting, whatever the url was checked against before
+        let error = agent(false)
+            .get("http://127.0.0.1:9/actor")
+            .call()
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("is not a public host"), "{}", error);
+    }
+}
@@ -0,0 +1,189 @@
+use std::collections::VecDeque;
+use std::net::IpAddr;
+
+use axum::extract::Request;
+use axum::middleware::Next;
+use axum::response::Response;
+
+use crate::prelude::*;
+use crate::time;
+
+// only the latest errors are interesting, older ones are in the server log
+const MAX_RECENT_ERRORS: usize = 20;
+
+pub struct RecentError {
+    pub at: i64,
+    pub method: String,
+    pub path: String,
+    pub status: u16,
+    pub client: Option<IpAddr>,
+}
+
+// server errors since the server started, newest first
+#[derive(Default)]
+pub struct RecentErrors(Mutex<VecDeque<RecentError>>);
+
+impl RecentErrors {
+    fn push(&self, error: RecentError) {
+        let mut errors = self.0.lock().unwrap();
+        errors.push_front(error);
+        errors.truncate(MAX_RECENT_ERRORS);
+    }
+}
+
+pub async fn record_errors(
+    ax::State(state): ax::State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let client = Client::from_parts(
+        request.headers(),
+        request.extensions(),
+        &state.config.lock().unwrap(),
+    );
+    let method = request.method().to_string();
+    let path = request.uri().path().to_string();
+    let response = next.run(request).await;
+
+    if response.status().is_server_error() {
+        state.recent_errors.push(RecentError {
+            at: chrono::Utc::now().timestamp(),
+            method,
+            path,
+            status: response.status().as_u16(),
+            client: client.ip,
+        });
+    }
+
+    response
+}
+
+pub(crate) fn display_size(bytes: i64) -> String {
+    match bytes {
+        bytes if bytes >= 1024 * 1024 => format!("{:.1} MiB", bytes as f64 / 1024.0 / 1024.0),
+        bytes if bytes >= 1024 => format!("{:.1} KiB", bytes as f64 / 1024.0),
+        bytes => format!("{} B", bytes),
+    }
+}
+
+fn make_overview(db: &Database, tz: Tz) -> Result<PreEscaped<String>, Error> {
+    let rows = [
+        ("Posts", Post::count_all(db)?.to_string()),
+        ("Photos", Photo::count_all(db)?.to_string()),
+        ("Files", File::count_all(db)?.to_string()),
+        (
+            "Comments waiting for approval",
+            Comment::get_pending(db)?.len().to_string(),
+        ),
+        ("Messages", Message::count_all(db)?.to_string()),
+        ("Users", User::get_all(db)?.len().to_string()),
+        ("API tokens", ApiToken::get_all(db)?.len().to_string()),
+        (
+            "Alt text coverage",
+            match AltText::coverage(db)? {
+                Some(coverage) => format!("{:.0}%", coverage),
+                None => "no photos or images".to_string(),
+            },
+        ),
+        ("Database size", display_size(db.size()?)),
+        (
+            "Last build",
+            match Build::last(db)? {
+                Some(build) => format!(
+                    "{} ({} posts in {:.1}s)",
+                    time::display_timestamp_time(build.finished_at, tz),
+                    build.post_count,
+                    build.duration_ms as f64 / 1000.0
+                ),
+                None => "never".to_string(),
+            },
+        ),
+    ];
+
+    Ok(html!(
+        table class="admin-overview" {
+            @for (name, value) in rows {
+                tr {
+                    th { (name) }
+                    td { (value) }
+                }
+            }
+        }
+    ))
+}
+
+// like the comment queue, the admin pages don't exist for anyone else
+pub async fn get_admin(
+    ax::State(state): ax::State<Arc<AppState>>,
+    RequireAdmin(user): RequireAdmin,
+    lite: Lite,
+) -> impl IntoResponse {
+    let db = &match state.lock_db().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let cfg = &state.config.lock().unwrap();
+
+    println!("GET admin, user = {:?}", user);
+
+    let (overview, missing_alt_texts, login_links) = match (
+        make_overview(db, cfg.timezone()),
+        make_missing_alt_texts(db),
+        make_login_links(db, cfg),
+    ) {
+        (Ok(overview), Ok(missing_alt_texts), Ok(login_links)) => {
+            (overview, missing_alt_texts, login_links)
+        }
+        _ => return make_error(500, "Failed to load dashboard").into_response(),
+    };
+
+    let errors = state.recent_errors.0.lock().unwrap();
+
+    let content = html!(
+        h2 { "Overview" }
+        (overview)
+
+        h2 { "Recent errors" }
+        @if errors.is_empty() {
+            p { "No errors since the server started." }
+        } @else {
+            table class="admin-errors" {
+                @for error in errors.iter() {
+                    tr {
+                        td { (time::display_timestamp_time(error.at, cfg.timezone())) }
+                        td { (error.status) }
+                        td { code { (error.method) " " (error.path) } }
+                        td { (error.client.map(|ip| ip.to_string()).unwrap_or_default()) }
+                    }
+                }
+            }
+        }
+
+        h2 id="rebuild" { "Rebuild" }
+        (state.rebuild.to_html(cfg.timezone()))
+
+        h2 id="alt-text" { "Missing alt text" }
+        (missing_alt_texts)
+
+        h2 id="login-links" { "Login links" }
+        (login_links)
+
+        h2 { "Manage" }
+        ul {
+            li { a href=(routes::COMMENTS) { "Comments" } }
+            li { a href=(routes::MESSAGES) { "Messages" } }
+            li { a href=(routes::SHORTLINKS) { "Shortlinks" } }
+            li { a href=(routes::STATS) { "Statistics" } }
+        }
+    );
+
+    let page = make_page(
+        PageMeta::new(Section::Admin).title("Admin").lite(lite),
+        vec!["/styles/post.css"],
+        content,
+        Some(user),
+        false,
+    );
+
+    ax::Html::from(page.into_string()).into_response()
+}
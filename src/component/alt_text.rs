@@ -0,0 +1,223 @@
+use crate::database::SqliteError;
+use crate::prelude::*;
+
+pub const PHOTO_KIND: &str = "photo";
+pub const IMAGE_KIND: &str = "image";
+
+// the dashboard only lists this many, the rest show up once those are done
+const MAX_LISTED: usize = 50;
+
+// A photo or markdown image of a post, recorded on every build. Photos have alt text when the post
+// captions them somewhere, images when their alt text isn't empty. Alt text added in the admin
+// dashboard is state, keyed by post and photo name or image url, and survives rebuilds.
+#[allow(dead_code)]
+pub struct AltTextItem {
+    pub post_id: String,
+    pub kind: String,
+    pub name: String,
+    pub has_alt: bool,
+}
+
+pub struct AltText;
+
+impl AltText {
+    pub fn setup(db: &Database) -> Result<(), Error> {
+        db.execute_batch(
+            r#"
+                CREATE TABLE IF NOT EXISTS alt_text_items (
+                    post_id TEXT NOT NULL,
+                    kind TEXT NOT NULL,
+                    name TEXT NOT NULL,
+                    has_alt BOOLEAN NOT NULL,
+                    PRIMARY KEY (post_id, kind, name)
+                );
+
+                CREATE TABLE IF NOT EXISTS alt_texts (
+                    post_id TEXT NOT NULL,
+                    name TEXT NOT NULL,
+                    alt_text TEXT NOT NULL,
+                    PRIMARY KEY (post_id, name)
+                );
+            "#,
+        )
+        .context("failed to create alt text tables")
+    }
+
+    fn item_from_row(row: &Row) -> Result<AltTextItem, SqliteError> {
+        Ok(AltTextItem {
+            post_id: row.get(0)?,
+            kind: row.get(1)?,
+            name: row.get(2)?,
+            has_alt: row.get(3)?,
+        })
+    }
+
+    // an image used several times has alt text if any of its uses has
+    pub fn record(
+        db: &Database,
+        post_id: &str,
+        kind: &str,
+        name: &str,
+        has_alt: bool,
+    ) -> Result<(), Error> {
+        db.execute(
+            r#"
+                INSERT INTO alt_text_items (post_id, kind, name, has_alt) VALUES (?, ?, ?, ?)
+                ON CONFLICT DO UPDATE SET has_alt = has_alt OR excluded.has_alt;
+            "#,
+            (post_id, kind, name, has_alt),
+        )
+        .context("failed to insert alt text item into database")
+    }
+
+    pub fn delete_items(db: &Database) -> Result<(), Error> {
+        db.execute("DELETE FROM alt_text_items", [])
+            .context("failed to delete all alt text items from database")
+    }
+
+    // items without alt text in the source or the dashboard, newest posts first
+    pub fn missing(db: &Database) -> Result<Vec<AltTextItem>, Error> {
+        db.query_mul(
+            r#"
+                SELECT items.post_id, items.kind, items.name, items.has_alt
+                FROM alt_text_items AS items
+                JOIN posts ON posts.id = items.post_id
+                WHERE NOT items.has_alt AND NOT EXISTS (
+                    SELECT 1 FROM alt_texts
+                    WHERE alt_texts.post_id = items.post_id AND alt_texts.name = items.name
+                )
+                ORDER BY posts.date DESC, items.kind, items.name;
+            "#,
+            [],
+            AltText::item_from_row,
+        )
+        .context("failed to query missing alt text from database")
+    }
+
+    // percentage of photos and images with alt text, none if there are no photos or images
+    pub fn coverage(db: &Database) -> Result<Option<f64>, Error> {
+        let (total, missing): (i64, i64) = db
+            .query_one(
+                r#"
+                    SELECT COUNT(*), COUNT(*) FILTER (
+                        WHERE NOT has_alt AND NOT EXISTS (
+                            SELECT 1 FROM alt_texts
+                            WHERE alt_texts.post_id = items.post_id AND alt_texts.name = items.name
+                        )
+                    )
+                    FROM alt_text_items AS items;
+                "#,
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .context("failed to query alt text coverage from database")?;
+
+        Ok((total > 0).then(|| (total - missing) as f64 / total as f64 * 100.0))
+    }
+
+    // alt text from the dashboard by photo name or image url
+    pub fn get_all(db: &Database, post_id: &str) -> Result<HashMap<String, String>, Error> {
+        Ok(db
+            .query_mul(
+                "SELECT name, alt_text FROM alt_texts WHERE post_id = ?;",
+                [post_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .context("failed to query alt texts from database")?
+            .into_iter()
+            .collect())
+    }
+
+    // empty alt text removes it again
+    pub fn set(db: &Database, post_id: &str, name: &str, alt_text: &str) -> Result<(), Error> {
+        match alt_text.trim() {
+            "" => db
+                .execute(
+                    "DELETE FROM alt_texts WHERE post_id = ? AND name = ?;",
+                    (post_id, name),
+                )
+                .context("failed to delete alt text from database"),
+            alt_text => db
+                .execute(
+                    r#"
+                        INSERT INTO alt_texts (post_id, name, alt_text) VALUES (?, ?, ?)
+                        ON CONFLICT DO UPDATE SET alt_text = excluded.alt_text;
+                    "#,
+                    (post_id, name, alt_text),
+                )
+                .context("failed to save alt text in database"),
+        }
+    }
+}
+
+pub fn make_missing_alt_texts(db: &Database) -> Result<PreEscaped<String>, Error> {
+    let missing = AltText::missing(db)?;
+    let mut posts = HashMap::<String, Post>::new();
+    for item in missing.iter().take(MAX_LISTED) {
+        if !posts.contains_key(&item.post_id) {
+            posts.insert(item.post_id.clone(), Post::by_id(db, &item.post_id)?);
+        }
+    }
+
+    Ok(html!(
+        @if missing.is_empty() {
+            p { "Every photo and image has alt text." }
+        } @else {
+            @if missing.len() > MAX_LISTED {
+                p { (missing.len()) " photos and images without alt text, showing the first " (MAX_LISTED) "." }
+            }
+            table class="admin-alt-text" {
+                @for item in missing.iter().take(MAX_LISTED) {
+                    @let post = &posts[&item.post_id];
+                    tr {
+                        td { a href=(post.url()) { (post.title) } }
+                        td { (item.kind) " " code { (item.name) } }
+                        td {
+                            form action=(routes::ALT_TEXT) method="post" {
+                                (csrf_field())
+                                input type="hidden" name="post_id" value=(item.post_id) {}
+                                input type="hidden" name="name" value=(item.name) {}
+                                input type="text" name="alt_text" placeholder="alt text" required {}
+                                " "
+                                input type="submit" value="Save" {}
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    ))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AltTextForm {
+    post_id: String,
+    name: String,
+    alt_text: String,
+}
+
+pub async fn post_alt_text(
+    ax::State(state): ax::State<Arc<AppState>>,
+    RequireAdmin(user): RequireAdmin,
+    form: ax::Form<AltTextForm>,
+) -> impl IntoResponse {
+    let db = &match state.lock_writer().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+
+    println!(
+        "POST alt text for {} of {}, user = {:?}",
+        form.name, form.post_id, user
+    );
+
+    if Post::by_id(db, &form.post_id).is_err() {
+        return make_error(404, "Post not found").into_response();
+    }
+
+    if AltText::set(db, &form.post_id, &form.name, &form.alt_text).is_err() {
+        return make_error(500, "Failed to save alt text").into_response();
+    }
+
+    ax::Redirect::to(&format!("{}#alt-text", routes::ADMIN)).into_response()
+}
@@ -0,0 +1,220 @@
+use crate::component::markdown::code_block;
+use crate::component::renderer::{ContentRenderer, InlineMarkup, Piece, Rendered};
+use crate::prelude::*;
+
+const INLINE: InlineMarkup = InlineMarkup {
+    code: &['`'],
+    emphasis: &[('*', "**"), ('_', "*")],
+    links,
+};
+
+const ADMONITIONS: &[&str] = &["NOTE", "TIP", "IMPORTANT", "WARNING", "CAUTION"];
+
+// AsciiDoc, e.g. `index.adoc`. Covers sections, lists, emphasis, links, images, admonitions,
+// listing, literal and quote blocks. Attributes and comments are left out, the metadata of the
+// post is in meta.json.
+pub struct AsciiDoc;
+
+impl ContentRenderer for AsciiDoc {
+    fn to_markdown(&self, source: &str, _assets_path: &str) -> Result<Rendered, Error> {
+        let mut output = String::with_capacity(source.len());
+        let mut lines = source.lines();
+        // the language of a `[source,rust]` line, for the block below it
+        let mut language = None;
+
+        while let Some(line) = lines.next() {
+            let trimmed = line.trim_end();
+
+            // delimited blocks, a line of at least four of the same character (three backticks)
+            if let Some(kind) = trimmed.chars().next().filter(|c| "-._/`".contains(*c))
+                && trimmed.len() >= if kind == '`' { 3 } else { 4 }
+                && trimmed.chars().all(|c| c == kind)
+            {
+                let delimiter = trimmed;
+                let mut contents = String::new();
+                for line in lines.by_ref() {
+                    if line.trim_end() == delimiter {
+                        break;
+                    }
+                    contents.push_str(line);
+                    contents.push('\n');
+                }
+
+                match kind {
+                    '-' | '`' => {
+                        output.push_str(&code_block(language.take().unwrap_or_default(), &contents))
+                    }
+                    '.' => output.push_str(&code_block("", &contents)),
+                    '_' => {
+                        for line in contents.lines() {
+                            output.push_str(&format!("> {}\n", INLINE.convert(line.trim())));
+                        }
+                    }
+                    // `////` comments
+                    _ => {}
+                }
+                output.push('\n');
+                language = None;
+                continue;
+            }
+
+            // block attributes, only the language of source blocks matters
+            if trimmed.starts_with('[') && trimmed.ends_with(']') && !trimmed.starts_with("[[") {
+                let attributes = trimmed[1..trimmed.len() - 1].split(',').collect::<Vec<_>>();
+                if attributes
+                    .first()
+                    .is_some_and(|style| style.trim() == "source")
+                {
+                    language = attributes.get(1).map(|language| language.trim());
+                }
+                continue;
+            }
+
+            // comments, document attributes like `:toc:` and anchors
+            if trimmed.starts_with("//")
+                || trimmed.starts_with("[[")
+                || (trimmed.starts_with(':') && trimmed[1..].contains(": "))
+                || (trimmed.starts_with(':') && trimmed.ends_with(':') && trimmed.len() > 1)
+            {
+                continue;
+            }
+
+            // a `+` on its own joins list items and blocks
+            if trimmed == "+" {
+                continue;
+            }
+
+            let equals = trimmed.len() - trimmed.trim_start_matches('=').len();
+            if equals > 0 && trimmed[equals..].starts_with(' ') {
+                output.push_str(&format!(
+                    "{} {}\n\n",
+                    "#".repeat(equals.min(6)),
+                    INLINE.convert(trimmed[equals..].trim())
+                ));
+                continue;
+            }
+
+            if let Some(image) = trimmed.strip_prefix("image::") {
+                output.push_str(&format!("{}\n", image_to_markdown(image)));
+                continue;
+            }
+
+            if let Some((label, text)) = trimmed.split_once(": ")
+                && ADMONITIONS.contains(&label)
+            {
+                let label = label[..1].to_string() + &label[1..].to_lowercase();
+                output.push_str(&format!("> **{}:** {}\n", label, INLINE.convert(text)));
+                continue;
+            }
+
+            // a block title like `.Results`
+            if let Some(title) = trimmed.strip_prefix('.')
+                && title.starts_with(|c: char| c.is_alphanumeric())
+            {
+                output.push_str(&format!("**{}**\n\n", INLINE.convert(title)));
+                continue;
+            }
+
+            // `*`, `**`, ... and `.`, `..`, ... items, nested by how many there are
+            let marker = trimmed.chars().next().filter(|c| *c == '*' || *c == '.');
+            if let Some(marker) = marker {
+                let depth = trimmed.len() - trimmed.trim_start_matches(marker).len();
+                if let Some(item) = trimmed[depth..].strip_prefix(' ') {
+                    let bullet = if marker == '*' { "-" } else { "1." };
+                    output.push_str(&format!(
+                        "{}{} {}\n",
+                        "   ".repeat(depth - 1),
+                        bullet,
+                        INLINE.convert(item)
+                    ));
+                    continue;
+                }
+            }
+
+            // a trailing ` +` is a hard line break
+            match trimmed.strip_suffix(" +") {
+                Some(text) => output.push_str(&format!("{}\\\n", INLINE.convert(text))),
+                None => {
+                    output.push_str(&INLINE.convert(trimmed));
+                    output.push('\n');
+                }
+            }
+        }
+
+        Ok(Rendered::markdown(output))
+    }
+}
+
+// `path[alt]` after `image:` or `image::`
+fn image_to_markdown(image: &str) -> String {
+    let (target, alt) = image
+        .split_once('[')
+        .map(|(target, alt)| (target, alt.trim_end_matches(']')))
+        .unwrap_or((image, ""));
+    let alt = alt.split(',').next().unwrap_or_default();
+    format!("![{}]({})", alt, target)
+}
+
+// `link:target[text]`, `https://url[text]`, bare urls and inline `image:path[alt]`
+fn links(text: &str) -> Vec<Piece> {
+    let mut pieces = vec![];
+    let mut rest = text;
+
+    loop {
+        let next = ["link:", "image:", "https://", "http://", "mailto:"]
+            .iter()
+            .filter_map(|prefix| {
+                // not in the middle of a word, e.g. `hyperlink:`
+                rest.match_indices(prefix)
+                    .map(|(start, _)| (start, *prefix))
+                    .find(|(start, _)| !rest[..*start].ends_with(|c: char| c.is_alphanumeric()))
+            })
+            .min_by_key(|(start, _)| *start);
+        let Some((start, prefix)) = next else {
+            break;
+        };
+
+        let after = &rest[start..];
+        let end = after
+            .find(|c: char| c.is_whitespace() || c == '[')
+            .unwrap_or(after.len());
+        let target = &after[..end];
+        let (text, consumed) = match after[end..].strip_prefix('[') {
+            Some(attributes) => match attributes.find(']') {
+                Some(close) => (Some(&attributes[..close]), end + close + 2),
+                None => (None, end),
+            },
+            None => (None, end),
+        };
+        // a bare url ends before punctuation closing the sentence
+        let (target, consumed) = match text {
+            None => {
+                let trimmed = target.trim_end_matches(['.', ',', ';', ':', '!', '?', ')']);
+                (trimmed, trimmed.len())
+            }
+            Some(_) => (target, consumed),
+        };
+
+        pieces.push(Piece::Text(rest[..start].to_string()));
+        pieces.push(Piece::Done(match prefix {
+            "image:" => image_to_markdown(&after[prefix.len()..consumed]),
+            "link:" => {
+                let target = &target[prefix.len()..];
+                format!(
+                    "[{}]({})",
+                    text.filter(|text| !text.is_empty()).unwrap_or(target),
+                    target
+                )
+            }
+            _ => format!(
+                "[{}]({})",
+                text.filter(|text| !text.is_empty()).unwrap_or(target),
+                target
+            ),
+        }));
+        rest = &rest[start + consumed..];
+    }
+
+    pieces.push(Piece::Text(rest.to_string()));
+    pieces
+}
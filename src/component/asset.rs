@@ -1,9 +1,13 @@
+use crate::component::file::modified_time;
+use crate::component::post::find_post;
+use crate::crypto;
 use crate::database::SqliteError;
 use crate::prelude::*;
 
 pub struct Asset {
     pub id: i64,
     pub name: String,
+    pub is_encrypted: bool,
 }
 
 impl Asset {
@@ -13,7 +17,7 @@ impl Asset {
                 CREATE TABLE IF NOT EXISTS styles (
                     id INTEGER PRIMARY KEY,
                     name TEXT NOT NULL,
-                    data BLOB NOT NULL
+                    data_hash TEXT NOT NULL
                 );
 
                 CREATE TABLE IF NOT EXISTS posts_assets (
@@ -27,30 +31,107 @@ impl Asset {
                 CREATE INDEX IF NOT EXISTS assets_name_index ON styles (name);
             "#,
         )
-        .context("failed to create styles table")
+        .context("failed to create styles table")?;
+
+        // assets read from a post directory, generated ones have no source
+        db.ensure_column("styles", "source_path", "TEXT NULL")
+            .context("failed to update styles table")?;
+        db.ensure_column("styles", "source_time", "INTEGER NULL")
+            .context("failed to update styles table")?;
+        db.ensure_column("styles", "mark", "BOOLEAN NOT NULL DEFAULT TRUE")
+            .context("failed to update styles table")?;
+        db.ensure_column("styles", "is_encrypted", "BOOLEAN NOT NULL DEFAULT FALSE")
+            .context("failed to update styles table")?;
+
+        Blob::migrate_column(db, "styles", "data", "data_hash")
     }
 
     fn from_row(row: &Row) -> Result<Self, SqliteError> {
         Ok(Self {
             id: row.get(0)?,
             name: row.get(1)?,
+            is_encrypted: row.get(2)?,
         })
     }
 
-    pub fn new(db: &Database, path: &Path) -> Result<Self, Error> {
+    // Unchanged assets are only marked again, like files. With a `key`, i.e. for posts that aren't
+    // public, the data is encrypted like private photos.
+    pub fn new(db: &Database, path: &Path, key: Option<&crypto::Key>) -> Result<Self, Error> {
         let name = path
             .file_name()
             .and_then(|n| n.to_str())
             .context("invalid asset path")?;
 
+        let source_time = modified_time(path)?;
+        let unchanged = db
+            .query_mul(
+                r#"
+                    UPDATE styles SET mark = TRUE
+                    WHERE source_path = ? AND source_time = ? AND is_encrypted = ?
+                    RETURNING id, name, is_encrypted;
+                "#,
+                (path.to_str(), source_time, key.is_some()),
+                Asset::from_row,
+            )
+            .context("failed to mark asset in database")?
+            .pop();
+        if let Some(asset) = unchanged {
+            return Ok(asset);
+        }
+
+        db.execute("DELETE FROM styles WHERE source_path = ?;", [path.to_str()])
+            .context("failed to delete outdated asset from database")?;
+
         let data = fs::read(path).context("failed to read asset file")?;
+        let asset = db
+            .query_one(
+                r#"
+                    INSERT INTO styles (name, data_hash, source_path, source_time, is_encrypted)
+                    VALUES (?, '', ?, ?, ?) RETURNING id, name, is_encrypted;
+                "#,
+                (name, path.to_str(), source_time, key.is_some()),
+                Asset::from_row,
+            )
+            .context("failed to insert asset into database")?;
+        asset.set_data(db, &data, key)?;
+        Ok(asset)
+    }
 
-        db.query_one(
-            "INSERT INTO styles (name, data) VALUES (?, ?) RETURNING id, name",
-            (name, data),
-            Asset::from_row,
+    pub fn from_data(
+        db: &Database,
+        name: &str,
+        data: &[u8],
+        key: Option<&crypto::Key>,
+    ) -> Result<Self, Error> {
+        let asset = db
+            .query_one(
+                r#"
+                    INSERT INTO styles (name, data_hash, is_encrypted) VALUES (?, '', ?)
+                    RETURNING id, name, is_encrypted;
+                "#,
+                (name, key.is_some()),
+                Asset::from_row,
+            )
+            .context("failed to insert asset into database")?;
+        asset.set_data(db, data, key)?;
+        Ok(asset)
+    }
+
+    // after the row is inserted, since encrypted data is bound to its id
+    fn set_data(&self, db: &Database, data: &[u8], key: Option<&crypto::Key>) -> Result<(), Error> {
+        let data = match key {
+            Some(key) => crypto::encrypt(key, data, self.context().as_bytes())?,
+            None => data.to_vec(),
+        };
+        db.execute(
+            "UPDATE styles SET data_hash = ? WHERE id = ?;",
+            (Blob::insert(db, &data)?, self.id),
         )
-        .context("failed to insert asset into database")
+        .context("failed to set asset data in database")
+    }
+
+    fn context(&self) -> String {
+        format!("asset/{}", self.id)
     }
 
     pub fn by_post_and_name(
@@ -60,7 +141,7 @@ impl Asset {
     ) -> Result<Self, Error> {
         db.query_one(
             r#"
-                SELECT styles.id, styles.name
+                SELECT styles.id, styles.name, styles.is_encrypted
                 FROM styles
                 JOIN posts_assets ON styles.id = posts_assets.asset_id
                 WHERE posts_assets.post_id = ? AND styles.name = ?;
@@ -71,43 +152,93 @@ impl Asset {
         .context("failed to query asset by post name and asset name from database")
     }
 
-    pub fn get_data(&self, db: &Database) -> Result<Vec<u8>, Error> {
-        db.query_one("SELECT data FROM styles WHERE id = ?;", [self.id], |row| {
-            row.get(0)
-        })
-        .context("failed to query data from database")
+    pub fn by_post(db: &Database, post_id: &str) -> Result<Vec<Self>, Error> {
+        db.query_mul(
+            r#"
+                SELECT styles.id, styles.name, styles.is_encrypted
+                FROM styles
+                JOIN posts_assets ON styles.id = posts_assets.asset_id
+                WHERE posts_assets.post_id = ?
+                ORDER BY styles.name;
+            "#,
+            [post_id],
+            Asset::from_row,
+        )
+        .context("failed to query assets of post from database")
     }
 
-    pub fn delete_all(db: &Database) -> Result<(), Error> {
-        db.execute("DELETE FROM styles", [])
-            .context("failed to delete all styles from database")
+    pub fn get_data(&self, db: &Database, cfg: &Config) -> Result<Vec<u8>, Error> {
+        let hash: String = db
+            .query_one(
+                "SELECT data_hash FROM styles WHERE id = ?;",
+                [self.id],
+                |row| row.get(0),
+            )
+            .context("failed to query data from database")?;
+        let data = Blob::get(db, &hash)?;
+        if !self.is_encrypted {
+            return Ok(data);
+        }
+
+        let key = cfg
+            .encryption_key()?
+            .context("asset is encrypted but no encryption key is configured")?;
+        crypto::decrypt(&key, &data, self.context().as_bytes())
+    }
+
+    // Assets read from files are kept across builds and marked again when their post is loaded,
+    // generated ones are made again anyway. Links to posts are always made again.
+    pub fn unmark_all(db: &Database) -> Result<(), Error> {
+        db.execute("DELETE FROM posts_assets", [])
+            .context("failed to delete all post assets from database")?;
+        db.execute("UPDATE styles SET mark = FALSE", [])
+            .context("failed to unmark all styles in database")
+    }
+
+    pub fn delete_unmarked(db: &Database) -> Result<(), Error> {
+        db.execute("DELETE FROM styles WHERE mark = FALSE", [])
+            .context("failed to delete unmarked styles from database")
     }
 }
 
 pub async fn get_asset(
     ax::State(state): ax::State<Arc<AppState>>,
     ax::Path((post, name)): ax::Path<(String, String)>,
+    ax::Query(params): ax::Query<HashMap<String, String>>,
+    headers: ax::HeaderMap,
+    cookie: ax::CookieJar,
 ) -> impl IntoResponse {
-    let db = &state.db.lock().unwrap();
+    let db = &match state.lock_db().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let cfg = &state.config.lock().unwrap();
+    let user = User::from_cookie(db, &cookie).ok();
+
+    println!("GET asset {}/{}, user = {:?}", post, name, user);
 
-    println!("GET asset {}/{}", post, name);
+    // assets are as private as their post
+    let post = match find_post(db, cfg, &post, user.as_ref()) {
+        Ok(post) => post,
+        Err((code, message)) => return make_error(code, message).into_response(),
+    };
 
-    let asset = match Asset::by_post_and_name(db, &post, &name) {
+    let asset = match Asset::by_post_and_name(db, &post.id, &name) {
         Ok(asset) => asset,
         Err(_) => return make_error(404, "Asset not found").into_response(),
     };
 
-    let content_type = mime_guess::from_path(&asset.name).first_or_octet_stream();
-
-    let header = ax::HeaderMap::from_iter(vec![(
-        ax::header::CONTENT_TYPE,
-        content_type.to_string().parse().unwrap(),
-    )]);
+    // only assets of public posts may be kept by shared caches
+    let mut header = file_headers(&routes::post_asset(&post.id, &asset.name), params.get("v"));
+    if !post.visible_to(None, cfg.timezone()) {
+        header.insert(ax::header::CACHE_CONTROL, "private".parse().unwrap());
+    }
 
-    let data = match asset.get_data(db) {
+    let data = match asset.get_data(db, cfg) {
         Ok(data) => data,
         Err(_) => return make_error(500, "Failed to get asset data").into_response(),
     };
 
-    (header, data).into_response()
+    // assets include the audio of episodes, which players fetch in parts
+    ranged_response(header, &headers, data)
 }
@@ -0,0 +1,119 @@
+use sha2::{Digest, Sha256};
+
+use crate::prelude::*;
+
+// File contents, stored once by the sha256 of their bytes. Files, assets and photos refer to
+// their contents by hash, so a logo used by every post or a photo in two posts takes the space of
+// one. The hash also tells whether the stored bytes are still the ones that were written.
+pub struct Blob;
+
+impl Blob {
+    pub fn setup(db: &Database) -> Result<(), Error> {
+        db.execute_batch(
+            r#"
+                CREATE TABLE IF NOT EXISTS blobs (
+                    hash TEXT PRIMARY KEY NOT NULL,
+                    data BLOB NOT NULL
+                );
+            "#,
+        )
+        .context("failed to create blobs table")
+    }
+
+    pub fn hash(data: &[u8]) -> String {
+        hex::encode(Sha256::digest(data))
+    }
+
+    // returns the hash to refer to the blob by, storing it unless it's already there
+    pub fn insert(db: &Database, data: &[u8]) -> Result<String, Error> {
+        let hash = Self::hash(data);
+        db.execute(
+            "INSERT OR IGNORE INTO blobs (hash, data) VALUES (?, ?);",
+            (&hash, data),
+        )
+        .context("failed to insert blob into database")?;
+        Ok(hash)
+    }
+
+    pub fn get(db: &Database, hash: &str) -> Result<Vec<u8>, Error> {
+        db.query_one("SELECT data FROM blobs WHERE hash = ?;", [hash], |row| {
+            row.get(0)
+        })
+        .context("failed to query blob from database")
+    }
+
+    // blobs nothing refers to anymore, e.g. the old contents of a changed file
+    pub fn delete_unused(db: &Database) -> Result<(), Error> {
+        db.execute(
+            r#"
+                DELETE FROM blobs WHERE hash NOT IN (
+                    SELECT data_hash FROM files
+                    UNION SELECT data_hash FROM styles
+                    UNION SELECT image_large_hash FROM photos
+                    UNION SELECT image_small_hash FROM photos
+                );
+            "#,
+            [],
+        )
+        .context("failed to delete unused blobs from database")
+    }
+
+    // hashes of blobs whose bytes don't match their hash anymore
+    pub fn find_corrupted(db: &Database) -> Result<Vec<String>, Error> {
+        let blobs = db
+            .query_mul("SELECT hash, data FROM blobs;", [], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, Vec<u8>>(1)?))
+            })
+            .context("failed to query blobs from database")?;
+
+        Ok(blobs
+            .into_iter()
+            .filter(|(hash, data)| *hash != Self::hash(data))
+            .map(|(hash, _)| hash)
+            .collect())
+    }
+
+    // Moves the contents of `table.old_column` (from before blobs) into blobs, referred to by
+    // `table.new_column`, and drops the old column.
+    pub fn migrate_column(
+        db: &Database,
+        table: &str,
+        old_column: &str,
+        new_column: &str,
+    ) -> Result<(), Error> {
+        if !db.has_column(table, old_column)? {
+            return Ok(());
+        }
+
+        println!("moving {}.{} into blobs", table, old_column);
+        db.ensure_column(table, new_column, "TEXT NOT NULL DEFAULT ''")?;
+
+        // one row at a time, photos can add up to more than fits in memory
+        let rowids = db
+            .query_mul(&format!("SELECT rowid FROM {};", table), [], |row| {
+                row.get::<_, i64>(0)
+            })
+            .context("failed to query old contents from database")?;
+        for rowid in rowids {
+            let data: Vec<u8> = db
+                .query_one(
+                    &format!("SELECT {} FROM {} WHERE rowid = ?;", old_column, table),
+                    [rowid],
+                    |row| row.get(0),
+                )
+                .context("failed to query old contents from database")?;
+            let hash = Self::insert(db, &data)?;
+            db.execute(
+                &format!("UPDATE {} SET {} = ? WHERE rowid = ?;", table, new_column),
+                (hash, rowid),
+            )
+            .context("failed to refer to blob in database")?;
+        }
+
+        db.execute_batch(&format!(
+            "ALTER TABLE {} DROP COLUMN {};",
+            table, old_column
+        ))
+        .context("failed to drop old contents column")
+    }
+}
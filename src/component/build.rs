@@ -0,0 +1,242 @@
+use std::time::Instant;
+
+use crate::component::admin::display_size;
+use crate::database::SqliteError;
+use crate::prelude::*;
+
+// Finished builds, kept across rebuilds so the admin pages can tell how fresh the content is.
+#[allow(dead_code)]
+pub struct Build {
+    pub finished_at: i64,
+    pub duration_ms: i64,
+    pub post_count: i64,
+}
+
+impl Build {
+    pub fn setup(db: &Database) -> Result<(), Error> {
+        db.execute_batch(
+            r#"
+                CREATE TABLE IF NOT EXISTS builds (
+                    finished_at INTEGER NOT NULL,
+                    duration_ms INTEGER NOT NULL,
+                    post_count INTEGER NOT NULL
+                );
+            "#,
+        )
+        .context("failed to create builds table")
+    }
+
+    fn from_row(row: &Row) -> Result<Self, SqliteError> {
+        Ok(Self {
+            finished_at: row.get(0)?,
+            duration_ms: row.get(1)?,
+            post_count: row.get(2)?,
+        })
+    }
+
+    pub fn record(db: &Database, duration_ms: i64) -> Result<(), Error> {
+        db.execute(
+            r#"
+                INSERT INTO builds (finished_at, duration_ms, post_count)
+                SELECT unixepoch(), ?, COUNT(*) FROM posts;
+            "#,
+            [duration_ms],
+        )
+        .context("failed to record build in database")
+    }
+
+    pub fn last(db: &Database) -> Result<Option<Self>, Error> {
+        Ok(db
+            .query_mul(
+                "SELECT finished_at, duration_ms, post_count FROM builds ORDER BY finished_at DESC LIMIT 1;",
+                [],
+                Build::from_row,
+            )
+            .context("failed to query last build from database")?
+            .pop())
+    }
+}
+
+// What a build did, printed as a table at the end of `website build` or as json with `--json`.
+// The counts come from comparing the database before and after, so the loaders don't have to
+// report anything themselves.
+#[derive(Serialize, Default)]
+pub struct BuildReport {
+    pub posts_added: usize,
+    pub posts_updated: usize,
+    pub posts_unchanged: usize,
+    pub posts_removed: usize,
+    pub photos_encoded: usize,
+    pub photos_skipped: usize,
+    // new blobs, i.e. photos, files and assets that weren't stored yet
+    pub bytes_written: i64,
+    pub alt_text_coverage: Option<f64>,
+    pub phases: Vec<Phase>,
+    // posts left out by `website build --keep-going`
+    pub failures: Vec<BuildFailure>,
+}
+
+#[derive(Serialize)]
+pub struct BuildFailure {
+    pub path: String,
+    pub error: String,
+}
+
+#[derive(Serialize)]
+pub struct Phase {
+    pub name: &'static str,
+    pub elapsed_ms: u128,
+}
+
+// the database before the content is rebuilt
+pub struct BuildSnapshot {
+    posts: HashMap<String, String>,
+    photos: Vec<(String, i64, bool)>,
+    last_blob: i64,
+}
+
+impl BuildSnapshot {
+    pub fn take(db: &Database) -> Result<Self, Error> {
+        let photos = db
+            .query_mul(
+                "SELECT id, source_time, is_encrypted FROM photos;",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .context("failed to query photos from database")?;
+        let last_blob = db
+            .query_one("SELECT COALESCE(MAX(rowid), 0) FROM blobs;", [], |row| {
+                row.get(0)
+            })
+            .context("failed to query blobs from database")?;
+
+        Ok(Self {
+            posts: post_fingerprints(db)?,
+            photos,
+            last_blob,
+        })
+    }
+}
+
+// everything a reader can see of a post, a post whose fingerprint changed was updated
+fn post_fingerprints(db: &Database) -> Result<HashMap<String, String>, Error> {
+    Ok(db
+        .query_mul(
+            r#"
+                SELECT id, json_array(
+                    title, description, date, permalink, source, is_private, allowed_group,
+                    expires, is_featured, featured_order,
+                    (SELECT json_group_array(tag) FROM posts_tags WHERE post_id = posts.id)
+                ) FROM posts;
+            "#,
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .context("failed to query posts from database")?
+        .into_iter()
+        .collect())
+}
+
+impl BuildReport {
+    // runs one step of the build and records how long it took
+    pub fn phase<T>(
+        &mut self,
+        name: &'static str,
+        run: impl FnOnce() -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let start = Instant::now();
+        let result = run();
+        self.phases.push(Phase {
+            name,
+            elapsed_ms: start.elapsed().as_millis(),
+        });
+        result
+    }
+
+    pub fn compare(&mut self, db: &Database, before: &BuildSnapshot) -> Result<(), Error> {
+        let posts = post_fingerprints(db)?;
+        for (id, fingerprint) in &posts {
+            match before.posts.get(id) {
+                None => self.posts_added += 1,
+                Some(previous) if previous != fingerprint => self.posts_updated += 1,
+                Some(_) => self.posts_unchanged += 1,
+            }
+        }
+        self.posts_removed = before
+            .posts
+            .keys()
+            .filter(|id| !posts.contains_key(*id))
+            .count();
+
+        // `Photo::new` skips photos whose source and encryption didn't change
+        let photos = BuildSnapshot::take(db)?.photos;
+        self.photos_skipped = photos
+            .iter()
+            .filter(|photo| before.photos.contains(photo))
+            .count();
+        self.photos_encoded = photos.len() - self.photos_skipped;
+
+        self.bytes_written = db
+            .query_one(
+                "SELECT COALESCE(SUM(length(data)), 0) FROM blobs WHERE rowid > ?;",
+                [before.last_blob],
+                |row| row.get(0),
+            )
+            .context("failed to query blobs from database")?;
+
+        Ok(())
+    }
+
+    pub fn elapsed_ms(&self, name: &str) -> u128 {
+        self.phases
+            .iter()
+            .filter(|phase| phase.name == name)
+            .map(|phase| phase.elapsed_ms)
+            .sum()
+    }
+
+    pub fn to_table(&self) -> String {
+        let mut rows = vec![
+            (
+                "posts".to_string(),
+                format!(
+                    "{} added, {} updated, {} unchanged, {} removed",
+                    self.posts_added, self.posts_updated, self.posts_unchanged, self.posts_removed
+                ),
+            ),
+            (
+                "photos".to_string(),
+                format!(
+                    "{} encoded, {} skipped",
+                    self.photos_encoded, self.photos_skipped
+                ),
+            ),
+            ("written".to_string(), display_size(self.bytes_written)),
+        ];
+        if let Some(coverage) = self.alt_text_coverage {
+            rows.push(("alt text".to_string(), format!("{:.1}%", coverage)));
+        }
+        for phase in &self.phases {
+            rows.push((phase.name.to_string(), format!("{} ms", phase.elapsed_ms)));
+        }
+        let total = self
+            .phases
+            .iter()
+            .map(|phase| phase.elapsed_ms)
+            .sum::<u128>();
+        rows.push(("total".to_string(), format!("{} ms", total)));
+
+        let width = rows.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+        let mut table = rows
+            .iter()
+            .map(|(name, value)| format!("{:width$}  {}\n", name, value, width = width))
+            .collect::<String>();
+        if !self.failures.is_empty() {
+            table.push_str(&format!("\n{} posts failed:\n", self.failures.len()));
+        }
+        for failure in &self.failures {
+            table.push_str(&format!("  {}: {}\n", failure.path, failure.error));
+        }
+        table
+    }
+}
@@ -0,0 +1,274 @@
+use crate::component::feed::{feed_user, FeedQuery};
+use crate::database::SqliteError;
+use crate::prelude::*;
+use crate::time;
+
+// lines longer than this have to be folded
+const MAX_LINE_LENGTH: usize = 75;
+
+// an event a post is about, e.g. a talk or a meetup, shown in `/calendar.ics`
+#[derive(Serialize, Deserialize)]
+pub(crate) struct EventMetadata {
+    pub start: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+}
+
+pub struct Event {
+    pub post_id: String,
+    pub start: String,
+    pub end: Option<String>,
+    pub location: Option<String>,
+}
+
+impl Event {
+    pub fn setup(db: &Database) -> Result<(), Error> {
+        db.execute_batch(
+            r#"
+                CREATE TABLE IF NOT EXISTS post_events (
+                    post_id TEXT PRIMARY KEY NOT NULL,
+                    start TEXT NOT NULL,
+                    end TEXT NULL,
+                    location TEXT NULL
+                );
+            "#,
+        )
+        .context("failed to create post_events table")
+    }
+
+    fn from_row(row: &Row) -> Result<Self, SqliteError> {
+        Ok(Self {
+            post_id: row.get(0)?,
+            start: row.get(1)?,
+            end: row.get(2)?,
+            location: row.get(3)?,
+        })
+    }
+
+    pub(crate) fn new(
+        db: &Database,
+        post_id: &str,
+        metadata: &EventMetadata,
+    ) -> Result<Self, Error> {
+        db.query_one(
+            r#"
+                INSERT INTO post_events (post_id, start, end, location) VALUES (?, ?, ?, ?)
+                RETURNING post_id, start, end, location;
+            "#,
+            (post_id, &metadata.start, &metadata.end, &metadata.location),
+            Event::from_row,
+        )
+        .context("failed to insert event into database")
+    }
+
+    pub fn by_post(db: &Database, post_id: &str) -> Result<Option<Self>, Error> {
+        Ok(db
+            .query_mul(
+                "SELECT post_id, start, end, location FROM post_events WHERE post_id = ?;",
+                [post_id],
+                Event::from_row,
+            )
+            .context("failed to query event from database")?
+            .pop())
+    }
+
+    pub fn get_all(db: &Database) -> Result<Vec<Self>, Error> {
+        db.query_mul(
+            "SELECT post_id, start, end, location FROM post_events ORDER BY start;",
+            [],
+            Event::from_row,
+        )
+        .context("failed to query events from database")
+    }
+
+    pub fn delete_all(db: &Database) -> Result<(), Error> {
+        db.execute("DELETE FROM post_events", [])
+            .context("failed to delete all events from database")
+    }
+
+    pub fn to_html(&self, tz: Tz) -> PreEscaped<String> {
+        html!(
+            p class="post-event" {
+                "📅 " (time::display_date(&self.start, tz))
+                @if let Some(end) = &self.end {
+                    " – " (time::display_date(end, tz))
+                }
+                @if let Some(location) = &self.location {
+                    ", " (location)
+                }
+            }
+        )
+    }
+}
+
+// commas, semicolons and newlines have a meaning in property values
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\r', "")
+        .replace('\n', "\\n")
+}
+
+// long lines continue on the next line after a space, without splitting a character
+fn fold_line(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len());
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > MAX_LINE_LENGTH {
+            folded.push_str("\r\n ");
+            length = 1;
+        }
+        folded.push(c);
+        length += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+// dates without a time are whole days, the end of a whole day event is the day after it
+fn date_property(name: &str, date: &str, tz: Tz, is_end: bool) -> Option<String> {
+    let parsed = time::parse_date(date, tz)?;
+    match date.len() {
+        10 => {
+            let day = match is_end {
+                true => parsed + chrono::Duration::days(1),
+                false => parsed,
+            };
+            Some(format!("{};VALUE=DATE:{}", name, day.format("%Y%m%d")))
+        }
+        _ => Some(format!(
+            "{}:{}",
+            name,
+            parsed.with_timezone(&chrono::Utc).format("%Y%m%dT%H%M%SZ")
+        )),
+    }
+}
+
+struct CalendarItem {
+    uid: String,
+    summary: String,
+    url: String,
+    start: String,
+    end: Option<String>,
+    location: Option<String>,
+}
+
+fn make_calendar(items: &[CalendarItem], tz: Tz) -> String {
+    let stamp = chrono::Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//kai//website//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+    ];
+
+    for item in items {
+        let Some(start) = date_property("DTSTART", &item.start, tz, false) else {
+            continue;
+        };
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!("UID:{}", item.uid));
+        lines.push(format!("DTSTAMP:{}", stamp));
+        lines.push(start);
+        // a whole day event without an end lasts that day
+        let end = item
+            .end
+            .as_deref()
+            .or((item.start.len() == 10).then_some(item.start.as_str()));
+        if let Some(end) = end.and_then(|end| date_property("DTEND", end, tz, true)) {
+            lines.push(end);
+        }
+        lines.push(format!("SUMMARY:{}", escape_text(&item.summary)));
+        lines.push(format!("URL:{}", item.url));
+        if let Some(location) = &item.location {
+            lines.push(format!("LOCATION:{}", escape_text(location)));
+        }
+        lines.push("END:VEVENT".to_string());
+    }
+
+    lines.push("END:VCALENDAR".to_string());
+    lines.iter().map(|line| fold_line(line)).collect()
+}
+
+// Events of the posts the reader can see, and for admins also the posts scheduled to be published.
+// Calendar apps can't log in, so like the feeds this takes a feed token.
+pub async fn get_calendar(
+    ax::State(state): ax::State<Arc<AppState>>,
+    ax::Query(query): ax::Query<FeedQuery>,
+    cookie: ax::CookieJar,
+) -> impl IntoResponse {
+    let db = &match state.lock_db().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let cfg = &state.config.lock().unwrap();
+    let tz = cfg.timezone();
+    let site_url = cfg.site_url.trim_end_matches('/');
+    let host = url::Url::parse(site_url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_else(|| "localhost".to_string());
+
+    let user = match feed_user(db, &query) {
+        Ok(Some(user)) => Some(user),
+        Ok(None) => User::from_cookie(db, &cookie).ok(),
+        Err(()) => return make_error(404, "Calendar not found").into_response(),
+    };
+
+    println!("GET calendar, user = {:?}", user);
+
+    let (posts, events) = match (Post::get_all(db), Event::get_all(db)) {
+        (Ok(posts), Ok(events)) => (posts, events),
+        _ => return make_error(500, "Failed to load calendar").into_response(),
+    };
+
+    let mut items = vec![];
+
+    for event in events {
+        let Some(post) = posts
+            .iter()
+            .find(|post| post.id == event.post_id)
+            .filter(|post| post.visible_to(user.as_ref(), tz))
+        else {
+            continue;
+        };
+        items.push(CalendarItem {
+            uid: format!("{}@{}", post.id, host),
+            summary: post.title.clone(),
+            url: format!("{}{}", site_url, post.url()),
+            start: event.start,
+            end: event.end,
+            location: event.location,
+        });
+    }
+
+    if user.as_ref().is_some_and(User::is_admin) {
+        for post in posts
+            .iter()
+            .filter(|post| !post.is_published(tz) && !post.is_expired(tz))
+        {
+            items.push(CalendarItem {
+                uid: format!("{}-scheduled@{}", post.id, host),
+                summary: format!("Publish: {}", post.title),
+                url: format!("{}{}", site_url, post.url()),
+                start: post.date.clone(),
+                end: None,
+                location: None,
+            });
+        }
+    }
+
+    let mut header = ax::HeaderMap::from_iter(vec![(
+        ax::header::CONTENT_TYPE,
+        "text/calendar; charset=utf-8".parse().unwrap(),
+    )]);
+    if user.is_some() {
+        header.insert(ax::header::CACHE_CONTROL, "private".parse().unwrap());
+    }
+
+    (header, make_calendar(&items, tz)).into_response()
+}
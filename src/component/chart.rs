@@ -0,0 +1,71 @@
+use crate::prelude::*;
+
+// Charts are plain inline svg drawn in `currentColor`, so they follow the page's colors and need
+// no client-side code.
+
+const BAR_WIDTH: u32 = 32;
+const BAR_GAP: u32 = 8;
+const BAR_CHART_HEIGHT: u32 = 100;
+const LABEL_HEIGHT: u32 = 16;
+
+const SPARKLINE_WIDTH: u32 = 120;
+const SPARKLINE_HEIGHT: u32 = 24;
+
+// one labelled bar per value, the exact value shows up on hover
+pub fn bar_chart(labels: &[String], values: &[i64]) -> PreEscaped<String> {
+    let max = values.iter().copied().max().unwrap_or(0).max(1);
+    let width = (labels.len() as u32 * (BAR_WIDTH + BAR_GAP)).max(BAR_WIDTH);
+    let height = BAR_CHART_HEIGHT + LABEL_HEIGHT;
+
+    html!(
+        svg class="chart" xmlns="http://www.w3.org/2000/svg" role="img"
+            width=(width) height=(height) viewBox=(format!("0 0 {} {}", width, height)) {
+            @for (index, (label, value)) in labels.iter().zip(values).enumerate() {
+                @let x = index as u32 * (BAR_WIDTH + BAR_GAP);
+                @let bar_height = scale(*value, 0, max, BAR_CHART_HEIGHT);
+                g {
+                    title { (label) ": " (value) }
+                    rect x=(x) y=(BAR_CHART_HEIGHT - bar_height) width=(BAR_WIDTH) height=(bar_height) fill="currentColor" {}
+                    text x=(x + BAR_WIDTH / 2) y=(height - 2) text-anchor="middle" font-size="11" fill="currentColor" { (label) }
+                }
+            }
+        }
+    )
+}
+
+// a small unlabelled line for showing a trend next to text, `title` shows up on hover
+pub fn sparkline(title: &str, values: &[i64]) -> PreEscaped<String> {
+    // a single point draws nothing, so it becomes a flat line
+    let values = match values {
+        [value] => &[*value, *value][..],
+        values => values,
+    };
+    let min = values.iter().copied().min().unwrap_or(0).min(0);
+    let max = values.iter().copied().max().unwrap_or(0).max(min + 1);
+    let step = SPARKLINE_WIDTH as f64 / values.len().saturating_sub(1).max(1) as f64;
+
+    let points = values
+        .iter()
+        .enumerate()
+        .map(|(index, value)| {
+            let y = SPARKLINE_HEIGHT - scale(*value, min, max, SPARKLINE_HEIGHT - 2) - 1;
+            format!("{:.1},{}", index as f64 * step, y)
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    html!(
+        svg class="sparkline" xmlns="http://www.w3.org/2000/svg" role="img"
+            width=(SPARKLINE_WIDTH) height=(SPARKLINE_HEIGHT)
+            viewBox=(format!("0 0 {} {}", SPARKLINE_WIDTH, SPARKLINE_HEIGHT)) {
+            title { (title) }
+            polyline points=(points) fill="none" stroke="currentColor" stroke-width="1.5" {}
+        }
+    )
+}
+
+// `value` between `min` and `max` mapped onto 0..=size
+fn scale(value: i64, min: i64, max: i64, size: u32) -> u32 {
+    let ratio = (value as f64 - min as f64) / (max as f64 - min as f64);
+    (ratio.clamp(0.0, 1.0) * size as f64).round() as u32
+}
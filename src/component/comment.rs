@@ -0,0 +1,337 @@
+use crate::component::post::find_post;
+use crate::database::SqliteError;
+use crate::prelude::*;
+use crate::time;
+
+const MAX_NAME_LENGTH: usize = 64;
+const MAX_BODY_LENGTH: usize = 4000;
+
+// forms sent back faster than a person can type, or long after the page was loaded, are most
+// likely spam
+const MIN_FORM_SECONDS: i64 = 3;
+const MAX_FORM_SECONDS: i64 = 24 * 60 * 60;
+
+// Comments are runtime state and survive rebuilds. New comments stay hidden until a logged-in
+// user approves them.
+#[allow(dead_code)]
+pub struct Comment {
+    pub id: i64,
+    pub post_id: String,
+    pub name: String,
+    pub body: String,
+    pub created_at: i64,
+    pub is_approved: bool,
+}
+
+impl Comment {
+    pub fn setup(db: &Database) -> Result<(), Error> {
+        db.execute_batch(
+            r#"
+                CREATE TABLE IF NOT EXISTS comments (
+                    id INTEGER PRIMARY KEY,
+                    post_id TEXT NOT NULL,
+                    name TEXT NOT NULL,
+                    body TEXT NOT NULL,
+                    created_at INTEGER NOT NULL,
+                    is_approved BOOLEAN NOT NULL DEFAULT FALSE
+                );
+
+                CREATE INDEX IF NOT EXISTS comments_post_index ON comments (post_id);
+            "#,
+        )
+        .context("failed to create comments table")
+    }
+
+    fn from_row(row: &Row) -> Result<Self, SqliteError> {
+        Ok(Self {
+            id: row.get(0)?,
+            post_id: row.get(1)?,
+            name: row.get(2)?,
+            body: row.get(3)?,
+            created_at: row.get(4)?,
+            is_approved: row.get(5)?,
+        })
+    }
+
+    pub fn new(db: &Database, post_id: &str, name: &str, body: &str) -> Result<Self, Error> {
+        db.query_one(
+            r#"
+                INSERT INTO comments (post_id, name, body, created_at)
+                VALUES (?, ?, ?, unixepoch())
+                RETURNING id, post_id, name, body, created_at, is_approved;
+            "#,
+            (post_id, name, body),
+            Comment::from_row,
+        )
+        .context("failed to insert comment into database")
+    }
+
+    pub fn get_approved(db: &Database, post_id: &str) -> Result<Vec<Self>, Error> {
+        db.query_mul(
+            r#"
+                SELECT id, post_id, name, body, created_at, is_approved FROM comments
+                WHERE post_id = ? AND is_approved ORDER BY created_at ASC;
+            "#,
+            [post_id],
+            Comment::from_row,
+        )
+        .context("failed to query comments from database")
+    }
+
+    pub fn get_pending(db: &Database) -> Result<Vec<Self>, Error> {
+        db.query_mul(
+            r#"
+                SELECT id, post_id, name, body, created_at, is_approved FROM comments
+                WHERE NOT is_approved ORDER BY created_at ASC;
+            "#,
+            [],
+            Comment::from_row,
+        )
+        .context("failed to query pending comments from database")
+    }
+
+    pub fn by_id(db: &Database, id: i64) -> Result<Self, Error> {
+        db.query_one(
+            "SELECT id, post_id, name, body, created_at, is_approved FROM comments WHERE id = ?;",
+            [id],
+            Comment::from_row,
+        )
+        .context("failed to query comment from database")
+    }
+
+    pub fn approve(&self, db: &Database) -> Result<(), Error> {
+        db.execute(
+            "UPDATE comments SET is_approved = TRUE WHERE id = ?;",
+            [self.id],
+        )
+        .context("failed to approve comment")?;
+        Ok(())
+    }
+
+    pub fn delete(&self, db: &Database) -> Result<(), Error> {
+        db.execute("DELETE FROM comments WHERE id = ?;", [self.id])
+            .context("failed to delete comment")?;
+        Ok(())
+    }
+
+    pub fn to_html(&self, tz: Tz) -> PreEscaped<String> {
+        html!(
+            div class="comment" id=(format!("comment-{}", self.id)) {
+                p class="comment-info" {
+                    strong { (self.name) } " · " (time::display_timestamp(self.created_at, tz))
+                }
+                @for paragraph in self.body.split("\n\n").filter(|p| !p.trim().is_empty()) {
+                    p { (paragraph.trim()) }
+                }
+            }
+        )
+    }
+}
+
+// the comment section at the bottom of a post, `pending` after the reader just sent a comment
+pub fn make_comments_section(
+    db: &Database,
+    cfg: &Config,
+    post: &Post,
+    pending: bool,
+) -> Result<PreEscaped<String>, Error> {
+    let comments = Comment::get_approved(db, &post.id)?;
+
+    Ok(html!(
+        section id="comments" {
+            h2 { "Comments" }
+
+            @for comment in &comments {
+                (comment.to_html(cfg.timezone()))
+            }
+
+            @if comments.is_empty() {
+                p { "No comments yet." }
+            }
+
+            @if pending {
+                p class="comment-notice" { "Thanks! Your comment will show up once it has been approved." }
+            }
+
+            form class="comment-form" action=(routes::post_comments(&post.slug)) method="post" {
+                (csrf_field())
+                input type="text" name="name" placeholder="name" maxlength=(MAX_NAME_LENGTH) required {}
+                textarea name="body" placeholder="comment" maxlength=(MAX_BODY_LENGTH) required {}
+                // left empty by people, filled in by most bots
+                input type="text" name="website" style="display:none" tabindex="-1" autocomplete="off" {}
+                input type="hidden" name="started" value=(chrono::Utc::now().timestamp()) {}
+                input type="submit" value="Comment" {}
+            }
+        }
+    ))
+}
+
+// `honeypot` is a hidden field left empty by people, `started` when the form was rendered
+pub(crate) fn looks_like_spam(honeypot: &str, started: i64) -> bool {
+    let elapsed = chrono::Utc::now().timestamp() - started;
+    !honeypot.is_empty() || !(MIN_FORM_SECONDS..=MAX_FORM_SECONDS).contains(&elapsed)
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CommentForm {
+    name: String,
+    body: String,
+    #[serde(default)]
+    website: String,
+    started: i64,
+}
+
+pub async fn post_comment(
+    ax::State(state): ax::State<Arc<AppState>>,
+    ax::Path(id): ax::Path<String>,
+    cookie: ax::CookieJar,
+    form: ax::Form<CommentForm>,
+) -> impl IntoResponse {
+    let db = &match state.lock_writer().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let cfg = &state.config.lock().unwrap();
+    let user = User::from_cookie(db, &cookie).ok();
+
+    println!("POST comment on {}, user = {:?}", id, user);
+
+    let post = match find_post(db, cfg, &id, user.as_ref()) {
+        Ok(post) => post,
+        Err((code, message)) => return make_error(code, message).into_response(),
+    };
+
+    if looks_like_spam(&form.website, form.started) {
+        println!("rejecting comment as spam");
+        return make_error(400, "Comment rejected").into_response();
+    }
+
+    let name = form.name.trim();
+    let body = form.body.trim().replace("\r\n", "\n");
+    if name.is_empty()
+        || body.is_empty()
+        || name.chars().count() > MAX_NAME_LENGTH
+        || body.chars().count() > MAX_BODY_LENGTH
+    {
+        return make_error(400, "Invalid comment").into_response();
+    }
+
+    if Comment::new(db, &post.id, name, &body).is_err() {
+        return make_error(500, "Failed to save comment").into_response();
+    }
+
+    ax::Redirect::to(&format!("{}?comment=pending#comments", post.url())).into_response()
+}
+
+pub async fn get_comments(
+    ax::State(state): ax::State<Arc<AppState>>,
+    // the queue doesn't exist as far as anonymous readers and guests are concerned
+    RequireModerator(user): RequireModerator,
+    lite: Lite,
+) -> impl IntoResponse {
+    let db = &match state.lock_db().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let cfg = &state.config.lock().unwrap();
+
+    println!("GET comments, user = {:?}", user);
+
+    let comments = match Comment::get_pending(db) {
+        Ok(comments) => comments,
+        Err(_) => return make_error(500, "Failed to load comments").into_response(),
+    };
+
+    // comments on posts the moderator can't read stay hidden from them
+    let comments = comments
+        .into_iter()
+        .filter_map(|comment| {
+            let post = Post::by_id(db, &comment.post_id).ok()?;
+            post.visible_to(Some(&user), cfg.timezone())
+                .then_some((post, comment))
+        })
+        .collect::<Vec<_>>();
+
+    let content = html!(
+        @if comments.is_empty() {
+            p { "No comments waiting for approval." }
+        }
+
+        @for (post, comment) in comments {
+            section class="comment-moderation" {
+                p { "On " a href=(post.url()) { (post.title) } }
+                (comment.to_html(cfg.timezone()))
+                form action=(routes::approve_comment(comment.id)) method="post" {
+                    (csrf_field())
+                    input type="submit" value="Approve" {}
+                }
+                form action=(routes::delete_comment(comment.id)) method="post" {
+                    (csrf_field())
+                    input type="submit" value="Delete" {}
+                }
+            }
+        }
+    );
+
+    let page = make_page(
+        PageMeta::new(Section::None).title("Comments").lite(lite),
+        vec!["/styles/post.css"],
+        content,
+        Some(user),
+        false,
+    );
+
+    ax::Html::from(page.into_string()).into_response()
+}
+
+pub async fn post_approve_comment(
+    state: ax::State<Arc<AppState>>,
+    ax::Path(id): ax::Path<i64>,
+    RequireModerator(user): RequireModerator,
+) -> impl IntoResponse {
+    moderate(&state, id, user, true).await
+}
+
+pub async fn post_delete_comment(
+    state: ax::State<Arc<AppState>>,
+    ax::Path(id): ax::Path<i64>,
+    RequireModerator(user): RequireModerator,
+) -> impl IntoResponse {
+    moderate(&state, id, user, false).await
+}
+
+async fn moderate(state: &AppState, id: i64, user: User, approve: bool) -> ax::Response {
+    let db = &match state.lock_writer().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let cfg = &state.config.lock().unwrap();
+
+    println!(
+        "POST {} comment {}, user = {:?}",
+        if approve { "approve" } else { "delete" },
+        id,
+        user
+    );
+
+    let comment = match Comment::by_id(db, id) {
+        Ok(comment) => comment,
+        Err(_) => return make_error(404, "Comment not found").into_response(),
+    };
+
+    let may_moderate = Post::by_id(db, &comment.post_id)
+        .is_ok_and(|post| post.visible_to(Some(&user), cfg.timezone()));
+    if !may_moderate {
+        return make_error(404, "Comment not found").into_response();
+    }
+
+    let result = match approve {
+        true => comment.approve(db),
+        false => comment.delete(db),
+    };
+
+    match result {
+        Ok(()) => ax::Redirect::to(routes::COMMENTS).into_response(),
+        Err(_) => make_error(500, "Failed to update comment").into_response(),
+    }
+}
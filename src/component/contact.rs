@@ -0,0 +1,325 @@
+use std::time::Duration;
+
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{SmtpTransport, Transport};
+
+use crate::component::comment::looks_like_spam;
+use crate::config::{ContactConfig, SmtpConfig};
+use crate::database::SqliteError;
+use crate::prelude::*;
+use crate::time;
+
+const MAX_NAME_LENGTH: usize = 64;
+const MAX_EMAIL_LENGTH: usize = 254;
+const MAX_BODY_LENGTH: usize = 10000;
+const SMTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Messages sent with the contact form, instead of a mail address in the footer that spammers can
+// collect. They are runtime state like comments: kept for the admins even when mailing them fails.
+pub struct Message {
+    pub id: i64,
+    pub name: String,
+    pub email: String,
+    pub body: String,
+    pub created_at: i64,
+    // when the message was handed to the smtp relay
+    pub sent_at: Option<i64>,
+}
+
+impl Message {
+    pub fn setup(db: &Database) -> Result<(), Error> {
+        db.execute_batch(
+            r#"
+                CREATE TABLE IF NOT EXISTS messages (
+                    id INTEGER PRIMARY KEY,
+                    name TEXT NOT NULL,
+                    email TEXT NOT NULL,
+                    body TEXT NOT NULL,
+                    created_at INTEGER NOT NULL,
+                    sent_at INTEGER NULL
+                );
+            "#,
+        )
+        .context("failed to create messages table")
+    }
+
+    fn from_row(row: &Row) -> Result<Self, SqliteError> {
+        Ok(Self {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            email: row.get(2)?,
+            body: row.get(3)?,
+            created_at: row.get(4)?,
+            sent_at: row.get(5)?,
+        })
+    }
+
+    pub fn new(db: &Database, name: &str, email: &str, body: &str) -> Result<Self, Error> {
+        db.query_one(
+            r#"
+                INSERT INTO messages (name, email, body, created_at)
+                VALUES (?, ?, ?, unixepoch())
+                RETURNING id, name, email, body, created_at, sent_at;
+            "#,
+            (name, email, body),
+            Message::from_row,
+        )
+        .context("failed to insert message into database")
+    }
+
+    pub fn get_all(db: &Database) -> Result<Vec<Self>, Error> {
+        db.query_mul(
+            r#"
+                SELECT id, name, email, body, created_at, sent_at FROM messages
+                ORDER BY created_at DESC, id DESC;
+            "#,
+            [],
+            Message::from_row,
+        )
+        .context("failed to query messages from database")
+    }
+
+    pub fn count_all(db: &Database) -> Result<i64, Error> {
+        db.query_one("SELECT COUNT(*) FROM messages;", [], |row| row.get(0))
+            .context("failed to count messages")
+    }
+
+    fn mark_sent(db: &Database, id: i64) -> Result<(), Error> {
+        db.execute(
+            "UPDATE messages SET sent_at = unixepoch() WHERE id = ?;",
+            [id],
+        )
+        .context("failed to mark message as sent")
+    }
+
+    fn to_html(&self, tz: Tz) -> PreEscaped<String> {
+        html!(
+            article class="message" {
+                p class="comment-meta" {
+                    strong { (self.name) } " "
+                    a href=(format!("mailto:{}", self.email)) { (self.email) } " · "
+                    (time::display_timestamp_time(self.created_at, tz))
+                    @if self.sent_at.is_none() {
+                        " · not mailed"
+                    }
+                }
+                @for paragraph in self.body.split("\n\n") {
+                    p { (paragraph) }
+                }
+            }
+        )
+    }
+}
+
+// blocking, the handler runs it on its own thread once the message is stored
+fn send_mail(contact: &ContactConfig, smtp: &SmtpConfig, message: &Message) -> Result<(), Error> {
+    let reply_to = Mailbox::new(
+        Some(message.name.clone()),
+        message
+            .email
+            .parse::<lettre::Address>()
+            .context("invalid sender address")?,
+    );
+    let mail = lettre::Message::builder()
+        .from(
+            smtp.from
+                .parse::<Mailbox>()
+                .context("invalid from address")?,
+        )
+        .reply_to(reply_to)
+        .to(contact
+            .to
+            .parse::<Mailbox>()
+            .context("invalid to address")?)
+        .subject(format!("Message from {}", message.name))
+        .header(ContentType::TEXT_PLAIN)
+        .body(message.body.clone())
+        .context("failed to build mail")?;
+
+    let mut transport = SmtpTransport::starttls_relay(&smtp.host)
+        .context("invalid smtp host")?
+        .port(smtp.port)
+        .timeout(Some(SMTP_TIMEOUT));
+    if let (Some(username), Some(password)) = (&smtp.username, smtp.password()) {
+        transport = transport.credentials(Credentials::new(username.clone(), password));
+    }
+
+    transport
+        .build()
+        .send(&mail)
+        .context(format!("smtp relay {} rejected the mail", smtp.host))?;
+    Ok(())
+}
+
+fn make_form() -> PreEscaped<String> {
+    html!(
+        form class="comment-form" action=(routes::CONTACT) method="post" {
+            (csrf_field())
+            input type="text" name="name" placeholder="name" maxlength=(MAX_NAME_LENGTH) required {}
+            input type="email" name="email" placeholder="email" maxlength=(MAX_EMAIL_LENGTH) required {}
+            textarea name="body" placeholder="message" maxlength=(MAX_BODY_LENGTH) required {}
+            // left empty by people, filled in by most bots
+            input type="text" name="website" style="display:none" tabindex="-1" autocomplete="off" {}
+            input type="hidden" name="started" value=(chrono::Utc::now().timestamp()) {}
+            input type="submit" value="Send" {}
+        }
+    )
+}
+
+pub async fn get_contact(
+    ax::State(state): ax::State<Arc<AppState>>,
+    ax::Query(params): ax::Query<HashMap<String, String>>,
+    cookie: ax::CookieJar,
+    lite: Lite,
+) -> impl IntoResponse {
+    let db = &match state.lock_db().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let cfg = &state.config.lock().unwrap();
+    let user = User::from_cookie(db, &cookie).ok();
+
+    println!("GET contact, user = {:?}", user);
+
+    if cfg.contact.is_none() {
+        return make_error(404, "Page not found").into_response();
+    }
+
+    let content = html!(
+        @if params.get("message").is_some_and(|message| message == "sent") {
+            p class="comment-notice" { "Thanks! Your message has been sent." }
+        } @else {
+            (make_form())
+        }
+    );
+
+    let page = make_page(
+        PageMeta::new(Section::None).title("Contact").lite(lite),
+        vec!["/styles/post.css"],
+        content,
+        user,
+        false,
+    );
+
+    ax::Html::from(page.into_string()).into_response()
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ContactForm {
+    name: String,
+    email: String,
+    body: String,
+    #[serde(default)]
+    website: String,
+    started: i64,
+}
+
+pub async fn post_contact(
+    ax::State(state): ax::State<Arc<AppState>>,
+    form: ax::Form<ContactForm>,
+) -> impl IntoResponse {
+    println!("POST contact");
+
+    let Some(contact) = state.config.lock().unwrap().contact.clone() else {
+        return make_error(404, "Page not found").into_response();
+    };
+
+    if looks_like_spam(&form.website, form.started) {
+        println!("rejecting message as spam");
+        return make_error(400, "Message rejected").into_response();
+    }
+
+    let name = form.name.trim();
+    let email = form.email.trim();
+    let body = form.body.trim().replace("\r\n", "\n");
+    if name.is_empty()
+        || body.is_empty()
+        || name.chars().count() > MAX_NAME_LENGTH
+        || email.chars().count() > MAX_EMAIL_LENGTH
+        || body.chars().count() > MAX_BODY_LENGTH
+        || email.parse::<lettre::Address>().is_err()
+    {
+        return make_error(400, "Invalid message").into_response();
+    }
+
+    let message = {
+        let db = &match state.lock_writer().await {
+            Ok(db) => db,
+            Err(response) => return response,
+        };
+        match Message::new(db, name, email, &body) {
+            Ok(message) => message,
+            Err(_) => return make_error(500, "Failed to save message").into_response(),
+        }
+    };
+
+    // the message is safe in the database, so the reader doesn't wait for the relay
+    if let Some(smtp) = contact.smtp.clone() {
+        let state = state.clone();
+        tokio::spawn(async move {
+            let id = message.id;
+            let sent =
+                tokio::task::spawn_blocking(move || send_mail(&contact, &smtp, &message)).await;
+            match sent {
+                Ok(Ok(())) => {
+                    if let Ok(db) = state.lock_writer().await
+                        && let Err(error) = Message::mark_sent(&db, id)
+                    {
+                        println!("warning: {}", error.message());
+                    }
+                }
+                Ok(Err(error)) => {
+                    println!(
+                        "warning: failed to mail message {}: {}",
+                        id,
+                        error.message()
+                    )
+                }
+                Err(error) => println!("warning: failed to mail message {}: {}", id, error),
+            }
+        });
+    }
+
+    ax::Redirect::to(&format!("{}?message=sent", routes::CONTACT)).into_response()
+}
+
+pub async fn get_messages(
+    ax::State(state): ax::State<Arc<AppState>>,
+    RequireAdmin(user): RequireAdmin,
+    lite: Lite,
+) -> impl IntoResponse {
+    let db = &match state.lock_db().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let cfg = &state.config.lock().unwrap();
+
+    println!("GET messages, user = {:?}", user);
+
+    let messages = match Message::get_all(db) {
+        Ok(messages) => messages,
+        Err(_) => return make_error(500, "Failed to load messages").into_response(),
+    };
+
+    let content = html!(
+        @if messages.is_empty() {
+            p { "No messages yet." }
+        }
+
+        @for message in &messages {
+            (message.to_html(cfg.timezone()))
+        }
+    );
+
+    let page = make_page(
+        PageMeta::new(Section::None).title("Messages").lite(lite),
+        vec!["/styles/post.css"],
+        content,
+        Some(user),
+        false,
+    );
+
+    ax::Html::from(page.into_string()).into_response()
+}
@@ -0,0 +1,135 @@
+use axum::body::Body;
+use axum::extract::Request;
+use axum::middleware::Next;
+use axum::response::Response;
+
+use crate::prelude::*;
+
+// Every browser gets a random token in a cookie, and every form posts it back. Another site can
+// make a browser post a form here with its cookies, but it can't read the cookie to fill in the
+// field. See `check_csrf`.
+const CSRF_COOKIE: &str = "csrf";
+const CSRF_FIELD: &str = "csrf";
+// for scripts and multipart forms, which aren't read before the handler
+const CSRF_HEADER: &str = "x-csrf-token";
+
+// these authenticate with a secret or signature of their own instead of the login cookie
+const EXEMPT_PATHS: &[&str] = &["/micropub", "/hooks/github", "/activitypub/inbox"];
+
+tokio::task_local! {
+    static TOKEN: String;
+}
+
+// the token of the request being handled, for forms that don't use `csrf_field`
+pub fn csrf_token() -> String {
+    TOKEN.try_with(String::clone).unwrap_or_default()
+}
+
+// goes into every form that posts to the site
+pub fn csrf_field() -> PreEscaped<String> {
+    html!(input type="hidden" name=(CSRF_FIELD) value=(csrf_token()) {})
+}
+
+fn query_token(uri: &ax::Uri) -> Option<String> {
+    url::form_urlencoded::parse(uri.query()?.as_bytes())
+        .find(|(name, _)| name == CSRF_FIELD)
+        .map(|(_, value)| value.into_owned())
+}
+
+fn is_form(request: &Request) -> bool {
+    request
+        .headers()
+        .get(ax::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/x-www-form-urlencoded"))
+}
+
+// the submitted token from the header, the query or the form, with the request to pass on unless
+// the form is too large
+async fn submitted_token(request: Request, max_size: usize) -> (Option<String>, Option<Request>) {
+    if let Some(token) = request
+        .headers()
+        .get(CSRF_HEADER)
+        .and_then(|value| value.to_str().ok())
+    {
+        return (Some(token.to_string()), Some(request));
+    }
+
+    if let Some(token) = query_token(request.uri()) {
+        return (Some(token), Some(request));
+    }
+
+    if !is_form(&request) {
+        return (None, Some(request));
+    }
+
+    // the form is read here, so the handler gets a copy of it
+    let (parts, body) = request.into_parts();
+    let Ok(bytes) = axum::body::to_bytes(body, max_size).await else {
+        return (None, None);
+    };
+    let token = url::form_urlencoded::parse(&bytes)
+        .find(|(name, _)| name == CSRF_FIELD)
+        .map(|(_, value)| value.into_owned());
+
+    (token, Some(Request::from_parts(parts, Body::from(bytes))))
+}
+
+// Rejects posts without the token of the cookie and hands out a token to browsers without one.
+// Requests with an `Authorization` header are left alone, browsers don't add one on their own.
+pub async fn check_csrf(
+    ax::State(state): ax::State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let cookie = ax::CookieJar::from_headers(request.headers())
+        .get(CSRF_COOKIE)
+        .map(|cookie| cookie.value().to_string())
+        .filter(|token| !token.is_empty());
+
+    let is_exempt = request.method() != ax::Method::POST
+        || EXEMPT_PATHS.contains(&request.uri().path())
+        || request.headers().contains_key(ax::header::AUTHORIZATION);
+
+    let request = match is_exempt {
+        true => request,
+        false => {
+            let path = request.uri().path().to_string();
+            // larger forms are rejected by the handler anyway
+            let max_size = state.config.lock().unwrap().max_body_size;
+            match submitted_token(request, max_size).await {
+                (Some(token), Some(request)) if cookie.as_ref() == Some(&token) => request,
+                (_, None) => {
+                    println!("POST {} rejected, form too large", path);
+                    return make_error(413, "Form too large").into_response();
+                }
+                _ => {
+                    println!("POST {} rejected, missing or wrong csrf token", path);
+                    return make_error(403, "Form expired, go back, reload the page and try again")
+                        .into_response();
+                }
+            }
+        }
+    };
+
+    let token = cookie.clone().unwrap_or_else(|| {
+        format!(
+            "{:016x}{:016x}",
+            rand::random::<u64>(),
+            rand::random::<u64>()
+        )
+    });
+    let response = TOKEN.scope(token.clone(), next.run(request)).await;
+
+    match cookie {
+        Some(_) => response,
+        None => {
+            let cookie = ax::Cookie::build((CSRF_COOKIE, token))
+                .path("/")
+                .http_only(true)
+                .secure(state.config.lock().unwrap().secure_cookies)
+                .same_site(ax::SameSite::Lax);
+            (ax::CookieJar::new().add(cookie), response).into_response()
+        }
+    }
+}
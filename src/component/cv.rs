@@ -0,0 +1,259 @@
+use crate::prelude::*;
+
+pub const CV_STYLE_NAME: &str = "cv.css";
+pub const CV_STYLE: &[u8] = include_bytes!("cv.css");
+
+// The CV at `/cv/`, from a JSON or TOML file at `cv_path` (by its extension). It is checked and
+// stored on every build, so a typo fails the build instead of the page.
+#[derive(Serialize, Deserialize)]
+pub struct Cv {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub headline: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    #[serde(default)]
+    pub work: Vec<CvEntry>,
+    #[serde(default)]
+    pub education: Vec<CvEntry>,
+    #[serde(default)]
+    pub skills: Vec<CvSkills>,
+}
+
+// a job or a degree
+#[derive(Serialize, Deserialize)]
+pub struct CvEntry {
+    // e.g. the role or the degree
+    pub title: String,
+    // e.g. the company or the university
+    pub place: String,
+    pub start: String,
+    // still there if missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    #[serde(default)]
+    pub highlights: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CvSkills {
+    pub name: String,
+    pub items: Vec<String>,
+}
+
+impl Cv {
+    pub fn setup(db: &Database) -> Result<(), Error> {
+        db.execute_batch(
+            r#"
+                CREATE TABLE IF NOT EXISTS cv (
+                    id INTEGER PRIMARY KEY CHECK (id = 1),
+                    data TEXT NOT NULL
+                );
+            "#,
+        )
+        .context("failed to create cv table")
+    }
+
+    pub fn load(db: &Database, source_path: &Path) -> Result<(), Error> {
+        let source = fs::read_to_string(source_path)
+            .context(format!("failed to read cv file {:?}", source_path))?;
+        let cv: Cv = match source_path
+            .extension()
+            .and_then(|extension| extension.to_str())
+        {
+            Some("toml") => toml::from_str(&source).context("failed to decode cv"),
+            _ => serde_json::from_str(&source).context("failed to decode cv"),
+        }
+        .map_err(|error| error.with_kind(ErrorKind::Validation))?;
+
+        db.execute(
+            "INSERT INTO cv (id, data) VALUES (1, ?);",
+            [serde_json::to_string(&cv)?],
+        )
+        .context("failed to insert cv into database")
+    }
+
+    pub fn get(db: &Database) -> Result<Option<Self>, Error> {
+        let data: Option<String> = db
+            .query_mul("SELECT data FROM cv WHERE id = 1;", [], |row| row.get(0))
+            .context("failed to query cv from database")?
+            .pop();
+
+        data.map(|data| serde_json::from_str(&data).context("failed to decode stored cv"))
+            .transpose()
+    }
+
+    pub fn delete_all(db: &Database) -> Result<(), Error> {
+        db.execute("DELETE FROM cv", [])
+            .context("failed to delete cv from database")
+    }
+
+    // for the PDF, in the order of the page
+    fn to_text(&self) -> String {
+        let mut text = String::new();
+        if let Some(summary) = &self.summary {
+            text.push_str(&format!("{}\n\n", summary));
+        }
+
+        for (name, entries) in [("WORK", &self.work), ("EDUCATION", &self.education)] {
+            if entries.is_empty() {
+                continue;
+            }
+            text.push_str(&format!("{}\n\n", name));
+            for entry in entries {
+                text.push_str(&entry.to_text());
+            }
+        }
+
+        if !self.skills.is_empty() {
+            text.push_str("SKILLS\n\n");
+            for skills in &self.skills {
+                text.push_str(&format!("{}: {}\n", skills.name, skills.items.join(", ")));
+            }
+        }
+
+        text
+    }
+
+    fn to_html(&self) -> PreEscaped<String> {
+        html!(
+            div class="cv" {
+                @if let Some(headline) = &self.headline {
+                    p class="cv-headline" { (headline) }
+                }
+                p class="cv-pdf" { a href=(routes::CV_PDF) { "PDF" } }
+                @if let Some(summary) = &self.summary {
+                    p { (summary) }
+                }
+
+                @if !self.work.is_empty() {
+                    h2 { "Work" }
+                    @for entry in &self.work {
+                        (entry.to_html())
+                    }
+                }
+
+                @if !self.education.is_empty() {
+                    h2 { "Education" }
+                    @for entry in &self.education {
+                        (entry.to_html())
+                    }
+                }
+
+                @if !self.skills.is_empty() {
+                    h2 { "Skills" }
+                    dl class="cv-skills" {
+                        @for skills in &self.skills {
+                            dt { (skills.name) }
+                            dd { (skills.items.join(", ")) }
+                        }
+                    }
+                }
+            }
+        )
+    }
+}
+
+impl CvEntry {
+    fn dates(&self) -> String {
+        format!(
+            "{} – {}",
+            self.start,
+            self.end.as_deref().unwrap_or("present")
+        )
+    }
+
+    fn to_text(&self) -> String {
+        let mut text = format!("{}, {}\n{}", self.title, self.place, self.dates());
+        if let Some(location) = &self.location {
+            text.push_str(&format!(" · {}", location));
+        }
+        text.push('\n');
+        for highlight in &self.highlights {
+            text.push_str(&format!("- {}\n", highlight));
+        }
+        text.push('\n');
+        text
+    }
+
+    fn to_html(&self) -> PreEscaped<String> {
+        html!(
+            section class="cv-entry" {
+                h3 { (self.title) ", " (self.place) }
+                p class="cv-meta" {
+                    (self.dates())
+                    @if let Some(location) = &self.location {
+                        " · " (location)
+                    }
+                }
+                @if !self.highlights.is_empty() {
+                    ul {
+                        @for highlight in &self.highlights {
+                            li { (highlight) }
+                        }
+                    }
+                }
+            }
+        )
+    }
+}
+
+pub async fn get_cv(
+    ax::State(state): ax::State<Arc<AppState>>,
+    cookie: ax::CookieJar,
+    lite: Lite,
+) -> impl IntoResponse {
+    let db = &match state.lock_db().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let user = User::from_cookie(db, &cookie).ok();
+
+    println!("GET cv, user = {:?}", user);
+
+    let cv = match Cv::get(db) {
+        Ok(Some(cv)) => cv,
+        Ok(None) => return make_error(404, "Page not found").into_response(),
+        Err(_) => return make_error(500, "Failed to load cv").into_response(),
+    };
+
+    let page = make_page(
+        PageMeta::new(Section::None)
+            .title(cv.name.clone())
+            .lite(lite),
+        vec!["/styles/post.css", "/styles/cv.css"],
+        cv.to_html(),
+        user,
+        false,
+    );
+
+    ax::Html::from(page.into_string()).into_response()
+}
+
+pub async fn get_cv_pdf(
+    ax::State(state): ax::State<Arc<AppState>>,
+    cookie: ax::CookieJar,
+) -> impl IntoResponse {
+    let db = &match state.lock_db().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let user = User::from_cookie(db, &cookie).ok();
+
+    println!("GET cv pdf, user = {:?}", user);
+
+    match Cv::get(db) {
+        Ok(Some(cv)) => pdf_response(
+            &state,
+            db,
+            "cv",
+            &cv.name,
+            cv.headline.as_deref().unwrap_or_default(),
+            &cv.to_text(),
+        ),
+        Ok(None) => make_error(404, "Page not found").into_response(),
+        Err(_) => make_error(500, "Failed to load cv").into_response(),
+    }
+}
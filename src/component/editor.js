@@ -0,0 +1,31 @@
+// Live preview for the post editor: the markdown is rendered by the server, so the preview looks
+// exactly like the post will.
+(function () {
+    var form = document.getElementById("editor");
+    var preview = document.getElementById("editor-preview");
+    if (!form || !preview) return;
+
+    var markdown = form.elements.markdown;
+    var timeout = null;
+    var latest = 0;
+
+    function update() {
+        var request = ++latest;
+        var body = new URLSearchParams({
+            post: form.dataset.post,
+            markdown: markdown.value,
+            csrf: form.elements.csrf.value,
+        });
+        fetch(form.dataset.preview, { method: "POST", body: body })
+            .then(function (response) { return response.text(); })
+            .then(function (html) {
+                // an older request finishing late must not overwrite a newer preview
+                if (request === latest) preview.innerHTML = html;
+            });
+    }
+
+    markdown.addEventListener("input", function () {
+        clearTimeout(timeout);
+        timeout = setTimeout(update, 300);
+    });
+})();
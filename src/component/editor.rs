@@ -0,0 +1,421 @@
+use axum::extract::Multipart;
+
+use crate::component::micropub::photo_file_name;
+use crate::component::post::strip_frontmatter;
+use crate::prelude::*;
+
+const EDITOR_SCRIPT: &str = include_str!("editor.js");
+
+// photos straight from a phone camera, several at once
+pub const MAX_UPLOAD_SIZE: usize = 64 * 1024 * 1024;
+
+// Edits the content file of a post in the browser. Saving writes the file in the source directory
+// and reloads the post, so there is no need to edit over ssh and rebuild. Like the rest of the
+// admin pages, the editor doesn't exist for anyone but admins.
+
+fn find_post(db: &Database, id: &str) -> Option<Post> {
+    Post::by_slug(db, id).or_else(|_| Post::by_id(db, id)).ok()
+}
+
+fn render_preview(
+    db: &Database,
+    cfg: &Config,
+    post: &Post,
+    markdown: &str,
+    cookie: &ax::CookieJar,
+) -> Result<String, Error> {
+    let source_path = post.get_source_path(db)?;
+    let markdown = expand_includes(
+        strip_frontmatter(markdown),
+        &source_path,
+        &source_path.join(&cfg.post_assets_path),
+    )?;
+
+    let photos = Photo::get_all(db, Some(&post.id))?;
+    let ctx = MarkdownContext {
+        photos: photos.iter().collect(),
+        math: post.has_math,
+        lite: false,
+        polls: make_polls(db, post, cookie)?,
+        links_appendix: post.has_links_appendix,
+        alt_texts: AltText::get_all(db, &post.id)?,
+        post_id: Some(&post.id),
+        assets_path: &cfg.post_assets_path,
+    };
+
+    markdown_to_html(&markdown, &ctx)
+}
+
+enum Notice<'a> {
+    Saved,
+    Uploaded(&'a str),
+    Failed(&'a str),
+}
+
+fn make_editor_page(
+    db: &Database,
+    cfg: &Config,
+    post: &Post,
+    markdown: &str,
+    notice: Option<Notice>,
+    cookie: &ax::CookieJar,
+    user: User,
+) -> PreEscaped<String> {
+    let preview = render_preview(db, cfg, post, markdown, cookie).unwrap_or_else(|error| {
+        html!(p { "Failed to render preview: " (error.chain_message()) }).into_string()
+    });
+
+    let content = html!(
+        h2 { "Editing " a href=(post.url()) { (post.title) } }
+
+        @match notice {
+            Some(Notice::Saved) => p class="editor-notice" { "Saved." },
+            Some(Notice::Uploaded(names)) => p class="editor-notice" {
+                "Uploaded " (names) ", add them with " code { "![[photo:name|caption]]" } "."
+            },
+            Some(Notice::Failed(error)) => p class="editor-error" { "Failed to save: " (error) },
+            None => {},
+        }
+
+        form id="editor" method="post" data-post=(post.id) data-preview=(routes::PREVIEW) {
+            (csrf_field())
+            textarea name="markdown" rows="30" spellcheck="true" style="width:100%;font-family:monospace" { (markdown) }
+            input type="submit" value="Save" {}
+        }
+
+        h2 { "Photos" }
+        form method="post" action=(format!("{}?csrf={}", routes::upload_photos(&post.id), csrf_token())) enctype="multipart/form-data" {
+            input type="file" name="photo" accept="image/*" multiple required {}
+            " "
+            label { input type="checkbox" name="private" {} " private" }
+            " "
+            input type="submit" value="Upload" {}
+        }
+
+        h2 { "Preview" }
+        article id="editor-preview" {
+            (PreEscaped(preview))
+        }
+
+        script { (PreEscaped(EDITOR_SCRIPT)) }
+    );
+
+    make_page(
+        PageMeta::new(Section::Admin).title(format!("Editing {}", post.title)),
+        vec!["/styles/photo.css", "/styles/post.css"],
+        content,
+        Some(user),
+        false,
+    )
+}
+
+pub async fn get_edit_post(
+    ax::State(state): ax::State<Arc<AppState>>,
+    ax::Path(id): ax::Path<String>,
+    ax::Query(params): ax::Query<HashMap<String, String>>,
+    RequireAdmin(user): RequireAdmin,
+    cookie: ax::CookieJar,
+) -> impl IntoResponse {
+    let db = &match state.lock_db().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let cfg = &state.config.lock().unwrap();
+
+    println!("GET edit post {}, user = {:?}", id, user);
+
+    let Some(post) = find_post(db, &id) else {
+        return make_error(404, "Post not found").into_response();
+    };
+
+    if post
+        .get_source_path(db)
+        .is_ok_and(|source_path| !is_markdown(cfg, &source_path))
+    {
+        return make_error(400, "Only markdown posts can be edited here").into_response();
+    }
+
+    let markdown = match post.get_source_path(db).and_then(|source_path| {
+        fs::read_to_string(source_path.join(&cfg.post_content_path))
+            .context("failed to read post content file")
+    }) {
+        Ok(markdown) => markdown,
+        Err(_) => return make_error(500, "Failed to read post source").into_response(),
+    };
+
+    let notice = match (params.contains_key("saved"), params.get("uploaded")) {
+        (true, _) => Some(Notice::Saved),
+        (false, Some(names)) => Some(Notice::Uploaded(names)),
+        (false, None) => None,
+    };
+    let page = make_editor_page(db, cfg, &post, &markdown, notice, &cookie, user);
+
+    ax::Html::from(page.into_string()).into_response()
+}
+
+#[derive(Deserialize, Debug)]
+pub struct EditForm {
+    markdown: String,
+}
+
+pub async fn post_edit_post(
+    ax::State(state): ax::State<Arc<AppState>>,
+    ax::Path(id): ax::Path<String>,
+    RequireAdmin(user): RequireAdmin,
+    cookie: ax::CookieJar,
+    form: ax::Form<EditForm>,
+) -> impl IntoResponse {
+    println!("POST edit post {}, user = {:?}", id, user);
+
+    // the locks are only held to look the post up, not while it is saved and reloaded
+    let (post, source_path, cfg) = {
+        let db = &match state.lock_db().await {
+            Ok(db) => db,
+            Err(response) => return response,
+        };
+        let cfg = state.config.lock().unwrap().clone();
+
+        let Some(post) = find_post(db, &id) else {
+            return make_error(404, "Post not found").into_response();
+        };
+        let Ok(source_path) = post.get_source_path(db) else {
+            return make_error(500, "Failed to find post source").into_response();
+        };
+        (post, source_path, cfg)
+    };
+    if !is_markdown(&cfg, &source_path) {
+        return make_error(400, "Only markdown posts can be edited here").into_response();
+    }
+
+    // browsers submit textareas with crlf line endings
+    let markdown = form.markdown.replace("\r\n", "\n");
+    let saved = {
+        let (id, markdown) = (post.id.clone(), markdown.clone());
+        tokio::task::spawn_blocking(move || save_markdown(&cfg, &id, &source_path, &markdown)).await
+    };
+
+    let error = match saved {
+        Ok(Ok(Ok(()))) => {
+            return ax::Redirect::to(&format!("{}?saved", routes::edit_post(&post.id)))
+                .into_response();
+        }
+        Ok(Ok(Err(error))) => error,
+        Ok(Err((code, message))) => return make_error(code, message).into_response(),
+        Err(_) => return make_error(500, "Failed to save post").into_response(),
+    };
+
+    let db = &match state.lock_db().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let page = make_editor_page(
+        db,
+        &state.config.lock().unwrap(),
+        &post,
+        &markdown,
+        Some(Notice::Failed(&error.chain_message())),
+        &cookie,
+        user,
+    );
+    (
+        ax::StatusCode::BAD_REQUEST,
+        ax::Html::from(page.into_string()),
+    )
+        .into_response()
+}
+
+// Writes the markdown and reloads the post, a small build of one post on its own connection since
+// the server's can't write content. A post that fails to load is put back the way it was, its
+// error is returned for the editor to show.
+fn save_markdown(
+    cfg: &Config,
+    id: &str,
+    source_path: &Path,
+    markdown: &str,
+) -> Result<Result<(), Error>, (u16, &'static str)> {
+    let path = source_path.join(&cfg.post_content_path);
+    let previous = fs::read_to_string(&path).map_err(|_| (500, "Failed to read post source"))?;
+    let build_db = Database::open(cfg).map_err(|_| (500, "Failed to open database"))?;
+    fs::write(&path, markdown).map_err(|_| (500, "Failed to write post source"))?;
+
+    match Post::reload(&build_db, cfg, id, source_path) {
+        Ok(_) => Ok(Ok(())),
+        Err(error) => {
+            if fs::write(&path, &previous).is_err()
+                || Post::reload(&build_db, cfg, id, source_path).is_err()
+            {
+                return Err((500, "Failed to restore post"));
+            }
+            Ok(Err(error))
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PreviewForm {
+    post: String,
+    markdown: String,
+}
+
+// the rendered markdown only, swapped into the editor page
+pub async fn post_preview(
+    ax::State(state): ax::State<Arc<AppState>>,
+    RequireAdmin(user): RequireAdmin,
+    cookie: ax::CookieJar,
+    form: ax::Form<PreviewForm>,
+) -> impl IntoResponse {
+    let db = &match state.lock_db().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let cfg = &state.config.lock().unwrap();
+
+    println!("POST preview of {}, user = {:?}", form.post, user);
+
+    let Some(post) = find_post(db, &form.post) else {
+        return make_error(404, "Post not found").into_response();
+    };
+
+    match render_preview(db, cfg, &post, &form.markdown, &cookie) {
+        Ok(html) => ax::Html::from(html).into_response(),
+        Err(error) => (
+            ax::StatusCode::BAD_REQUEST,
+            ax::Html::from(
+                html!(p { "Failed to render preview: " (error.chain_message()) }).into_string(),
+            ),
+        )
+            .into_response(),
+    }
+}
+
+struct Upload {
+    photos: Vec<(String, Vec<u8>)>,
+    private: bool,
+}
+
+async fn read_upload(mut multipart: Multipart) -> Option<Upload> {
+    let mut upload = Upload {
+        photos: vec![],
+        private: false,
+    };
+
+    while let Some(field) = multipart.next_field().await.ok()? {
+        match (field.name(), field.file_name()) {
+            (Some("photo"), Some(file_name)) => {
+                let file_name = file_name.to_string();
+                upload
+                    .photos
+                    .push((file_name, field.bytes().await.ok()?.to_vec()));
+            }
+            (Some("private"), _) => upload.private = true,
+            _ => {}
+        }
+    }
+
+    Some(upload)
+}
+
+// the photos go into the post's source directory like any other photo, then the post is reloaded
+// so they are resized, encoded and attached to it
+pub async fn post_upload_photos(
+    ax::State(state): ax::State<Arc<AppState>>,
+    ax::Path(id): ax::Path<String>,
+    RequireAdmin(user): RequireAdmin,
+    multipart: Multipart,
+) -> impl IntoResponse {
+    // the database can't be held while the body is still arriving
+    let upload = read_upload(multipart).await;
+
+    println!("POST upload photos to {}, user = {:?}", id, user);
+
+    // see `post_edit_post`
+    let (post, source_path, cfg) = {
+        let db = &match state.lock_db().await {
+            Ok(db) => db,
+            Err(response) => return response,
+        };
+        let cfg = state.config.lock().unwrap().clone();
+
+        let Some(post) = find_post(db, &id) else {
+            return make_error(404, "Post not found").into_response();
+        };
+        let Ok(source_path) = post.get_source_path(db) else {
+            return make_error(500, "Failed to find post source").into_response();
+        };
+        (post, source_path, cfg)
+    };
+
+    let Some(upload) = upload.filter(|upload| !upload.photos.is_empty()) else {
+        return make_error(400, "No photos uploaded").into_response();
+    };
+
+    let saved = {
+        let id = post.id.clone();
+        tokio::task::spawn_blocking(move || save_photos(&cfg, &id, &source_path, &upload)).await
+    };
+    let names = match saved {
+        Ok(Ok(names)) => names,
+        Ok(Err((code, message))) => return make_error(code, message).into_response(),
+        Err(_) => return make_error(500, "Failed to load uploaded photos").into_response(),
+    };
+
+    let names =
+        url::form_urlencoded::byte_serialize(names.join(", ").as_bytes()).collect::<String>();
+    ax::Redirect::to(&format!(
+        "{}?uploaded={}",
+        routes::edit_post(&post.id),
+        names
+    ))
+    .into_response()
+}
+
+// the names the photos were saved under, see `save_markdown`
+fn save_photos(
+    cfg: &Config,
+    id: &str,
+    source_path: &Path,
+    upload: &Upload,
+) -> Result<Vec<String>, (u16, &'static str)> {
+    // a broken photo would fail every later build
+    if upload
+        .photos
+        .iter()
+        .any(|(_, data)| image::load_from_memory(data).is_err())
+    {
+        return Err((400, "Photo is not a supported image"));
+    }
+
+    let photos_dir = source_path.join(match upload.private {
+        true => &cfg.post_private_photos_path,
+        false => &cfg.post_public_photos_path,
+    });
+    fs::create_dir_all(&photos_dir).map_err(|_| (500, "Failed to create photos directory"))?;
+    let build_db = Database::open(cfg).map_err(|_| (500, "Failed to open database"))?;
+
+    let mut names = vec![];
+    for (index, (file_name, data)) in upload.photos.iter().enumerate() {
+        let base = photo_file_name(file_name, index);
+        let mut name = base.clone();
+        let mut n = 2;
+        while photos_dir.join(&name).exists() || names.contains(&name) {
+            name = format!("{}-{}", n, base);
+            n += 1;
+        }
+
+        fs::write(photos_dir.join(&name), data).map_err(|_| (500, "Failed to write photo"))?;
+        names.push(name);
+    }
+
+    if Post::reload(&build_db, cfg, id, source_path).is_err() {
+        // leave the post as it was before the upload
+        for name in &names {
+            let _ = fs::remove_file(photos_dir.join(name));
+        }
+        if Post::reload(&build_db, cfg, id, source_path).is_err() {
+            return Err((500, "Failed to restore post"));
+        }
+        return Err((500, "Failed to load uploaded photos"));
+    }
+
+    Ok(names)
+}
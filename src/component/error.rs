@@ -8,13 +8,14 @@ pub fn make_error(code: u16, message: &str) -> impl IntoResponse {
     let content = html! {
         section class="error" {
             p { (message)}
-            p { a href="/" { "> return home <"} }
+            p { a href=(routes::INDEX) { "> return home <"} }
         }
     };
 
     let page = make_page(
-        Some(&title),
-        &message,
+        PageMeta::new(Section::None)
+            .title(&title)
+            .description(&message),
         vec!["/styles/error.css"],
         content,
         None,
@@ -24,11 +25,153 @@ pub fn make_error(code: u16, message: &str) -> impl IntoResponse {
     (code, ax::Html::from(page.into_string())).into_response()
 }
 
+// how many "did you mean" links the 404 page shows at most
+const MAX_SUGGESTIONS: usize = 3;
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let cost = usize::from(a != *b);
+            current.push(
+                (previous[j] + cost)
+                    .min(previous[j + 1] + 1)
+                    .min(current[j] + 1),
+            );
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+// Posts and photos the reader can see whose slug, id or permalink is close to the last part of
+// the path, closest first. Typos and links with a changed slug usually end up here.
+fn find_suggestions(
+    db: &Database,
+    cfg: &Config,
+    user: Option<&User>,
+    path: &str,
+) -> Result<Vec<(String, String)>, Error> {
+    let Some(name) = path
+        .split('/')
+        .rfind(|segment| !segment.is_empty())
+        .map(str::to_lowercase)
+    else {
+        return Ok(vec![]);
+    };
+    let max_distance = (name.chars().count() / 3).max(2);
+
+    let mut candidates = vec![];
+    for post in Post::get_all(db)? {
+        if !post.visible_to(user, cfg.timezone()) {
+            continue;
+        }
+        let mut names = post.get_aliases(db)?;
+        names.extend([post.slug.clone(), post.id.clone()]);
+        if let Some(distance) = names
+            .iter()
+            .map(|other| edit_distance(&name, &other.to_lowercase()))
+            .min()
+        {
+            candidates.push((distance, post.url(), post.title.clone()));
+        }
+    }
+
+    // photo ids are random, only worth comparing for photo urls
+    if path.starts_with(routes::PHOTOS) {
+        for photo in Photo::get_all(db, None)? {
+            let visible = photo.visible_to(user)
+                && photo
+                    .get_post(db)
+                    .is_ok_and(|post| post.visible_to(user, cfg.timezone()));
+            if visible {
+                let url = routes::photo(&photo.id);
+                candidates.push((edit_distance(&name, &photo.id), url.clone(), url));
+            }
+        }
+    }
+
+    candidates.retain(|(distance, _, _)| *distance <= max_distance);
+    candidates.sort();
+    candidates.dedup_by(|a, b| a.1 == b.1);
+
+    Ok(candidates
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, url, title)| (url, title))
+        .collect())
+}
+
+// a 404 that still leads somewhere
+pub fn make_not_found(
+    db: &Database,
+    cfg: &Config,
+    user: Option<User>,
+    message: &str,
+    path: &str,
+) -> ax::Response {
+    let (suggestions, posts_table) = match (
+        find_suggestions(db, cfg, user.as_ref(), path),
+        make_posts_table(db, cfg, user.as_ref(), None, Some(5), false, true),
+    ) {
+        (Ok(suggestions), Ok(posts_table)) => (suggestions, posts_table),
+        _ => return make_error(404, "Page not found").into_response(),
+    };
+
+    let content = html! {
+        section class="error" {
+            p { "Error 404: " (message) }
+            @if !suggestions.is_empty() {
+                p { "Did you mean:" }
+                ul {
+                    @for (url, title) in &suggestions {
+                        li { a href=(url) { (title) } }
+                    }
+                }
+            }
+            p { a href=(routes::INDEX) { "> return home <"} }
+        }
+
+        h1 { "Recent posts" }
+        (posts_table)
+    };
+
+    let page = make_page(
+        PageMeta::new(Section::None)
+            .title("404")
+            .description(format!("Error 404: {}", message)),
+        vec!["/styles/error.css", "/styles/post.css"],
+        content,
+        user,
+        true,
+    );
+
+    (
+        ax::StatusCode::NOT_FOUND,
+        ax::Html::from(page.into_string()),
+    )
+        .into_response()
+}
+
 pub async fn get_not_found(
+    ax::State(state): ax::State<Arc<AppState>>,
     uri: ax::Uri,
     ax::Query(params): ax::Query<HashMap<String, String>>,
+    cookie: ax::CookieJar,
 ) -> impl IntoResponse {
     let uri = uri.path();
+
+    if let Some(redirect) = state.config.lock().unwrap().redirect(uri) {
+        println!("redirecting {} to {}", uri, redirect.to());
+        let status = match redirect.is_permanent() {
+            true => ax::StatusCode::MOVED_PERMANENTLY,
+            false => ax::StatusCode::FOUND,
+        };
+        return (status, [(ax::header::LOCATION, redirect.to().to_string())]).into_response();
+    }
+
     let code = params
         .get("code")
         .unwrap_or(&"404".to_string())
@@ -37,10 +180,16 @@ pub async fn get_not_found(
 
     println!("GET error {}", code);
 
-    if !uri.ends_with('/') && code == 404 {
-        println!("redirecting with trailing slash");
-        return ax::Redirect::to(&format!("{}/", uri)).into_response();
+    if code != 404 {
+        return make_error(code, "Page not found").into_response();
     }
 
-    make_error(code, "Page not found").into_response()
+    // the plain error page will do if the database is busy
+    let Ok(db) = state.lock_db().await else {
+        return make_error(404, "Page not found").into_response();
+    };
+    let cfg = &state.config.lock().unwrap();
+    let user = User::from_cookie(&db, &cookie).ok();
+
+    make_not_found(&db, cfg, user, "Page not found", uri)
 }
@@ -0,0 +1,195 @@
+use crate::prelude::*;
+use crate::time;
+
+#[derive(Deserialize, Debug)]
+pub struct FeedQuery {
+    token: Option<String>,
+}
+
+// Feeds are anonymous unless they are requested with a user's feed token, see
+// `User::feed_token`. A token that doesn't exist (anymore) is a 404 rather than the public feed,
+// so a reset token is noticed.
+pub(crate) fn feed_user(db: &Database, query: &FeedQuery) -> Result<Option<User>, ()> {
+    match &query.token {
+        Some(token) => User::by_feed_token(db, token).map(Some).map_err(|_| ()),
+        None => Ok(None),
+    }
+}
+
+// the hub and the feed's own url for WebSub subscribers, only on public feeds since the hub
+// fetches the feed anonymously
+fn websub_links(cfg: &Config, path: &str, user: Option<&User>) -> Option<(String, String)> {
+    let hub = cfg.websub_hub.as_ref().filter(|_| user.is_none())?;
+    let url = format!("{}{}", cfg.site_url.trim_end_matches('/'), path);
+    Some((hub.clone(), url))
+}
+
+fn make_feed_response(
+    feed: PreEscaped<String>,
+    user: Option<&User>,
+    websub: Option<&(String, String)>,
+) -> ax::Response {
+    let mut header = ax::HeaderMap::from_iter(vec![(
+        ax::header::CONTENT_TYPE,
+        "application/rss+xml; charset=utf-8".parse().unwrap(),
+    )]);
+
+    // private feeds must not end up in shared caches or search engines
+    if user.is_some() {
+        header.insert(ax::header::CACHE_CONTROL, "private".parse().unwrap());
+        header.insert("x-robots-tag", "noindex".parse().unwrap());
+    }
+
+    if let Some((hub, url)) = websub
+        && let Ok(link) = format!("<{}>; rel=\"hub\", <{}>; rel=\"self\"", hub, url).parse()
+    {
+        header.insert(ax::header::LINK, link);
+    }
+
+    (header, feed.into_string()).into_response()
+}
+
+pub async fn get_photos_feed(
+    ax::State(state): ax::State<Arc<AppState>>,
+    ax::Query(query): ax::Query<FeedQuery>,
+) -> impl IntoResponse {
+    let db = &match state.lock_db().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let cfg = &state.config.lock().unwrap();
+    let site_url = cfg.site_url.trim_end_matches('/');
+
+    let Ok(user) = feed_user(db, &query) else {
+        return make_error(404, "Feed not found").into_response();
+    };
+
+    println!("GET photos feed, user = {:?}", user);
+
+    let photos = match Photo::get_all(db, None) {
+        Ok(photos) => photos
+            .into_iter()
+            .filter(|photo| photo.visible_to(user.as_ref()))
+            .filter(|photo| {
+                photo
+                    .get_post(db)
+                    .is_ok_and(|post| post.visible_to(user.as_ref(), cfg.timezone()))
+            })
+            .take(cfg.feed_length as usize)
+            .collect::<Vec<_>>(),
+        Err(_) => return make_error(500, "Failed to get photos").into_response(),
+    };
+
+    let websub = websub_links(cfg, routes::PHOTOS_FEED, user.as_ref());
+    let feed = html!(
+        (PreEscaped(r#"<?xml version="1.0" encoding="UTF-8"?>"#))
+        rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom" {
+            channel {
+                title { "Kai - Photos" }
+                link { (site_url) (routes::PHOTOS) }
+                @if let Some((hub, url)) = &websub {
+                    atom:link rel="hub" href=(hub) {}
+                    atom:link rel="self" type="application/rss+xml" href=(url) {}
+                }
+                description { "A gallery of all photos." }
+                @for photo in photos {
+                    @let post = match photo.get_post(db) {
+                        Ok(post) => post,
+                        Err(_) => return make_error(500, "Failed to get post").into_response(),
+                    };
+                    @let post_url = format!("{}{}", site_url, post.url());
+                    @let photo_url = format!("{}{}", site_url, routes::photo(&photo.id));
+                    @let preview = html!(
+                        p { img src=(format!("{}{}", site_url, routes::photo_sized(&photo.id, PhotoSize::Small))) alt=(format!("photo {}", photo.id)); }
+                        p { a href=(post_url) { (post.title) } }
+                    );
+
+                    item {
+                        title { (post.title) }
+                        link { (post_url) }
+                        guid isPermaLink="true" { (photo_url) }
+                        @if let Some(date) = time::rfc822_date(&post.date, cfg.timezone()) {
+                            pubDate { (date) }
+                        }
+                        description { (preview.into_string()) }
+                    }
+                }
+            }
+        }
+    );
+
+    make_feed_response(feed, user.as_ref(), websub.as_ref())
+}
+
+pub async fn get_posts_feed(
+    ax::State(state): ax::State<Arc<AppState>>,
+    ax::Query(query): ax::Query<FeedQuery>,
+) -> impl IntoResponse {
+    let db = &match state.lock_db().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let cfg = &state.config.lock().unwrap();
+    let site_url = cfg.site_url.trim_end_matches('/');
+
+    let Ok(user) = feed_user(db, &query) else {
+        return make_error(404, "Feed not found").into_response();
+    };
+
+    println!("GET posts feed, user = {:?}", user);
+
+    let posts = match Post::get_all(db) {
+        Ok(posts) => posts
+            .into_iter()
+            .filter(|post| post.visible_to(user.as_ref(), cfg.timezone()))
+            .take(cfg.feed_length as usize)
+            .collect::<Vec<_>>(),
+        Err(_) => return make_error(500, "Failed to get posts").into_response(),
+    };
+
+    // episodes attach their audio, so podcast apps can subscribe to the feed
+    let posts = match posts
+        .into_iter()
+        .map(|post| Ok((post.get_audio(db)?, post)))
+        .collect::<Result<Vec<_>, Error>>()
+    {
+        Ok(posts) => posts,
+        Err(_) => return make_error(500, "Failed to get audio").into_response(),
+    };
+
+    let websub = websub_links(cfg, routes::POSTS_FEED, user.as_ref());
+    let feed = html!(
+        (PreEscaped(r#"<?xml version="1.0" encoding="UTF-8"?>"#))
+        rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom" {
+            channel {
+                title { "Kai - Posts" }
+                link { (site_url) (routes::POSTS) }
+                @if let Some((hub, url)) = &websub {
+                    atom:link rel="hub" href=(hub) {}
+                    atom:link rel="self" type="application/rss+xml" href=(url) {}
+                }
+                description { "All posts." }
+                @for (audio, post) in posts {
+                    @let post_url = format!("{}{}", site_url, post.url());
+
+                    item {
+                        title { (post.title) }
+                        link { (post_url) }
+                        guid isPermaLink="false" { (post.id) }
+                        @if let Some(date) = time::rfc822_date(&post.date, cfg.timezone()) {
+                            pubDate { (date) }
+                        }
+                        @if let Some(description) = &post.description {
+                            description { (description) }
+                        }
+                        @if let Some(audio) = audio {
+                            enclosure url=(format!("{}{}", site_url, audio.url)) length=(audio.length) type=(audio.content_type) {}
+                        }
+                    }
+                }
+            }
+        }
+    );
+
+    make_feed_response(feed, user.as_ref(), websub.as_ref())
+}
@@ -1,5 +1,54 @@
+use axum::extract::Request;
+use axum::middleware::Next;
+
+use crate::component::admin::display_size;
 use crate::database::SqliteError;
 use crate::prelude::*;
+use crate::time;
+
+// for urls with the version of their contents, which never change
+const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+// hex digits of the content hash in a version, plenty to tell versions of one file apart
+const VERSION_LENGTH: i64 = 10;
+
+// the start of the content hash by url
+type Versions = Arc<HashMap<String, String>>;
+
+tokio::task_local! {
+    static FILE_VERSIONS: Versions;
+}
+
+// nanoseconds, so a file changed right after a build is still seen as changed
+pub(crate) fn modified_time(path: &Path) -> Result<i64, Error> {
+    Ok(path
+        .metadata()?
+        .modified()?
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_nanos() as i64)
+}
+
+// a file on the `/files/` listing
+pub struct ListedFile {
+    pub name: String,
+    pub size: i64,
+    // in nanoseconds, none for built-in files
+    pub modified: Option<i64>,
+}
+
+impl ListedFile {
+    // the directory the file is in, relative to `/files/`, empty for the top
+    pub fn directory(&self) -> &str {
+        self.name
+            .rsplit_once('/')
+            .map_or("", |(directory, _)| directory)
+    }
+
+    pub fn file_name(&self) -> &str {
+        self.name
+            .rsplit_once('/')
+            .map_or(&self.name, |(_, name)| name)
+    }
+}
 
 #[allow(dead_code)]
 pub struct File {
@@ -16,7 +65,7 @@ impl File {
                     id INTEGER PRIMARY KEY,
                     name TEXT NOT NULL,
                     path TEXT NOT NULL,
-                    data BLOB NOT NULL
+                    data_hash TEXT NOT NULL
                 );
 
                 CREATE INDEX IF NOT EXISTS files_id_index ON files (id);
@@ -24,7 +73,17 @@ impl File {
                 CREATE INDEX IF NOT EXISTS files_path_index ON files (path);
             "#,
         )
-        .context("failed to create files table")
+        .context("failed to create files table")?;
+
+        // where a file was read from and when it was last changed, null for built-in ones
+        db.ensure_column("files", "source_path", "TEXT NULL")
+            .context("failed to update files table")?;
+        db.ensure_column("files", "source_time", "INTEGER NULL")
+            .context("failed to update files table")?;
+        db.ensure_column("files", "mark", "BOOLEAN NOT NULL DEFAULT TRUE")
+            .context("failed to update files table")?;
+
+        Blob::migrate_column(db, "files", "data", "data_hash")
     }
 
     fn from_row(row: &Row) -> Result<Self, SqliteError> {
@@ -35,23 +94,97 @@ impl File {
         })
     }
 
-    pub fn new(db: &Database, parent_path: &Path, source_path: &Path) -> Result<File, Error> {
+    // files that ship with the binary, e.g. scripts for optional features. A file with the same
+    // name in the files directory replaces the built-in one.
+    pub fn add_builtin(db: &Database, path: &str, name: &str, data: &[u8]) -> Result<(), Error> {
+        let is_replaced = db
+            .query_one(
+                "SELECT EXISTS (SELECT 1 FROM files WHERE path = ? AND name = ? AND mark);",
+                (path, name),
+                |row| row.get(0),
+            )
+            .context("failed to query file from database")?;
+        if is_replaced {
+            return Ok(());
+        }
+
+        // the one from the last build, its contents change with the binary
+        db.execute(
+            "DELETE FROM files WHERE path = ? AND name = ?;",
+            (path, name),
+        )
+        .context("failed to delete built-in file from database")?;
+        db.execute(
+            "INSERT INTO files (name, path, data_hash) VALUES (?, ?, ?)",
+            (name, path, Blob::insert(db, data)?),
+        )
+        .context("failed to insert built-in file into database")?;
+        Ok(())
+    }
+
+    // `source_path` lies somewhere below `root_path`, a directory of the files directory. The file
+    // is served under the name of that directory with its path relative to it, e.g.
+    // `/files/fonts/serif.woff2`. Like photos, a file that didn't change since the last build is
+    // only marked, not read again.
+    pub fn new(db: &Database, root_path: &Path, source_path: &Path) -> Result<File, Error> {
         let name = source_path
-            .file_name()
-            .and_then(|n| n.to_str())
-            .context("invalid file path")?;
+            .strip_prefix(root_path)
+            .ok()
+            .and_then(|relative| {
+                relative
+                    .iter()
+                    .map(|part| part.to_str())
+                    .collect::<Option<Vec<_>>>()
+            })
+            .filter(|parts| !parts.is_empty())
+            .context("invalid file path")?
+            .join("/");
+        let name = name.as_str();
 
-        let path = parent_path
+        let path = root_path
             .iter()
             .next_back()
             .context("invalid file path")?
             .to_str();
 
+        let source_time = modified_time(source_path)?;
+        let unchanged = db
+            .query_mul(
+                r#"
+                    UPDATE files SET mark = TRUE
+                    WHERE source_path = ? AND source_time = ? AND name = ? AND path = ?
+                    RETURNING id, name, path;
+                "#,
+                (source_path.to_str(), source_time, name, path),
+                File::from_row,
+            )
+            .context("failed to mark file in database")?
+            .pop();
+        if let Some(file) = unchanged {
+            return Ok(file);
+        }
+
+        db.execute(
+            "DELETE FROM files WHERE source_path = ?;",
+            [source_path.to_str()],
+        )
+        .context("failed to delete outdated file from database")?;
+
         let data = fs::read(source_path).context("failed to read file")?;
 
         db.query_one(
-            "INSERT INTO files (name, path, data) VALUES (?, ?, ?) RETURNING id, name, path",
-            (name, path, data),
+            r#"
+                INSERT INTO files (name, path, data_hash, source_path, source_time)
+                VALUES (?, ?, ?, ?, ?)
+                RETURNING id, name, path;
+            "#,
+            (
+                name,
+                path,
+                Blob::insert(db, &data)?,
+                source_path.to_str(),
+                source_time,
+            ),
             File::from_row,
         )
         .context("failed to insert file into database")
@@ -59,70 +192,514 @@ impl File {
 
     pub fn by_path_and_name(db: &Database, path: &str, name: &str) -> Result<File, Error> {
         db.query_one(
-            "SELECT id, name, path, data FROM files WHERE path = ? AND name = ?",
+            "SELECT id, name, path FROM files WHERE path = ? AND name = ?",
             (path, name),
             File::from_row,
         )
         .context("failed to query file from database")
     }
 
+    pub fn get_all(db: &Database) -> Result<Vec<File>, Error> {
+        db.query_mul(
+            "SELECT id, name, path FROM files ORDER BY path, name",
+            [],
+            File::from_row,
+        )
+        .context("failed to query files from database")
+    }
+
+    // the files served under `/files/` in one of `directories` or below, sorted by directory
+    pub fn get_listed(db: &Database, directories: &[String]) -> Result<Vec<ListedFile>, Error> {
+        let files = db
+            .query_mul(
+                r#"
+                    SELECT files.name, length(blobs.data), files.source_time
+                    FROM files JOIN blobs ON blobs.hash = files.data_hash
+                    WHERE files.path = 'files';
+                "#,
+                [],
+                |row| {
+                    Ok(ListedFile {
+                        name: row.get(0)?,
+                        size: row.get(1)?,
+                        modified: row.get(2)?,
+                    })
+                },
+            )
+            .context("failed to query listed files from database")?;
+
+        let mut files = files
+            .into_iter()
+            .filter(|file| {
+                directories.iter().any(|directory| {
+                    let directory = directory.trim_matches('/');
+                    directory.is_empty()
+                        || file.directory() == directory
+                        || file.directory().starts_with(&format!("{}/", directory))
+                })
+            })
+            .collect::<Vec<_>>();
+        files.sort_by(|a, b| (a.directory(), a.file_name()).cmp(&(b.directory(), b.file_name())));
+        Ok(files)
+    }
+
     pub fn get_data(&self, db: &Database) -> Result<Vec<u8>, Error> {
-        db.query_one("SELECT data FROM files WHERE id = ?", [self.id], |row| {
-            row.get(0)
+        let hash: String = db
+            .query_one(
+                "SELECT data_hash FROM files WHERE id = ?",
+                [self.id],
+                |row| row.get(0),
+            )
+            .context("failed to query file data from database")?;
+        Blob::get(db, &hash)
+    }
+
+    pub fn count_all(db: &Database) -> Result<u32, Error> {
+        db.query_one("SELECT COUNT(*) FROM files;", [], |row| row.get(0))
+            .context("failed to count files in database")
+    }
+
+    // files are kept across builds, the ones that aren't marked again are gone from the source
+    pub fn unmark_all(db: &Database) -> Result<(), Error> {
+        db.execute("UPDATE files SET mark = FALSE", [])
+            .context("failed to unmark all files in database")
+    }
+
+    pub fn delete_unmarked(db: &Database) -> Result<(), Error> {
+        db.execute("DELETE FROM files WHERE mark = FALSE", [])
+            .context("failed to delete unmarked files in database")
+    }
+
+    // the start of the content hash by url, of files and post assets
+    fn get_versions(db: &Database) -> Result<HashMap<String, String>, Error> {
+        let files = db
+            .query_mul(
+                "SELECT path, name, substr(data_hash, 1, ?) FROM files;",
+                [VERSION_LENGTH],
+                |row| {
+                    Ok((
+                        format!("/{}/{}", row.get::<_, String>(0)?, row.get::<_, String>(1)?),
+                        row.get(2)?,
+                    ))
+                },
+            )
+            .context("failed to query file versions from database")?;
+        let assets = db
+            .query_mul(
+                r#"
+                    SELECT posts_assets.post_id, styles.name, substr(styles.data_hash, 1, ?)
+                    FROM styles
+                    JOIN posts_assets ON styles.id = posts_assets.asset_id;
+                "#,
+                [VERSION_LENGTH],
+                |row| {
+                    Ok((
+                        routes::post_asset(&row.get::<_, String>(0)?, &row.get::<_, String>(1)?),
+                        row.get(2)?,
+                    ))
+                },
+            )
+            .context("failed to query asset versions from database")?;
+
+        Ok(files.into_iter().chain(assets).collect())
+    }
+}
+
+// File versions as of a database version, loaded again once a build changed the database.
+#[derive(Default)]
+pub struct FileVersions(Mutex<Option<((i64, u64), Versions)>>);
+
+impl FileVersions {
+    fn get(&self, db: &Database) -> Result<Versions, Error> {
+        let version = db.version()?;
+        let mut cached = self.0.lock().unwrap();
+        match &*cached {
+            Some((cached_version, versions)) if *cached_version == version => Ok(versions.clone()),
+            _ => {
+                let versions = Arc::new(File::get_versions(db)?);
+                *cached = Some((version, versions.clone()));
+                Ok(versions)
+            }
+        }
+    }
+}
+
+// makes the file versions available to `versioned_url` while the request is handled
+pub async fn version_files(
+    ax::State(state): ax::State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> ax::Response {
+    let versions = match state.lock_db().await {
+        Ok(db) => state.file_versions.get(&db).unwrap_or_default(),
+        Err(_) => Arc::default(),
+    };
+    FILE_VERSIONS.scope(versions, next.run(request)).await
+}
+
+// `url` with the version of its contents, e.g. `/styles/page.css?v=1a2b3c4d5e`, so browsers can
+// keep it for good and still get the new one after a deploy. Unknown urls are left alone.
+pub fn versioned_url(url: &str) -> String {
+    FILE_VERSIONS
+        .try_with(|versions| {
+            versions
+                .get(url)
+                .map(|version| format!("{}?v={}", url, version))
         })
-        .context("failed to query file data from database")
+        .ok()
+        .flatten()
+        .unwrap_or_else(|| url.to_string())
+}
+
+// Only a request for the current version of `url` may be cached for good, an older or a newer one
+// comes from a page rendered before or after a deploy.
+fn is_current_version(url: &str, requested: Option<&String>) -> bool {
+    FILE_VERSIONS
+        .try_with(|versions| {
+            versions
+                .get(url)
+                .is_some_and(|version| Some(version) == requested)
+        })
+        .unwrap_or(false)
+}
+
+// headers for the contents of `url`, which can be kept for good if the current version was asked for
+pub fn file_headers(url: &str, requested: Option<&String>) -> ax::HeaderMap {
+    let content_type = mime_guess::from_path(url).first_or_octet_stream();
+    let mut headers = ax::HeaderMap::from_iter(vec![(
+        ax::header::CONTENT_TYPE,
+        content_type.to_string().parse().unwrap(),
+    )]);
+    if is_current_version(url, requested) {
+        headers.insert(ax::header::CACHE_CONTROL, IMMUTABLE.parse().unwrap());
     }
+    headers
+}
+
+// The contents with `headers`, or only the part asked for with a `Range` header so audio players
+// can seek. Only a single range is supported, a request for several gets the whole file.
+pub fn ranged_response(
+    mut headers: ax::HeaderMap,
+    request: &ax::HeaderMap,
+    data: Vec<u8>,
+) -> ax::Response {
+    headers.insert(ax::header::ACCEPT_RANGES, "bytes".parse().unwrap());
 
-    pub fn delete_all(db: &Database) -> Result<(), Error> {
-        db.execute("DELETE FROM files", [])
-            .context("failed to delete all files from database")
+    let range = request
+        .get(ax::header::RANGE)
+        .and_then(|value| value.to_str().ok());
+    let Some(range) = range else {
+        headers.insert(ax::header::CONTENT_LENGTH, data.len().into());
+        return (headers, data).into_response();
+    };
+
+    let length = data.len() as u64;
+    match parse_range(range, length) {
+        Some(Ok((start, end))) => {
+            headers.insert(
+                ax::header::CONTENT_RANGE,
+                format!("bytes {}-{}/{}", start, end, length)
+                    .parse()
+                    .unwrap(),
+            );
+            let part = data[start as usize..=end as usize].to_vec();
+            headers.insert(ax::header::CONTENT_LENGTH, part.len().into());
+            (ax::StatusCode::PARTIAL_CONTENT, headers, part).into_response()
+        }
+        Some(Err(())) => {
+            headers.insert(
+                ax::header::CONTENT_RANGE,
+                format!("bytes */{}", length).parse().unwrap(),
+            );
+            (ax::StatusCode::RANGE_NOT_SATISFIABLE, headers).into_response()
+        }
+        None => {
+            headers.insert(ax::header::CONTENT_LENGTH, data.len().into());
+            (headers, data).into_response()
+        }
     }
 }
 
+// the first and last byte of `bytes=start-end`, `bytes=start-` or `bytes=-suffix`, an error if
+// the range lies outside the data and nothing if it's invalid or not a single range
+fn parse_range(range: &str, length: u64) -> Option<Result<(u64, u64), ()>> {
+    let range = range.strip_prefix("bytes=")?.trim();
+    if range.contains(',') {
+        return None;
+    }
+    let (start, end) = range.split_once('-')?;
+
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix = suffix.parse::<u64>().ok()?;
+            (length.saturating_sub(suffix), length.checked_sub(1)?)
+        }
+        (start, "") => (start.parse().ok()?, length.saturating_sub(1)),
+        (start, end) => {
+            let (start, end) = (start.parse::<u64>().ok()?, end.parse::<u64>().ok()?);
+            // a backwards range is invalid rather than unsatisfiable, so it's ignored
+            if start > end {
+                return None;
+            }
+            (start, end.min(length.saturating_sub(1)))
+        }
+    };
+
+    if start >= length || start > end {
+        return Some(Err(()));
+    }
+    Some(Ok((start, end)))
+}
+
 pub async fn get_style(
     ax::State(state): ax::State<Arc<AppState>>,
     ax::Path(name): ax::Path<String>,
+    ax::Query(params): ax::Query<HashMap<String, String>>,
+    request: ax::HeaderMap,
 ) -> impl IntoResponse {
-    let db = &state.db.lock().unwrap();
+    let db = &match state.lock_db().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
     println!("GET style {}", name);
-    get(db, "styles", &name).into_response()
+    get(db, "styles", &name, params.get("v"), &request).into_response()
+}
+
+pub async fn get_script(
+    ax::State(state): ax::State<Arc<AppState>>,
+    ax::Path(name): ax::Path<String>,
+    ax::Query(params): ax::Query<HashMap<String, String>>,
+    request: ax::HeaderMap,
+) -> impl IntoResponse {
+    let db = &match state.lock_db().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    println!("GET script {}", name);
+    get(db, "scripts", &name, params.get("v"), &request).into_response()
+}
+
+// A listing of the files in the directories of `public_files`, for pointing people at a downloads
+// area. Without any the page doesn't exist.
+pub async fn get_files(
+    ax::State(state): ax::State<Arc<AppState>>,
+    cookie: ax::CookieJar,
+    lite: Lite,
+) -> impl IntoResponse {
+    let db = &match state.lock_db().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let cfg = &state.config.lock().unwrap();
+    let user = User::from_cookie(db, &cookie).ok();
+
+    println!("GET files, user = {:?}", user);
+
+    if cfg.public_files.is_empty() {
+        return make_error(404, "Page not found").into_response();
+    }
+    let Ok(files) = File::get_listed(db, &cfg.public_files) else {
+        return make_error(500, "Failed to load files").into_response();
+    };
+
+    let mut directories: Vec<(&str, Vec<&ListedFile>)> = vec![];
+    for file in &files {
+        match directories.last_mut() {
+            Some((directory, files)) if *directory == file.directory() => files.push(file),
+            _ => directories.push((file.directory(), vec![file])),
+        }
+    }
+
+    let content = html!(
+        @if directories.is_empty() {
+            p { "No files yet." }
+        }
+        @for (directory, files) in &directories {
+            h2 { "/files/" (directory) @if !directory.is_empty() { "/" } }
+            table class="files" {
+                @for file in files {
+                    tr {
+                        td { a href=(routes::file(&file.name)) { (file.file_name()) } }
+                        td { (display_size(file.size)) }
+                        td {
+                            @if let Some(modified) = file.modified {
+                                (time::display_timestamp(modified / 1_000_000_000, cfg.timezone()))
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    );
+
+    let page = make_page(
+        PageMeta::new(Section::None).title("Files").lite(lite),
+        vec!["/styles/post.css"],
+        content,
+        user,
+        false,
+    );
+
+    ax::Html::from(page.into_string()).into_response()
 }
 
+// `name` can contain slashes for files in subdirectories
 pub async fn get_file(
     ax::State(state): ax::State<Arc<AppState>>,
     ax::Path(name): ax::Path<String>,
+    ax::Query(params): ax::Query<HashMap<String, String>>,
+    request: ax::HeaderMap,
 ) -> impl IntoResponse {
-    let db = &state.db.lock().unwrap();
+    let db = &match state.lock_db().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let disposition = content_disposition(&state.config.lock().unwrap().attachment_files, &name);
+
     println!("GET file {}", name);
-    get(db, "files", &name).into_response()
+    let mut response = get(db, "files", &name, params.get("v"), &request).into_response();
+    if response.status().is_success() {
+        response
+            .headers_mut()
+            .insert(ax::header::CONTENT_DISPOSITION, disposition);
+    }
+    response
+}
+
+// Files in a directory or with an extension of `attachment_files` are downloaded instead of
+// shown, under their own name either way so a saved file isn't called `download`.
+fn content_disposition(attachment_files: &[String], name: &str) -> axum::http::HeaderValue {
+    let is_attachment = attachment_files
+        .iter()
+        .any(|pattern| match pattern.strip_prefix('.') {
+            Some(extension) => Path::new(name)
+                .extension()
+                .is_some_and(|other| other.eq_ignore_ascii_case(extension)),
+            None => {
+                let directory = pattern.trim_matches('/');
+                directory.is_empty() || name.starts_with(&format!("{}/", directory))
+            }
+        });
+
+    let file_name = name.rsplit_once('/').map_or(name, |(_, name)| name);
+    // the plain name for old browsers, with anything they might choke on replaced
+    let fallback = file_name
+        .chars()
+        .map(|c| match c {
+            ' ' | '.' | '-' | '_' => c,
+            c if c.is_ascii_alphanumeric() => c,
+            _ => '_',
+        })
+        .collect::<String>();
+    let encoded = routes::encode_segment(file_name);
+
+    format!(
+        "{}; filename=\"{}\"; filename*=UTF-8''{}",
+        if is_attachment {
+            "attachment"
+        } else {
+            "inline"
+        },
+        fallback,
+        encoded
+    )
+    .parse()
+    .unwrap()
 }
 
 pub async fn get_asset(
     ax::State(state): ax::State<Arc<AppState>>,
     ax::Path(name): ax::Path<String>,
+    ax::Query(params): ax::Query<HashMap<String, String>>,
+    request: ax::HeaderMap,
 ) -> impl IntoResponse {
-    let db = &state.db.lock().unwrap();
+    let db = &match state.lock_db().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
     println!("GET asset {}", name);
-    get(db, "assets", &name).into_response()
+    get(db, "assets", &name, params.get("v"), &request).into_response()
 }
 
-fn get(db: &Database, path: &str, name: &str) -> impl IntoResponse {
+// a single range of the file if asked for, so downloads can resume and videos seek
+fn get(
+    db: &Database,
+    path: &str,
+    name: &str,
+    version: Option<&String>,
+    request: &ax::HeaderMap,
+) -> impl IntoResponse {
     match File::by_path_and_name(db, path, name) {
         Ok(file) => {
-            let content_type = mime_guess::from_path(name).first_or_octet_stream();
-
-            let header = ax::HeaderMap::from_iter(vec![(
-                ax::header::CONTENT_TYPE,
-                content_type.to_string().parse().unwrap(),
-            )]);
+            let header = file_headers(&format!("/{}/{}", path, name), version);
 
             let data = match file.get_data(db) {
                 Ok(data) => data,
                 Err(_) => return make_error(500, "Failed to get file data").into_response(),
             };
 
-            (header, data).into_response()
+            ranged_response(header, request, data)
         }
         Err(_) => make_error(404, "File not found").into_response(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_ranges_are_parsed() {
+        assert_eq!(parse_range("bytes=2-5", 10), Some(Ok((2, 5))));
+        assert_eq!(parse_range("bytes=7-", 10), Some(Ok((7, 9))));
+        assert_eq!(parse_range("bytes=-3", 10), Some(Ok((7, 9))));
+        // a suffix longer than the file is all of it, an end past it is cut off
+        assert_eq!(parse_range("bytes=-30", 10), Some(Ok((0, 9))));
+        assert_eq!(parse_range("bytes=5-30", 10), Some(Ok((5, 9))));
+    }
+
+    #[test]
+    fn ranges_outside_the_data_are_unsatisfiable() {
+        assert_eq!(parse_range("bytes=10-", 10), Some(Err(())));
+        assert_eq!(parse_range("bytes=-0", 10), Some(Err(())));
+        assert_eq!(parse_range("bytes=0-", 0), Some(Err(())));
+    }
+
+    #[test]
+    fn other_ranges_get_the_whole_file() {
+        assert_eq!(parse_range("bytes=0-1,4-5", 10), None);
+        assert_eq!(parse_range("items=0-1", 10), None);
+        assert_eq!(parse_range("bytes=a-b", 10), None);
+        assert_eq!(parse_range("bytes=5", 10), None);
+        assert_eq!(parse_range("bytes=-5", 0), None);
+        assert_eq!(parse_range("bytes=5-3", 10), None);
+        assert_eq!(parse_range("bytes=15-12", 10), None);
+    }
+
+    #[test]
+    fn downloads_are_named_after_the_file() {
+        let attachments = ["downloads".to_string(), ".zip".to_string()];
+        let disposition = |name| content_disposition(&attachments, name);
+
+        assert_eq!(
+            disposition("downloads/slides.pdf"),
+            "attachment; filename=\"slides.pdf\"; filename*=UTF-8''slides.pdf"
+        );
+        assert_eq!(
+            disposition("old/Archive.ZIP"),
+            "attachment; filename=\"Archive.ZIP\"; filename*=UTF-8''Archive.ZIP"
+        );
+        assert_eq!(
+            disposition("downloadsx/notes.txt"),
+            "inline; filename=\"notes.txt\"; filename*=UTF-8''notes.txt"
+        );
+        assert_eq!(
+            disposition("say \"hi\" \u{fc}.txt"),
+            "inline; filename=\"say _hi_ _.txt\"; filename*=UTF-8''say%20%22hi%22%20%C3%BC.txt"
+        );
+        assert_eq!(
+            content_disposition(&["/".to_string()], "a.txt"),
+            "attachment; filename=\"a.txt\"; filename*=UTF-8''a.txt"
+        );
+    }
+}
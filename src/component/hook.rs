@@ -0,0 +1,91 @@
+use std::process::Command;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::prelude::*;
+
+const SIGNATURE_HEADER: &str = "x-hub-signature-256";
+const EVENT_HEADER: &str = "x-github-event";
+
+// github signs the body with the webhook secret, `sha256=<hex>`
+fn check_signature(secret: &str, headers: &ax::HeaderMap, body: &[u8]) -> bool {
+    let Some(signature) = headers
+        .get(SIGNATURE_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("sha256="))
+        .and_then(|value| hex::decode(value).ok())
+    else {
+        return false;
+    };
+
+    let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(secret.as_bytes()) else {
+        return false;
+    };
+    mac.update(body);
+    mac.verify_slice(&signature).is_ok()
+}
+
+fn git_pull(repository_path: &str) -> Result<(), Error> {
+    let output = Command::new("git")
+        .args(["-C", repository_path, "pull", "--ff-only"])
+        .output()
+        .context("failed to run git")?;
+
+    match output.status.success() {
+        true => Ok(()),
+        false => Err(Error::new(format!(
+            "git pull failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))),
+    }
+}
+
+// Pushes to the content repository pull it and start a rebuild. Photos that didn't change are not
+// processed again, so a rebuild after a text edit is quick.
+pub async fn post_github_hook(
+    ax::State(state): ax::State<Arc<AppState>>,
+    headers: ax::HeaderMap,
+    body: axum::body::Bytes,
+) -> impl IntoResponse {
+    let event = headers
+        .get(EVENT_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+
+    println!("POST github hook, event = {:?}", event);
+
+    let Some(webhook) = state.config.lock().unwrap().github_webhook.clone() else {
+        return make_error(404, "Page not found").into_response();
+    };
+
+    if !check_signature(&webhook.secret, &headers, &body) {
+        return (ax::StatusCode::UNAUTHORIZED, "invalid signature").into_response();
+    }
+
+    match event.as_str() {
+        // sent once when the webhook is created
+        "ping" => return (ax::StatusCode::OK, "pong").into_response(),
+        "push" => {}
+        _ => return (ax::StatusCode::ACCEPTED, "ignored").into_response(),
+    }
+
+    let pulled = tokio::task::spawn_blocking(move || git_pull(&webhook.repository_path)).await;
+    match pulled {
+        Ok(Ok(())) => {}
+        Ok(Err(error)) => {
+            println!("{}", error.chain_message());
+            return (ax::StatusCode::INTERNAL_SERVER_ERROR, error.chain_message()).into_response();
+        }
+        Err(_) => {
+            return (ax::StatusCode::INTERNAL_SERVER_ERROR, "git pull failed").into_response();
+        }
+    }
+
+    // github shows the failed delivery, so it can be redelivered once the running rebuild is done
+    match start_rebuild(&state) {
+        true => (ax::StatusCode::ACCEPTED, "rebuild started").into_response(),
+        false => (ax::StatusCode::CONFLICT, "a rebuild is already running").into_response(),
+    }
+}
@@ -3,30 +3,38 @@ use crate::prelude::*;
 pub async fn get_index(
     ax::State(state): ax::State<Arc<AppState>>,
     cookies: ax::CookieJar,
+    lite: Lite,
 ) -> impl IntoResponse {
-    let db = &state.db.lock().unwrap();
+    let db = &match state.lock_db().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let cfg = &state.config.lock().unwrap();
     let user = User::from_cookie(db, &cookies).ok();
 
     println!("GET index, user = {:?}", user);
 
-    let posts_table = match make_posts_table(db, None, Some(5), false, true) {
+    let posts_table = match make_posts_table(db, cfg, user.as_ref(), None, Some(5), false, true) {
         Ok(posts_table) => posts_table,
         Err(_) => return make_error(500, "Failed to load posts table").into_response(),
     };
 
-    let content = html! {
-        h1 { "About me" }
+    let featured_table = match make_featured_table(db, cfg, user.as_ref()) {
+        Ok(featured_table) => featured_table,
+        Err(_) => return make_error(500, "Failed to load featured posts").into_response(),
+    };
 
-        p {
-            "For my master's, I'm currently studying " a href = "https://cbb.ethz.ch/" { "Computational Biology and Bioinformatics" } " at ETH Zurich. I studied " a href = "https://curriculum.maastrichtuniversity.nl/education/bachelor/data-science-and-artificial-intelligence" { "Data Science and AI" } " for my bachelor's at Maastricht University."
-        }
+    let intro = StaticPage::by_slug(db, "intro").ok();
 
-        p {
-            "I've worked with " a href = "https://www.i-medtech.nl/" { "i-Med Technology"} " for over 2 years, where I've been developing and implementing various image processing techniques for a digital surgical loupe."
+    let content = html! {
+        @if let Some(intro) = intro {
+            (PreEscaped(intro.html))
         }
 
-        p {
-            "I'm half Japanese, half British, and I've lived in the UK, Japan, Spain, the Netherlands, and Switzerland. I can speak English, Spanish, and Japanese."
+        @if let Some(featured_table) = featured_table {
+            h1 { "Featured" }
+
+            (featured_table)
         }
 
         h1 { "Recent posts" }
@@ -35,8 +43,7 @@ pub async fn get_index(
     };
 
     let page = make_page(
-        None,
-        "Kai's personal website.",
+        PageMeta::new(Section::None).lite(lite),
         vec!["/styles/post.css"],
         content,
         user,
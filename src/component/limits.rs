@@ -0,0 +1,40 @@
+use std::time::Duration;
+
+use axum::error_handling::HandleErrorLayer;
+use tower::limit::GlobalConcurrencyLimitLayer;
+use tower::timeout::error::Elapsed;
+use tower::timeout::TimeoutLayer;
+use tower::{BoxError, ServiceBuilder};
+
+use crate::prelude::*;
+
+async fn handle_limit_error(error: BoxError) -> ax::Response {
+    match error.is::<Elapsed>() {
+        true => {
+            println!("request timed out");
+            make_error(408, "Request took too long").into_response()
+        }
+        false => {
+            println!("request failed: {}", error);
+            make_error(500, "Internal server error").into_response()
+        }
+    }
+}
+
+// A slow client or a huge body can't hold a request slot forever: every request gets a deadline,
+// bodies are capped unless a route allows more (uploads, micropub), and only so many requests are
+// handled at once, the rest wait for a free slot. The concurrency limit is shared by all routes.
+pub fn apply_limits(router: ax::Router<Arc<AppState>>, cfg: &Config) -> ax::Router<Arc<AppState>> {
+    router
+        .layer(axum::extract::DefaultBodyLimit::max(cfg.max_body_size))
+        .layer(
+            ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(handle_limit_error))
+                .layer(TimeoutLayer::new(Duration::from_secs(
+                    cfg.request_timeout_secs,
+                )))
+                .layer(GlobalConcurrencyLimitLayer::new(
+                    cfg.max_concurrent_requests,
+                )),
+        )
+}
@@ -0,0 +1,81 @@
+use std::convert::Infallible;
+
+use axum::extract::{FromRequestParts, Request};
+use axum::http::request::Parts;
+use axum::middleware::Next;
+use axum::response::Response;
+
+use crate::prelude::*;
+
+// `?lite=1` switches every page to a text-only version for readers on slow connections: no
+// photos or images (just links to them), no stylesheets besides a few inlined rules. The choice
+// is remembered in a cookie until `?lite=0`.
+const LITE_COOKIE: &str = "lite";
+
+tokio::task_local! {
+    // the query of the request, so the lite and theme switches keep e.g. `page=` and `tag=`
+    static QUERY: String;
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Lite(pub bool);
+
+impl<S: Send + Sync> FromRequestParts<S> for Lite {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        if let Some(lite) = lite_param(&parts.uri) {
+            return Ok(Lite(lite));
+        }
+
+        let cookies = ax::CookieJar::from_headers(&parts.headers);
+        Ok(Lite(
+            cookies
+                .get(LITE_COOKIE)
+                .is_some_and(|cookie| cookie.value() == "1"),
+        ))
+    }
+}
+
+fn lite_param(uri: &ax::Uri) -> Option<bool> {
+    uri.query()?
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("lite="))
+        .map(|value| value == "1")
+}
+
+// the current page with `?lite=` set
+pub fn lite_href(lite: bool) -> String {
+    href_with_param("lite", if lite { "1" } else { "0" })
+}
+
+// the current page with `name` set to `value`, the other parameters left as they are
+pub fn href_with_param(name: &str, value: &str) -> String {
+    let query = QUERY.try_with(|query| query.clone()).unwrap_or_default();
+    let prefix = format!("{}=", name);
+    let param = format!("{}{}", prefix, value);
+    let mut params = query
+        .split('&')
+        .filter(|pair| !pair.is_empty() && !pair.starts_with(&prefix))
+        .collect::<Vec<_>>();
+    params.push(&param);
+    format!("?{}", params.join("&"))
+}
+
+// makes the query available to `lite_href` and stores or clears the cookie whenever a request
+// sets `?lite=`
+pub async fn remember_lite(request: Request, next: Next) -> Response {
+    let lite = lite_param(request.uri());
+    let query = request.uri().query().unwrap_or_default().to_string();
+    let response = QUERY.scope(query, next.run(request)).await;
+
+    let cookies = match lite {
+        Some(true) => {
+            ax::CookieJar::new().add(ax::Cookie::build((LITE_COOKIE, "1")).path("/").permanent())
+        }
+        Some(false) => ax::CookieJar::new().add(ax::Cookie::build(LITE_COOKIE).path("/").removal()),
+        None => return response,
+    };
+
+    (cookies, response).into_response()
+}
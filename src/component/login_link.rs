@@ -0,0 +1,285 @@
+use sha2::{Digest, Sha256};
+
+use crate::database::SqliteError;
+use crate::prelude::*;
+use crate::time;
+
+// A one-time login url, e.g. for family members who shouldn't have to remember a key. Minting one
+// adds a user to the group with a random key nobody ever sees, opening the link logs in as that
+// user. Only the hash of the link is stored.
+#[allow(dead_code)]
+pub struct LoginLink {
+    pub link_hash: String,
+    pub key_hash: String,
+    pub group_name: String,
+    pub created_at: i64,
+    pub expires_at: i64,
+}
+
+impl LoginLink {
+    pub fn setup(db: &Database) -> Result<(), Error> {
+        db.execute_batch(
+            r#"
+                CREATE TABLE IF NOT EXISTS login_links (
+                    link_hash TEXT PRIMARY KEY NOT NULL,
+                    key_hash TEXT NOT NULL,
+                    created_at INTEGER NOT NULL,
+                    expires_at INTEGER NOT NULL,
+                    used_at INTEGER NULL
+                );
+            "#,
+        )
+        .context("failed to create login_links table")
+    }
+
+    fn from_row(row: &Row) -> Result<Self, SqliteError> {
+        Ok(Self {
+            link_hash: row.get(0)?,
+            key_hash: row.get(1)?,
+            group_name: row.get(2)?,
+            created_at: row.get(3)?,
+            expires_at: row.get(4)?,
+        })
+    }
+
+    // returns the link together with its secret, which can't be recovered later
+    pub fn mint(db: &Database, group_name: &str, ttl: i64) -> Result<(Self, String), Error> {
+        let user = User::new(db, &hex::encode(rand::random::<[u8; 32]>()), group_name)?;
+        let secret = hex::encode(rand::random::<[u8; 32]>());
+
+        let (created_at, expires_at) = db
+            .query_one(
+                r#"
+                    INSERT INTO login_links (link_hash, key_hash, created_at, expires_at)
+                    VALUES (?, ?, unixepoch(), unixepoch() + ?)
+                    RETURNING created_at, expires_at;
+                "#,
+                (Self::link_hash(&secret), &user.key_hash, ttl),
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .context("failed to insert login link into database")?;
+
+        let link = Self {
+            link_hash: Self::link_hash(&secret),
+            key_hash: user.key_hash,
+            group_name: user.group_name,
+            created_at,
+            expires_at,
+        };
+        Ok((link, secret))
+    }
+
+    // unused and not expired
+    pub fn by_secret(db: &Database, secret: &str) -> Result<Self, Error> {
+        db.query_one(
+            r#"
+                SELECT link_hash, links.key_hash, users.group_name, created_at, expires_at
+                FROM login_links AS links JOIN users ON users.key_hash = links.key_hash
+                WHERE link_hash = ? AND used_at IS NULL AND expires_at > unixepoch();
+            "#,
+            [Self::link_hash(secret)],
+            LoginLink::from_row,
+        )
+        .context("failed to query login link from database")
+    }
+
+    // marks the link as used, none if it was already used, expired or never existed
+    pub fn redeem(db: &Database, secret: &str) -> Result<Option<Self>, Error> {
+        let Ok(link) = Self::by_secret(db, secret) else {
+            return Ok(None);
+        };
+
+        db.execute(
+            "UPDATE login_links SET used_at = unixepoch() WHERE link_hash = ? AND used_at IS NULL;",
+            [&link.link_hash],
+        )
+        .context("failed to update login link in database")
+        .map(|_| Some(link))
+    }
+
+    pub fn get_pending(db: &Database) -> Result<Vec<Self>, Error> {
+        db.query_mul(
+            r#"
+                SELECT link_hash, links.key_hash, users.group_name, created_at, expires_at
+                FROM login_links AS links JOIN users ON users.key_hash = links.key_hash
+                WHERE used_at IS NULL AND expires_at > unixepoch()
+                ORDER BY created_at DESC;
+            "#,
+            [],
+            LoginLink::from_row,
+        )
+        .context("failed to query login links from database")
+    }
+
+    fn link_hash(secret: &str) -> String {
+        hex::encode(Sha256::digest(secret.as_bytes()))
+    }
+}
+
+fn mint_form(cfg: &Config) -> PreEscaped<String> {
+    html!(
+        form action=(routes::LOGIN_LINKS) method="post" {
+            (csrf_field())
+            input type="text" name="group" placeholder="group" required {}
+            " valid for "
+            input type="number" name="expires_days" min="1" value=((cfg.login_link_ttl / (24 * 60 * 60)).max(1)) {}
+            " days "
+            input type="submit" value="Create login link" {}
+        }
+    )
+}
+
+pub fn make_login_links(db: &Database, cfg: &Config) -> Result<PreEscaped<String>, Error> {
+    let links = LoginLink::get_pending(db)?;
+
+    Ok(html!(
+        @if links.is_empty() {
+            p { "No unused login links." }
+        } @else {
+            table class="admin-login-links" {
+                @for link in links {
+                    tr {
+                        td { (link.group_name) }
+                        td { "expires " (time::display_timestamp(link.expires_at, cfg.timezone())) }
+                    }
+                }
+            }
+        }
+        (mint_form(cfg))
+    ))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct MintLoginLinkForm {
+    group: String,
+    expires_days: Option<i64>,
+}
+
+// the link is only shown on this page, like api tokens on the command line
+pub async fn post_mint_login_link(
+    ax::State(state): ax::State<Arc<AppState>>,
+    RequireAdmin(user): RequireAdmin,
+    client: Client,
+    lite: Lite,
+    form: ax::Form<MintLoginLinkForm>,
+) -> impl IntoResponse {
+    let db = &match state.lock_writer().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let cfg = &state.config.lock().unwrap();
+
+    println!(
+        "POST mint login link for group {}, user = {:?}",
+        form.group, user
+    );
+
+    let group = form.group.trim();
+    let ttl = match form.expires_days {
+        Some(days) if days > 0 => days * 24 * 60 * 60,
+        Some(_) => return make_error(400, "Invalid expiry").into_response(),
+        None => cfg.login_link_ttl,
+    };
+    if group.is_empty() {
+        return make_error(400, "Invalid group").into_response();
+    }
+
+    let Ok((link, secret)) = LoginLink::mint(db, group, ttl) else {
+        return make_error(500, "Failed to create login link").into_response();
+    };
+    let url = client.absolute_url(cfg, &routes::login_link(&secret));
+
+    let content = html!(
+        p {
+            "Login link for " code { (link.group_name) } ", it works once until "
+            (time::display_timestamp_time(link.expires_at, cfg.timezone()))
+            " and won't be shown again:"
+        }
+        p { a href=(url) { code { (url) } } }
+        p { a href=(format!("{}#login-links", routes::ADMIN)) { "Back to the dashboard" } }
+    );
+
+    let page = make_page(
+        PageMeta::new(Section::Admin).title("Login link").lite(lite),
+        vec![],
+        content,
+        Some(user),
+        false,
+    );
+
+    ax::Html::from(page.into_string()).into_response()
+}
+
+// Chat apps open links to show a preview, which mustn't use up the link, so this only asks to
+// log in and the form does it.
+pub async fn get_login_link(
+    ax::State(state): ax::State<Arc<AppState>>,
+    ax::Path(secret): ax::Path<String>,
+    lite: Lite,
+) -> impl IntoResponse {
+    let db = &match state.lock_db().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+
+    let link = LoginLink::by_secret(db, &secret).ok();
+
+    println!("GET login link, valid = {}", link.is_some());
+
+    let Some(link) = link else {
+        return make_error(404, "This login link has expired or was already used").into_response();
+    };
+
+    let content = html!(
+        form action=(routes::login_link(&secret)) method="post" {
+            (csrf_field())
+            p { "Log in to see what is shared with " code { (link.group_name) } "." }
+            input type="submit" value="Log in" {}
+        }
+    );
+
+    let page = make_page(
+        PageMeta::new(Section::Login).title("Login").lite(lite),
+        vec!["/styles/login.css"],
+        content,
+        None,
+        false,
+    );
+
+    ax::Html::from(page.into_string()).into_response()
+}
+
+pub async fn post_login_link(
+    ax::State(state): ax::State<Arc<AppState>>,
+    ax::Path(secret): ax::Path<String>,
+) -> impl IntoResponse {
+    let db = &match state.lock_writer().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let cfg = &state.config.lock().unwrap();
+
+    let link = match LoginLink::redeem(db, &secret) {
+        Ok(link) => link,
+        Err(_) => return make_error(500, "Failed to log in").into_response(),
+    };
+
+    println!(
+        "POST login link, group = {:?}",
+        link.as_ref().map(|link| &link.group_name)
+    );
+
+    let Some(link) = link else {
+        return make_error(404, "This login link has expired or was already used").into_response();
+    };
+
+    let Ok((session, secret)) = Session::new(db, &link.key_hash, cfg.session_ttl) else {
+        return make_error(500, "Failed to log in").into_response();
+    };
+
+    (
+        ax::CookieJar::new().add(Session::cookie(cfg, Some((&session, secret)))),
+        ax::Redirect::to(routes::INDEX),
+    )
+        .into_response()
+}
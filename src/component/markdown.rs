@@ -0,0 +1,851 @@
+use std::io::Write;
+
+use comrak::nodes::{AstNode, NodeValue};
+use sha2::{Digest, Sha256};
+
+use crate::prelude::*;
+
+#[derive(Default)]
+pub struct MarkdownContext<'a> {
+    // photos the reader is allowed to see, referenced by `![[photo:name|caption]]`
+    pub photos: Vec<&'a Photo>,
+    // renders `$...$`, `$$...$$` and ```math blocks as MathML
+    pub math: bool,
+    // turns photos and images into plain links, see `Lite`
+    pub lite: bool,
+    // rendered polls by id, replacing `{{poll id}}` paragraphs
+    pub polls: HashMap<String, String>,
+    // numbers the external links and lists them in a "Links" section at the end, for print
+    pub links_appendix: bool,
+    // alt text from the admin dashboard by photo name or image url, for photos without a caption
+    // and images without alt text
+    pub alt_texts: HashMap<String, String>,
+    // the post the markdown belongs to. Relative links into its assets directory, e.g.
+    // `![](assets/diagram.png)`, are resolved to its asset route, so the source also works in any
+    // markdown editor.
+    pub post_id: Option<&'a str>,
+    pub assets_path: &'a str,
+}
+
+pub fn markdown_to_html(markdown: &str, ctx: &MarkdownContext) -> Result<String, Error> {
+    let mut options = comrak::Options::default();
+    options.extension.math_dollars = ctx.math;
+    options.extension.math_code = ctx.math;
+    let anchors = HeadingAnchors::default();
+    let mut plugins = comrak::options::Plugins::default();
+    plugins.render.heading_adapter = Some(&anchors);
+
+    let arena = comrak::Arena::new();
+    let root = comrak::parse_document(&arena, markdown, &options);
+    expand_photo_shortcodes(&arena, root, ctx);
+    expand_poll_shortcodes(&arena, root, ctx);
+    fill_alt_texts(&arena, root, ctx);
+    // after the alt texts, which are stored by the url as written
+    resolve_asset_links(root, ctx);
+    if ctx.math {
+        render_math(&arena, root);
+    }
+    if ctx.lite {
+        images_to_links(root);
+    }
+    let links = match ctx.links_appendix {
+        true => number_links(&arena, root),
+        false => vec![],
+    };
+
+    let mut content = String::new();
+    comrak::format_html_with_plugins(root, &options, &mut content, &plugins)
+        .context("failed to compile markdown")?;
+
+    if !links.is_empty() {
+        let appendix = html!(
+            section class="links-appendix" {
+                h2 { "Links" }
+                ol {
+                    @for link in &links {
+                        li { a href=(link) { (link) } }
+                    }
+                }
+            }
+        );
+        content.push_str(&appendix.into_string());
+    }
+
+    Ok(content)
+}
+
+const INCLUDE_START: &str = "{{include ";
+const INCLUDE_END: &str = "}}";
+
+// replaces `{{include assets/main.rs}}` lines with the file's contents in a fenced code block, so
+// code samples can't drift from the files they were taken from
+pub fn expand_includes(
+    markdown: &str,
+    post_path: &Path,
+    assets_path: &Path,
+) -> Result<String, Error> {
+    let mut output = String::with_capacity(markdown.len());
+    let mut fence: Option<&str> = None;
+
+    for line in markdown.split_inclusive('\n') {
+        let trimmed = line.trim();
+
+        if let Some(open) = fence {
+            if closes_fence(trimmed, open) {
+                fence = None;
+            }
+            output.push_str(line);
+            continue;
+        }
+
+        if let Some(marker) = code_fence(trimmed) {
+            fence = Some(marker);
+            output.push_str(line);
+            continue;
+        }
+
+        let Some(include) = trimmed
+            .strip_prefix(INCLUDE_START)
+            .and_then(|rest| rest.strip_suffix(INCLUDE_END))
+        else {
+            output.push_str(line);
+            continue;
+        };
+
+        let include = include.trim();
+        let path = fs::canonicalize(post_path.join(include))
+            .context(format!("failed to find included file {}", include))?;
+
+        // only files from the post's own assets directory may be included
+        let inside_assets =
+            fs::canonicalize(assets_path).is_ok_and(|assets_path| path.starts_with(assets_path));
+        if !inside_assets {
+            return Err(Error::new(format!(
+                "included file {} is not in the assets directory",
+                include
+            ))
+            .with_kind(ErrorKind::Validation));
+        }
+
+        let contents = fs::read_to_string(&path)
+            .context(format!("failed to read included file {}", include))?;
+
+        output.push_str(&code_block(include_language(&path), &contents));
+    }
+
+    Ok(output)
+}
+
+// a fenced block of `contents`, with a fence longer than any run of backticks inside it
+pub fn code_block(language: &str, contents: &str) -> String {
+    let longest_run = contents
+        .split(|c| c != '`')
+        .map(|run| run.len())
+        .max()
+        .unwrap_or(0);
+    let fence = "`".repeat(longest_run.max(2) + 1);
+
+    let mut block = format!("{}{}\n{}", fence, language, contents);
+    if !contents.ends_with('\n') {
+        block.push('\n');
+    }
+    block.push_str(&fence);
+    block.push('\n');
+    block
+}
+
+// the run of backticks or tildes opening a fenced code block, if the line starts one
+fn code_fence(line: &str) -> Option<&str> {
+    let marker = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let run = &line[..line.len() - line.trim_start_matches(marker).len()];
+    (run.len() >= 3).then_some(run)
+}
+
+fn closes_fence(line: &str, open: &str) -> bool {
+    code_fence(line).is_some_and(|close| close.starts_with(open) && line.len() == close.len())
+}
+
+pub struct Diagram {
+    pub name: String,
+    pub svg: Vec<u8>,
+}
+
+// replaces ```dot and ```mermaid blocks with images rendered by graphviz or mermaid-cli, so
+// diagrams can live in the post source. The svgs are returned to be stored as post assets.
+pub fn render_diagrams(markdown: &str, post_id: &str) -> (String, Vec<Diagram>) {
+    let mut output = String::with_capacity(markdown.len());
+    let mut diagrams = vec![];
+    let mut lines = markdown.split_inclusive('\n');
+
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim();
+        let Some(fence) = code_fence(trimmed) else {
+            output.push_str(line);
+            continue;
+        };
+
+        let mut block = String::new();
+        let mut closing = None;
+        for line in lines.by_ref() {
+            if closes_fence(line.trim(), fence) {
+                closing = Some(line);
+                break;
+            }
+            block.push_str(line);
+        }
+
+        let language = trimmed[fence.len()..].trim();
+        let rendered = match language {
+            "dot" | "graphviz" => Some(render_dot(&block)),
+            "mermaid" => Some(render_mermaid(&block)),
+            _ => None,
+        };
+
+        match rendered {
+            Some(Ok(svg)) => {
+                let name = format!("diagram-{}.svg", &hex::encode(Sha256::digest(&block))[..16]);
+                output.push_str(&format!(
+                    "![{} diagram](/posts/{}/assets/{})\n",
+                    language, post_id, name
+                ));
+                diagrams.push(Diagram { name, svg });
+                continue;
+            }
+            // without the tools installed the source is left as a normal code block
+            Some(Err(error)) => {
+                println!(
+                    "warning: failed to render {} diagram: {}",
+                    language,
+                    error.message()
+                );
+            }
+            None => {}
+        }
+
+        output.push_str(line);
+        output.push_str(&block);
+        output.push_str(closing.unwrap_or_default());
+    }
+
+    (output, diagrams)
+}
+
+fn render_dot(source: &str) -> Result<Vec<u8>, Error> {
+    let mut child = std::process::Command::new("dot")
+        .arg("-Tsvg")
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .context("failed to run dot")?;
+
+    child
+        .stdin
+        .take()
+        .context("failed to open dot stdin")?
+        .write_all(source.as_bytes())
+        .context("failed to write to dot")?;
+
+    let output = child.wait_with_output().context("failed to run dot")?;
+    if !output.status.success() {
+        return Err(Error::new(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    Ok(output.stdout)
+}
+
+fn render_mermaid(source: &str) -> Result<Vec<u8>, Error> {
+    let dir = std::env::temp_dir().join(format!("website-mermaid-{:016x}", rand::random::<u64>()));
+    fs::create_dir_all(&dir).context("failed to create mermaid directory")?;
+
+    let input = dir.join("input.mmd");
+    let output = dir.join("output.svg");
+    let result = fs::write(&input, source)
+        .context("failed to write mermaid source")
+        .and_then(|_| {
+            std::process::Command::new("mmdc")
+                .arg("--input")
+                .arg(&input)
+                .arg("--output")
+                .arg(&output)
+                .output()
+                .context("failed to run mmdc")
+        })
+        .and_then(|result| match result.status.success() {
+            true => fs::read(&output).context("failed to read mermaid output"),
+            false => Err(Error::new(
+                String::from_utf8_lossy(&result.stderr).trim().to_string(),
+            )),
+        });
+
+    let _ = fs::remove_dir_all(&dir);
+    result
+}
+
+fn include_language(path: &Path) -> &str {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default();
+
+    match extension {
+        "rs" => "rust",
+        "py" => "python",
+        "js" | "mjs" => "javascript",
+        "ts" => "typescript",
+        "sh" | "bash" => "bash",
+        "c" | "h" => "c",
+        "cpp" | "cc" | "hpp" => "cpp",
+        "md" => "markdown",
+        "yml" => "yaml",
+        "hs" => "haskell",
+        "rb" => "ruby",
+        _ => extension,
+    }
+}
+
+enum Segment<'s> {
+    Text(&'s str),
+    Photo(&'s str, Option<&'s str>),
+}
+
+const PHOTO_SHORTCODE_START: &str = "![[photo:";
+const PHOTO_SHORTCODE_END: &str = "]]";
+
+fn split_photo_shortcodes(text: &str) -> Vec<Segment<'_>> {
+    let mut segments = vec![];
+    let mut rest = text;
+
+    while let Some(start) = rest.find(PHOTO_SHORTCODE_START) {
+        let inner_start = start + PHOTO_SHORTCODE_START.len();
+        let Some(inner_len) = rest[inner_start..].find(PHOTO_SHORTCODE_END) else {
+            break;
+        };
+
+        if start > 0 {
+            segments.push(Segment::Text(&rest[..start]));
+        }
+
+        let inner = &rest[inner_start..inner_start + inner_len];
+        let (name, caption) = match inner.split_once('|') {
+            Some((name, caption)) => (name.trim(), Some(caption.trim())),
+            None => (inner.trim(), None),
+        };
+        segments.push(Segment::Photo(name, caption));
+
+        rest = &rest[inner_start + inner_len + PHOTO_SHORTCODE_END.len()..];
+    }
+
+    if !rest.is_empty() {
+        segments.push(Segment::Text(rest));
+    }
+
+    segments
+}
+
+pub fn photo_shortcode_names(markdown: &str) -> Vec<&str> {
+    split_photo_shortcodes(markdown)
+        .into_iter()
+        .filter_map(|segment| match segment {
+            Segment::Photo(name, _) => Some(name),
+            Segment::Text(_) => None,
+        })
+        .collect()
+}
+
+// names of the photos that are captioned at least once, the caption doubles as their alt text
+pub fn captioned_photo_names(markdown: &str) -> Vec<&str> {
+    split_photo_shortcodes(markdown)
+        .into_iter()
+        .filter_map(|segment| match segment {
+            Segment::Photo(name, Some(caption)) if !caption.trim().is_empty() => Some(name),
+            _ => None,
+        })
+        .collect()
+}
+
+// urls of all images in the document, with whether they have alt text
+pub fn markdown_images(markdown: &str) -> Vec<(String, bool)> {
+    let arena = comrak::Arena::new();
+    let root = comrak::parse_document(&arena, markdown, &comrak::Options::default());
+
+    root.descendants()
+        .filter_map(|node| match &node.data().value {
+            NodeValue::Image(link) => Some((link.url.clone(), has_text(node))),
+            _ => None,
+        })
+        .collect()
+}
+
+fn has_text<'a>(node: &'a AstNode<'a>) -> bool {
+    node.descendants().any(|child| match &child.data().value {
+        NodeValue::Text(text) => !text.trim().is_empty(),
+        NodeValue::Code(_) => true,
+        _ => false,
+    })
+}
+
+fn photo_shortcode_html(ctx: &MarkdownContext, name: &str, caption: Option<&str>) -> String {
+    // photos that don't exist or are hidden from the reader are silently dropped
+    match ctx.photos.iter().find(|photo| photo.name() == name) {
+        Some(photo) => photo
+            .to_html(
+                &routes::photo_sized(&photo.id, PhotoSize::Large),
+                "↪ full res",
+                caption,
+                ctx.alt_texts.get(name).map(String::as_str),
+                Lite(ctx.lite),
+            )
+            .into_string(),
+        None => String::new(),
+    }
+}
+
+// urls of all links in the document, without duplicates
+pub fn markdown_links(markdown: &str) -> Vec<String> {
+    let arena = comrak::Arena::new();
+    let root = comrak::parse_document(&arena, markdown, &comrak::Options::default());
+
+    let mut links: Vec<String> = vec![];
+    for node in root.descendants() {
+        if let NodeValue::Link(link) = &node.data().value
+            && !links.contains(&link.url)
+        {
+            links.push(link.url.clone());
+        }
+    }
+    links
+}
+
+// removes the shortcodes of photos that are missing or hidden from the reader
+pub fn filter_photo_shortcodes(markdown: &str, ctx: &MarkdownContext) -> String {
+    split_photo_shortcodes(markdown)
+        .into_iter()
+        .map(|segment| match segment {
+            Segment::Text(text) => text.to_string(),
+            Segment::Photo(name, caption) if ctx.photos.iter().any(|p| p.name() == name) => {
+                match caption {
+                    Some(caption) => format!("![[photo:{}|{}]]", name, caption),
+                    None => format!("![[photo:{}]]", name),
+                }
+            }
+            Segment::Photo(..) => String::new(),
+        })
+        .collect()
+}
+
+// replaces every photo shortcode, e.g. with a plain image for other generators
+pub fn map_photo_shortcodes(
+    markdown: &str,
+    mut replace: impl FnMut(&str, Option<&str>) -> String,
+) -> String {
+    split_photo_shortcodes(markdown)
+        .into_iter()
+        .map(|segment| match segment {
+            Segment::Text(text) => text.to_string(),
+            Segment::Photo(name, caption) => replace(name, caption),
+        })
+        .collect()
+}
+
+// the text of the first paragraphs, for posts without a description
+pub fn markdown_excerpt(markdown: &str, length: usize) -> String {
+    let arena = comrak::Arena::new();
+    let root = comrak::parse_document(&arena, markdown, &comrak::Options::default());
+
+    let mut excerpt = String::new();
+    for paragraph in root
+        .children()
+        .filter(|node| matches!(node.data().value, NodeValue::Paragraph))
+    {
+        for node in paragraph.descendants() {
+            match &node.data().value {
+                NodeValue::Text(text) if poll_shortcode_id(text).is_none() => {
+                    for segment in split_photo_shortcodes(text) {
+                        if let Segment::Text(text) = segment {
+                            excerpt.push_str(text);
+                        }
+                    }
+                }
+                NodeValue::Code(code) => excerpt.push_str(&code.literal),
+                NodeValue::SoftBreak | NodeValue::LineBreak => excerpt.push(' '),
+                _ => {}
+            }
+        }
+        excerpt.push(' ');
+        if excerpt.chars().count() > length {
+            break;
+        }
+    }
+    excerpt.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+pub fn markdown_to_text(markdown: &str, ctx: &MarkdownContext) -> String {
+    let mut options = comrak::Options::default();
+    options.extension.math_dollars = ctx.math;
+    options.extension.math_code = ctx.math;
+
+    let arena = comrak::Arena::new();
+    let root = comrak::parse_document(&arena, markdown, &options);
+
+    let mut text = String::new();
+    write_text(root, ctx, &mut text);
+    format!("{}\n", text.trim_end())
+}
+
+fn write_text<'a>(node: &'a AstNode<'a>, ctx: &MarkdownContext, out: &mut String) {
+    let write_children = |out: &mut String| {
+        for child in node.children() {
+            write_text(child, ctx, out);
+        }
+    };
+
+    let value = &node.data().value;
+    match value {
+        NodeValue::Text(text) => {
+            for segment in split_photo_shortcodes(text) {
+                match segment {
+                    Segment::Text(text) => out.push_str(text),
+                    Segment::Photo(name, caption) => {
+                        if ctx.photos.iter().any(|photo| photo.name() == name) {
+                            out.push_str(&format!("[photo: {}]", caption.unwrap_or(name)));
+                        }
+                    }
+                }
+            }
+        }
+        NodeValue::Code(code) => out.push_str(&code.literal),
+        NodeValue::Math(math) => out.push_str(&math.literal),
+        NodeValue::CodeBlock(block) => out.push_str(&block.literal),
+        NodeValue::SoftBreak | NodeValue::LineBreak => out.push('\n'),
+        NodeValue::HtmlBlock(_) | NodeValue::HtmlInline(_) => {}
+        NodeValue::Item(_) => {
+            out.push_str("- ");
+            write_children(out);
+        }
+        NodeValue::Image(_) => {
+            out.push_str("[image: ");
+            write_children(out);
+            out.push(']');
+        }
+        NodeValue::Link(link) => {
+            write_children(out);
+            out.push_str(&format!(" <{}>", link.url));
+        }
+        NodeValue::TableCell => {
+            write_children(out);
+            out.push_str(" | ");
+        }
+        _ => write_children(out),
+    }
+
+    // blocks are separated by an empty line, except inside lists and tables
+    let in_item = node
+        .parent()
+        .is_some_and(|parent| matches!(parent.data().value, NodeValue::Item(_)));
+    if matches!(value, NodeValue::TableRow(_)) || (value.block() && in_item) {
+        if !out.ends_with('\n') {
+            out.push('\n');
+        }
+    } else if value.block() && !matches!(value, NodeValue::Document) && !out.is_empty() {
+        while !out.ends_with("\n\n") {
+            out.push('\n');
+        }
+    }
+}
+
+fn expand_photo_shortcodes<'a>(
+    arena: &'a comrak::Arena<'a>,
+    root: &'a AstNode<'a>,
+    ctx: &MarkdownContext,
+) {
+    let text_nodes = root
+        .descendants()
+        .filter(|node| match &node.data().value {
+            NodeValue::Text(text) => text.contains(PHOTO_SHORTCODE_START),
+            _ => false,
+        })
+        .collect::<Vec<_>>();
+
+    for node in text_nodes {
+        let text = match &node.data().value {
+            NodeValue::Text(text) => text.to_string(),
+            _ => continue,
+        };
+        let segments = split_photo_shortcodes(&text);
+
+        // a shortcode on its own line replaces the whole paragraph, so the block markup isn't
+        // nested inside a <p>
+        if let [Segment::Photo(name, caption)] = segments.as_slice()
+            && let Some(parent) = node.parent()
+            && matches!(parent.data().value, NodeValue::Paragraph)
+            && parent.children().count() == 1
+        {
+            let html = photo_shortcode_html(ctx, name, *caption);
+            parent.insert_before(arena.alloc(AstNode::from(NodeValue::Raw(html))));
+            parent.detach();
+            continue;
+        }
+
+        for segment in segments {
+            let value = match segment {
+                Segment::Text(text) => NodeValue::Text(text.to_string().into()),
+                Segment::Photo(name, caption) => {
+                    NodeValue::Raw(photo_shortcode_html(ctx, name, caption))
+                }
+            };
+            node.insert_before(arena.alloc(AstNode::from(value)));
+        }
+        node.detach();
+    }
+}
+
+// unknown polls are left as they are, so the mistake is visible
+fn expand_poll_shortcodes<'a>(
+    arena: &'a comrak::Arena<'a>,
+    root: &'a AstNode<'a>,
+    ctx: &MarkdownContext,
+) {
+    let paragraphs = root
+        .descendants()
+        .filter(|node| matches!(node.data().value, NodeValue::Paragraph))
+        .filter_map(|node| {
+            let child = node
+                .first_child()
+                .filter(|_| node.children().count() == 1)?;
+            let html = match &child.data().value {
+                NodeValue::Text(text) => ctx.polls.get(poll_shortcode_id(text)?)?.clone(),
+                _ => return None,
+            };
+            Some((node, html))
+        })
+        .collect::<Vec<_>>();
+
+    for (node, html) in paragraphs {
+        node.insert_before(arena.alloc(AstNode::from(NodeValue::Raw(html))));
+        node.detach();
+    }
+}
+
+// Marks every external link with its number in the appendix, the same url always gets the same
+// number. Links within the site are left alone.
+fn number_links<'a>(arena: &'a comrak::Arena<'a>, root: &'a AstNode<'a>) -> Vec<String> {
+    let nodes = root
+        .descendants()
+        .filter_map(|node| match &node.data().value {
+            NodeValue::Link(link)
+                if link.url.starts_with("http://") || link.url.starts_with("https://") =>
+            {
+                Some((node, link.url.clone()))
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    let mut links: Vec<String> = vec![];
+    for (node, url) in nodes {
+        let number = match links.iter().position(|link| *link == url) {
+            Some(index) => index + 1,
+            None => {
+                links.push(url);
+                links.len()
+            }
+        };
+        let html = html!(sup class="link-ref" { "[" (number) "]" }).into_string();
+        node.insert_after(arena.alloc(AstNode::from(NodeValue::Raw(html))));
+    }
+    links
+}
+
+// images without alt text get the one from the dashboard, if there is one
+fn fill_alt_texts<'a>(arena: &'a comrak::Arena<'a>, root: &'a AstNode<'a>, ctx: &MarkdownContext) {
+    let images = root
+        .descendants()
+        .filter_map(|node| match &node.data().value {
+            NodeValue::Image(link) if !has_text(node) => {
+                Some((node, ctx.alt_texts.get(&link.url)?.clone()))
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    for (node, alt_text) in images {
+        node.append(arena.alloc(AstNode::from(NodeValue::Text(alt_text.into()))));
+    }
+}
+
+fn resolve_asset_links<'a>(root: &'a AstNode<'a>, ctx: &MarkdownContext) {
+    let Some(post_id) = ctx.post_id else {
+        return;
+    };
+
+    for node in root.descendants() {
+        if let NodeValue::Link(link) | NodeValue::Image(link) = &mut node.data_mut().value
+            && let Some(name) = asset_name(&link.url, ctx.assets_path)
+        {
+            link.url = routes::post_asset(post_id, name);
+        }
+    }
+}
+
+// `assets/diagram.png` or `./assets/diagram.png` for an assets path of `assets`, assets are only
+// served from the top of the directory
+fn asset_name<'u>(url: &'u str, assets_path: &str) -> Option<&'u str> {
+    let assets_path = assets_path.trim_start_matches("./").trim_end_matches('/');
+    let name = url
+        .trim_start_matches("./")
+        .strip_prefix(assets_path)?
+        .strip_prefix('/')?;
+    (!assets_path.is_empty() && !name.is_empty() && !name.contains('/')).then_some(name)
+}
+
+// the alt text stays as the link text
+fn images_to_links<'a>(root: &'a AstNode<'a>) {
+    for node in root.descendants() {
+        let link = match &node.data().value {
+            NodeValue::Image(link) => link.clone(),
+            _ => continue,
+        };
+        node.data_mut().value = NodeValue::Link(link);
+    }
+}
+
+fn render_math<'a>(arena: &'a comrak::Arena<'a>, root: &'a AstNode<'a>) {
+    let math_nodes = root
+        .descendants()
+        .filter(|node| match &node.data().value {
+            NodeValue::Math(_) => true,
+            NodeValue::CodeBlock(block) => block.info.trim() == "math",
+            _ => false,
+        })
+        .collect::<Vec<_>>();
+
+    for node in math_nodes {
+        let (literal, display) = match &node.data().value {
+            NodeValue::Math(math) => (math.literal.clone(), math.display_math),
+            NodeValue::CodeBlock(block) => (block.literal.clone(), true),
+            _ => continue,
+        };
+
+        let style = match display {
+            true => latex2mathml::DisplayStyle::Block,
+            false => latex2mathml::DisplayStyle::Inline,
+        };
+
+        // invalid latex is shown as is instead of failing the whole page
+        let html = match latex2mathml::latex_to_mathml(literal.trim(), style) {
+            Ok(mathml) => escape_mathml_text(&mathml),
+            Err(_) => html!(code class="math-error" { (literal) }).into_string(),
+        };
+
+        node.insert_before(arena.alloc(AstNode::from(NodeValue::Raw(html))));
+        node.detach();
+    }
+}
+
+const MATHML_TAGS: &[&str] = &[
+    "math",
+    "semantics",
+    "annotation",
+    "mrow",
+    "mi",
+    "mn",
+    "mo",
+    "mtext",
+    "mspace",
+    "mfrac",
+    "msqrt",
+    "mroot",
+    "msub",
+    "msup",
+    "msubsup",
+    "mover",
+    "munder",
+    "munderover",
+    "mmultiscripts",
+    "mprescripts",
+    "none",
+    "mtable",
+    "mtr",
+    "mtd",
+    "mstyle",
+    "mpadded",
+    "mphantom",
+    "menclose",
+];
+
+// latex2mathml writes operators and text verbatim, so `a < b` or `\text{<b>}` would otherwise
+// end up as markup
+fn escape_mathml_text(mathml: &str) -> String {
+    let mut output = String::with_capacity(mathml.len());
+    let mut in_tag = false;
+
+    for (i, c) in mathml.char_indices() {
+        match c {
+            '<' if !in_tag && is_mathml_tag(&mathml[i + 1..]) => {
+                in_tag = true;
+                output.push(c);
+            }
+            '>' if in_tag => {
+                in_tag = false;
+                output.push(c);
+            }
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            _ => output.push(c),
+        }
+    }
+
+    output
+}
+
+fn is_mathml_tag(rest: &str) -> bool {
+    let rest = rest.strip_prefix('/').unwrap_or(rest);
+    let name_len = rest
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or(rest.len());
+    let (name, after) = rest.split_at(name_len);
+    MATHML_TAGS.contains(&name) && after.starts_with([' ', '>', '/'])
+}
+
+// adds ids and "#" links to h2/h3 headings so sections can be linked to
+#[derive(Default)]
+struct HeadingAnchors {
+    anchorizer: Mutex<comrak::Anchorizer>,
+    current: Mutex<Option<String>>,
+}
+
+impl comrak::adapters::HeadingAdapter for HeadingAnchors {
+    fn enter(
+        &self,
+        output: &mut dyn std::fmt::Write,
+        heading: &comrak::adapters::HeadingMeta,
+        _sourcepos: Option<comrak::nodes::Sourcepos>,
+    ) -> std::fmt::Result {
+        if !(2..=3).contains(&heading.level) {
+            return write!(output, "<h{}>", heading.level);
+        }
+
+        let id = self.anchorizer.lock().unwrap().anchorize(&heading.content);
+        write!(output, "<h{} id=\"{}\" data-section>", heading.level, id)?;
+        *self.current.lock().unwrap() = Some(id);
+        Ok(())
+    }
+
+    fn exit(
+        &self,
+        output: &mut dyn std::fmt::Write,
+        heading: &comrak::adapters::HeadingMeta,
+    ) -> std::fmt::Result {
+        if let Some(id) = self.current.lock().unwrap().take() {
+            write!(
+                output,
+                " <a class=\"heading-anchor\" href=\"#{}\">#</a>",
+                id
+            )?;
+        }
+
+        writeln!(output, "</h{}>", heading.level)
+    }
+}
@@ -0,0 +1,372 @@
+use axum::extract::{FromRequest, Multipart, Request};
+
+use crate::component::post::{slugify, PostMetadata};
+use crate::prelude::*;
+use crate::time;
+
+// photos straight from a phone camera are large
+pub const MAX_REQUEST_SIZE: usize = 32 * 1024 * 1024;
+
+const MAX_TITLE_LENGTH: usize = 60;
+
+// Micropub (https://micropub.spec.indieweb.org/) lets mobile clients publish short notes. Every
+// entry becomes a regular post directory in `posts_path`, so it can be edited like any other post
+// afterwards, and is loaded into the database right away without a full rebuild.
+#[derive(Default)]
+struct Entry {
+    access_token: Option<String>,
+    kind: Option<String>,
+    name: Option<String>,
+    content: String,
+    categories: Vec<String>,
+    // uploaded files, by file name
+    photos: Vec<(String, Vec<u8>)>,
+    // photos hosted elsewhere, with their alt text
+    photo_urls: Vec<(String, Option<String>)>,
+}
+
+impl Entry {
+    fn set(&mut self, key: &str, value: String) {
+        match key.trim_end_matches("[]") {
+            "access_token" => self.access_token = Some(value),
+            "h" => self.kind = Some(value),
+            "name" => self.name = Some(value),
+            "content" => self.content = value,
+            "category" => self.categories.push(value),
+            "photo" => self.photo_urls.push((value, None)),
+            _ => {}
+        }
+    }
+
+    fn from_form(body: &[u8]) -> Self {
+        let mut entry = Entry::default();
+        for (key, value) in url::form_urlencoded::parse(body) {
+            entry.set(&key, value.into_owned());
+        }
+        entry
+    }
+
+    fn from_json(body: &[u8]) -> Result<Self, &'static str> {
+        let json: serde_json::Value =
+            serde_json::from_slice(body).map_err(|_| "request body is not valid json")?;
+        let properties = &json["properties"];
+        let strings = |name: &str| {
+            properties[name]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|value| value.as_str().map(str::to_string))
+                .collect::<Vec<_>>()
+        };
+
+        // content is either plain text or an object with a plain text or html value
+        let content = match &properties["content"][0] {
+            serde_json::Value::String(content) => content.as_str(),
+            content => content["value"]
+                .as_str()
+                .or(content["html"].as_str())
+                .unwrap_or_default(),
+        };
+
+        let photo_urls = properties["photo"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|photo| match photo {
+                serde_json::Value::String(url) => Some((url.clone(), None)),
+                photo => Some((
+                    photo["value"].as_str()?.to_string(),
+                    photo["alt"].as_str().map(str::to_string),
+                )),
+            })
+            .collect();
+
+        Ok(Entry {
+            access_token: json["access_token"].as_str().map(str::to_string),
+            kind: json["type"][0]
+                .as_str()
+                .map(|kind| kind.trim_start_matches("h-").to_string()),
+            name: strings("name").into_iter().next(),
+            content: content.to_string(),
+            categories: strings("category"),
+            photos: vec![],
+            photo_urls,
+        })
+    }
+
+    async fn from_multipart(mut multipart: Multipart) -> Result<Self, &'static str> {
+        let mut entry = Entry::default();
+
+        while let Some(field) = multipart
+            .next_field()
+            .await
+            .map_err(|_| "invalid multipart body")?
+        {
+            let name = field.name().unwrap_or_default().to_string();
+            let file_name = field.file_name().map(str::to_string);
+
+            match file_name {
+                Some(file_name) if name.trim_end_matches("[]") == "photo" => {
+                    let data = field.bytes().await.map_err(|_| "failed to read photo")?;
+                    entry.photos.push((file_name, data.to_vec()));
+                }
+                _ => {
+                    let value = field.text().await.map_err(|_| "invalid multipart field")?;
+                    entry.set(&name, value);
+                }
+            }
+        }
+
+        Ok(entry)
+    }
+}
+
+// a token needs this scope to publish
+pub const MICROPUB_SCOPE: &str = "micropub";
+
+fn check_scope(token: &ApiToken) -> Result<(), (u16, &'static str, &'static str)> {
+    match token.has_scope(MICROPUB_SCOPE) {
+        true => Ok(()),
+        false => Err((403, "insufficient_scope", "token lacks the micropub scope")),
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct MicropubQuery {
+    q: Option<String>,
+}
+
+pub async fn get_micropub(
+    ax::Query(query): ax::Query<MicropubQuery>,
+    Bearer(token): Bearer,
+) -> impl IntoResponse {
+    println!("GET micropub, q = {:?}, token = {:?}", query.q, token);
+
+    if let Err((code, error, description)) = check_scope(&token) {
+        return api_error(code, error, description);
+    }
+
+    match query.q.as_deref() {
+        Some("config") => axum::Json(serde_json::json!({})).into_response(),
+        Some("syndicate-to") => {
+            axum::Json(serde_json::json!({ "syndicate-to": [] })).into_response()
+        }
+        _ => api_error(400, "invalid_request", "unsupported query"),
+    }
+}
+
+// the token is either in the authorization header, which is checked before reading a possibly
+// large upload, or in the body as `access_token`
+pub async fn post_micropub(
+    ax::State(state): ax::State<Arc<AppState>>,
+    bearer: Option<Bearer>,
+    client: Client,
+    request: Request,
+) -> impl IntoResponse {
+    println!(
+        "POST micropub, token = {:?}",
+        bearer.as_ref().map(|bearer| &bearer.0)
+    );
+
+    if let Some(Bearer(token)) = &bearer
+        && let Err((code, error, description)) = check_scope(token)
+    {
+        return api_error(code, error, description);
+    }
+
+    let content_type = request
+        .headers()
+        .get(ax::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+
+    let entry = if content_type.starts_with("multipart/form-data") {
+        match Multipart::from_request(request, &()).await {
+            Ok(multipart) => Entry::from_multipart(multipart).await,
+            Err(_) => Err("invalid multipart body"),
+        }
+    } else {
+        match axum::body::to_bytes(request.into_body(), MAX_REQUEST_SIZE).await {
+            Ok(body) if content_type.starts_with("application/json") => Entry::from_json(&body),
+            Ok(body) => Ok(Entry::from_form(&body)),
+            Err(_) => Err("failed to read request body"),
+        }
+    };
+
+    let entry = match entry {
+        Ok(entry) => entry,
+        Err(description) => return api_error(400, "invalid_request", description),
+    };
+
+    if entry.kind.as_deref().is_some_and(|kind| kind != "entry") {
+        return api_error(400, "invalid_request", "only h-entry is supported");
+    }
+    if entry.content.trim().is_empty() && entry.photos.is_empty() && entry.photo_urls.is_empty() {
+        return api_error(400, "invalid_request", "entry has no content");
+    }
+
+    if bearer.is_none() {
+        let db = &match state.lock_db().await {
+            Ok(db) => db,
+            Err(response) => return response,
+        };
+        let Some(secret) = &entry.access_token else {
+            return api_error(401, "unauthorized", "missing access token");
+        };
+        let Some(token) = ApiToken::authenticate(db, secret) else {
+            return api_error(403, "forbidden", "invalid access token");
+        };
+        if let Err((code, error, description)) = check_scope(&token) {
+            return api_error(code, error, description);
+        }
+    }
+
+    // Photos are decoded and the post is written without holding the database or the config.
+    // The server's connection can't write content, this is a small build of one post.
+    let cfg = state.config.lock().unwrap().clone();
+    let created = tokio::task::spawn_blocking(move || {
+        // a broken photo would fail every later build, so it's better to refuse it now
+        if entry
+            .photos
+            .iter()
+            .any(|(_, data)| image::load_from_memory(data).is_err())
+        {
+            return Ok(Err("photo is not a supported image"));
+        }
+        let post = Database::open(&cfg).and_then(|build_db| create_post(&build_db, &cfg, entry))?;
+        Ok::<_, Error>(Ok(client.absolute_url(&cfg, &post.url())))
+    })
+    .await;
+
+    match created {
+        Ok(Ok(Ok(url))) => {
+            println!("published {} through micropub", url);
+            (ax::StatusCode::CREATED, [(ax::header::LOCATION, url)]).into_response()
+        }
+        Ok(Ok(Err(description))) => api_error(400, "invalid_request", description),
+        Ok(Err(error)) => {
+            println!("error: failed to publish micropub entry: {:?}", error);
+            api_error(500, "server_error", "failed to create post")
+        }
+        Err(error) => {
+            println!("error: failed to publish micropub entry: {}", error);
+            api_error(500, "server_error", "failed to create post")
+        }
+    }
+}
+
+fn create_post(db: &Database, cfg: &Config, entry: Entry) -> Result<Post, Error> {
+    let now = time::now(cfg.timezone());
+    let title = match entry.name.as_deref().map(str::trim) {
+        Some(name) if !name.is_empty() => name.to_string(),
+        _ => note_title(&entry.content)
+            .unwrap_or_else(|| format!("Note from {}", now.format("%Y-%m-%d %H:%M"))),
+    };
+
+    let base_name = format!("{}-{}", now.format("%Y-%m-%d"), slugify(&title));
+    let mut dir = Path::new(&cfg.posts_path).join(&base_name);
+    for suffix in 2.. {
+        if !dir.exists() {
+            break;
+        }
+        dir = Path::new(&cfg.posts_path).join(format!("{}-{}", base_name, suffix));
+    }
+
+    fs::create_dir_all(&dir).context("failed to create post directory")?;
+
+    let date = now.format("%Y-%m-%d %H:%M").to_string();
+    let result = write_post(db, cfg, &dir, entry, title, date);
+    if result.is_err() {
+        let _ = fs::remove_dir_all(&dir);
+    }
+    result
+}
+
+fn write_post(
+    db: &Database,
+    cfg: &Config,
+    dir: &Path,
+    entry: Entry,
+    title: String,
+    date: String,
+) -> Result<Post, Error> {
+    let mut markdown = entry.content.trim().replace("\r\n", "\n");
+
+    let photos_dir = dir.join(&cfg.post_public_photos_path);
+    let mut names = vec![];
+    for (file_name, data) in &entry.photos {
+        let name = photo_file_name(file_name, names.len());
+        let name = match names.contains(&name) {
+            true => format!("{}-{}", names.len() + 1, name),
+            false => name,
+        };
+
+        fs::create_dir_all(&photos_dir).context("failed to create photos directory")?;
+        fs::write(photos_dir.join(&name), data).context("failed to write photo")?;
+        markdown.push_str(&format!("\n\n![[photo:{}]]", name));
+        names.push(name);
+    }
+
+    for (url, alt) in &entry.photo_urls {
+        markdown.push_str(&format!(
+            "\n\n![{}]({})",
+            alt.as_deref().unwrap_or_default(),
+            url
+        ));
+    }
+    markdown.push('\n');
+
+    let mut metadata = PostMetadata::new(title, date);
+    metadata.tags = entry.categories;
+
+    metadata.to_json_file(dir.join(&cfg.post_metadata_path).to_str().unwrap())?;
+    fs::write(dir.join(&cfg.post_content_path), markdown)
+        .context("failed to write post content file")?;
+
+    let post = Post::new(db, cfg, dir)?;
+    Post::assign_slugs(db)?;
+    Post::by_id(db, &post.id)
+}
+
+// notes have no name, so the start of the first line stands in for the title
+fn note_title(content: &str) -> Option<String> {
+    let line = content
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())?;
+    if line.chars().count() <= MAX_TITLE_LENGTH {
+        return Some(line.to_string());
+    }
+
+    let mut title = String::new();
+    for word in line.split_whitespace() {
+        if title.chars().count() + word.chars().count() >= MAX_TITLE_LENGTH {
+            break;
+        }
+        if !title.is_empty() {
+            title.push(' ');
+        }
+        title.push_str(word);
+    }
+    if title.is_empty() {
+        title = line.chars().take(MAX_TITLE_LENGTH).collect();
+    }
+    Some(format!("{}…", title))
+}
+
+// only the last path component and a few safe characters survive from the uploaded name
+pub(crate) fn photo_file_name(file_name: &str, index: usize) -> String {
+    let name = Path::new(file_name)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+        .collect::<String>();
+
+    match name.trim_start_matches('.') {
+        "" => format!("photo-{}.jpg", index + 1),
+        name => name.to_string(),
+    }
+}
@@ -1,23 +1,124 @@
+pub mod activitypub;
+pub mod admin;
+pub mod alt_text;
+pub mod asciidoc;
 pub mod asset;
+pub mod blob;
+pub mod build;
+pub mod calendar;
+pub mod chart;
+pub mod comment;
+pub mod contact;
+pub mod csrf;
+pub mod cv;
+pub mod editor;
 pub mod error;
+pub mod feed;
 pub mod file;
+pub mod hook;
 pub mod index;
+pub mod limits;
+pub mod lite;
+pub mod login_link;
+pub mod markdown;
+pub mod micropub;
+pub mod notebook;
+pub mod org;
 pub mod page;
+pub mod page_cache;
+pub mod pdf;
 pub mod photo;
+pub mod poll;
 pub mod post;
 pub mod project;
+pub mod proxy;
+pub mod rebuild;
+pub mod renderer;
+pub mod role;
+pub mod session;
+pub mod shortlink;
+pub mod static_page;
+pub mod stats;
+pub mod theme;
+pub mod token;
+pub mod tombstone;
+pub mod track;
+pub mod trailing_slash;
 pub mod user;
 
 pub mod prelude {
+    pub use super::activitypub::{
+        deliver_posts, get_actor, get_followers, get_note, get_outbox, get_webfinger, post_inbox,
+        Follower,
+    };
+    pub use super::admin::{get_admin, record_errors, RecentErrors};
+    pub use super::alt_text::{
+        make_missing_alt_texts, post_alt_text, AltText, IMAGE_KIND, PHOTO_KIND,
+    };
     pub use super::asset::{get_asset, Asset};
-    pub use super::error::{get_not_found, make_error};
+    pub use super::blob::Blob;
+    pub use super::build::{Build, BuildFailure, BuildReport, BuildSnapshot};
+    pub use super::calendar::{get_calendar, Event};
+    pub use super::comment::{
+        get_comments, make_comments_section, post_approve_comment, post_comment,
+        post_delete_comment, Comment,
+    };
+    pub use super::contact::{get_contact, get_messages, post_contact, Message};
+    pub use super::csrf::{check_csrf, csrf_field, csrf_token};
+    pub use super::cv::{get_cv, get_cv_pdf, Cv, CV_STYLE, CV_STYLE_NAME};
+    pub use super::editor::{get_edit_post, post_edit_post, post_preview, post_upload_photos};
+    pub use super::error::{get_not_found, make_error, make_not_found};
+    pub use super::feed::{get_photos_feed, get_posts_feed};
     pub use super::file::{
-        get_asset as get_file_asset, get_file as get_file_file, get_style as get_file_style, File,
+        file_headers, get_asset as get_file_asset, get_file as get_file_file, get_files,
+        get_script as get_file_script, get_style as get_file_style, ranged_response, version_files,
+        versioned_url, File, FileVersions,
     };
+    pub use super::hook::post_github_hook;
     pub use super::index::get_index;
-    pub use super::page::make_page;
-    pub use super::photo::{get_photo, get_photos, Photo};
-    pub use super::post::{get_post, get_posts, make_posts_table, Post};
-    pub use super::project::get_projects;
-    pub use super::user::{get_login, post_login, post_logout, User};
+    pub use super::limits::apply_limits;
+    pub use super::lite::{href_with_param, lite_href, remember_lite, Lite};
+    pub use super::login_link::{
+        get_login_link, make_login_links, post_login_link, post_mint_login_link, LoginLink,
+    };
+    pub use super::markdown::{
+        captioned_photo_names, expand_includes, filter_photo_shortcodes, markdown_excerpt,
+        markdown_images, markdown_links, markdown_to_html, markdown_to_text, photo_shortcode_names,
+        render_diagrams, MarkdownContext,
+    };
+    pub use super::micropub::{get_micropub, post_micropub};
+    pub use super::page::{make_page, set_links, PageMeta, Section};
+    pub use super::page_cache::{cache_pages, PageCache};
+    pub use super::pdf::{pdf_response, PdfCache};
+    pub use super::photo::{
+        get_photo, get_photos, get_photos_map, Photo, PHOTO_MAP_SCRIPT, PHOTO_MAP_SCRIPT_NAME,
+    };
+    pub use super::poll::{
+        make_polls, poll_shortcode_id, poll_shortcode_ids, post_poll_vote, Poll,
+    };
+    pub use super::post::{
+        get_post, get_post_markdown, get_post_pdf, get_post_qr, get_post_text, get_posts,
+        make_featured_table, make_posts_table, Post, PROGRESS_SCRIPT, PROGRESS_SCRIPT_NAME,
+    };
+    pub use super::project::{get_projects, Project, PROJECTS_STYLE, PROJECTS_STYLE_NAME};
+    pub use super::proxy::Client;
+    pub use super::rebuild::{post_rebuild, start_rebuild, RebuildStatus};
+    pub use super::renderer::{find_content, is_markdown, ContentRenderer, Rendered};
+    pub use super::role::{Permission, RequireAdmin, RequireModerator, Role};
+    pub use super::session::{Session, SESSION_COOKIE};
+    pub use super::shortlink::{
+        get_shortlink, get_shortlinks, post_delete_shortlink, post_shortlink, Shortlink,
+    };
+    pub use super::static_page::{get_now, get_page, StaticPage, NOW_SLUG};
+    pub use super::stats::get_stats;
+    pub use super::theme::{remember_theme, Theme, THEME_STYLE, THEME_STYLE_NAME};
+    pub use super::token::{api_error, ApiToken, Bearer};
+    pub use super::tombstone::Tombstone;
+    pub use super::track::{
+        Track, LEAFLET_SCRIPT_NAME, LEAFLET_STYLE_NAME, TRACK_SCRIPT, TRACK_SCRIPT_NAME,
+    };
+    pub use super::trailing_slash::{normalize_trailing_slash, strip_trailing_slash};
+    pub use super::user::{
+        get_login, post_login, post_logout, post_reset_feeds, FailedLogins, User,
+    };
 }
@@ -0,0 +1,236 @@
+use base64::Engine;
+use sha2::{Digest, Sha256};
+
+use crate::component::markdown::code_block;
+use crate::component::renderer::{ContentRenderer, Rendered, RenderedAsset};
+use crate::prelude::*;
+
+// A Jupyter notebook, e.g. `index.ipynb`: markdown cells stay as they are, code cells become code
+// blocks followed by their outputs. Images in outputs and cell attachments are stored as post
+// assets, like rendered diagrams.
+pub struct Notebook;
+
+#[derive(Deserialize)]
+struct NotebookFile {
+    cells: Vec<Cell>,
+    #[serde(default)]
+    metadata: NotebookMetadata,
+}
+
+#[derive(Deserialize, Default)]
+struct NotebookMetadata {
+    #[serde(default)]
+    kernelspec: Option<Kernelspec>,
+    #[serde(default)]
+    language_info: Option<LanguageInfo>,
+}
+
+#[derive(Deserialize)]
+struct Kernelspec {
+    #[serde(default)]
+    language: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct LanguageInfo {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct Cell {
+    cell_type: String,
+    source: Text,
+    #[serde(default)]
+    outputs: Vec<Output>,
+    #[serde(default)]
+    attachments: HashMap<String, HashMap<String, Text>>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "output_type", rename_all = "snake_case")]
+enum Output {
+    Stream {
+        text: Text,
+    },
+    ExecuteResult {
+        data: HashMap<String, Text>,
+    },
+    DisplayData {
+        data: HashMap<String, Text>,
+    },
+    Error {
+        ename: String,
+        evalue: String,
+        #[serde(default)]
+        traceback: Vec<String>,
+    },
+}
+
+// notebooks store text either whole or as a list of lines
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Text {
+    Whole(String),
+    Lines(Vec<String>),
+}
+
+impl Text {
+    fn joined(&self) -> String {
+        match self {
+            Text::Whole(text) => text.clone(),
+            Text::Lines(lines) => lines.concat(),
+        }
+    }
+}
+
+// the richest representation a post can show, in order of preference
+const IMAGE_TYPES: &[(&str, &str)] = &[
+    ("image/svg+xml", "svg"),
+    ("image/png", "png"),
+    ("image/jpeg", "jpg"),
+    ("image/gif", "gif"),
+];
+
+impl ContentRenderer for Notebook {
+    fn to_markdown(&self, source: &str, assets_path: &str) -> Result<Rendered, Error> {
+        let notebook: NotebookFile = serde_json::from_str(source)
+            .context("failed to decode notebook")
+            .map_err(|error| error.with_kind(ErrorKind::Validation))?;
+
+        let language = notebook
+            .metadata
+            .language_info
+            .map(|info| info.name)
+            .or(notebook
+                .metadata
+                .kernelspec
+                .and_then(|kernelspec| kernelspec.language))
+            .unwrap_or_default();
+
+        let mut converted = Converter {
+            rendered: Rendered::markdown(String::new()),
+        };
+
+        for cell in &notebook.cells {
+            let source = cell.source.joined();
+            match cell.cell_type.as_str() {
+                "markdown" => {
+                    let mut source = source;
+                    for (name, data) in &cell.attachments {
+                        if let Some(url) = converted.add_image(data, assets_path)? {
+                            source = source.replace(&format!("attachment:{}", name), &url);
+                        }
+                    }
+                    converted.push(&source);
+                }
+                "code" => {
+                    if source.trim().is_empty() {
+                        continue;
+                    }
+                    converted.push(&code_block(&language, &source));
+                    for output in &cell.outputs {
+                        converted.push_output(output, assets_path)?;
+                    }
+                }
+                // raw cells are meant for other exporters
+                _ => {}
+            }
+        }
+
+        Ok(converted.rendered)
+    }
+}
+
+// the markdown so far, with the images it links to
+struct Converter {
+    rendered: Rendered,
+}
+
+impl Converter {
+    fn push(&mut self, markdown: &str) {
+        self.rendered.markdown.push_str(markdown.trim_end());
+        self.rendered.markdown.push_str("\n\n");
+    }
+
+    fn push_output(&mut self, output: &Output, assets_path: &str) -> Result<(), Error> {
+        match output {
+            Output::Stream { text } => self.push(&code_block("text", &text.joined())),
+            Output::ExecuteResult { data } | Output::DisplayData { data } => {
+                if let Some(url) = self.add_image(data, assets_path)? {
+                    self.push(&format!("![output]({})", url));
+                } else if let Some(markdown) = data.get("text/markdown") {
+                    self.push(&markdown.joined());
+                } else if let Some(text) = data.get("text/plain") {
+                    self.push(&code_block("text", &text.joined()));
+                }
+            }
+            Output::Error {
+                ename,
+                evalue,
+                traceback,
+            } => {
+                let text = match traceback.is_empty() {
+                    true => format!("{}: {}", ename, evalue),
+                    false => strip_ansi(&traceback.join("\n")),
+                };
+                self.push(&code_block("text", &text));
+            }
+        }
+        Ok(())
+    }
+
+    // stores the first image in `data`, named by its content so a rebuild keeps the urls
+    fn add_image(
+        &mut self,
+        data: &HashMap<String, Text>,
+        assets_path: &str,
+    ) -> Result<Option<String>, Error> {
+        let Some((mime, extension)) = IMAGE_TYPES
+            .iter()
+            .find(|(mime, _)| data.contains_key(*mime))
+        else {
+            return Ok(None);
+        };
+
+        let text = data[*mime].joined();
+        let bytes = match *mime {
+            "image/svg+xml" => text.into_bytes(),
+            _ => base64::engine::general_purpose::STANDARD
+                .decode(text.split_whitespace().collect::<String>())
+                .context("invalid image in notebook")
+                .map_err(|error| error.with_kind(ErrorKind::Validation))?,
+        };
+
+        let name = format!(
+            "notebook-{}.{}",
+            &hex::encode(Sha256::digest(&bytes))[..16],
+            extension
+        );
+        let url = format!("{}/{}", assets_path.trim_end_matches('/'), name);
+        if !self.rendered.assets.iter().any(|asset| asset.name == name) {
+            self.rendered
+                .assets
+                .push(RenderedAsset { name, data: bytes });
+        }
+        Ok(Some(url))
+    }
+}
+
+// tracebacks are colored for terminals
+fn strip_ansi(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\u{1b}' {
+            // an escape sequence ends with its first letter
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+            continue;
+        }
+        output.push(c);
+    }
+    output
+}
@@ -0,0 +1,176 @@
+use crate::component::markdown::code_block;
+use crate::component::renderer::{ContentRenderer, InlineMarkup, Piece, Rendered};
+use crate::prelude::*;
+
+const INLINE: InlineMarkup = InlineMarkup {
+    code: &['=', '~'],
+    emphasis: &[('*', "**"), ('/', "*"), ('_', "*")],
+    links,
+};
+
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "svg", "webp"];
+
+// Org-mode notes, e.g. `index.org`. Covers what notes are written with: headlines, lists, emphasis,
+// links, source, example and quote blocks. Keywords like `#+TITLE`, comments and property drawers
+// are left out, the metadata of the post is in meta.json.
+pub struct Org;
+
+impl ContentRenderer for Org {
+    fn to_markdown(&self, source: &str, _assets_path: &str) -> Result<Rendered, Error> {
+        let mut output = String::with_capacity(source.len());
+        let mut lines = source.lines();
+
+        while let Some(line) = lines.next() {
+            let trimmed = line.trim();
+            let keyword = trimmed.to_ascii_lowercase();
+
+            if let Some(block) = keyword.strip_prefix("#+begin_") {
+                let name = block
+                    .split_whitespace()
+                    .next()
+                    .unwrap_or_default()
+                    .to_string();
+                let end = format!("#+end_{}", name);
+                let mut contents = String::new();
+                for line in lines.by_ref() {
+                    if line.trim().eq_ignore_ascii_case(&end) {
+                        break;
+                    }
+                    contents.push_str(line);
+                    contents.push('\n');
+                }
+
+                match name.as_str() {
+                    "src" => {
+                        let language = trimmed.split_whitespace().nth(1).unwrap_or_default();
+                        output.push_str(&code_block(language, &dedent(&contents)));
+                    }
+                    "example" => output.push_str(&code_block("", &dedent(&contents))),
+                    "quote" => {
+                        for line in contents.lines() {
+                            output.push_str(&format!("> {}\n", INLINE.convert(line.trim())));
+                        }
+                    }
+                    "comment" => {}
+                    _ => output.push_str(&contents),
+                }
+                output.push('\n');
+                continue;
+            }
+
+            if trimmed.eq_ignore_ascii_case(":properties:")
+                || trimmed.eq_ignore_ascii_case(":logbook:")
+            {
+                for line in lines.by_ref() {
+                    if line.trim().eq_ignore_ascii_case(":end:") {
+                        break;
+                    }
+                }
+                continue;
+            }
+
+            // keywords and comments
+            if trimmed.starts_with("#+") || trimmed == "#" || trimmed.starts_with("# ") {
+                continue;
+            }
+
+            let stars = line.len() - line.trim_start_matches('*').len();
+            if stars > 0 && line[stars..].starts_with(' ') {
+                // the title of the post is the only `#`, top-level headlines are its sections
+                let title = strip_tags(line[stars..].trim());
+                output.push_str(&format!(
+                    "{} {}\n\n",
+                    "#".repeat((stars + 1).min(6)),
+                    INLINE.convert(title)
+                ));
+                continue;
+            }
+
+            let indent = &line[..line.len() - line.trim_start().len()];
+            if let Some(item) = trimmed.strip_prefix("+ ") {
+                output.push_str(&format!("{}- {}\n", indent, INLINE.convert(item)));
+                continue;
+            }
+
+            // `1)` items are `1.` in markdown
+            if let Some((number, item)) = trimmed.split_once(") ")
+                && !number.is_empty()
+                && number.chars().all(|c| c.is_ascii_digit())
+            {
+                output.push_str(&format!("{}{}. {}\n", indent, number, INLINE.convert(item)));
+                continue;
+            }
+
+            output.push_str(indent);
+            output.push_str(&INLINE.convert(trimmed));
+            output.push('\n');
+        }
+
+        Ok(Rendered::markdown(output))
+    }
+}
+
+// headline tags like `:work:notes:` have no place in markdown
+fn strip_tags(title: &str) -> &str {
+    match title.rsplit_once(' ') {
+        Some((rest, tags)) if tags.len() > 2 && tags.starts_with(':') && tags.ends_with(':') => {
+            rest.trim_end()
+        }
+        _ => title,
+    }
+}
+
+// blocks are usually indented with their headline
+fn dedent(text: &str) -> String {
+    let indent = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    text.lines()
+        .map(|line| line.get(indent..).unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// `[[target][description]]` and `[[target]]`, links to images without a description become
+// images. Photo shortcodes (`![[photo:...]]`) are left to the markdown renderer.
+fn links(text: &str) -> Vec<Piece> {
+    let mut pieces = vec![];
+    let mut rest = text;
+
+    while let Some(start) = rest.find("[[") {
+        let Some(end) = rest[start..].find("]]").map(|end| start + end) else {
+            break;
+        };
+        if rest[..start].ends_with('!') {
+            pieces.push(Piece::Text(rest[..start - 1].to_string()));
+            pieces.push(Piece::Done(rest[start - 1..end + 2].to_string()));
+            rest = &rest[end + 2..];
+            continue;
+        }
+
+        pieces.push(Piece::Text(rest[..start].to_string()));
+        let link = &rest[start + 2..end];
+        let (target, description) = match link.split_once("][") {
+            Some((target, description)) => (target, Some(description)),
+            None => (link, None),
+        };
+        let target = target.strip_prefix("file:").unwrap_or(target);
+
+        let is_image = Path::new(target)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| IMAGE_EXTENSIONS.contains(&extension.to_lowercase().as_str()));
+        pieces.push(Piece::Done(match description {
+            None if is_image => format!("![]({})", target),
+            None => format!("[{}]({})", target, target),
+            Some(description) => format!("[{}]({})", description, target),
+        }));
+        rest = &rest[end + 2..];
+    }
+
+    pieces.push(Piece::Text(rest.to_string()));
+    pieces
+}
@@ -1,10 +1,151 @@
+use std::sync::OnceLock;
+
 use maud::{Markup, PreEscaped, DOCTYPE};
 
 use crate::prelude::*;
 
+static LINKS: OnceLock<Vec<LinkConfig>> = OnceLock::new();
+
+pub fn set_links(links: Vec<LinkConfig>) {
+    let _ = LINKS.set(links);
+}
+
+fn links() -> &'static [LinkConfig] {
+    LINKS
+        .get()
+        .map(|links| links.as_slice())
+        .unwrap_or_default()
+}
+
+const SITE_NAME: &str = "Kai";
+const SITE_DESCRIPTION: &str = "Kai's personal website.";
+pub const MAX_DESCRIPTION_LENGTH: usize = 160;
+
+// the only styling lite pages get, inlined to save a request
+const LITE_STYLE: &str = "body{max-width:40em;margin:auto;padding:0 1em;font-family:sans-serif;line-height:1.5}nav,footer{display:flex;flex-wrap:wrap;gap:1em}pre{overflow-x:auto}";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Section {
+    None,
+    Posts,
+    Projects,
+    Photos,
+    Login,
+    Admin,
+}
+
+impl Section {
+    fn name(self) -> Option<&'static str> {
+        match self {
+            Section::None => None,
+            Section::Posts => Some("Posts"),
+            Section::Projects => Some("Projects"),
+            Section::Photos => Some("Photos"),
+            Section::Login => Some("Login"),
+            Section::Admin => Some("Admin"),
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            Section::None => SITE_DESCRIPTION,
+            Section::Posts => "A list of all posts.",
+            Section::Projects => "A list of all projects.",
+            Section::Photos => "A gallery of all photos.",
+            Section::Login => "Login page.",
+            Section::Admin => "Site administration.",
+        }
+    }
+}
+
+pub struct PageMeta {
+    section: Section,
+    title: Option<String>,
+    description: Option<String>,
+    canonical: Option<String>,
+    scripts: Vec<String>,
+    lite: bool,
+}
+
+impl PageMeta {
+    pub fn new(section: Section) -> Self {
+        Self {
+            section,
+            title: None,
+            description: None,
+            canonical: None,
+            scripts: vec![],
+            lite: false,
+        }
+    }
+
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        let description = description.into();
+        if !description.trim().is_empty() {
+            self.description = Some(description);
+        }
+        self
+    }
+
+    pub fn canonical(mut self, url: impl Into<String>) -> Self {
+        self.canonical = Some(url.into());
+        self
+    }
+
+    pub fn scripts(mut self, scripts: Vec<String>) -> Self {
+        self.scripts = scripts;
+        self
+    }
+
+    pub fn lite(mut self, lite: Lite) -> Self {
+        self.lite = lite.0;
+        self
+    }
+
+    // e.g. "Kai - Posts - Some post", skipping the section if it is the page itself
+    fn full_title(&self) -> String {
+        let mut parts = vec![SITE_NAME];
+        if let Some(name) = self.section.name()
+            && self.title.as_deref() != Some(name)
+        {
+            parts.push(name);
+        }
+        if let Some(title) = &self.title {
+            parts.push(title);
+        }
+        parts.join(" - ")
+    }
+
+    fn full_description(&self) -> String {
+        let description = self
+            .description
+            .as_deref()
+            .unwrap_or(self.section.description())
+            .trim();
+
+        if description.chars().count() <= MAX_DESCRIPTION_LENGTH {
+            return description.to_string();
+        }
+
+        let truncated: String = description
+            .chars()
+            .take(MAX_DESCRIPTION_LENGTH - 1)
+            .collect();
+        let truncated = match truncated.rfind(char::is_whitespace) {
+            Some(index) => &truncated[..index],
+            None => &truncated,
+        };
+        format!("{}…", truncated.trim_end())
+    }
+}
+
 pub fn make_page(
-    title: Option<&str>,
-    description: &str,
+    meta: PageMeta,
     additional_styles: Vec<&str>,
     content: impl Into<String>,
     user: Option<User>,
@@ -12,48 +153,64 @@ pub fn make_page(
 ) -> Markup {
     html! {
         (DOCTYPE)
-        html {
+        html class=[Theme::current().class()] {
             head {
-                @if let Some(title) = title {
-                    title { "Kai - " (title) }
-                } @else {
-                    title { "Kai" }
+                title { (meta.full_title()) }
+                meta name="description" content=(meta.full_description()) {}
+                @if let Some(canonical) = &meta.canonical {
+                    link rel="canonical" href=(canonical) {}
                 }
-                meta name="description" content=(description) {}
                 meta name="viewport" content="width=device-width, initial-scale=1" {}
-                link rel="icon" href="/assets/logo.jpg" {}
-                link rel="stylesheet" href="/styles/page.css" {}
-                @for additional_style in additional_styles {
-                    link rel="stylesheet" href=(additional_style) {}
+                @if meta.lite {
+                    style { (PreEscaped(LITE_STYLE)) }
+                } @else {
+                    link rel="icon" href=(versioned_url("/assets/logo.jpg")) {}
+                    link rel="stylesheet" href=(versioned_url("/styles/page.css")) {}
+                    link rel="stylesheet" href=(versioned_url(&routes::style(THEME_STYLE_NAME))) {}
+                    @for additional_style in additional_styles {
+                        link rel="stylesheet" href=(versioned_url(additional_style)) {}
+                    }
+                    @for script in &meta.scripts {
+                        script src=(versioned_url(script)) defer {}
+                    }
+                }
+                @for link in links().iter().filter(|link| link.rel_me) {
+                    link rel="me" href=(link.href) {}
                 }
             }
 
             body {
                 nav {
-                    a href="/" id="nav-left" {
-                        img src="/assets/logo.jpg" alt = "logo" {}
+                    a href=(routes::INDEX) id="nav-left" {
+                        @if !meta.lite {
+                            img src=(versioned_url("/assets/logo.jpg")) alt = "logo" {}
+                        }
                         div {
                             div { "Kai" }
                             div { "Kitagawa-Jones"}
                         }
                     }
                     div id="nav-right" {
-                        a href="/posts/" { "Posts" }
-                        a href="/projects/" { "Projects" }
-                        a href="/photos/" { "Photos" }
+                        (nav_link(routes::POSTS, "Posts", meta.section == Section::Posts))
+                        (nav_link(routes::PROJECTS, "Projects", meta.section == Section::Projects))
+                        (nav_link(routes::PHOTOS, "Photos", meta.section == Section::Photos))
+                        @if user.as_ref().is_some_and(User::is_admin) {
+                            (nav_link(routes::ADMIN, "Admin", meta.section == Section::Admin))
+                        }
                         @if !hide_user {
                             @if user.is_some() {
-                                form action="/logout/" method="post" {
+                                form action=(routes::LOGOUT) method="post" {
+                                    (csrf_field())
                                     input type="submit" value="Logout" {}
                                 }
                             } @else {
-                                a href="/login/" { "Login" }
+                                (nav_link(routes::LOGIN, "Login", meta.section == Section::Login))
                             }
                         }
                     }
                 }
 
-                @if let Some(title) = title {
+                @if let Some(title) = &meta.title {
                     header { h1 { (title) } }
                 }
 
@@ -62,20 +219,37 @@ pub fn make_page(
                 }
 
                 footer {
-                    div {
-                        img class="icon" src="/assets/github.svg" alt="github" {}
-                        a href="https://github.com/kai-kj" { "kai-kj" }
-                    }
-                    div {
-                        img class="icon" src="/assets/linkedin.svg" alt="linkedin" {}
-                        a href="https://linkedin.com/in/kaikitagawajones/" { "Kai Kitagawa-Jones" }
+                    @for link in links() {
+                        div {
+                            @if let Some(icon) = link.icon.as_ref().filter(|_| !meta.lite) {
+                                img class="icon" src=(versioned_url(&routes::asset(icon))) alt=(icon_name(icon)) {}
+                            }
+                            a href=(link.href) rel=[link.rel_me.then_some("me")] { (link.text) }
+                        }
                     }
                     div {
-                        img class="icon" src="/assets/mail.svg" alt="mail" {}
-                        a href="mailto:kaikitagawajones@gmail.com" { "kaikitagawajones@gmail.com" }
+                        @if meta.lite {
+                            a href=(lite_href(false)) { "Full version" }
+                        } @else {
+                            (Theme::switcher())
+                            a href=(lite_href(true)) { "Text-only version" }
+                        }
                     }
                 }
             }
         }
     }
 }
+
+fn nav_link(href: &str, text: &str, active: bool) -> Markup {
+    html! {
+        a href=(href) class=[active.then_some("active")] aria-current=[active.then_some("page")] { (text) }
+    }
+}
+
+fn icon_name(icon: &str) -> &str {
+    Path::new(icon)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or(icon)
+}
@@ -0,0 +1,128 @@
+use std::convert::Infallible;
+use std::time::{Duration, Instant};
+
+use axum::body::Body;
+use axum::extract::{FromRequestParts, Request};
+use axum::middleware::Next;
+use axum::response::Response;
+
+use crate::prelude::*;
+
+// scheduled and expiring posts change the pages without a write to the database
+const MAX_AGE: Duration = Duration::from_secs(60);
+// random queries shouldn't be able to fill the memory
+const MAX_PAGES: usize = 256;
+// stands in for the csrf token of whoever the page was rendered for
+const TOKEN_PLACEHOLDER: &str = "{{csrf-token}}";
+
+type CacheKey = (String, bool, Theme);
+
+struct CachedPage {
+    version: (i64, u64),
+    at: Instant,
+    content_type: axum::http::HeaderValue,
+    body: String,
+}
+
+// Rendered pages for anonymous readers, who all see the same thing, by url, lite mode and theme. A page
+// is rendered again once anything was written to the database, e.g. by a rebuild or an approved
+// comment, or when it gets too old.
+#[derive(Default)]
+pub struct PageCache(Mutex<HashMap<CacheKey, CachedPage>>);
+
+impl PageCache {
+    fn get(&self, key: &CacheKey, version: (i64, u64)) -> Option<ax::Response> {
+        let pages = self.0.lock().unwrap();
+        let page = pages
+            .get(key)
+            .filter(|page| page.version == version && page.at.elapsed() < MAX_AGE)?;
+
+        let body = page.body.replace(TOKEN_PLACEHOLDER, &csrf_token());
+        Some(
+            (
+                [(ax::header::CONTENT_TYPE, page.content_type.clone())],
+                body,
+            )
+                .into_response(),
+        )
+    }
+
+    fn insert(&self, key: CacheKey, page: CachedPage) {
+        let mut pages = self.0.lock().unwrap();
+        pages.retain(|_, cached| cached.version == page.version && cached.at.elapsed() < MAX_AGE);
+        if pages.len() < MAX_PAGES {
+            pages.insert(key, page);
+        }
+    }
+}
+
+// the index, the post list and posts, with or without the trailing slash
+fn is_cacheable(path: &str) -> bool {
+    match path.trim_end_matches('/') {
+        "" | "/posts" => true,
+        path => path
+            .strip_prefix("/posts/")
+            .is_some_and(|id| !id.contains(['/', '.'])),
+    }
+}
+
+pub async fn cache_pages(
+    ax::State(state): ax::State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let is_anonymous = ax::CookieJar::from_headers(request.headers())
+        .get(SESSION_COOKIE)
+        .is_none();
+    if request.method() != ax::Method::GET || !is_anonymous || !is_cacheable(request.uri().path()) {
+        return next.run(request).await;
+    }
+
+    let (mut parts, body) = request.into_parts();
+    let Ok::<_, Infallible>(Lite(lite)) = Lite::from_request_parts(&mut parts, &()).await;
+    let Ok::<_, Infallible>(theme) = Theme::from_request_parts(&mut parts, &()).await;
+    let key = (parts.uri.to_string(), lite, theme);
+    let request = Request::from_parts(parts, body);
+
+    let Some(version) = state.lock_db().await.ok().and_then(|db| db.version().ok()) else {
+        return next.run(request).await;
+    };
+    if let Some(response) = state.page_cache.get(&key, version) {
+        println!("GET {} from page cache", key.0);
+        return response;
+    }
+
+    let response = next.run(request).await;
+    let content_type = match response.headers().get(ax::header::CONTENT_TYPE) {
+        Some(content_type)
+            if response.status() == ax::StatusCode::OK
+                && !response.headers().contains_key(ax::header::SET_COOKIE) =>
+        {
+            content_type.clone()
+        }
+        _ => return response,
+    };
+
+    let (parts, body) = response.into_parts();
+    let Ok(bytes) = axum::body::to_bytes(body, usize::MAX).await else {
+        return make_error(500, "Failed to render page").into_response();
+    };
+
+    let token = csrf_token();
+    let body = String::from_utf8_lossy(&bytes);
+    let body = match token.is_empty() {
+        true => body.into_owned(),
+        false => body.replace(&token, TOKEN_PLACEHOLDER),
+    };
+    state.page_cache.insert(
+        key,
+        CachedPage {
+            version,
+            at: Instant::now(),
+            content_type,
+            body,
+        },
+    );
+
+    Response::from_parts(parts, Body::from(bytes))
+}
@@ -0,0 +1,267 @@
+use pdf_writer::{Content, Finish, Name, Pdf, Rect, Ref, Str, TextStr};
+use sha2::{Digest, Sha256};
+
+use crate::prelude::*;
+
+// A4 in points, with margins of about 2cm
+const PAGE_WIDTH: f32 = 595.0;
+const PAGE_HEIGHT: f32 = 842.0;
+const MARGIN: f32 = 56.0;
+
+const TITLE_SIZE: f32 = 20.0;
+const BODY_SIZE: f32 = 11.0;
+const LINE_HEIGHT: f32 = 1.4;
+
+const REGULAR_FONT: Name = Name(b"F1");
+const BOLD_FONT: Name = Name(b"F2");
+
+// Advance widths of Helvetica for the printable ASCII characters, in thousandths of the font
+// size. The PDFs only use the fonts every reader has built in, so nothing has to be embedded, at
+// the price of only covering the Windows-1252 characters.
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556,
+    556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667,
+    611, 778, 722, 278, 500, 667, 556, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667,
+    667, 611, 278, 278, 278, 469, 556, 333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500,
+    222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+
+// PDFs are rendered on the first request and kept until the next build. The key covers everything
+// that ends up in the document, so readers who may see different photos get different PDFs.
+pub struct PdfCache;
+
+impl PdfCache {
+    pub fn setup(db: &Database) -> Result<(), Error> {
+        db.execute_batch(
+            r#"
+                CREATE TABLE IF NOT EXISTS pdf_cache (
+                    key TEXT PRIMARY KEY NOT NULL,
+                    data BLOB NOT NULL
+                );
+            "#,
+        )
+        .context("failed to create pdf_cache table")
+    }
+
+    fn get(db: &Database, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        Ok(db
+            .query_mul("SELECT data FROM pdf_cache WHERE key = ?;", [key], |row| {
+                row.get(0)
+            })
+            .context("failed to query pdf from database")?
+            .pop())
+    }
+
+    fn insert(db: &Database, key: &str, data: &[u8]) -> Result<(), Error> {
+        db.execute(
+            "INSERT OR IGNORE INTO pdf_cache (key, data) VALUES (?, ?);",
+            (key, data),
+        )
+        .context("failed to insert pdf into database")
+    }
+
+    pub fn delete_all(db: &Database) -> Result<(), Error> {
+        db.execute("DELETE FROM pdf_cache", [])
+            .context("failed to delete all pdfs from database")
+    }
+}
+
+// The PDF of a plain text document, from the cache if it was rendered before. A new one is
+// stored in the background, the reader doesn't wait for the writer.
+pub fn pdf_response(
+    state: &Arc<AppState>,
+    db: &Database,
+    file_name: &str,
+    title: &str,
+    subtitle: &str,
+    text: &str,
+) -> ax::Response {
+    let key = hex::encode(Sha256::digest(
+        [title, subtitle, text].join("\0").as_bytes(),
+    ));
+
+    let data = match PdfCache::get(db, &key) {
+        Ok(Some(data)) => data,
+        Ok(None) => {
+            let data = render_pdf(title, subtitle, text);
+            let state = state.clone();
+            let stored = data.clone();
+            tokio::spawn(async move {
+                if let Ok(db) = state.lock_writer().await
+                    && let Err(error) = PdfCache::insert(&db, &key, &stored)
+                {
+                    println!("warning: {}", error.message());
+                }
+            });
+            data
+        }
+        Err(_) => return make_error(500, "Failed to load pdf").into_response(),
+    };
+
+    (
+        [
+            (ax::header::CONTENT_TYPE, "application/pdf".to_string()),
+            (
+                ax::header::CONTENT_DISPOSITION,
+                format!("inline; filename=\"{}.pdf\"", file_name),
+            ),
+        ],
+        data,
+    )
+        .into_response()
+}
+
+struct Line {
+    font: Name<'static>,
+    size: f32,
+    text: Vec<u8>,
+}
+
+// A4 pages with the title in bold, then the text. Lines longer than the page are wrapped at
+// spaces, empty lines are kept, so `markdown_to_text` output keeps its paragraphs.
+pub fn render_pdf(title: &str, subtitle: &str, text: &str) -> Vec<u8> {
+    let width = PAGE_WIDTH - 2.0 * MARGIN;
+    let mut lines = vec![];
+    for line in wrap(title, TITLE_SIZE, width) {
+        lines.push(Line {
+            font: BOLD_FONT,
+            size: TITLE_SIZE,
+            text: line,
+        });
+    }
+    for line in subtitle.lines().chain(["", ""]) {
+        lines.push(Line {
+            font: REGULAR_FONT,
+            size: BODY_SIZE,
+            text: encode(line),
+        });
+    }
+    for line in text.lines() {
+        for wrapped in wrap(line, BODY_SIZE, width) {
+            lines.push(Line {
+                font: REGULAR_FONT,
+                size: BODY_SIZE,
+                text: wrapped,
+            });
+        }
+    }
+
+    let mut pages = vec![];
+    let mut content = Content::new();
+    let mut y = PAGE_HEIGHT - MARGIN;
+    for line in lines {
+        let height = line.size * LINE_HEIGHT;
+        if y - height < MARGIN {
+            pages.push(content.finish());
+            content = Content::new();
+            y = PAGE_HEIGHT - MARGIN;
+        }
+        y -= height;
+        if !line.text.is_empty() {
+            content.begin_text();
+            content.set_font(line.font, line.size);
+            content.next_line(MARGIN, y);
+            content.show(Str(&line.text));
+            content.end_text();
+        }
+    }
+    pages.push(content.finish());
+
+    let catalog_id = Ref::new(1);
+    let tree_id = Ref::new(2);
+    let regular_id = Ref::new(3);
+    let bold_id = Ref::new(4);
+    let info_id = Ref::new(5);
+    let page_ids = (0..pages.len())
+        .map(|i| Ref::new(6 + 2 * i as i32))
+        .collect::<Vec<_>>();
+
+    let mut pdf = Pdf::new();
+    pdf.catalog(catalog_id).pages(tree_id);
+    pdf.pages(tree_id)
+        .kids(page_ids.iter().copied())
+        .count(pages.len() as i32);
+    pdf.type1_font(regular_id)
+        .base_font(Name(b"Helvetica"))
+        .encoding_predefined(Name(b"WinAnsiEncoding"));
+    pdf.type1_font(bold_id)
+        .base_font(Name(b"Helvetica-Bold"))
+        .encoding_predefined(Name(b"WinAnsiEncoding"));
+    pdf.document_info(info_id).title(TextStr(title));
+
+    for (page_id, data) in page_ids.iter().zip(&pages) {
+        let content_id = Ref::new(page_id.get() + 1);
+        let mut page = pdf.page(*page_id);
+        page.media_box(Rect::new(0.0, 0.0, PAGE_WIDTH, PAGE_HEIGHT));
+        page.parent(tree_id);
+        page.contents(content_id);
+        let mut resources = page.resources();
+        resources
+            .fonts()
+            .pair(REGULAR_FONT, regular_id)
+            .pair(BOLD_FONT, bold_id);
+        resources.finish();
+        page.finish();
+        pdf.stream(content_id, data);
+    }
+
+    pdf.finish()
+}
+
+// Windows-1252, which is what the built-in fonts can show. Latin-1 maps to itself, the rest
+// becomes a question mark.
+fn encode(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| match c {
+            '\t' => b' ',
+            '€' => 0x80,
+            '…' => 0x85,
+            '‘' => 0x91,
+            '’' => 0x92,
+            '“' => 0x93,
+            '”' => 0x94,
+            '•' => 0x95,
+            '–' => 0x96,
+            '—' => 0x97,
+            ' '..='~' | '\u{a0}'..='\u{ff}' => c as u8,
+            _ => b'?',
+        })
+        .collect()
+}
+
+fn char_width(c: u8, size: f32) -> f32 {
+    let width = match c {
+        b' '..=b'~' => HELVETICA_WIDTHS[(c - b' ') as usize],
+        _ => 556,
+    };
+    // bold is a little wider, wrapping a bit early is better than running off the page
+    width as f32 * size * 1.1 / 1000.0
+}
+
+fn wrap(line: &str, size: f32, width: f32) -> Vec<Vec<u8>> {
+    let line = encode(line.trim_end());
+    let mut lines = vec![];
+    let mut current: Vec<u8> = vec![];
+    let mut current_width = 0.0;
+
+    for word in line.split_inclusive(|c| *c == b' ') {
+        let word_width = word.iter().map(|c| char_width(*c, size)).sum::<f32>();
+        if current_width + word_width > width && !current.is_empty() {
+            lines.push(current.trim_ascii_end().to_vec());
+            current = vec![];
+            current_width = 0.0;
+        }
+        // a single word longer than the line is cut
+        for c in word {
+            let c_width = char_width(*c, size);
+            if current_width + c_width > width && !current.is_empty() {
+                lines.push(std::mem::take(&mut current));
+                current_width = 0.0;
+            }
+            current.push(*c);
+            current_width += c_width;
+        }
+    }
+    lines.push(current.trim_ascii_end().to_vec());
+    lines
+}
@@ -1,10 +1,15 @@
 use std::hash::{Hash, Hasher};
 
+use crate::crypto;
 use crate::database::SqliteError;
 use crate::prelude::*;
+use exif::{In, Tag, Value};
 use image::codecs::jpeg::JpegEncoder;
 use image::ImageReader;
 
+pub const PHOTO_MAP_SCRIPT_NAME: &str = "photo-map.js";
+pub const PHOTO_MAP_SCRIPT: &[u8] = include_bytes!("photo_map.js");
+
 #[allow(dead_code)]
 pub struct Photo {
     pub id: String,
@@ -12,6 +17,8 @@ pub struct Photo {
     pub is_private: bool,
     pub source_path: String,
     pub source_time: i64,
+    pub allowed_group: Option<String>,
+    pub is_encrypted: bool,
 }
 
 impl Photo {
@@ -24,8 +31,10 @@ impl Photo {
                     is_private BOOLEAN NOT NULL,
                     source_path TEXT NOT NULL UNIQUE,
                     source_time INTEGER NOT NULL,
-                    image_large_jpg BLOB NOT NULL,
-                    image_small_jpg BLOB NOT NULL
+                    allowed_group TEXT NULL,
+                    is_encrypted BOOLEAN NOT NULL DEFAULT FALSE,
+                    image_large_hash TEXT NOT NULL,
+                    image_small_hash TEXT NOT NULL
                 );
 
                 CREATE TABLE IF NOT EXISTS posts_photos (
@@ -39,7 +48,19 @@ impl Photo {
                 CREATE INDEX IF NOT EXISTS photos_source_path_index ON photos (source_path);
             "#,
         )
-        .context("failed to create photos table")
+        .context("failed to create photos table")?;
+
+        db.ensure_column("photos", "allowed_group", "TEXT NULL")
+            .context("failed to update photos table")?;
+        db.ensure_column("photos", "is_encrypted", "BOOLEAN NOT NULL DEFAULT FALSE")
+            .context("failed to update photos table")?;
+        db.ensure_column("photos", "latitude", "REAL NULL")
+            .context("failed to update photos table")?;
+        db.ensure_column("photos", "longitude", "REAL NULL")
+            .context("failed to update photos table")?;
+
+        Blob::migrate_column(db, "photos", "image_large_jpg", "image_large_hash")?;
+        Blob::migrate_column(db, "photos", "image_small_jpg", "image_small_hash")
     }
 
     fn from_row(row: &Row) -> Result<Self, SqliteError> {
@@ -49,6 +70,8 @@ impl Photo {
             is_private: row.get(2)?,
             source_path: row.get(3)?,
             source_time: row.get(4)?,
+            allowed_group: row.get(5)?,
+            is_encrypted: row.get(6)?,
         })
     }
 
@@ -57,6 +80,7 @@ impl Photo {
         cfg: &Config,
         source_path: &Path,
         is_private: bool,
+        allowed_group: Option<&str>,
     ) -> Result<Photo, Error> {
         let source_time = source_path
             .metadata()?
@@ -64,19 +88,22 @@ impl Photo {
             .duration_since(std::time::UNIX_EPOCH)?
             .as_secs() as i64;
 
-        println!("loading photo {:?}", source_path);
+        // private photos are encrypted whenever a key is configured
+        let key = cfg.encryption_key()?.filter(|_| is_private);
+        // the place of an encrypted photo would give away part of what the encryption hides
+        let location = read_location(source_path).filter(|_| key.is_none());
 
         if let Ok(existing_photo) = Photo::get_by_path(db, source_path) {
-            if existing_photo.source_time >= source_time {
-                println!("photo is up to date, skipping");
+            if existing_photo.source_time >= source_time
+                && existing_photo.is_encrypted == key.is_some()
+            {
                 existing_photo.mark(db)?;
+                existing_photo.set_visibility(db, is_private, allowed_group)?;
+                existing_photo.set_location(db, location)?;
                 return Ok(existing_photo);
             }
 
-            println!("photo is outdated, updating");
             existing_photo.delete(db)?;
-        } else {
-            println!("photo is new, inserting");
         }
 
         let image_large = ImageReader::open(source_path)
@@ -84,8 +111,6 @@ impl Photo {
             .decode()
             .context("failed to decode photo")?;
 
-        println!("size: {}x{}", image_large.width(), image_large.height());
-
         let scale = f32::min(
             cfg.photo_max_preview_size as f32 / image_large.width() as f32,
             cfg.photo_max_preview_size as f32 / image_large.height() as f32,
@@ -117,37 +142,42 @@ impl Photo {
         source_path.hash(&mut hasher);
         let id = format!("{:016x}", hasher.finish());
 
+        if let Some(key) = &key {
+            data_large = crypto::encrypt(key, &data_large, id.as_bytes())?;
+            data_small = crypto::encrypt(key, &data_small, id.as_bytes())?;
+        }
+
         db.query_one(
             r#"
-                INSERT INTO photos (id, is_private, source_path, source_time, image_large_jpg, image_small_jpg)
-                VALUES (?, ?, ?, ?, ?, ?)
-                RETURNING id, is_private, source_path, source_time, image_large_jpg, image_small_jpg
+                INSERT INTO photos (id, is_private, source_path, source_time, allowed_group, is_encrypted, image_large_hash, image_small_hash, latitude, longitude)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                RETURNING id, mark, is_private, source_path, source_time, allowed_group, is_encrypted
             "#,
-            (id, is_private, source_path, source_time, data_large, data_small),
+            (id, is_private, source_path, source_time, allowed_group, key.is_some(), Blob::insert(db, &data_large)?, Blob::insert(db, &data_small)?, location.map(|(latitude, _)| latitude), location.map(|(_, longitude)| longitude)),
             Photo::from_row,
         ).context("failed to insert photo into database")
     }
 
     pub fn get_by_id(db: &Database, id: &str) -> Result<Photo, Error> {
         db.query_one(
-            "SELECT id, mark, is_private, source_path, source_time FROM photos WHERE id = ?;",
+            "SELECT id, mark, is_private, source_path, source_time, allowed_group, is_encrypted FROM photos WHERE id = ?;",
             [id],
-            |row| Self::from_row(row),
+            Self::from_row,
         )
         .context("failed to query photo by id from database")
     }
 
     pub fn get_by_path(db: &Database, source_path: &Path) -> Result<Photo, Error> {
         db.query_one(
-            "SELECT id, mark, is_private, source_path, source_time FROM photos WHERE source_path = ?",
+            "SELECT id, mark, is_private, source_path, source_time, allowed_group, is_encrypted FROM photos WHERE source_path = ?",
             [source_path.to_str().unwrap()],
-            |row| Self::from_row(row),
+            Self::from_row,
         )
     }
 
     pub fn get_all(db: &Database, post_id: Option<&str>) -> Result<Vec<Photo>, Error> {
         let mut query = r#"
-            SELECT photos.id, photos.mark, photos.is_private, photos.source_path, photos.source_time
+            SELECT photos.id, photos.mark, photos.is_private, photos.source_path, photos.source_time, photos.allowed_group, photos.is_encrypted
             FROM photos
             JOIN posts_photos ON photos.id = posts_photos.photo_id
             JOIN posts ON posts_photos.post_id = posts.id
@@ -161,13 +191,30 @@ impl Photo {
         query.push_str("\nORDER BY posts.date DESC, photos.source_time DESC;");
 
         if let Some(post_id) = post_id {
-            db.query_mul(&query, [post_id], |row| Self::from_row(row))
+            db.query_mul(&query, [post_id], Self::from_row)
         } else {
-            db.query_mul(&query, [], |row| Self::from_row(row))
+            db.query_mul(&query, [], Self::from_row)
         }
         .context("failed to query photos from database")
     }
 
+    // photos with a place from their EXIF data, with their latitude and longitude
+    pub fn get_located(db: &Database) -> Result<Vec<(Photo, f64, f64)>, Error> {
+        db.query_mul(
+            r#"
+                SELECT photos.id, photos.mark, photos.is_private, photos.source_path, photos.source_time, photos.allowed_group, photos.is_encrypted, photos.latitude, photos.longitude
+                FROM photos
+                JOIN posts_photos ON photos.id = posts_photos.photo_id
+                JOIN posts ON posts_photos.post_id = posts.id
+                WHERE photos.latitude IS NOT NULL AND photos.longitude IS NOT NULL
+                ORDER BY posts.date DESC, photos.source_time DESC;
+            "#,
+            [],
+            |row| Ok((Self::from_row(row)?, row.get(7)?, row.get(8)?)),
+        )
+        .context("failed to query located photos from database")
+    }
+
     pub fn count_all(db: &Database) -> Result<u32, Error> {
         db.query_one("SELECT COUNT(*) FROM photos;", [], |row| row.get(0))
             .context("failed to count photos in database")
@@ -178,6 +225,46 @@ impl Photo {
             .context("failed to mark photo in database")
     }
 
+    pub fn set_visibility(
+        &self,
+        db: &Database,
+        is_private: bool,
+        allowed_group: Option<&str>,
+    ) -> Result<(), Error> {
+        db.execute(
+            "UPDATE photos SET is_private = ?, allowed_group = ? WHERE id = ?",
+            (is_private, allowed_group, &self.id),
+        )
+        .context("failed to set visibility of photo in database")
+    }
+
+    fn set_location(&self, db: &Database, location: Option<(f64, f64)>) -> Result<(), Error> {
+        db.execute(
+            "UPDATE photos SET latitude = ?, longitude = ? WHERE id = ?",
+            (
+                location.map(|(latitude, _)| latitude),
+                location.map(|(_, longitude)| longitude),
+                &self.id,
+            ),
+        )
+        .context("failed to set location of photo in database")
+    }
+
+    pub fn decrypt(&self, cfg: &Config, data: Vec<u8>) -> Result<Vec<u8>, Error> {
+        if !self.is_encrypted {
+            return Ok(data);
+        }
+
+        let key = cfg
+            .encryption_key()?
+            .context("photo is encrypted but no encryption key is configured")?;
+        crypto::decrypt(&key, &data, self.id.as_bytes())
+    }
+
+    pub fn visible_to(&self, user: Option<&User>) -> bool {
+        !self.is_private || user.is_some_and(|user| user.can_see(self.allowed_group.as_deref()))
+    }
+
     pub fn delete(self, db: &Database) -> Result<(), Error> {
         db.execute("DELETE FROM photos WHERE id = ?", [&self.id])
             .context("failed to delete photo from database")
@@ -194,21 +281,25 @@ impl Photo {
     }
 
     pub fn get_image_small(&self, db: &Database) -> Result<Vec<u8>, Error> {
-        db.query_one(
-            "SELECT image_small_jpg FROM photos WHERE id = ?;",
-            [&self.id],
-            |row| row.get(0),
-        )
-        .context("failed to query image_small from database")
+        let hash: String = db
+            .query_one(
+                "SELECT image_small_hash FROM photos WHERE id = ?;",
+                [&self.id],
+                |row| row.get(0),
+            )
+            .context("failed to query image_small from database")?;
+        Blob::get(db, &hash)
     }
 
     pub fn get_image_large(&self, db: &Database) -> Result<Vec<u8>, Error> {
-        db.query_one(
-            "SELECT image_small_jpg FROM photos WHERE id = ?;",
-            [&self.id],
-            |row| row.get(0),
-        )
-        .context("failed to query image_large from database")
+        let hash: String = db
+            .query_one(
+                "SELECT image_large_hash FROM photos WHERE id = ?;",
+                [&self.id],
+                |row| row.get(0),
+            )
+            .context("failed to query image_large from database")?;
+        Blob::get(db, &hash)
     }
 
     pub fn get_post(&self, db: &Database) -> Result<Post, Error> {
@@ -221,24 +312,83 @@ impl Photo {
         .context("failed to query post from database")
     }
 
-    pub fn to_html(&self, link_url: &str, link_text: &str) -> PreEscaped<String> {
+    pub fn name(&self) -> &str {
+        Path::new(&self.source_path)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(&self.source_path)
+    }
+
+    pub fn to_html(
+        &self,
+        link_url: &str,
+        link_text: &str,
+        caption: Option<&str>,
+        alt_text: Option<&str>,
+        lite: Lite,
+    ) -> PreEscaped<String> {
+        if lite.0 {
+            return html!(
+                p class = "photo-preview" {
+                    a href = (routes::photo(&self.id)) { "[photo: " (caption.unwrap_or(self.name())) "]" }
+                    " "
+                    a class = "photo-link" href = (link_url) { (link_text) }
+                }
+            );
+        }
+
         html!(
             div class = "photo-preview" {
                 div {
-                    img class = "photo" src=(format!("/photos/{}?size=small", self.id)) alt = (format!("photo {}", self.id)) {}
+                    img class = "photo" src=(routes::photo_sized(&self.id, PhotoSize::Small)) alt = (caption.or(alt_text).map(|c| c.to_string()).unwrap_or(format!("photo {}", self.id))) {}
                     a class = "photo-link" href = (link_url) { (link_text) }
                 }
+                @if let Some(caption) = caption {
+                    p class = "photo-caption" { (caption) }
+                }
             }
         )
     }
 }
 
+// Where a photo was taken, from the GPS tags in its EXIF data. Photos without them, or with tags
+// that make no sense, have no place.
+fn read_location(source_path: &Path) -> Option<(f64, f64)> {
+    let file = fs::File::open(source_path).ok()?;
+    let exif = exif::Reader::new()
+        .read_from_container(&mut std::io::BufReader::new(file))
+        .ok()?;
+
+    let coordinate = |tag: Tag, reference: Tag, negative: u8| -> Option<f64> {
+        let Value::Rational(parts) = &exif.get_field(tag, In::PRIMARY)?.value else {
+            return None;
+        };
+        let [degrees, minutes, seconds] = parts.as_slice() else {
+            return None;
+        };
+        let value = degrees.to_f64() + minutes.to_f64() / 60.0 + seconds.to_f64() / 3600.0;
+        match &exif.get_field(reference, In::PRIMARY)?.value {
+            Value::Ascii(values) if values.first()?.first() == Some(&negative) => Some(-value),
+            Value::Ascii(_) => Some(value),
+            _ => None,
+        }
+    };
+
+    let latitude = coordinate(Tag::GPSLatitude, Tag::GPSLatitudeRef, b'S')?;
+    let longitude = coordinate(Tag::GPSLongitude, Tag::GPSLongitudeRef, b'W')?;
+    (latitude.abs() <= 90.0 && longitude.abs() <= 180.0).then_some((latitude, longitude))
+}
+
 pub async fn get_photos(
     ax::State(state): ax::State<Arc<AppState>>,
     ax::Query(params): ax::Query<HashMap<String, String>>,
     cookies: ax::CookieJar,
+    lite: Lite,
 ) -> impl IntoResponse {
-    let db = &state.db.lock().unwrap();
+    let db = &match state.lock_db().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
     let cfg = &state.config.lock().unwrap();
     let user = User::from_cookie(db, &cookies).ok();
 
@@ -252,7 +402,12 @@ pub async fn get_photos(
     let photos = match Photo::get_all(db, None) {
         Ok(photos) => photos
             .into_iter()
-            .filter(|photo| !photo.is_private || user.is_some())
+            .filter(|photo| photo.visible_to(user.as_ref()))
+            .filter(|photo| {
+                photo
+                    .get_post(db)
+                    .is_ok_and(|post| post.visible_to(user.as_ref(), cfg.timezone()))
+            })
             .collect::<Vec<_>>(),
         Err(_) => return make_error(500, "Failed to get photos").into_response(),
     };
@@ -270,30 +425,41 @@ pub async fn get_photos(
         .take(cfg.photos_per_page as usize);
 
     let content = html!(
+        @if cfg.map.is_some() {
+            p { a href=(routes::PHOTOS_MAP) { "↪ on a map" } }
+        }
         @for photo in photos {
-            @let post = match photo.get_post(db) {
+            @let (post, alt_texts) = match photo.get_post(db).and_then(|post| {
+                let alt_texts = AltText::get_all(db, &post.id)?;
+                Ok((post, alt_texts))
+            }) {
                 Ok(post) => post,
                 Err(_) => return make_error(500, "Failed to get post").into_response(),
             };
 
-            (photo.to_html(&format!("/posts/{}/", post.id), "↪ to post"))
+            (photo.to_html(&post.url(), "↪ to post", None, alt_texts.get(photo.name()).map(String::as_str), lite))
         }
         section id="photo-navigation" {
             @if page > 1 {
-                a href="/photos/?page=1" { "<<first" } " "
-                a href=(format!("/photos/?page={}", page - 1)) { "<prev" } " "
+                a href=(routes::photos_page(1)) { "<<first" } " "
+                a href=(routes::photos_page(page - 1)) { "<prev" } " "
             }
             "page " (page) " of " (last_page)
             @if page < last_page {
-                " " a href=(format!("/photos/?page={}", page + 1)) { "next>" }
-                " " a href=(format!("/photos/?page={}", last_page)) { "last>>" }
+                " " a href=(routes::photos_page(page + 1)) { "next>" }
+                " " a href=(routes::photos_page(last_page)) { "last>>" }
             }
         }
     );
 
     let page = make_page(
-        Some("Photos"),
-        "A gallery of all photos.",
+        PageMeta::new(Section::Photos)
+            .title("Photos")
+            .description(format!(
+                "A gallery of all photos, page {} of {}.",
+                page, last_page
+            ))
+            .lite(lite),
         vec!["/styles/photo.css"],
         content,
         user,
@@ -303,37 +469,148 @@ pub async fn get_photos(
     ax::Html::from(page.into_string()).into_response()
 }
 
+#[derive(Serialize)]
+struct MapPhoto {
+    lat: f64,
+    lon: f64,
+    thumbnail: String,
+    post: String,
+    title: String,
+}
+
+// The photos with a place on a map, each linking to its post. Private photos and photos of hidden
+// posts are left out like in the gallery. The list is there for readers without javascript.
+pub async fn get_photos_map(
+    ax::State(state): ax::State<Arc<AppState>>,
+    cookies: ax::CookieJar,
+    lite: Lite,
+) -> impl IntoResponse {
+    let db = &match state.lock_db().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let cfg = &state.config.lock().unwrap();
+    let user = User::from_cookie(db, &cookies).ok();
+
+    println!("GET photos map, user = {:?}", user);
+
+    let Some(map) = &cfg.map else {
+        return make_error(404, "Page not found").into_response();
+    };
+
+    let photos = match Photo::get_located(db) {
+        Ok(photos) => photos
+            .into_iter()
+            .filter(|(photo, _, _)| photo.visible_to(user.as_ref()))
+            .filter_map(|(photo, lat, lon)| {
+                let post = photo.get_post(db).ok()?;
+                post.visible_to(user.as_ref(), cfg.timezone())
+                    .then_some((photo, post, lat, lon))
+            })
+            .collect::<Vec<_>>(),
+        Err(_) => return make_error(500, "Failed to get photos").into_response(),
+    };
+
+    let markers = photos
+        .iter()
+        .map(|(photo, post, lat, lon)| MapPhoto {
+            lat: *lat,
+            lon: *lon,
+            thumbnail: routes::photo_sized(&photo.id, PhotoSize::Small),
+            post: post.url(),
+            title: post.title.clone(),
+        })
+        .collect::<Vec<_>>();
+    let markers = match serde_json::to_string(&markers) {
+        Ok(markers) => markers,
+        Err(_) => return make_error(500, "Failed to get photos").into_response(),
+    };
+
+    let content = html!(
+        @if !lite.0 {
+            div id="photo-map" data-photos=(markers) data-tiles=(map.tile_url) data-attribution=(map.attribution) {}
+        }
+        @if photos.is_empty() {
+            p { "No photos with a place yet." }
+        }
+        ul class="photo-places" {
+            @for (photo, post, lat, lon) in &photos {
+                li {
+                    a href=(routes::photo(&photo.id)) { (photo.name()) }
+                    " " (format!("({:.4}, {:.4})", lat, lon)) " · "
+                    a href=(post.url()) { (post.title) }
+                }
+            }
+        }
+    );
+
+    let mut meta = PageMeta::new(Section::Photos)
+        .title("Photo map")
+        .description("Where the photos were taken.")
+        .lite(lite);
+    let mut styles = vec!["/styles/photo.css"];
+    let leaflet_style = routes::style(LEAFLET_STYLE_NAME);
+    if !lite.0 {
+        meta = meta.scripts(vec![
+            routes::script(LEAFLET_SCRIPT_NAME),
+            routes::script(PHOTO_MAP_SCRIPT_NAME),
+        ]);
+        styles.push(&leaflet_style);
+    }
+
+    let page = make_page(meta, styles, content, user, false);
+
+    ax::Html::from(page.into_string()).into_response()
+}
+
 pub async fn get_photo(
     ax::State(state): ax::State<Arc<AppState>>,
     ax::Path(id): ax::Path<String>,
     ax::Query(params): ax::Query<HashMap<String, String>>,
     cookie: ax::CookieJar,
 ) -> impl IntoResponse {
-    let db = &state.db.lock().unwrap();
+    let db = &match state.lock_db().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let cfg = &state.config.lock().unwrap();
     let user = User::from_cookie(db, &cookie).ok();
 
-    let size = match params.get("size").map(|s| s.as_str()) {
-        Some("small") => "small",
-        Some("large") => "large",
-        _ => "large",
-    };
+    let size = PhotoSize::from_query(params.get("size").map(|s| s.as_str()));
 
-    println!("GET photo {}, size = {}, user = {:?}", id, size, user);
+    println!(
+        "GET photo {}, size = {}, user = {:?}",
+        id,
+        size.as_str(),
+        user
+    );
 
     let photo = match Photo::get_by_id(db, &id) {
         Ok(photo) => photo,
         Err(_) => return make_error(404, "Photo not found").into_response(),
     };
 
-    if photo.is_private && user.is_none() {
+    if !photo.visible_to(user.as_ref()) {
+        return ax::StatusCode::FORBIDDEN.into_response();
+    }
+
+    // public photos go with their post, like in the feed
+    let Ok(post) = photo.get_post(db) else {
+        return make_error(404, "Photo not found").into_response();
+    };
+    if post.is_expired(cfg.timezone()) {
+        return make_error(410, "This post has expired").into_response();
+    }
+    if !post.visible_to(user.as_ref(), cfg.timezone()) {
         return ax::StatusCode::FORBIDDEN.into_response();
     }
 
     let data = match match size {
-        "small" => photo.get_image_small(db),
-        "large" => photo.get_image_large(db),
-        _ => unreachable!(),
-    } {
+        PhotoSize::Small => photo.get_image_small(db),
+        PhotoSize::Large => photo.get_image_large(db),
+    }
+    .and_then(|data| photo.decrypt(cfg, data))
+    {
         Ok(data) => data,
         Err(_) => return make_error(500, "Failed to get photo data").into_response(),
     };
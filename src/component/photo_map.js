@@ -0,0 +1,39 @@
+// The map at `/photos/map/`: a marker for each photo in the list below it, with the thumbnail and
+// a link to its post. Without Leaflet the list is all there is.
+(function () {
+    var element = document.getElementById("photo-map");
+    if (!element || !window.L) return;
+
+    var photos;
+    try {
+        photos = JSON.parse(element.dataset.photos);
+    } catch (e) {
+        return;
+    }
+    if (photos.length === 0) return;
+
+    element.style.height = "70vh";
+
+    var map = L.map(element);
+    L.tileLayer(element.dataset.tiles, {
+        attribution: element.dataset.attribution,
+        maxZoom: 18,
+    }).addTo(map);
+
+    var bounds = [];
+    photos.forEach(function (photo) {
+        var popup = document.createElement("a");
+        popup.href = photo.post;
+        var image = document.createElement("img");
+        image.src = photo.thumbnail;
+        image.alt = photo.title;
+        image.style.maxWidth = "200px";
+        popup.appendChild(image);
+        popup.appendChild(document.createElement("br"));
+        popup.appendChild(document.createTextNode(photo.title));
+
+        L.marker([photo.lat, photo.lon]).bindPopup(popup).addTo(map);
+        bounds.push([photo.lat, photo.lon]);
+    });
+    map.fitBounds(bounds, { padding: [32, 32], maxZoom: 14 });
+})();
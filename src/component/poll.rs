@@ -0,0 +1,329 @@
+use crate::component::post::find_post;
+use crate::database::SqliteError;
+use crate::prelude::*;
+
+const POLL_SHORTCODE_START: &str = "{{poll ";
+const POLL_SHORTCODE_END: &str = "}}";
+
+// readers are anonymous, so each browser gets a random id to vote with
+const VOTER_COOKIE: &str = "voter";
+
+// more votes than this on a single poll within a minute are most likely a script
+const MAX_VOTES_PER_MINUTE: i64 = 20;
+
+const MIN_OPTIONS: usize = 2;
+
+// a poll as declared in the post metadata, shown where the post says `{{poll id}}`
+#[derive(Serialize, Deserialize)]
+pub(crate) struct PollMetadata {
+    pub id: String,
+    pub question: String,
+    pub options: Vec<String>,
+}
+
+// Polls are content and rebuilt with their post, votes are state and survive rebuilds. Votes for
+// options that no longer exist are ignored.
+#[allow(dead_code)]
+pub struct Poll {
+    pub post_id: String,
+    pub id: String,
+    pub question: String,
+    pub options: Vec<String>,
+}
+
+impl Poll {
+    pub fn setup(db: &Database) -> Result<(), Error> {
+        db.execute_batch(
+            r#"
+                CREATE TABLE IF NOT EXISTS polls (
+                    post_id TEXT NOT NULL,
+                    id TEXT NOT NULL,
+                    question TEXT NOT NULL,
+                    options TEXT NOT NULL,
+                    PRIMARY KEY (post_id, id)
+                );
+
+                CREATE TABLE IF NOT EXISTS poll_votes (
+                    post_id TEXT NOT NULL,
+                    poll_id TEXT NOT NULL,
+                    option_index INTEGER NOT NULL,
+                    voter TEXT NOT NULL,
+                    created_at INTEGER NOT NULL,
+                    UNIQUE (post_id, poll_id, voter)
+                );
+            "#,
+        )
+        .context("failed to create polls tables")
+    }
+
+    fn from_row(row: &Row) -> Result<Self, SqliteError> {
+        let options: String = row.get(3)?;
+        Ok(Self {
+            post_id: row.get(0)?,
+            id: row.get(1)?,
+            question: row.get(2)?,
+            options: serde_json::from_str(&options).unwrap_or_default(),
+        })
+    }
+
+    pub(crate) fn new(
+        db: &Database,
+        post_id: &str,
+        metadata: &PollMetadata,
+    ) -> Result<Self, Error> {
+        if metadata.id.is_empty() || !metadata.id.chars().all(is_id_char) {
+            return Err(Error::new(format!("invalid poll id {:?}", metadata.id))
+                .with_kind(ErrorKind::Validation));
+        }
+        if metadata.options.len() < MIN_OPTIONS {
+            return Err(Error::new(format!(
+                "poll {:?} needs at least {} options",
+                metadata.id, MIN_OPTIONS
+            ))
+            .with_kind(ErrorKind::Validation));
+        }
+
+        db.query_one(
+            r#"
+                INSERT INTO polls (post_id, id, question, options) VALUES (?, ?, ?, ?)
+                RETURNING post_id, id, question, options;
+            "#,
+            (
+                post_id,
+                &metadata.id,
+                &metadata.question,
+                serde_json::to_string(&metadata.options)?,
+            ),
+            Poll::from_row,
+        )
+        .context(format!(
+            "failed to insert poll {:?}, is the id used twice?",
+            metadata.id
+        ))
+    }
+
+    pub fn get_all(db: &Database, post_id: &str) -> Result<Vec<Self>, Error> {
+        db.query_mul(
+            "SELECT post_id, id, question, options FROM polls WHERE post_id = ?;",
+            [post_id],
+            Poll::from_row,
+        )
+        .context("failed to query polls from database")
+    }
+
+    pub fn by_id(db: &Database, post_id: &str, id: &str) -> Result<Self, Error> {
+        db.query_one(
+            "SELECT post_id, id, question, options FROM polls WHERE post_id = ? AND id = ?;",
+            [post_id, id],
+            Poll::from_row,
+        )
+        .context("failed to query poll from database")
+    }
+
+    pub fn delete_all(db: &Database) -> Result<(), Error> {
+        db.execute("DELETE FROM polls", [])
+            .context("failed to delete all polls from database")
+    }
+
+    // votes per option, in the order of the options
+    pub fn results(&self, db: &Database) -> Result<Vec<i64>, Error> {
+        let rows: Vec<(i64, i64)> = db
+            .query_mul(
+                r#"
+                    SELECT option_index, COUNT(*) FROM poll_votes
+                    WHERE post_id = ? AND poll_id = ? GROUP BY option_index;
+                "#,
+                [&self.post_id, &self.id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .context("failed to query poll results from database")?;
+
+        let mut results = vec![0; self.options.len()];
+        for (index, count) in rows {
+            if let Some(result) = usize::try_from(index)
+                .ok()
+                .and_then(|index| results.get_mut(index))
+            {
+                *result = count;
+            }
+        }
+        Ok(results)
+    }
+
+    pub fn has_voted(&self, db: &Database, voter: &str) -> Result<bool, Error> {
+        db.query_one(
+            "SELECT COUNT(*) > 0 FROM poll_votes WHERE post_id = ? AND poll_id = ? AND voter = ?;",
+            [&self.post_id, &self.id, voter],
+            |row| row.get(0),
+        )
+        .context("failed to query poll vote from database")
+    }
+
+    fn recent_votes(&self, db: &Database) -> Result<i64, Error> {
+        db.query_one(
+            r#"
+                SELECT COUNT(*) FROM poll_votes
+                WHERE post_id = ? AND poll_id = ? AND created_at > unixepoch() - 60;
+            "#,
+            [&self.post_id, &self.id],
+            |row| row.get(0),
+        )
+        .context("failed to count recent poll votes in database")
+    }
+
+    // a second vote from the same voter is ignored
+    pub fn vote(&self, db: &Database, voter: &str, option: usize) -> Result<(), Error> {
+        db.execute(
+            r#"
+                INSERT OR IGNORE INTO poll_votes (post_id, poll_id, option_index, voter, created_at)
+                VALUES (?, ?, ?, ?, unixepoch());
+            "#,
+            (&self.post_id, &self.id, option as i64, voter),
+        )
+        .context("failed to insert poll vote into database")
+    }
+
+    pub fn to_html(&self, post: &Post, results: &[i64], voted: bool) -> PreEscaped<String> {
+        let total = results.iter().sum::<i64>();
+
+        html!(
+            div class="poll" id=(format!("poll-{}", self.id)) {
+                p class="poll-question" { strong { (self.question) } }
+
+                @if voted {
+                    @for (option, count) in self.options.iter().zip(results) {
+                        div class="poll-result" {
+                            label { (option) " · " (count) " (" (percent(*count, total)) "%)" }
+                            progress max=(total.max(1)) value=(count) {}
+                        }
+                    }
+                    p class="poll-total" { (total) " votes" }
+                } @else {
+                    form action=(routes::post_poll(&post.slug, &self.id)) method="post" {
+                        (csrf_field())
+                        @for (index, option) in self.options.iter().enumerate() {
+                            label class="poll-option" {
+                                input type="radio" name="option" value=(index) required {}
+                                " " (option)
+                            }
+                        }
+                        input type="submit" value="Vote" {}
+                    }
+                    p class="poll-total" { (total) " votes so far" }
+                }
+            }
+        )
+    }
+}
+
+fn percent(count: i64, total: i64) -> i64 {
+    match total {
+        0 => 0,
+        total => (count as f64 / total as f64 * 100.0).round() as i64,
+    }
+}
+
+// `{{poll id}}` on a line of its own
+pub fn poll_shortcode_id(line: &str) -> Option<&str> {
+    let id = line
+        .trim()
+        .strip_prefix(POLL_SHORTCODE_START)?
+        .strip_suffix(POLL_SHORTCODE_END)?
+        .trim();
+    (!id.is_empty() && id.chars().all(is_id_char)).then_some(id)
+}
+
+// poll ids end up in urls
+fn is_id_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '_'
+}
+
+pub fn poll_shortcode_ids(markdown: &str) -> Vec<&str> {
+    markdown.lines().filter_map(poll_shortcode_id).collect()
+}
+
+// the rendered polls of a post by id, for `MarkdownContext::polls`
+pub fn make_polls(
+    db: &Database,
+    post: &Post,
+    cookie: &ax::CookieJar,
+) -> Result<HashMap<String, String>, Error> {
+    let voter = cookie.get(VOTER_COOKIE).map(|cookie| cookie.value());
+
+    let mut polls = HashMap::new();
+    for poll in Poll::get_all(db, &post.id)? {
+        let voted = match voter {
+            Some(voter) => poll.has_voted(db, voter)?,
+            None => false,
+        };
+        let html = poll.to_html(post, &poll.results(db)?, voted);
+        polls.insert(poll.id, html.into_string());
+    }
+    Ok(polls)
+}
+
+#[derive(Deserialize, Debug)]
+pub struct VoteForm {
+    option: usize,
+}
+
+pub async fn post_poll_vote(
+    ax::State(state): ax::State<Arc<AppState>>,
+    ax::Path((id, poll_id)): ax::Path<(String, String)>,
+    cookie: ax::CookieJar,
+    form: ax::Form<VoteForm>,
+) -> impl IntoResponse {
+    let db = &match state.lock_writer().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let cfg = &state.config.lock().unwrap();
+    let user = User::from_cookie(db, &cookie).ok();
+
+    println!("POST vote on poll {} of {}, user = {:?}", poll_id, id, user);
+
+    let post = match find_post(db, cfg, &id, user.as_ref()) {
+        Ok(post) => post,
+        Err((code, message)) => return make_error(code, message).into_response(),
+    };
+
+    let Ok(poll) = Poll::by_id(db, &post.id, &poll_id) else {
+        return make_error(404, "Poll not found").into_response();
+    };
+
+    if form.option >= poll.options.len() {
+        return make_error(400, "Invalid option").into_response();
+    }
+
+    match poll.recent_votes(db) {
+        Ok(votes) if votes < MAX_VOTES_PER_MINUTE => {}
+        Ok(_) => return make_error(429, "Too many votes, please try again later").into_response(),
+        Err(_) => return make_error(500, "Failed to save vote").into_response(),
+    }
+
+    let (cookie, voter) = match cookie.get(VOTER_COOKIE) {
+        Some(voter) => {
+            let voter = voter.value().to_string();
+            (cookie, voter)
+        }
+        None => {
+            let voter = format!("{:016x}", rand::random::<u64>());
+            let cookie = cookie.add(
+                ax::Cookie::build((VOTER_COOKIE, voter.clone()))
+                    .path("/")
+                    .permanent(),
+            );
+            (cookie, voter)
+        }
+    };
+
+    if poll.vote(db, &voter, form.option).is_err() {
+        return make_error(500, "Failed to save vote").into_response();
+    }
+
+    (
+        cookie,
+        ax::Redirect::to(&format!("{}#poll-{}", post.url(), poll.id)),
+    )
+        .into_response()
+}
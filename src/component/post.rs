@@ -1,24 +1,97 @@
+use sha2::{Digest, Sha256};
+
+use crate::component::calendar::EventMetadata;
+use crate::component::page::MAX_DESCRIPTION_LENGTH;
+use crate::component::poll::PollMetadata;
+use crate::crypto;
 use crate::database::SqliteError;
 use crate::prelude::*;
+use crate::time;
 
 #[derive(Serialize, Deserialize)]
-struct PostMetadata {
+pub(crate) struct PostMetadata {
     pub id: Option<String>,
     pub title: String,
     pub description: Option<String>,
     pub date: String,
     pub tags: Vec<String>,
-    pub permalink: Option<String>,
+    pub permalink: Option<Permalinks>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub private: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_group: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub math: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<String>,
+    // featured posts are listed above the recent posts on the index, lowest order first
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub featured: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub featured_order: Option<i64>,
+    // names of files in the post's assets, added to the page for interactive posts
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub styles: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scripts: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub polls: Vec<PollMetadata>,
+    // ends the post with a numbered list of its external links
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub links_appendix: bool,
+    // the post is about an event, which is listed in the calendar
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event: Option<EventMetadata>,
+    // name of an audio file in the post's assets, played on the page and attached to the feed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio: Option<String>,
+}
+
+// a single permalink or a list of them, the first one is kept in the posts table and all of them
+// redirect to the post
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum Permalinks {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Permalinks {
+    fn all(&self) -> &[String] {
+        match self {
+            Permalinks::One(permalink) => std::slice::from_ref(permalink),
+            Permalinks::Many(permalinks) => permalinks,
+        }
+    }
 }
 
 impl PostMetadata {
-    fn from_json_str(json_str: &str) -> Result<PostMetadata, Error> {
-        serde_json::from_str(json_str).context("failed to decode post metadata")
+    // metadata for a generated post, with a fresh id and everything else left at the defaults
+    pub(crate) fn new(title: String, date: String) -> PostMetadata {
+        PostMetadata {
+            id: Some(format!("{:016x}", rand::random::<u64>())),
+            title,
+            description: None,
+            date,
+            tags: vec![],
+            permalink: None,
+            private: false,
+            allowed_group: None,
+            math: false,
+            expires: None,
+            featured: false,
+            featured_order: None,
+            styles: vec![],
+            scripts: vec![],
+            polls: vec![],
+            links_appendix: false,
+            event: None,
+            audio: None,
+        }
     }
 
-    fn from_json_file(path: &str) -> Result<PostMetadata, Error> {
-        let json_str = fs::read_to_string(path).context("failed to read metadata file")?;
-        PostMetadata::from_json_str(&json_str)
+    pub(crate) fn from_json_str(json_str: &str) -> Result<PostMetadata, Error> {
+        serde_json::from_str(json_str).context("failed to decode post metadata")
     }
 
     fn to_json_str(&self) -> Result<String, Error> {
@@ -30,11 +103,152 @@ impl PostMetadata {
         Ok(String::from_utf8(buf)?)
     }
 
-    fn to_json_file(&self, path: &str) -> Result<(), Error> {
+    pub(crate) fn to_json_file(&self, path: &str) -> Result<(), Error> {
         fs::write(path, self.to_json_str()?).context("failed to write metadata file")
     }
 }
 
+#[derive(Clone, Copy)]
+enum FrontmatterFormat {
+    Yaml,
+    Toml,
+}
+
+struct Frontmatter<'s> {
+    format: FrontmatterFormat,
+    // byte offset of the line after the opening delimiter
+    start: usize,
+    metadata: &'s str,
+    body: &'s str,
+}
+
+impl Frontmatter<'_> {
+    fn parse(&self) -> Result<PostMetadata, Error> {
+        match self.format {
+            FrontmatterFormat::Yaml => serde_yaml::from_str(self.metadata)
+                .map_err(|error| Error::new(error.to_string()).with_kind(ErrorKind::Validation)),
+            FrontmatterFormat::Toml => toml::from_str(self.metadata)
+                .map_err(|error| Error::new(error.to_string()).with_kind(ErrorKind::Validation)),
+        }
+        .context("failed to decode post frontmatter")
+    }
+
+    // inserts the id as the first key instead of re-serializing, so comments and formatting in
+    // the frontmatter survive
+    fn with_id(&self, source: &str, id: &str) -> String {
+        let line = match self.format {
+            FrontmatterFormat::Yaml => format!("id: \"{}\"\n", id),
+            FrontmatterFormat::Toml => format!("id = \"{}\"\n", id),
+        };
+        format!("{}{}{}", &source[..self.start], line, &source[self.start..])
+    }
+}
+
+// `json` with the id added as the first key, leaving the rest as it was written
+fn json_with_id(json: &str, id: &str) -> String {
+    let Some(open) = json.find('{') else {
+        return json.to_string();
+    };
+    let (head, rest) = json.split_at(open + 1);
+    let is_empty = rest.trim_start().starts_with('}');
+    let entry = format!("\"id\": \"{}\"{}", id, if is_empty { "" } else { "," });
+
+    // on a line of its own, indented like the key after it
+    match rest.strip_prefix('\n') {
+        Some(lines) if !is_empty => {
+            let indent = &lines[..lines.len() - lines.trim_start_matches([' ', '\t']).len()];
+            format!("{}\n{}{}{}", head, indent, entry, rest)
+        }
+        _ => format!("{} {} {}", head, entry, rest.trim_start()),
+    }
+}
+
+// `---` delimits yaml and `+++` toml frontmatter, as in hugo
+fn split_frontmatter(source: &str) -> Option<Frontmatter<'_>> {
+    let first_line = source.split_inclusive('\n').next()?;
+    let (format, delimiter) = match first_line.trim_end() {
+        "---" => (FrontmatterFormat::Yaml, "---"),
+        "+++" => (FrontmatterFormat::Toml, "+++"),
+        _ => return None,
+    };
+
+    let start = first_line.len();
+    let mut offset = start;
+    for line in source[start..].split_inclusive('\n') {
+        if line.trim_end() == delimiter {
+            return Some(Frontmatter {
+                format,
+                start,
+                metadata: &source[start..offset],
+                body: &source[offset + line.len()..],
+            });
+        }
+        offset += line.len();
+    }
+
+    None
+}
+
+// the markdown of a post without its frontmatter, if it has one
+pub(crate) fn strip_frontmatter(source: &str) -> &str {
+    split_frontmatter(source).map_or(source, |frontmatter| frontmatter.body)
+}
+
+// The frontmatter as it is written, for posts of other generators whose keys differ from ours.
+// Toml dates become strings like yaml ones.
+pub(crate) fn frontmatter_value(source: &str) -> Result<Option<(serde_json::Value, &str)>, Error> {
+    let Some(frontmatter) = split_frontmatter(source) else {
+        return Ok(None);
+    };
+
+    let value = match frontmatter.format {
+        FrontmatterFormat::Yaml => serde_yaml::from_str(frontmatter.metadata)
+            .map_err(|error| Error::new(error.to_string()).with_kind(ErrorKind::Validation)),
+        FrontmatterFormat::Toml => toml::from_str::<toml::Table>(frontmatter.metadata)
+            .map(|table| toml_to_json(toml::Value::Table(table)))
+            .map_err(|error| Error::new(error.to_string()).with_kind(ErrorKind::Validation)),
+    }
+    .context("failed to decode post frontmatter")?;
+
+    Ok(Some((value, frontmatter.body)))
+}
+
+fn toml_to_json(value: toml::Value) -> serde_json::Value {
+    match value {
+        toml::Value::String(string) => string.into(),
+        toml::Value::Integer(integer) => integer.into(),
+        toml::Value::Float(float) => float.into(),
+        toml::Value::Boolean(boolean) => boolean.into(),
+        toml::Value::Datetime(datetime) => datetime.to_string().into(),
+        toml::Value::Array(array) => array.into_iter().map(toml_to_json).collect(),
+        toml::Value::Table(table) => table
+            .into_iter()
+            .map(|(key, value)| (key, toml_to_json(value)))
+            .collect(),
+    }
+}
+
+const WORDS_PER_MINUTE: i64 = 200;
+
+const MAX_SLUG_LENGTH: usize = 64;
+
+pub const PROGRESS_SCRIPT_NAME: &str = "progress.js";
+pub const PROGRESS_SCRIPT: &[u8] = include_bytes!("progress.js");
+
+const STYLE_RESOURCE: &str = "style";
+const SCRIPT_RESOURCE: &str = "script";
+const AUDIO_RESOURCE: &str = "audio";
+
+// the audio of an episode, see `PostMetadata::audio`
+pub struct Audio {
+    pub url: String,
+    pub length: i64,
+    pub content_type: String,
+}
+
+// posts without a slug (e.g. titles without any latin letters) are served under their id
+const COLUMNS: &str = "id, title, description, date, permalink, is_private, allowed_group, word_count, has_math, expires, COALESCE(slug, id), is_featured, featured_order, has_links_appendix";
+
 #[allow(dead_code)]
 pub struct Post {
     pub id: String,
@@ -42,6 +256,15 @@ pub struct Post {
     pub description: Option<String>,
     pub date: String,
     pub permalink: Option<String>,
+    pub is_private: bool,
+    pub allowed_group: Option<String>,
+    pub word_count: i64,
+    pub has_math: bool,
+    pub expires: Option<String>,
+    pub slug: String,
+    pub is_featured: bool,
+    pub featured_order: Option<i64>,
+    pub has_links_appendix: bool,
 }
 
 impl Post {
@@ -54,11 +277,33 @@ impl Post {
                     description TEXT NULL,
                     date TEXT NOT NULL,
                     permalink TEXT NULL,
-                    source TEXT NOT NULL
+                    source TEXT NOT NULL,
+                    is_private BOOLEAN NOT NULL DEFAULT FALSE,
+                    allowed_group TEXT NULL,
+                    word_count INTEGER NOT NULL DEFAULT 0,
+                    has_math BOOLEAN NOT NULL DEFAULT FALSE,
+                    expires TEXT NULL,
+                    slug TEXT NULL,
+                    is_featured BOOLEAN NOT NULL DEFAULT FALSE,
+                    featured_order INTEGER NULL,
+                    has_links_appendix BOOLEAN NOT NULL DEFAULT FALSE
                 );
 
                 CREATE INDEX IF NOT EXISTS posts_id_index ON posts (id);
 
+                CREATE TABLE IF NOT EXISTS post_aliases (
+                    alias TEXT PRIMARY KEY NOT NULL,
+                    post_id TEXT NOT NULL,
+                    FOREIGN KEY (post_id) REFERENCES posts (id) ON DELETE CASCADE
+                );
+
+                CREATE TABLE IF NOT EXISTS posts_resources (
+                    post_id TEXT NOT NULL,
+                    kind TEXT NOT NULL,
+                    name TEXT NOT NULL,
+                    FOREIGN KEY (post_id) REFERENCES posts (id) ON DELETE CASCADE
+                );
+
                 CREATE TABLE IF NOT EXISTS posts_tags (
                     post_id TEXT NOT NULL,
                     tag TEXT NOT NULL,
@@ -66,7 +311,35 @@ impl Post {
                 );
             "#,
         )
-        .context("failed to create posts table")
+        .context("failed to create posts table")?;
+
+        db.ensure_column("posts", "is_private", "BOOLEAN NOT NULL DEFAULT FALSE")
+            .context("failed to update posts table")?;
+        db.ensure_column("posts", "allowed_group", "TEXT NULL")
+            .context("failed to update posts table")?;
+        db.ensure_column("posts", "word_count", "INTEGER NOT NULL DEFAULT 0")
+            .context("failed to update posts table")?;
+        db.ensure_column("posts", "has_math", "BOOLEAN NOT NULL DEFAULT FALSE")
+            .context("failed to update posts table")?;
+        db.ensure_column("posts", "expires", "TEXT NULL")
+            .context("failed to update posts table")?;
+        db.ensure_column("posts", "slug", "TEXT NULL")
+            .context("failed to update posts table")?;
+        db.ensure_column("posts", "is_featured", "BOOLEAN NOT NULL DEFAULT FALSE")
+            .context("failed to update posts table")?;
+        db.ensure_column("posts", "featured_order", "INTEGER NULL")
+            .context("failed to update posts table")?;
+        db.ensure_column("posts", "source_path", "TEXT NULL")
+            .context("failed to update posts table")?;
+        db.ensure_column(
+            "posts",
+            "has_links_appendix",
+            "BOOLEAN NOT NULL DEFAULT FALSE",
+        )
+        .context("failed to update posts table")?;
+
+        db.execute_batch("CREATE UNIQUE INDEX IF NOT EXISTS posts_slug_index ON posts (slug);")
+            .context("failed to create posts slug index")
     }
 
     fn from_row(row: &Row) -> Result<Self, SqliteError> {
@@ -76,72 +349,216 @@ impl Post {
             description: row.get(2)?,
             date: row.get(3)?,
             permalink: row.get(4)?,
+            is_private: row.get(5)?,
+            allowed_group: row.get(6)?,
+            word_count: row.get(7)?,
+            has_math: row.get(8)?,
+            expires: row.get(9)?,
+            slug: row.get(10)?,
+            is_featured: row.get(11)?,
+            featured_order: row.get(12)?,
+            has_links_appendix: row.get(13)?,
         })
     }
 
     pub fn new(db: &Database, cfg: &Config, source_path: &Path) -> Result<Post, Error> {
-        println!("loading post {:?}", source_path);
-
-        let index_path = source_path.join(&cfg.post_content_path);
         let metadata_path = source_path.join(&cfg.post_metadata_path);
 
-        let source = fs::read_to_string(&index_path).context("failed to read post content file")?;
-        let mut metadata = PostMetadata::from_json_file(metadata_path.to_str().unwrap())?;
+        let assets_path = source_path.join(&cfg.post_assets_path);
 
-        if metadata.id.is_none() {
-            let id: u64 = rand::random();
-            metadata.id = Some(format!("{:016x}", id));
-            metadata.to_json_file(metadata_path.to_str().unwrap())?;
-        }
+        let (index_path, renderer) = find_content(cfg, source_path)?;
+        let raw_source =
+            fs::read_to_string(&index_path).context("failed to read post content file")?;
+        let Rendered {
+            markdown: source,
+            assets: rendered_assets,
+        } = renderer.to_markdown(&raw_source, &cfg.post_assets_path)?;
 
-        metadata.tags = metadata
+        // The metadata is used as written, what the database needs is derived from it below. The
+        // only change to the sources is the id, and only with `write_post_ids`.
+        let (metadata, source) = match split_frontmatter(&source) {
+            // meta.json wins if it exists, otherwise the metadata has to be in the frontmatter
+            Some(frontmatter) if !metadata_path.exists() && renderer.has_frontmatter() => {
+                let mut metadata = frontmatter.parse()?;
+                if metadata.id.is_none() {
+                    let id = Post::new_id(cfg, source_path);
+                    if cfg.write_post_ids
+                        && let Some(raw_frontmatter) = split_frontmatter(&raw_source)
+                    {
+                        fs::write(&index_path, raw_frontmatter.with_id(&raw_source, &id))
+                            .context("failed to write post content file")?;
+                    }
+                    metadata.id = Some(id);
+                }
+                (metadata, frontmatter.body.to_string())
+            }
+            _ => {
+                let json =
+                    fs::read_to_string(&metadata_path).context("failed to read metadata file")?;
+                let mut metadata = PostMetadata::from_json_str(&json)?;
+                if metadata.id.is_none() {
+                    let id = Post::new_id(cfg, source_path);
+                    if cfg.write_post_ids {
+                        fs::write(&metadata_path, json_with_id(&json, &id))
+                            .context("failed to write metadata file")?;
+                    }
+                    metadata.id = Some(id);
+                }
+                (metadata, source)
+            }
+        };
+
+        let source = expand_includes(&source, source_path, &assets_path)?;
+
+        let (source, diagrams) = render_diagrams(&source, metadata.id.as_ref().unwrap());
+
+        let tags = metadata
             .tags
             .iter()
             .map(|tag| tag.to_lowercase().replace(" ", "_"))
-            .collect();
+            .collect::<Vec<_>>();
 
-        println!("id: {}", metadata.id.as_ref().unwrap());
-        println!("title: {}", metadata.title);
-        println!("date: {}", metadata.date);
-        println!("tags: {:?}", metadata.tags);
+        if time::parse_date(&metadata.date, cfg.timezone()).is_none() {
+            return Err(Error::new(format!("invalid post date {:?}", metadata.date))
+                .with_kind(ErrorKind::Validation));
+        }
+
+        if let Some(expires) = &metadata.expires
+            && time::parse_date(expires, cfg.timezone()).is_none()
+        {
+            return Err(
+                Error::new(format!("invalid post expiry date {:?}", expires))
+                    .with_kind(ErrorKind::Validation),
+            );
+        }
+
+        if let Some(event) = &metadata.event {
+            for date in std::iter::once(&event.start).chain(&event.end) {
+                if time::parse_date(date, cfg.timezone()).is_none() {
+                    return Err(Error::new(format!("invalid event date {:?}", date))
+                        .with_kind(ErrorKind::Validation));
+                }
+            }
+        }
+
+        let aliases = metadata
+            .permalink
+            .as_ref()
+            .map_or(&[][..], |permalinks| permalinks.all());
+        Post::check_collisions(db, metadata.id.as_ref().unwrap(), aliases, source_path)?;
 
         let post = db
             .query_one(
-                r#"
-                INSERT INTO posts (id, title, description, date, permalink, source)
-                VALUES (?, ?, ?, ?, ?, ?)
-                RETURNING id, title, description, date, permalink, source;
-            "#,
+                &format!(
+                    r#"
+                        INSERT INTO posts (id, title, description, date, permalink, source, is_private, allowed_group, word_count, has_math, expires, is_featured, featured_order, source_path, has_links_appendix)
+                        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                        RETURNING {};
+                    "#,
+                    COLUMNS
+                ),
                 (
                     metadata.id.as_ref().unwrap(),
                     &metadata.title,
                     &metadata.description,
                     &metadata.date,
-                    &metadata.permalink,
+                    metadata.permalink.as_ref().and_then(|permalinks| permalinks.all().first()),
                     &source,
+                    metadata.private,
+                    &metadata.allowed_group,
+                    count_words(&source),
+                    metadata.math,
+                    &metadata.expires,
+                    metadata.featured,
+                    metadata.featured_order,
+                    source_path.to_str(),
+                    metadata.links_appendix,
                 ),
                 Post::from_row,
             )
             .context("failed to insert post into database")?;
 
-        let assets_path = source_path.join(&cfg.post_assets_path);
+        // photos inherit the restrictions of their post
+        let is_restricted = metadata.private || metadata.allowed_group.is_some();
+        let allowed_group = metadata.allowed_group.as_deref();
+
         let public_photos_path = source_path.join(&cfg.post_public_photos_path);
         let private_photos_path = source_path.join(&cfg.post_private_photos_path);
 
+        // as are its assets, which are encrypted like private photos
+        let key = cfg.encryption_key()?.filter(|_| is_restricted);
+        let mut assets = vec![];
+
         if assets_path.exists() {
             for asset_path in fs::read_dir(assets_path).expect("failed to read styles directory") {
-                let asset = Asset::new(db, &asset_path?.path())?;
-                db.execute(
-                    "INSERT INTO posts_assets (post_id, asset_id) VALUES (?, ?);",
-                    (metadata.id.as_ref().unwrap(), asset.id),
-                )
-                .context("failed to insert into posts_assets table")?;
+                let asset_path = asset_path?.path();
+                if asset_path
+                    .extension()
+                    .is_some_and(|extension| extension == "gpx")
+                {
+                    Track::new(db, &post.id, &asset_path)?;
+                }
+                assets.push(Asset::new(db, &asset_path, key.as_ref())?);
             }
         }
 
+        for diagram in diagrams {
+            assets.push(Asset::from_data(
+                db,
+                &diagram.name,
+                &diagram.svg,
+                key.as_ref(),
+            )?);
+        }
+
+        for asset in rendered_assets {
+            assets.push(Asset::from_data(
+                db,
+                &asset.name,
+                &asset.data,
+                key.as_ref(),
+            )?);
+        }
+
+        let resources = metadata
+            .styles
+            .iter()
+            .map(|name| (STYLE_RESOURCE, name))
+            .chain(metadata.scripts.iter().map(|name| (SCRIPT_RESOURCE, name)))
+            .chain(metadata.audio.iter().map(|name| (AUDIO_RESOURCE, name)));
+
+        for (kind, name) in resources {
+            if !assets.iter().any(|asset| &asset.name == name) {
+                return Err(Error::new(format!(
+                    "{} {:?} is not one of the post's assets",
+                    kind, name
+                ))
+                .with_kind(ErrorKind::Validation));
+            }
+
+            db.execute(
+                "INSERT INTO posts_resources (post_id, kind, name) VALUES (?, ?, ?);",
+                (&post.id, kind, name),
+            )
+            .context("failed to insert into posts_resources table")?;
+        }
+
+        for asset in assets {
+            db.execute(
+                "INSERT INTO posts_assets (post_id, asset_id) VALUES (?, ?);",
+                (metadata.id.as_ref().unwrap(), asset.id),
+            )
+            .context("failed to insert into posts_assets table")?;
+        }
+
+        let mut photo_names = vec![];
+
         if let Ok(public_photos) = fs::read_dir(&public_photos_path) {
             for photo_path in public_photos {
-                let photo = Photo::new(db, cfg, &photo_path?.path(), false)?;
+                let photo_path = photo_path?.path();
+                let photo = Photo::new(db, cfg, &photo_path, is_restricted, allowed_group)
+                    .context(format!("failed to load photo {:?}", photo_path))?;
+                photo_names.push(photo.name().to_string());
                 db.execute(
                     "INSERT INTO posts_photos (post_id, photo_id) VALUES (?, ?);",
                     (metadata.id.as_ref().unwrap(), photo.id),
@@ -152,7 +569,10 @@ impl Post {
 
         if let Ok(private_photos) = fs::read_dir(&private_photos_path) {
             for photo_path in private_photos {
-                let photo = Photo::new(db, cfg, &photo_path?.path(), true)?;
+                let photo_path = photo_path?.path();
+                let photo = Photo::new(db, cfg, &photo_path, true, allowed_group)
+                    .context(format!("failed to load photo {:?}", photo_path))?;
+                photo_names.push(photo.name().to_string());
                 db.execute(
                     "INSERT INTO posts_photos (post_id, photo_id) VALUES (?, ?);",
                     (metadata.id.as_ref().unwrap(), photo.id),
@@ -161,29 +581,360 @@ impl Post {
             }
         }
 
-        post.set_tags(db, &metadata.tags)?;
+        for name in photo_shortcode_names(&source) {
+            if !photo_names.iter().any(|photo_name| photo_name == name) {
+                println!("warning: shortcode references unknown photo {}", name);
+            }
+        }
+
+        let captioned = captioned_photo_names(&source);
+        for name in &photo_names {
+            AltText::record(
+                db,
+                &post.id,
+                PHOTO_KIND,
+                name,
+                captioned.contains(&name.as_str()),
+            )?;
+        }
+        for (url, has_alt) in markdown_images(&source) {
+            AltText::record(db, &post.id, IMAGE_KIND, &url, has_alt)?;
+        }
+
+        for poll in &metadata.polls {
+            Poll::new(db, &post.id, poll)?;
+        }
+
+        if let Some(event) = &metadata.event {
+            Event::new(db, &post.id, event)?;
+        }
+
+        for id in poll_shortcode_ids(&source) {
+            if !metadata.polls.iter().any(|poll| poll.id == id) {
+                println!("warning: shortcode references unknown poll {}", id);
+            }
+        }
+
+        for alias in aliases {
+            db.execute(
+                "INSERT INTO post_aliases (alias, post_id) VALUES (?, ?);",
+                (alias, &post.id),
+            )
+            .context(format!("failed to insert alias {:?}", alias))?;
+        }
+
+        post.set_tags(db, &tags)?;
         Ok(post)
     }
 
     pub fn by_id(db: &Database, id: &str) -> Result<Post, Error> {
         db.query_one(
-            "SELECT id, title, description, date, permalink FROM posts WHERE id = ?;",
+            &format!("SELECT {} FROM posts WHERE id = ?;", COLUMNS),
             [id],
             Post::from_row,
         )
         .context("failed to query photo by source path from database")
     }
 
-    pub fn by_permalink(db: &Database, permalink: &str) -> Result<Post, Error> {
+    pub fn by_slug(db: &Database, slug: &str) -> Result<Post, Error> {
         db.query_one(
-            "SELECT id, title, description, date, permalink FROM posts WHERE permalink = ?;",
-            [permalink],
+            &format!("SELECT {} FROM posts WHERE slug = ?;", COLUMNS),
+            [slug],
             Post::from_row,
         )
-        .context("failed to query post id by permalink from database")
+        .context("failed to query post by slug from database")
+    }
+
+    // a post by a slug it had before its title changed
+    pub fn by_old_slug(db: &Database, slug: &str) -> Result<Post, Error> {
+        db.query_one(
+            &format!(
+                "SELECT {} FROM posts WHERE id = (SELECT post_id FROM post_slugs WHERE slug = ?);",
+                COLUMNS
+            ),
+            [slug],
+            Post::from_row,
+        )
+        .context("failed to query post by old slug from database")
+    }
+
+    pub fn by_alias(db: &Database, alias: &str) -> Result<Post, Error> {
+        db.query_one(
+            &format!(
+                "SELECT {} FROM posts WHERE id = (SELECT post_id FROM post_aliases WHERE alias = ?);",
+                COLUMNS
+            ),
+            [alias],
+            Post::from_row,
+        )
+        .context("failed to query post by alias from database")
+    }
+
+    pub fn get_aliases(&self, db: &Database) -> Result<Vec<String>, Error> {
+        db.query_mul(
+            "SELECT alias FROM post_aliases WHERE post_id = ?;",
+            [&self.id],
+            |row| row.get(0),
+        )
+        .context("failed to query post aliases from database")
+    }
+
+    // A random id that is written back, or else one that stays the same as long as the directory
+    // of the post keeps its name.
+    fn new_id(cfg: &Config, source_path: &Path) -> String {
+        match cfg.write_post_ids {
+            true => format!("{:016x}", rand::random::<u64>()),
+            false => {
+                let name = source_path.file_name().unwrap_or_default();
+                hex::encode(&Sha256::digest(name.as_encoded_bytes())[..8])
+            }
+        }
+    }
+
+    // Ids and permalinks share the urls of posts, so none may be used by two posts or twice by the
+    // same one. Checked against the posts loaded so far, the error names both directories.
+    fn check_collisions(
+        db: &Database,
+        id: &str,
+        aliases: &[String],
+        source_path: &Path,
+    ) -> Result<(), Error> {
+        let here = source_path.display();
+        for (i, alias) in aliases.iter().enumerate() {
+            if aliases[..i].contains(alias) {
+                return Err(Error::new(format!(
+                    "permalink {:?} is declared twice in {}",
+                    alias, here
+                ))
+                .with_kind(ErrorKind::Validation));
+            }
+        }
+
+        for key in std::iter::once(id).chain(aliases.iter().map(String::as_str)) {
+            let other: Option<Option<String>> = db
+                .query_mul(
+                    r#"
+                        SELECT source_path FROM posts WHERE id = ?1
+                        UNION ALL
+                        SELECT posts.source_path FROM post_aliases
+                        JOIN posts ON posts.id = post_aliases.post_id
+                        WHERE alias = ?1;
+                    "#,
+                    [key],
+                    |row| row.get(0),
+                )
+                .context("failed to query post ids and aliases from database")?
+                .pop();
+
+            if let Some(other) = other {
+                let what = match key == id {
+                    true => "id",
+                    false => "permalink",
+                };
+                return Err(Error::new(format!(
+                    "post {} {:?} of {} is already used by {}",
+                    what,
+                    key,
+                    here,
+                    other.as_deref().unwrap_or("another post")
+                ))
+                .with_kind(ErrorKind::Validation));
+            }
+        }
+
+        Ok(())
+    }
+
+    // every slug a post ever had, state so slugs stay put across rebuilds
+    pub fn setup_slugs(db: &Database) -> Result<(), Error> {
+        db.execute_batch(
+            r#"
+                CREATE TABLE IF NOT EXISTS post_slugs (
+                    slug TEXT PRIMARY KEY NOT NULL,
+                    post_id TEXT NOT NULL,
+                    assigned INTEGER NOT NULL
+                );
+            "#,
+        )
+        .context("failed to create post_slugs table")
+    }
+
+    // Slugs are derived from the titles once all posts are loaded. A post keeps its slug for as
+    // long as its title gives the same one, and a slug once given to a post is never given to
+    // another, so urls stay put. The old slug of a renamed post redirects to the new one.
+    pub fn assign_slugs(db: &Database) -> Result<(), Error> {
+        // slugs are unique, so reassigning them after a single post was reloaded must start over
+        db.execute("UPDATE posts SET slug = NULL;", [])
+            .context("failed to reset post slugs in database")?;
+
+        let posts: Vec<(String, String)> = db
+            .query_mul(
+                "SELECT id, title FROM posts ORDER BY date ASC, id ASC;",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .context("failed to query posts from database")?;
+
+        // a slug must never shadow the id or alias of another post
+        let ids: HashSet<String> = db
+            .query_mul(
+                "SELECT id FROM posts UNION SELECT alias FROM post_aliases;",
+                [],
+                |row| row.get(0),
+            )
+            .context("failed to query post ids and aliases from database")?
+            .into_iter()
+            .collect();
+
+        // by slug, the latest of a post last
+        let mut slugs: Vec<(String, String)> = db
+            .query_mul(
+                "SELECT slug, post_id FROM post_slugs ORDER BY assigned;",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .context("failed to query post slugs from database")?;
+
+        for (id, title) in posts {
+            let base = slugify(&title);
+            if base.is_empty() {
+                continue;
+            }
+
+            let is_from_title = |slug: &str| {
+                slug == base
+                    || slug
+                        .strip_prefix(&format!("{}-", base))
+                        .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+            };
+            let current = slugs
+                .iter()
+                .rev()
+                .find(|(_, post_id)| *post_id == id)
+                .map(|(slug, _)| slug.clone());
+
+            let slug = match current {
+                Some(current) if is_from_title(&current) => current,
+                _ => {
+                    let is_taken = |slug: &str| {
+                        (ids.contains(slug) && slug != id)
+                            || slugs
+                                .iter()
+                                .any(|(other, post_id)| other == slug && *post_id != id)
+                    };
+                    let mut slug = base.clone();
+                    let mut n = 2;
+                    while is_taken(&slug) {
+                        slug = format!("{}-{}", base, n);
+                        n += 1;
+                    }
+
+                    db.execute(
+                        r#"
+                            INSERT INTO post_slugs (slug, post_id, assigned)
+                            VALUES (?, ?, (SELECT COALESCE(MAX(assigned), 0) + 1 FROM post_slugs))
+                            ON CONFLICT (slug) DO UPDATE SET assigned = excluded.assigned;
+                        "#,
+                        (&slug, &id),
+                    )
+                    .context("failed to insert post slug into database")?;
+                    slugs.retain(|(other, _)| *other != slug);
+                    slugs.push((slug.clone(), id.clone()));
+                    slug
+                }
+            };
+
+            db.execute("UPDATE posts SET slug = ? WHERE id = ?;", (&slug, &id))
+                .context("failed to update post slug in database")?;
+        }
+
+        Ok(())
+    }
+
+    pub fn get_audio(&self, db: &Database) -> Result<Option<Audio>, Error> {
+        Ok(db
+            .query_mul(
+                r#"
+                    SELECT styles.name, length(blobs.data) - IIF(styles.is_encrypted, ?, 0)
+                    FROM posts_resources
+                    JOIN posts_assets ON posts_assets.post_id = posts_resources.post_id
+                    JOIN styles ON styles.id = posts_assets.asset_id
+                        AND styles.name = posts_resources.name
+                    JOIN blobs ON blobs.hash = styles.data_hash
+                    WHERE posts_resources.post_id = ? AND posts_resources.kind = ?;
+                "#,
+                (crypto::OVERHEAD as i64, &self.id, AUDIO_RESOURCE),
+                |row| Ok((row.get::<_, String>(0)?, row.get(1)?)),
+            )
+            .context("failed to query post audio from database")?
+            .pop()
+            .map(|(name, length)| Audio {
+                content_type: mime_guess::from_path(&name)
+                    .first_or_octet_stream()
+                    .to_string(),
+                url: routes::post_asset(&self.id, &name),
+                length,
+            }))
+    }
+
+    // urls of the post's own stylesheets or scripts, in the order of the metadata
+    pub fn get_resources(&self, db: &Database, kind: &str) -> Result<Vec<String>, Error> {
+        db.query_mul(
+            "SELECT name FROM posts_resources WHERE post_id = ? AND kind = ? ORDER BY rowid;",
+            (&self.id, kind),
+            |row| row.get::<_, String>(0),
+        )
+        .map(|names| {
+            names
+                .into_iter()
+                .map(|name| routes::post_asset(&self.id, &name))
+                .collect()
+        })
+        .context("failed to query post resources from database")
+    }
+
+    pub fn url(&self) -> String {
+        routes::post(&self.slug)
+    }
+
+    pub fn canonical_url(&self, cfg: &Config) -> String {
+        format!("{}{}", cfg.site_url.trim_end_matches('/'), self.url())
+    }
+
+    pub fn reading_time(&self) -> i64 {
+        i64::max(
+            1,
+            (self.word_count + WORDS_PER_MINUTE - 1) / WORDS_PER_MINUTE,
+        )
+    }
+
+    pub fn is_published(&self, tz: Tz) -> bool {
+        time::parse_date(&self.date, tz).is_none_or(|date| date <= time::now(tz))
+    }
+
+    pub fn is_expired(&self, tz: Tz) -> bool {
+        self.expires
+            .as_deref()
+            .and_then(|expires| time::parse_date(expires, tz))
+            .is_some_and(|expires| expires <= time::now(tz))
+    }
+
+    // scheduled posts are treated like private posts until their date has passed
+    fn readable_by(&self, user: Option<&User>, tz: Tz) -> bool {
+        (!self.is_private && self.allowed_group.is_none() && self.is_published(tz))
+            || user.is_some_and(|user| user.can_see(self.allowed_group.as_deref()))
+    }
+
+    // expired posts are unlisted for everyone, their pages answer with 410 Gone
+    pub fn visible_to(&self, user: Option<&User>, tz: Tz) -> bool {
+        !self.is_expired(tz) && self.readable_by(user, tz)
     }
 
     pub fn delete_all(db: &Database) -> Result<(), Error> {
+        db.execute("DELETE FROM post_aliases", [])
+            .context("failed to delete all post aliases from database")?;
+        db.execute("DELETE FROM posts_resources", [])
+            .context("failed to delete all post resources from database")?;
         db.execute("DELETE FROM posts", [])
             .context("failed to delete all posts from database")
     }
@@ -221,40 +972,159 @@ impl Post {
         .context("failed to query source for post from database")
     }
 
+    // the directory the post was loaded from
+    pub fn get_source_path(&self, db: &Database) -> Result<PathBuf, Error> {
+        let source_path: Option<String> = db
+            .query_one(
+                "SELECT source_path FROM posts WHERE id = ?;",
+                [&self.id],
+                |row| row.get(0),
+            )
+            .context("failed to query source path for post from database")?;
+        source_path
+            .map(PathBuf::from)
+            .context("post has no source path, rebuild the site first")
+    }
+
+    // removes everything loaded for a post except its photos, which `Post::new` picks up again
+    pub fn delete(db: &Database, id: &str) -> Result<(), Error> {
+        db.execute(
+            "DELETE FROM styles WHERE id IN (SELECT asset_id FROM posts_assets WHERE post_id = ?);",
+            [id],
+        )
+        .context("failed to delete post assets from database")?;
+
+        for table in [
+            "post_aliases",
+            "posts_resources",
+            "posts_tags",
+            "posts_photos",
+            "posts_assets",
+            "polls",
+            "post_events",
+            "post_tracks",
+        ] {
+            db.execute(&format!("DELETE FROM {} WHERE post_id = ?;", table), [id])
+                .context(format!("failed to delete post from {} table", table))?;
+        }
+
+        db.execute("DELETE FROM posts WHERE id = ?;", [id])
+            .context("failed to delete post from database")
+    }
+
+    // loads a post again from its source directory, e.g. after it was edited in the browser. A
+    // post that fails to load is left out, like a failed build would.
+    pub fn reload(
+        db: &Database,
+        cfg: &Config,
+        id: &str,
+        source_path: &Path,
+    ) -> Result<Post, Error> {
+        Post::delete(db, id)?;
+
+        let post = match Post::new(db, cfg, source_path) {
+            Ok(post) => post,
+            Err(error) => {
+                Post::delete(db, id)?;
+                return Err(error);
+            }
+        };
+
+        Post::assign_slugs(db)?;
+        Post::by_id(db, &post.id)
+    }
+
     pub fn get_all(db: &Database) -> Result<Vec<Post>, Error> {
         db.query_mul(
-            r#"
-                SELECT id, title, description, date, permalink
-                FROM posts
-                ORDER BY date DESC;
-            "#,
+            &format!("SELECT {} FROM posts ORDER BY date DESC;", COLUMNS),
             [],
             Post::from_row,
         )
         .context("failed to query posts from database")
     }
+
+    pub fn count_all(db: &Database) -> Result<u32, Error> {
+        db.query_one("SELECT COUNT(*) FROM posts;", [], |row| row.get(0))
+            .context("failed to count posts in database")
+    }
+
+    pub fn get_featured(db: &Database) -> Result<Vec<Post>, Error> {
+        db.query_mul(
+            &format!(
+                "SELECT {} FROM posts WHERE is_featured ORDER BY featured_order IS NULL, featured_order ASC, date DESC;",
+                COLUMNS
+            ),
+            [],
+            Post::from_row,
+        )
+        .context("failed to query featured posts from database")
+    }
+}
+
+// looks a post up by slug, id or alias. Visibility is checked here, before any redirect, so the
+// location never gives away the slug of a hidden post.
+pub(crate) fn find_post(
+    db: &Database,
+    cfg: &Config,
+    id: &str,
+    user: Option<&User>,
+) -> Result<Post, (u16, &'static str)> {
+    let post = match Post::by_slug(db, id)
+        .or_else(|_| Post::by_id(db, id))
+        .or_else(|_| Post::by_alias(db, id))
+        .or_else(|_| Post::by_old_slug(db, id))
+    {
+        Ok(post) => post,
+        Err(_) if Tombstone::exists(db, id).unwrap_or(false) => {
+            return Err((410, "This post has been removed"));
+        }
+        Err(_) => return Err((404, "Post not found")),
+    };
+
+    if !post.readable_by(user, cfg.timezone()) {
+        return Err((404, "Post not found"));
+    }
+
+    if post.is_expired(cfg.timezone()) {
+        return Err((410, "This post has expired"));
+    }
+
+    Ok(post)
 }
 
 pub async fn get_post(
     ax::State(state): ax::State<Arc<AppState>>,
     ax::Path(id): ax::Path<String>,
+    ax::Query(params): ax::Query<HashMap<String, String>>,
     cookie: ax::CookieJar,
+    lite: Lite,
 ) -> impl IntoResponse {
-    let db = &state.db.lock().unwrap();
+    let db = &match state.lock_db().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let cfg = &state.config.lock().unwrap();
     let user = User::from_cookie(db, &cookie).ok();
 
     println!("GET post {}, user = {:?}", id, user);
 
-    let post = match Post::by_id(db, &id) {
+    let post = match find_post(db, cfg, &id, user.as_ref()) {
         Ok(post) => post,
-        Err(_) => {
-            return match Post::by_permalink(db, &id) {
-                Ok(post) => ax::Redirect::to(&format!("/posts/{}/", post.id)).into_response(),
-                Err(_) => make_error(404, "Post not found").into_response(),
-            };
+        Err((404, message)) => {
+            return make_not_found(db, cfg, user, message, &routes::post(&id));
         }
+        Err((code, message)) => return make_error(code, message).into_response(),
     };
 
+    // ids and aliases permanently redirect to the slug
+    if id != post.slug {
+        return (
+            ax::StatusCode::MOVED_PERMANENTLY,
+            [(ax::header::LOCATION, post.url())],
+        )
+            .into_response();
+    }
+
     let tags = match post.get_tags(db) {
         Ok(tags) => tags,
         Err(_) => return make_error(500, "Failed to load tags").into_response(),
@@ -267,7 +1137,7 @@ pub async fn get_post(
 
     let photos_filtered: Vec<_> = photos_all
         .iter()
-        .filter(|photo| !photo.is_private || user.is_some())
+        .filter(|photo| photo.visible_to(user.as_ref()))
         .collect();
 
     let n_hidden = photos_all.len() - photos_filtered.len();
@@ -277,38 +1147,169 @@ pub async fn get_post(
         Err(_) => return make_error(500, "Failed to load markdown").into_response(),
     };
 
-    let source_html = match markdown_to_html(&source_md) {
+    let comments = match make_comments_section(
+        db,
+        cfg,
+        &post,
+        params
+            .get("comment")
+            .is_some_and(|comment| comment == "pending"),
+    ) {
+        Ok(comments) => comments,
+        Err(_) => return make_error(500, "Failed to load comments").into_response(),
+    };
+
+    let show_progress = cfg.reading_progress && !lite.0;
+    let is_admin = user.as_ref().is_some_and(User::is_admin);
+
+    let polls = match make_polls(db, &post, &cookie) {
+        Ok(polls) => polls,
+        Err(_) => return make_error(500, "Failed to load polls").into_response(),
+    };
+
+    let alt_texts = match AltText::get_all(db, &post.id) {
+        Ok(alt_texts) => alt_texts,
+        Err(_) => return make_error(500, "Failed to load alt text").into_response(),
+    };
+
+    let event = match Event::by_post(db, &post.id) {
+        Ok(event) => event,
+        Err(_) => return make_error(500, "Failed to load event").into_response(),
+    };
+
+    let audio = match post.get_audio(db) {
+        Ok(audio) => audio,
+        Err(_) => return make_error(500, "Failed to load audio").into_response(),
+    };
+
+    let tracks = match Track::by_post(db, &post.id) {
+        Ok(tracks) => tracks,
+        Err(_) => return make_error(500, "Failed to load tracks").into_response(),
+    };
+    // lite pages stay without scripts, the svg of the track is shown instead
+    let map = cfg.map.as_ref().filter(|_| !lite.0 && !tracks.is_empty());
+
+    let markdown_context = MarkdownContext {
+        photos: photos_filtered.clone(),
+        math: post.has_math,
+        lite: lite.0,
+        polls,
+        links_appendix: post.has_links_appendix,
+        alt_texts: alt_texts.clone(),
+        post_id: Some(&post.id),
+        assets_path: &cfg.post_assets_path,
+    };
+
+    let shortlink = match Shortlink::by_post(db, &post.id) {
+        Ok(shortlink) => shortlink,
+        Err(_) => return make_error(500, "Failed to load shortlink").into_response(),
+    };
+
+    let source_html = match markdown_to_html(&source_md, &markdown_context) {
         Ok(source_html) => source_html,
         Err(_) => return make_error(500, "Failed to get html").into_response(),
     };
 
     let content = html!(
         section class="post-info" {
-            p { (post.date) }
+            p { (time::display_date(&post.date, cfg.timezone())) }
+            p class="post-reading-time" {
+                "~" (post.reading_time()) " min read · "
+                a href=(routes::post_pdf(&post.slug)) { "PDF" }
+            }
+            @if let Some(event) = &event {
+                (event.to_html(cfg.timezone()))
+            }
             p {
                 @for tag in tags {
-                    a class="tag" href=(format!("/posts/?tag={}", tag)) { code { (format!("#{}", tag)) } } " ";
+                    a class="tag" href=(routes::posts_tagged(&tag)) { code { (format!("#{}", tag)) } } " ";
                 }
             }
+            @if is_admin {
+                p { a href=(routes::edit_post(&post.id)) { "Edit" } }
+            }
         }
 
         br{}
 
-        (PreEscaped(source_html))
+        @if show_progress {
+            div id="reading-progress" {}
+        }
+
+        @if let Some(audio) = &audio {
+            audio class="post-audio" controls preload="metadata" {
+                source src=(audio.url) type=(audio.content_type) {}
+                a href=(audio.url) { "Download the audio" }
+            }
+        }
+
+        article id="post-body" data-post=(post.id) {
+            (PreEscaped(source_html))
+        }
+
+        @for track in &tracks {
+            (track.to_html(map))
+        }
 
         @for photo in photos_filtered {
-            (photo.to_html(&format!("/photos/{}?size=large/", photo.id), "↪ full res"))
+            (photo.to_html(&routes::photo_sized(&photo.id, PhotoSize::Large), "↪ full res", None, alt_texts.get(photo.name()).map(String::as_str), lite))
+        }
+
+        @if n_hidden > 0 && user.is_none() {
+            p id="hidden-message" { "(" (n_hidden) " photos hidden, " a href=(routes::LOGIN) { "log in" } " to see all)" }
+        } @else if n_hidden > 0 {
+            p id="hidden-message" { "(" (n_hidden) " photos hidden)" }
         }
 
-        @if n_hidden > 0 {
-            p id="hidden-message" { "(" (n_hidden) " photos hidden, " a href="/login/" { "log in" } " to see all)" }
+        // for showing the post to people in the room, e.g. at a meetup
+        section class="post-share" {
+            h2 { "Share" }
+            img src=(routes::post_qr(&post.slug)) alt="QR code linking to this post" width="160" height="160" {}
+            p { code { (post.canonical_url(cfg)) } }
+            @if let Some(shortlink) = &shortlink {
+                p { "or " code { (cfg.site_url.trim_end_matches('/')) (routes::shortlink(&shortlink.code)) } }
+            }
         }
+
+        (comments)
     );
 
+    let (post_styles, mut post_scripts) = match (
+        post.get_resources(db, STYLE_RESOURCE),
+        post.get_resources(db, SCRIPT_RESOURCE),
+    ) {
+        (Ok(styles), Ok(scripts)) => (styles, scripts),
+        _ => return make_error(500, "Failed to load post resources").into_response(),
+    };
+    if show_progress {
+        post_scripts.insert(0, routes::script(PROGRESS_SCRIPT_NAME));
+    }
+    if map.is_some() {
+        post_scripts.insert(0, routes::script(TRACK_SCRIPT_NAME));
+        post_scripts.insert(0, routes::script(LEAFLET_SCRIPT_NAME));
+    }
+
+    let leaflet_style = routes::style(LEAFLET_STYLE_NAME);
+    let mut styles = vec!["/styles/photo.css", "/styles/post.css"];
+    if post.has_math {
+        styles.push("/styles/math.css");
+    }
+    if map.is_some() {
+        styles.push(&leaflet_style);
+    }
+    styles.extend(post_styles.iter().map(String::as_str));
+
     let page = make_page(
-        Some(&post.title),
-        &post.description.unwrap_or("".to_string()),
-        vec!["/styles/photo.css", "/styles/post.css"],
+        PageMeta::new(Section::Posts)
+            .title(&post.title)
+            .canonical(post.canonical_url(cfg))
+            .description(
+                post.description
+                    .unwrap_or_else(|| markdown_excerpt(&source_md, MAX_DESCRIPTION_LENGTH)),
+            )
+            .scripts(post_scripts)
+            .lite(lite),
+        styles,
         content,
         user,
         false,
@@ -317,18 +1318,158 @@ pub async fn get_post(
     ax::Html::from(page.into_string()).into_response()
 }
 
+pub async fn get_post_markdown(
+    ax::State(state): ax::State<Arc<AppState>>,
+    ax::Path(id): ax::Path<String>,
+    cookie: ax::CookieJar,
+) -> impl IntoResponse {
+    get_post_source(&state, &id, &cookie, SourceFormat::Markdown).await
+}
+
+pub async fn get_post_text(
+    ax::State(state): ax::State<Arc<AppState>>,
+    ax::Path(id): ax::Path<String>,
+    cookie: ax::CookieJar,
+) -> impl IntoResponse {
+    get_post_source(&state, &id, &cookie, SourceFormat::Text).await
+}
+
+pub async fn get_post_pdf(
+    ax::State(state): ax::State<Arc<AppState>>,
+    ax::Path(id): ax::Path<String>,
+    cookie: ax::CookieJar,
+) -> impl IntoResponse {
+    get_post_source(&state, &id, &cookie, SourceFormat::Pdf).await
+}
+
+pub async fn get_post_qr(
+    ax::State(state): ax::State<Arc<AppState>>,
+    ax::Path(id): ax::Path<String>,
+    cookie: ax::CookieJar,
+) -> impl IntoResponse {
+    get_post_source(&state, &id, &cookie, SourceFormat::Qr).await
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum SourceFormat {
+    Markdown,
+    Text,
+    Pdf,
+    Qr,
+}
+
+impl SourceFormat {
+    fn file_name(self) -> &'static str {
+        match self {
+            SourceFormat::Markdown => "index.md",
+            SourceFormat::Text => "index.txt",
+            SourceFormat::Pdf => "pdf",
+            SourceFormat::Qr => "qr.svg",
+        }
+    }
+}
+
+// `index.md` is the stored source, `index.txt` a plain text rendering of it and `pdf` that text
+// typeset, all without the photos the reader isn't allowed to see. `qr.svg` is the canonical url
+// of the post as a QR code, for showing it on a slide.
+async fn get_post_source(
+    state: &Arc<AppState>,
+    id: &str,
+    cookie: &ax::CookieJar,
+    format: SourceFormat,
+) -> ax::Response {
+    let db = &match state.lock_db().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let cfg = &state.config.lock().unwrap();
+    let user = User::from_cookie(db, cookie).ok();
+    let file_name = format.file_name();
+
+    println!("GET post {}/{}, user = {:?}", id, file_name, user);
+
+    let post = match find_post(db, cfg, id, user.as_ref()) {
+        Ok(post) => post,
+        Err((code, message)) => return make_error(code, message).into_response(),
+    };
+
+    if id != post.slug {
+        return (
+            ax::StatusCode::MOVED_PERMANENTLY,
+            [(ax::header::LOCATION, format!("{}{}", post.url(), file_name))],
+        )
+            .into_response();
+    }
+
+    let (photos, source) = match (Photo::get_all(db, Some(&post.id)), post.get_source(db)) {
+        (Ok(photos), Ok(source)) => (photos, source),
+        _ => return make_error(500, "Failed to load post").into_response(),
+    };
+
+    let ctx = MarkdownContext {
+        photos: photos
+            .iter()
+            .filter(|photo| photo.visible_to(user.as_ref()))
+            .collect(),
+        math: post.has_math,
+        lite: false,
+        polls: HashMap::new(),
+        links_appendix: false,
+        alt_texts: HashMap::new(),
+        post_id: None,
+        assets_path: "",
+    };
+
+    let date = time::display_date(&post.date, cfg.timezone());
+    let (content_type, body) = match format {
+        SourceFormat::Text => (
+            "text/plain; charset=utf-8",
+            format!("{}\n{}\n\n", post.title, date) + &markdown_to_text(&source, &ctx),
+        ),
+        SourceFormat::Markdown => (
+            "text/markdown; charset=utf-8",
+            filter_photo_shortcodes(&source, &ctx),
+        ),
+        SourceFormat::Pdf => {
+            let text = markdown_to_text(&source, &ctx);
+            return pdf_response(state, db, &post.slug, &post.title, &date, &text);
+        }
+        SourceFormat::Qr => return qr_response(&post.canonical_url(cfg)),
+    };
+
+    ([(ax::header::CONTENT_TYPE, content_type)], body).into_response()
+}
+
+fn qr_response(url: &str) -> ax::Response {
+    let Ok(code) = qrcode::QrCode::new(url.as_bytes()) else {
+        return make_error(500, "Failed to make QR code").into_response();
+    };
+    let svg = code
+        .render::<qrcode::render::svg::Color>()
+        .min_dimensions(256, 256)
+        .build();
+
+    ([(ax::header::CONTENT_TYPE, "image/svg+xml")], svg).into_response()
+}
+
 pub async fn get_posts(
     ax::State(state): ax::State<Arc<AppState>>,
     ax::Query(params): ax::Query<HashMap<String, String>>,
     cookie: ax::CookieJar,
+    lite: Lite,
 ) -> impl IntoResponse {
-    let db = &state.db.lock().unwrap();
+    let db = &match state.lock_db().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let cfg = &state.config.lock().unwrap();
     let tag = params.get("tag").map(|s| s.to_lowercase());
     let user = User::from_cookie(db, &cookie).ok();
 
     println!("GET posts, tag: {:?}, user = {:?}", tag, user);
 
-    let posts_table = match make_posts_table(db, tag.clone(), None, false, true) {
+    let posts_table = match make_posts_table(db, cfg, user.as_ref(), tag.clone(), None, false, true)
+    {
         Ok(posts_table) => posts_table,
         Err(_) => return make_error(500, "Failed to load posts table").into_response(),
     };
@@ -336,8 +1477,8 @@ pub async fn get_posts(
     let content = html! {
         @if let Some(tag) = tag.as_ref() {
             section class="post-header" {
-                p { "Only showing posts tagged with " a class="tag" href=(format!("/posts/?tag={}", tag)) { code { (format!("#{}", tag)) } } }
-                p { a href="/posts/" { "> show all <" } }
+                p { "Only showing posts tagged with " a class="tag" href=(routes::posts_tagged(tag)) { code { (format!("#{}", tag)) } } }
+                p { a href=(routes::POSTS) { "> show all <" } }
             }
         }
 
@@ -345,8 +1486,13 @@ pub async fn get_posts(
     };
 
     let page = make_page(
-        Some("Posts"),
-        "A list of all posts.",
+        match &tag {
+            Some(tag) => PageMeta::new(Section::Posts)
+                .title("Posts")
+                .description(format!("A list of all posts tagged with #{}.", tag)),
+            None => PageMeta::new(Section::Posts).title("Posts"),
+        }
+        .lite(lite),
         vec!["/styles/post.css"],
         content,
         user,
@@ -358,6 +1504,8 @@ pub async fn get_posts(
 
 pub fn make_posts_table(
     db: &Database,
+    cfg: &Config,
+    user: Option<&User>,
     tag: Option<String>,
     limit: Option<u32>,
     with_description: bool,
@@ -365,9 +1513,39 @@ pub fn make_posts_table(
 ) -> Result<PreEscaped<String>, Error> {
     let posts = Post::get_all(db)?
         .into_iter()
+        .filter(|post| post.visible_to(user, cfg.timezone()))
         .take(limit.unwrap_or(u32::MAX) as usize)
         .collect::<Vec<_>>();
 
+    render_posts_table(db, cfg, posts, tag, with_description, with_date)
+}
+
+// None if the reader can't see any featured posts
+pub fn make_featured_table(
+    db: &Database,
+    cfg: &Config,
+    user: Option<&User>,
+) -> Result<Option<PreEscaped<String>>, Error> {
+    let posts = Post::get_featured(db)?
+        .into_iter()
+        .filter(|post| post.visible_to(user, cfg.timezone()))
+        .collect::<Vec<_>>();
+
+    if posts.is_empty() {
+        return Ok(None);
+    }
+
+    render_posts_table(db, cfg, posts, None, true, false).map(Some)
+}
+
+fn render_posts_table(
+    db: &Database,
+    cfg: &Config,
+    posts: Vec<Post>,
+    tag: Option<String>,
+    with_description: bool,
+    with_date: bool,
+) -> Result<PreEscaped<String>, Error> {
     Ok(html!(
         table class="post-table" {
             @for post in posts {
@@ -377,19 +1555,20 @@ pub fn make_posts_table(
                     tr {
                         td {
                             div class="post-title" {
-                                a href=(format!("/posts/{}/", post.id))  { (post.title) }
+                                a href=(post.url())  { (post.title) }
                             }
                             div class="post-tags" {
                                 @for tag in tags {
-                                    a class="tag" href=(format!("/posts/?tag={}", tag)) { code { (format!("#{}", tag)) } } " ";
+                                    a class="tag" href=(routes::posts_tagged(&tag)) { code { (format!("#{}", tag)) } } " ";
                                 }
                             }
+                            div class="post-reading-time" { "~" (post.reading_time()) " min read" }
                             @if with_description {
                                 div class="post-description" { (post.description.unwrap_or("".to_string())) }
                             }
                         }
                         @if with_date {
-                            td class="post-date" { (post.date) }
+                            td class="post-date" { (time::display_date(&post.date, cfg.timezone())) }
                         }
                     }
                 }
@@ -398,13 +1577,25 @@ pub fn make_posts_table(
     ))
 }
 
-fn markdown_to_html(markdown: &str) -> Result<String, Error> {
-    let arena = comrak::Arena::new();
-    let root = comrak::parse_document(&arena, markdown, &comrak::Options::default());
-    let mut content = String::new();
-    comrak::format_html(root, &comrak::Options::default(), &mut content)
-        .context("failed to compile markdown")?;
-    Ok(content)
+// "Hello, World!" -> "hello-world"
+pub(crate) fn slugify(title: &str) -> String {
+    let mut slug = String::new();
+    for c in title.chars().flat_map(|c| c.to_lowercase()) {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.truncate(MAX_SLUG_LENGTH);
+    slug.trim_end_matches('-').to_string()
+}
+
+fn count_words(markdown: &str) -> i64 {
+    markdown
+        .split_whitespace()
+        .filter(|word| word.chars().any(|c| c.is_alphanumeric()))
+        .count() as i64
 }
 
 // fn next_color(prev_color: &mut Option<u32>) -> u32 {
@@ -416,3 +1607,37 @@ fn markdown_to_html(markdown: &str) -> Result<String, Error> {
 //         }
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slugs_are_lowercase_words_joined_by_dashes() {
+        assert_eq!(slugify("Hello, World!"), "hello-world");
+        assert_eq!(
+            slugify("  Rust 2024: what's new?  "),
+            "rust-2024-what-s-new"
+        );
+        assert_eq!(slugify("\u{dc}n\u{ef}code \u{c7}a va"), "n-code-a-va");
+        assert_eq!(slugify("\u{65e5}\u{672c}"), "");
+        assert_eq!(slugify(&"abc ".repeat(40)).len(), MAX_SLUG_LENGTH - 1);
+    }
+
+    #[test]
+    fn ids_are_added_in_front_keeping_the_layout() {
+        assert_eq!(
+            json_with_id("{\n  \"title\": \"Post\"\n}\n", "abc"),
+            "{\n  \"id\": \"abc\",\n  \"title\": \"Post\"\n}\n"
+        );
+        assert_eq!(
+            json_with_id("{\n\t\"title\": \"Post\"\n}", "abc"),
+            "{\n\t\"id\": \"abc\",\n\t\"title\": \"Post\"\n}"
+        );
+        assert_eq!(
+            json_with_id("{\"title\":\"Post\"}", "abc"),
+            "{ \"id\": \"abc\", \"title\":\"Post\"}"
+        );
+        assert_eq!(json_with_id("{}", "abc"), "{ \"id\": \"abc\" }");
+    }
+}
@@ -0,0 +1,62 @@
+// Reading progress for posts: a bar at the top of the page, and the last read section is
+// remembered per post so coming back to it picks up where the reader left off.
+(function () {
+    var body = document.getElementById("post-body");
+    var bar = document.getElementById("reading-progress");
+    if (!body || !bar) return;
+
+    var key = "reading-progress:" + body.dataset.post;
+    var sections = body.querySelectorAll("[data-section]");
+
+    bar.style.cssText = "position:fixed;top:0;left:0;height:3px;width:0;background:currentColor;z-index:10";
+
+    function progress() {
+        var rect = body.getBoundingClientRect();
+        var total = rect.height - window.innerHeight;
+        if (total <= 0) return 1;
+        return Math.min(1, Math.max(0, -rect.top / total));
+    }
+
+    function currentSection() {
+        var current = null;
+        for (var i = 0; i < sections.length; i++) {
+            if (sections[i].getBoundingClientRect().top > window.innerHeight / 3) break;
+            current = sections[i].id;
+        }
+        return current;
+    }
+
+    var pending = false;
+    window.addEventListener("scroll", function () {
+        if (pending) return;
+        pending = true;
+        window.requestAnimationFrame(function () {
+            pending = false;
+            var value = progress();
+            bar.style.width = value * 100 + "%";
+            try {
+                localStorage.setItem(key, JSON.stringify({
+                    section: currentSection(),
+                    offset: window.scrollY,
+                    done: value >= 1,
+                }));
+            } catch (e) {}
+        });
+    }, { passive: true });
+
+    // deep links always win over the stored position
+    if (window.location.hash) return;
+
+    var saved = null;
+    try {
+        saved = JSON.parse(localStorage.getItem(key));
+    } catch (e) {}
+    if (!saved || saved.done) return;
+
+    var section = saved.section && document.getElementById(saved.section);
+    if (section) {
+        section.scrollIntoView();
+    } else if (saved.offset) {
+        window.scrollTo(0, saved.offset);
+    }
+})();
@@ -1,24 +1,369 @@
+use std::time::Duration;
+
+use crate::config::{GithubProjectsConfig, GithubRepositories};
+use crate::database::SqliteError;
 use crate::prelude::*;
 
+const TIMEOUT: Duration = Duration::from_secs(10);
+const GITHUB_GRAPHQL: &str = "https://api.github.com/graphql";
+const GITHUB_API: &str = "https://api.github.com";
+
+pub const PROJECTS_STYLE_NAME: &str = "projects.css";
+pub const PROJECTS_STYLE: &[u8] = include_bytes!("projects.css");
+
+const PINNED_QUERY: &str = r#"
+    query($login: String!, $first: Int!) {
+        user(login: $login) {
+            pinnedItems(first: $first, types: REPOSITORY) {
+                nodes {
+                    ... on Repository {
+                        name
+                        description
+                        url
+                        stargazerCount
+                        primaryLanguage { name }
+                    }
+                }
+            }
+        }
+    }
+"#;
+
+// A card on the projects page. Entries from the config come first, in their order, followed by
+// the repositories fetched from GitHub. The table is content, but a build only replaces the
+// repositories when GitHub answered, so an outage keeps the cards of the previous build.
+pub struct Project {
+    pub url: String,
+    pub name: String,
+    pub description: String,
+    pub language: Option<String>,
+    // only known for repositories from GitHub
+    pub stars: Option<i64>,
+}
+
+impl Project {
+    pub fn setup(db: &Database) -> Result<(), Error> {
+        db.execute_batch(
+            r#"
+                CREATE TABLE IF NOT EXISTS projects (
+                    url TEXT PRIMARY KEY NOT NULL,
+                    name TEXT NOT NULL,
+                    description TEXT NOT NULL,
+                    language TEXT NULL,
+                    stars INTEGER NULL,
+                    from_github INTEGER NOT NULL,
+                    position INTEGER NOT NULL
+                );
+            "#,
+        )
+        .context("failed to create projects table")
+    }
+
+    fn from_row(row: &Row) -> Result<Self, SqliteError> {
+        Ok(Self {
+            url: row.get(0)?,
+            name: row.get(1)?,
+            description: row.get(2)?,
+            language: row.get(3)?,
+            stars: row.get(4)?,
+        })
+    }
+
+    pub fn get_all(db: &Database) -> Result<Vec<Self>, Error> {
+        db.query_mul(
+            r#"
+                SELECT url, name, description, language, stars FROM projects
+                ORDER BY from_github, position;
+            "#,
+            [],
+            Project::from_row,
+        )
+        .context("failed to query projects from database")
+    }
+
+    fn insert(
+        db: &Database,
+        project: &Project,
+        from_github: bool,
+        position: usize,
+    ) -> Result<(), Error> {
+        db.execute(
+            r#"
+                INSERT OR IGNORE INTO projects (url, name, description, language, stars, from_github, position)
+                VALUES (?, ?, ?, ?, ?, ?, ?);
+            "#,
+            (
+                &project.url,
+                &project.name,
+                &project.description,
+                &project.language,
+                project.stars,
+                from_github,
+                position as i64,
+            ),
+        )
+        .context("failed to insert project into database")
+    }
+
+    // Replaces the projects with the ones in the config and, if configured, the repositories from
+    // GitHub. A failed fetch is only a warning, like webmentions, and keeps the previous ones.
+    pub fn sync(db: &Database, cfg: &Config) -> Result<(), Error> {
+        let github = match &cfg.github_projects {
+            Some(github) => match fetch(github, cfg.github_token().as_deref()) {
+                Ok(projects) => Some(projects),
+                Err(error) => {
+                    println!(
+                        "warning: failed to fetch projects of {} from GitHub: {}",
+                        github.user,
+                        error.message()
+                    );
+                    None
+                }
+            },
+            None => Some(Vec::new()),
+        };
+
+        match github {
+            Some(_) => db.execute("DELETE FROM projects;", []),
+            None => db.execute("DELETE FROM projects WHERE from_github = 0;", []),
+        }
+        .context("failed to delete projects from database")?;
+
+        // a manual entry for a repository wins over the fetched one
+        for (position, project) in cfg.projects.iter().enumerate() {
+            let project = Project {
+                url: project.url.clone(),
+                name: project.name.clone(),
+                description: project.description.clone(),
+                language: project.language.clone(),
+                stars: None,
+            };
+            db.execute("DELETE FROM projects WHERE url = ?;", [&project.url])
+                .context("failed to delete project from database")?;
+            Project::insert(db, &project, false, position)?;
+        }
+
+        for (position, project) in github.iter().flatten().enumerate() {
+            Project::insert(db, project, true, position)?;
+        }
+
+        Ok(())
+    }
+
+    fn to_html(&self) -> PreEscaped<String> {
+        html! {
+            article class="project" {
+                h2 { a href=(self.url) { (self.name) } }
+                @if !self.description.is_empty() {
+                    p { (self.description) }
+                }
+                p class="project-meta" {
+                    @if let Some(language) = &self.language {
+                        span { (language) }
+                    }
+                    @if let Some(stars) = self.stars {
+                        span title="Stars" { "★ " (stars) }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct PinnedResponse {
+    data: Option<PinnedData>,
+}
+
+#[derive(Deserialize)]
+struct PinnedData {
+    user: Option<PinnedUser>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PinnedUser {
+    pinned_items: PinnedItems,
+}
+
+#[derive(Deserialize)]
+struct PinnedItems {
+    nodes: Vec<PinnedRepository>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PinnedRepository {
+    name: String,
+    description: Option<String>,
+    url: String,
+    stargazer_count: i64,
+    primary_language: Option<Language>,
+}
+
+#[derive(Deserialize)]
+struct Language {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct StarredRepository {
+    name: String,
+    description: Option<String>,
+    html_url: String,
+    stargazers_count: i64,
+    language: Option<String>,
+}
+
+fn fetch(github: &GithubProjectsConfig, token: Option<&str>) -> Result<Vec<Project>, Error> {
+    let agent = ureq::AgentBuilder::new()
+        .timeout(TIMEOUT)
+        .user_agent(concat!(
+            "website/",
+            env!("CARGO_PKG_VERSION"),
+            " (projects)"
+        ))
+        .build();
+
+    match github.repositories {
+        GithubRepositories::Pinned => fetch_pinned(&agent, github, token),
+        GithubRepositories::Starred => fetch_starred(&agent, github, token),
+    }
+}
+
+// pinned repositories are only in the GraphQL API, which always needs a token
+fn fetch_pinned(
+    agent: &ureq::Agent,
+    github: &GithubProjectsConfig,
+    token: Option<&str>,
+) -> Result<Vec<Project>, Error> {
+    let token = token.context("pinned repositories need a token")?;
+    let body = serde_json::json!({
+        "query": PINNED_QUERY,
+        "variables": { "login": github.user, "first": github.max_projects },
+    });
+
+    let response = agent
+        .post(GITHUB_GRAPHQL)
+        .set("Authorization", &format!("Bearer {}", token))
+        .send_string(&body.to_string())
+        .context("GitHub rejected the query")?
+        .into_string()
+        .context("failed to read response from GitHub")?;
+    let response: PinnedResponse =
+        serde_json::from_str(&response).context("invalid response from GitHub")?;
+
+    let user = response
+        .data
+        .and_then(|data| data.user)
+        .context(format!("no GitHub user named {}", github.user))?;
+
+    Ok(user
+        .pinned_items
+        .nodes
+        .into_iter()
+        .map(|repository| Project {
+            url: repository.url,
+            name: repository.name,
+            description: repository.description.unwrap_or_default(),
+            language: repository.primary_language.map(|language| language.name),
+            stars: Some(repository.stargazer_count),
+        })
+        .collect())
+}
+
+fn fetch_starred(
+    agent: &ureq::Agent,
+    github: &GithubProjectsConfig,
+    token: Option<&str>,
+) -> Result<Vec<Project>, Error> {
+    let mut request = agent
+        .get(&format!("{}/users/{}/starred", GITHUB_API, github.user))
+        .query("per_page", &github.max_projects.to_string())
+        .set("Accept", "application/vnd.github+json");
+    if let Some(token) = token {
+        request = request.set("Authorization", &format!("Bearer {}", token));
+    }
+
+    let response = request
+        .call()
+        .context("GitHub rejected the request")?
+        .into_string()
+        .context("failed to read response from GitHub")?;
+    let repositories: Vec<StarredRepository> =
+        serde_json::from_str(&response).context("invalid response from GitHub")?;
+
+    Ok(repositories
+        .into_iter()
+        .map(|repository| Project {
+            url: repository.html_url,
+            name: repository.name,
+            description: repository.description.unwrap_or_default(),
+            language: repository.language,
+            stars: Some(repository.stargazers_count),
+        })
+        .collect())
+}
+
 pub async fn get_projects(
     ax::State(state): ax::State<Arc<AppState>>,
     cookie: ax::CookieJar,
+    lite: Lite,
 ) -> impl IntoResponse {
-    let db = &state.db.lock().unwrap();
+    let db = &match state.lock_db().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let cfg = &state.config.lock().unwrap();
     let user = User::from_cookie(db, &cookie).ok();
 
     println!("GET projects, user = {:?}", user);
 
-    let posts_table = match make_posts_table(db, Some("project".to_string()), None, true, false) {
-        Ok(posts_table) => posts_table,
-        Err(_) => return make_error(500, "Failed to load posts table").into_response(),
+    let projects = match Project::get_all(db) {
+        Ok(projects) => projects,
+        Err(_) => return make_error(500, "Failed to load projects").into_response(),
     };
 
+    // without any configured projects, the page lists the posts tagged "project" like before
+    if projects.is_empty() {
+        let posts_table = match make_posts_table(
+            db,
+            cfg,
+            user.as_ref(),
+            Some("project".to_string()),
+            None,
+            true,
+            false,
+        ) {
+            Ok(posts_table) => posts_table,
+            Err(_) => return make_error(500, "Failed to load posts table").into_response(),
+        };
+
+        let page = make_page(
+            PageMeta::new(Section::Projects)
+                .title("Projects")
+                .lite(lite),
+            vec!["/styles/post.css"],
+            posts_table,
+            user,
+            false,
+        );
+
+        return ax::Html::from(page.into_string()).into_response();
+    }
+
     let page = make_page(
-        Some("Projects"),
-        "A list of all projects.",
-        vec!["/styles/post.css"],
-        posts_table,
+        PageMeta::new(Section::Projects)
+            .title("Projects")
+            .lite(lite),
+        vec!["/styles/projects.css"],
+        html! {
+            div class="projects" {
+                @for project in &projects {
+                    (project.to_html())
+                }
+            }
+        },
         user,
         false,
     );
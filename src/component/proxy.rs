@@ -0,0 +1,85 @@
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+
+use axum::extract::{ConnectInfo, FromRequestParts};
+use axum::http::request::Parts;
+
+use crate::prelude::*;
+
+// Who sent a request. Behind a reverse proxy every connection comes from the proxy, so the
+// `X-Forwarded-For` and `X-Forwarded-Proto` headers it adds are used instead, but only when the
+// connection comes from one of `trusted_proxies`, anyone else could send them too. Connections
+// without an address (the unix socket) can only come from the machine itself and are trusted.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Client {
+    // none if the proxy didn't say
+    pub ip: Option<IpAddr>,
+    pub https: bool,
+}
+
+impl Client {
+    pub fn from_parts(
+        headers: &ax::HeaderMap,
+        extensions: &axum::http::Extensions,
+        cfg: &Config,
+    ) -> Client {
+        let peer = extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(address)| address.ip());
+        let trusted = |ip: &IpAddr| cfg.trusted_proxies.contains(ip);
+
+        if peer.as_ref().is_some_and(|peer| !trusted(peer)) {
+            return Client {
+                ip: peer,
+                https: false,
+            };
+        }
+
+        // each proxy appends the address it got the request from, so the client is the last one
+        // that isn't a proxy itself
+        let forwarded: Vec<IpAddr> = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|ip| ip.trim().parse().ok())
+            .collect();
+        let ip = forwarded
+            .iter()
+            .rev()
+            .find(|ip| !trusted(ip))
+            .or(forwarded.first())
+            .copied()
+            .or(peer);
+
+        let https = headers
+            .get("x-forwarded-proto")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(',').next())
+            .is_some_and(|proto| proto.trim().eq_ignore_ascii_case("https"));
+
+        Client { ip, https }
+    }
+
+    // `site_url` with `path`, over https if the client came over https even if the configured
+    // url says http
+    pub fn absolute_url(&self, cfg: &Config, path: &str) -> String {
+        let site_url = cfg.site_url.trim_end_matches('/');
+        match site_url.strip_prefix("http://") {
+            Some(rest) if self.https => format!("https://{}{}", rest, path),
+            _ => format!("{}{}", site_url, path),
+        }
+    }
+}
+
+impl FromRequestParts<Arc<AppState>> for Client {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let cfg = state.config.lock().unwrap();
+        Ok(Client::from_parts(&parts.headers, &parts.extensions, &cfg))
+    }
+}
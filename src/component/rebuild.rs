@@ -0,0 +1,135 @@
+use crate::prelude::*;
+use crate::time;
+
+// a token needs this scope to trigger a rebuild
+pub const REBUILD_SCOPE: &str = "rebuild";
+
+pub struct RebuildResult {
+    pub finished_at: i64,
+    pub error: Option<String>,
+}
+
+#[derive(Default)]
+struct RebuildState {
+    running_since: Option<i64>,
+    last: Option<RebuildResult>,
+}
+
+// Rebuilds triggered on the server since it started. Only one runs at a time.
+#[derive(Default)]
+pub struct RebuildStatus(Mutex<RebuildState>);
+
+impl RebuildStatus {
+    // false if a rebuild is already running
+    fn start(&self) -> bool {
+        let mut state = self.0.lock().unwrap();
+        if state.running_since.is_some() {
+            return false;
+        }
+        state.running_since = Some(chrono::Utc::now().timestamp());
+        true
+    }
+
+    fn finish(&self, result: Result<(), Error>) {
+        let mut state = self.0.lock().unwrap();
+        state.running_since = None;
+        state.last = Some(RebuildResult {
+            finished_at: chrono::Utc::now().timestamp(),
+            error: result.err().map(|error| error.chain_message()),
+        });
+    }
+
+    pub fn to_html(&self, tz: Tz) -> PreEscaped<String> {
+        let state = self.0.lock().unwrap();
+
+        html!(
+            @if let Some(since) = state.running_since {
+                p { "Rebuilding since " (time::display_timestamp_time(since, tz)) "…" }
+            } @else {
+                @match &state.last {
+                    Some(RebuildResult { finished_at, error: None }) => {
+                        p { "Last rebuild succeeded at " (time::display_timestamp_time(*finished_at, tz)) "." }
+                    }
+                    Some(RebuildResult { finished_at, error: Some(error) }) => {
+                        p { "Last rebuild failed at " (time::display_timestamp_time(*finished_at, tz)) ": " code { (error) } }
+                    }
+                    None => p { "No rebuilds since the server started." },
+                }
+                form action=(routes::REBUILD) method="post" {
+                    (csrf_field())
+                    input type="submit" value="Rebuild now" {}
+                }
+            }
+        )
+    }
+}
+
+// Runs the build on its own connection and in one transaction, so pages keep being served from the
+// current content while it runs and after it fails. The result shows up on the admin dashboard.
+pub fn start_rebuild(state: &Arc<AppState>) -> bool {
+    if !state.rebuild.start() {
+        return false;
+    }
+
+    let state = state.clone();
+    let config = state.config.lock().unwrap().clone();
+    tokio::task::spawn_blocking(move || {
+        println!("rebuilding");
+        let result = Database::open(&config)
+            .and_then(|db| crate::run_build(&db, &config, true, false, false));
+        match &result {
+            Ok(report) => print!("rebuild done\n{}", report.to_table()),
+            Err(error) => println!("rebuild failed: {}", error.chain_message()),
+        }
+        state.rebuild.finish(result.map(|_| ()));
+    });
+
+    true
+}
+
+pub async fn post_rebuild(
+    ax::State(state): ax::State<Arc<AppState>>,
+    bearer: Option<Bearer>,
+    cookie: ax::CookieJar,
+) -> impl IntoResponse {
+    let user = match state.lock_db().await {
+        Ok(db) => User::from_cookie(&db, &cookie).ok(),
+        Err(response) => return response,
+    };
+
+    println!(
+        "POST rebuild, token = {:?}, user = {:?}",
+        bearer.as_ref().map(|bearer| &bearer.0),
+        user
+    );
+
+    match &bearer {
+        Some(Bearer(token)) if !token.has_scope(REBUILD_SCOPE) => {
+            return api_error(
+                403,
+                "insufficient_scope",
+                "token is missing the rebuild scope",
+            );
+        }
+        Some(_) => {}
+        None if user.as_ref().is_some_and(User::is_admin) => {}
+        None => return make_error(404, "Page not found").into_response(),
+    }
+
+    let started = start_rebuild(&state);
+
+    match (bearer, started) {
+        (Some(_), true) => (
+            ax::StatusCode::ACCEPTED,
+            axum::Json(serde_json::json!({ "status": "started" })),
+        )
+            .into_response(),
+        (Some(_), false) => (
+            ax::StatusCode::CONFLICT,
+            axum::Json(serde_json::json!({ "status": "already running" })),
+        )
+            .into_response(),
+        // the dashboard shows whether it's running
+        (None, _) => ax::Redirect::to(&format!("{}#rebuild", routes::ADMIN)).into_response(),
+    }
+}
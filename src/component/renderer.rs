@@ -0,0 +1,197 @@
+use crate::component::asciidoc::AsciiDoc;
+use crate::component::notebook::Notebook;
+use crate::component::org::Org;
+use crate::prelude::*;
+
+// A format posts can be written in. Pages are rendered from markdown with comrak, and so are the
+// text and PDF versions, feeds and webmentions, so a renderer brings its format into the site's
+// markdown when the post is loaded and everything after that works the same for every format.
+pub trait ContentRenderer: Sync {
+    fn to_markdown(&self, source: &str, assets_path: &str) -> Result<Rendered, Error>;
+
+    // only markdown has a frontmatter, other formats keep their metadata in meta.json
+    fn has_frontmatter(&self) -> bool {
+        false
+    }
+}
+
+pub struct Rendered {
+    pub markdown: String,
+    // files the markdown links to in the post's assets, e.g. the plots of a notebook
+    pub assets: Vec<RenderedAsset>,
+}
+
+pub struct RenderedAsset {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+impl Rendered {
+    pub fn markdown(markdown: String) -> Self {
+        Self {
+            markdown,
+            assets: vec![],
+        }
+    }
+}
+
+// the default, comrak renders it as it is
+pub struct Markdown;
+
+impl ContentRenderer for Markdown {
+    fn to_markdown(&self, source: &str, _assets_path: &str) -> Result<Rendered, Error> {
+        Ok(Rendered::markdown(source.to_string()))
+    }
+
+    fn has_frontmatter(&self) -> bool {
+        true
+    }
+}
+
+// by the extension of the content file, anything unknown is markdown
+const RENDERERS: &[(&str, &dyn ContentRenderer)] = &[
+    ("md", &Markdown),
+    ("adoc", &AsciiDoc),
+    ("asciidoc", &AsciiDoc),
+    ("org", &Org),
+    ("ipynb", &Notebook),
+];
+
+fn renderer_for(path: &Path) -> &'static dyn ContentRenderer {
+    let extension = path.extension().and_then(|extension| extension.to_str());
+    RENDERERS
+        .iter()
+        .find(|(known, _)| Some(*known) == extension)
+        .map_or(&Markdown, |(_, renderer)| *renderer)
+}
+
+// The content file of a post and how to render it: `post_content_path` if it exists, otherwise
+// the first file with the same name and the extension of another format, e.g. `index.org`.
+pub fn find_content(
+    cfg: &Config,
+    source_path: &Path,
+) -> Result<(PathBuf, &'static dyn ContentRenderer), Error> {
+    let index_path = source_path.join(&cfg.post_content_path);
+    if index_path.exists() {
+        return Ok((index_path.clone(), renderer_for(&index_path)));
+    }
+
+    RENDERERS
+        .iter()
+        .map(|(extension, renderer)| (index_path.with_extension(extension), *renderer))
+        .find(|(path, _)| path.exists())
+        .context(format!("post has no content file {:?}", index_path))
+        .map_err(|error| error.with_kind(ErrorKind::Validation))
+}
+
+// whether the post is written in markdown, the other formats are edited with their own tools
+pub fn is_markdown(cfg: &Config, source_path: &Path) -> bool {
+    find_content(cfg, source_path).is_ok_and(|(_, renderer)| renderer.has_frontmatter())
+}
+
+// Inline markup of formats that mark it with the same character on both sides, like `*bold*` in
+// org. A marker opens after a space or an opening bracket and closes before a space or a
+// punctuation mark, so `a*b*c` and urls stay as they are. Code is never converted further.
+pub(crate) struct InlineMarkup<'a> {
+    // e.g. `=` and `~` in org, the text between them becomes a code span
+    pub code: &'a [char],
+    // e.g. `/` in org becomes `*` in markdown
+    pub emphasis: &'a [(char, &'a str)],
+    // turns the links of the format into markdown, before emphasis is converted
+    pub links: fn(&str) -> Vec<Piece>,
+}
+
+pub(crate) enum Piece {
+    Text(String),
+    // already markdown
+    Done(String),
+}
+
+impl InlineMarkup<'_> {
+    pub fn convert(&self, line: &str) -> String {
+        let mut output = String::with_capacity(line.len());
+        for piece in self.code_spans(line) {
+            match piece {
+                Piece::Done(done) => output.push_str(&done),
+                Piece::Text(text) => {
+                    for piece in (self.links)(&text) {
+                        match piece {
+                            Piece::Done(done) => output.push_str(&done),
+                            Piece::Text(text) => output.push_str(&self.emphasis(&text)),
+                        }
+                    }
+                }
+            }
+        }
+        output
+    }
+
+    fn code_spans(&self, line: &str) -> Vec<Piece> {
+        let chars = line.chars().collect::<Vec<_>>();
+        let mut pieces = vec![];
+        let mut text = String::new();
+        let mut i = 0;
+
+        while i < chars.len() {
+            if self.code.contains(&chars[i])
+                && let Some(end) = closing(&chars, i)
+            {
+                let code = chars[i + 1..end].iter().collect::<String>();
+                pieces.push(Piece::Text(std::mem::take(&mut text)));
+                let fence = if code.contains('`') { "``" } else { "`" };
+                pieces.push(Piece::Done(format!("{} {} {}", fence, code, fence)));
+                i = end + 1;
+                continue;
+            }
+            text.push(chars[i]);
+            i += 1;
+        }
+
+        pieces.push(Piece::Text(text));
+        pieces
+    }
+
+    fn emphasis(&self, text: &str) -> String {
+        let chars = text.chars().collect::<Vec<_>>();
+        let mut output = String::with_capacity(text.len());
+        let mut i = 0;
+
+        while i < chars.len() {
+            if let Some((_, replacement)) = self.emphasis.iter().find(|(c, _)| *c == chars[i])
+                && let Some(end) = closing(&chars, i)
+            {
+                let inner = chars[i + 1..end].iter().collect::<String>();
+                output.push_str(replacement);
+                output.push_str(&self.emphasis(&inner));
+                output.push_str(replacement);
+                i = end + 1;
+                continue;
+            }
+            output.push(chars[i]);
+            i += 1;
+        }
+
+        output
+    }
+}
+
+// the marker closing the one at `open`, if it opens anything
+fn closing(chars: &[char], open: usize) -> Option<usize> {
+    let marker = chars[open];
+    let opens =
+        (open == 0 || chars[open - 1].is_whitespace() || "([{\"'-".contains(chars[open - 1]))
+            && chars
+                .get(open + 1)
+                .is_some_and(|c| !c.is_whitespace() && *c != marker);
+    if !opens {
+        return None;
+    }
+
+    (open + 2..chars.len()).find(|&end| {
+        chars[end] == marker
+            && !chars[end - 1].is_whitespace()
+            && chars
+                .get(end + 1)
+                .is_none_or(|c| c.is_whitespace() || ".,;:!?)]}\"'-".contains(*c))
+    })
+}
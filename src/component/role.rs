@@ -0,0 +1,112 @@
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+
+use crate::prelude::*;
+
+// members of this group get the admin pages
+const ADMIN_GROUP: &str = "admin";
+// members of this group only see what is shared with the group itself
+const GUEST_GROUP: &str = "guest";
+
+// What a logged in user may do follows from their group: `admin` and `guest` are roles of their
+// own, every other group (friends, family, ...) is a member. Anonymous readers have no role and
+// only see public content.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Role {
+    Admin,
+    Member,
+    Guest,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Permission {
+    // private posts and photos without a group, and scheduled posts
+    ViewPrivate,
+    // posts and photos shared with any group, not just the user's own
+    ViewAllGroups,
+    // approving and deleting comments on posts the user can read
+    Moderate,
+    // the admin dashboard, the editor and rebuilds
+    Administer,
+}
+
+impl Role {
+    pub fn of_group(group_name: &str) -> Role {
+        match group_name {
+            ADMIN_GROUP => Role::Admin,
+            GUEST_GROUP => Role::Guest,
+            _ => Role::Member,
+        }
+    }
+
+    pub fn permissions(self) -> &'static [Permission] {
+        match self {
+            Role::Admin => &[
+                Permission::ViewPrivate,
+                Permission::ViewAllGroups,
+                Permission::Moderate,
+                Permission::Administer,
+            ],
+            Role::Member => &[Permission::ViewPrivate, Permission::Moderate],
+            Role::Guest => &[],
+        }
+    }
+
+    pub fn has(self, permission: Permission) -> bool {
+        self.permissions().contains(&permission)
+    }
+}
+
+// the logged in user if they have the permission, otherwise the page doesn't exist for them
+async fn require(
+    parts: &mut Parts,
+    state: &Arc<AppState>,
+    permission: Permission,
+) -> Result<User, ax::Response> {
+    let db = state.lock_db().await?;
+    let cookies = ax::CookieJar::from_headers(&parts.headers);
+
+    match User::from_cookie(&db, &cookies) {
+        Ok(user) if user.has_permission(permission) => Ok(user),
+        user => {
+            println!(
+                "{} {} rejected, user = {:?} lacks {:?}",
+                parts.method,
+                parts.uri.path(),
+                user.ok(),
+                permission
+            );
+            Err(make_error(404, "Page not found").into_response())
+        }
+    }
+}
+
+pub struct RequireAdmin(pub User);
+
+impl FromRequestParts<Arc<AppState>> for RequireAdmin {
+    type Rejection = ax::Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        require(parts, state, Permission::Administer)
+            .await
+            .map(RequireAdmin)
+    }
+}
+
+pub struct RequireModerator(pub User);
+
+impl FromRequestParts<Arc<AppState>> for RequireModerator {
+    type Rejection = ax::Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        require(parts, state, Permission::Moderate)
+            .await
+            .map(RequireModerator)
+    }
+}
@@ -0,0 +1,112 @@
+use sha2::{Digest, Sha256};
+
+use crate::database::SqliteError;
+use crate::prelude::*;
+
+pub const SESSION_COOKIE: &str = "session";
+
+// A login. The cookie holds a random secret and only its hash is stored, so logging out or an
+// expired session can't be brought back by replaying an old cookie.
+#[allow(dead_code)]
+pub struct Session {
+    pub session_hash: String,
+    pub key_hash: String,
+    pub created_at: i64,
+    pub expires_at: i64,
+}
+
+impl Session {
+    pub fn setup(db: &Database) -> Result<(), Error> {
+        db.execute_batch(
+            r#"
+                CREATE TABLE IF NOT EXISTS sessions (
+                    session_hash TEXT PRIMARY KEY NOT NULL,
+                    key_hash TEXT NOT NULL,
+                    created_at INTEGER NOT NULL,
+                    expires_at INTEGER NOT NULL
+                );
+            "#,
+        )
+        .context("failed to create sessions table")
+    }
+
+    fn from_row(row: &Row) -> Result<Self, SqliteError> {
+        Ok(Self {
+            session_hash: row.get(0)?,
+            key_hash: row.get(1)?,
+            created_at: row.get(2)?,
+            expires_at: row.get(3)?,
+        })
+    }
+
+    // returns the session together with the secret for the cookie
+    pub fn new(db: &Database, key_hash: &str, ttl: i64) -> Result<(Self, String), Error> {
+        let secret = hex::encode(rand::random::<[u8; 32]>());
+
+        let session = db
+            .query_one(
+                r#"
+                    INSERT INTO sessions (session_hash, key_hash, created_at, expires_at)
+                    VALUES (?, ?, unixepoch(), unixepoch() + ?)
+                    RETURNING session_hash, key_hash, created_at, expires_at;
+                "#,
+                (Self::session_hash(&secret), key_hash, ttl),
+                Session::from_row,
+            )
+            .context("failed to insert session into database")?;
+
+        Ok((session, secret))
+    }
+
+    // expired and unknown sessions are treated the same
+    pub fn by_secret(db: &Database, secret: &str) -> Result<Self, Error> {
+        db.query_one(
+            r#"
+                SELECT session_hash, key_hash, created_at, expires_at FROM sessions
+                WHERE session_hash = ? AND expires_at > unixepoch();
+            "#,
+            [Self::session_hash(secret)],
+            Session::from_row,
+        )
+        .context("failed to query session from database")
+    }
+
+    pub fn delete(db: &Database, secret: &str) -> Result<(), Error> {
+        db.execute(
+            "DELETE FROM sessions WHERE session_hash = ?;",
+            [Self::session_hash(secret)],
+        )
+        .context("failed to delete session from database")
+    }
+
+    pub fn delete_expired(db: &Database) -> Result<(), Error> {
+        db.execute("DELETE FROM sessions WHERE expires_at <= unixepoch();", [])
+            .context("failed to delete expired sessions from database")
+    }
+
+    // the cookie for a new session, or the one removing it again when `session` is none
+    pub fn cookie(cfg: &Config, session: Option<(&Session, String)>) -> ax::Cookie<'static> {
+        let value = session
+            .as_ref()
+            .map(|(_, secret)| secret.clone())
+            .unwrap_or_default();
+        let cookie = ax::Cookie::build((SESSION_COOKIE, value))
+            .path("/")
+            .http_only(true)
+            .secure(cfg.secure_cookies)
+            .same_site(ax::SameSite::Lax);
+
+        match session {
+            Some((session, _)) => cookie
+                .max_age(::time::Duration::seconds(
+                    session.expires_at - session.created_at,
+                ))
+                .build(),
+            None => cookie.removal().build(),
+        }
+    }
+
+    fn session_hash(secret: &str) -> String {
+        hex::encode(Sha256::digest(secret.as_bytes()))
+    }
+}
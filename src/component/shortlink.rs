@@ -0,0 +1,321 @@
+use crate::component::post::find_post;
+use crate::database::SqliteError;
+use crate::prelude::*;
+
+// no 0/o or 1/l/i, so a code read out loud or off a slide can't be mistyped
+const ALPHABET: &[u8] = b"23456789abcdefghjkmnpqrstuvwxyz";
+const CODE_LENGTH: usize = 4;
+const MAX_CODE_LENGTH: usize = 32;
+
+// Short urls under /s/ for sharing links verbally or on slides. Every post gets a code on the
+// build after it appears, custom ones point at a post or any url and are added by admins. Codes
+// are state, so they keep working across rebuilds and even after their post is gone (410).
+pub struct Shortlink {
+    pub code: String,
+    pub post_id: Option<String>,
+    pub url: Option<String>,
+}
+
+impl Shortlink {
+    pub fn setup(db: &Database) -> Result<(), Error> {
+        db.execute_batch(
+            r#"
+                CREATE TABLE IF NOT EXISTS shortlinks (
+                    code TEXT PRIMARY KEY NOT NULL,
+                    post_id TEXT NULL,
+                    url TEXT NULL,
+                    created_at INTEGER NOT NULL
+                );
+            "#,
+        )
+        .context("failed to create shortlinks table")
+    }
+
+    fn from_row(row: &Row) -> Result<Self, SqliteError> {
+        Ok(Self {
+            code: row.get(0)?,
+            post_id: row.get(1)?,
+            url: row.get(2)?,
+        })
+    }
+
+    // gives every post without a code a random one, called by the build after the posts are in
+    pub fn assign(db: &Database) -> Result<(), Error> {
+        let post_ids = db
+            .query_mul(
+                r#"
+                    SELECT id FROM posts
+                    WHERE id NOT IN (SELECT post_id FROM shortlinks WHERE post_id IS NOT NULL);
+                "#,
+                [],
+                |row| row.get::<_, String>(0),
+            )
+            .context("failed to query posts without shortlinks from database")?;
+
+        for post_id in post_ids {
+            let mut length = CODE_LENGTH;
+            let code = loop {
+                let code = Self::random_code(length);
+                if Self::by_code(db, &code).is_err() {
+                    break code;
+                }
+                length += 1;
+            };
+            Self::insert(db, &code, Some(&post_id), None)?;
+        }
+
+        Ok(())
+    }
+
+    // `target` is a post id or slug, a path on the site or an absolute url
+    pub fn add(db: &Database, code: &str, target: &str) -> Result<Self, Error> {
+        let code = code.to_lowercase();
+        let is_valid_code = !code.is_empty()
+            && code.len() <= MAX_CODE_LENGTH
+            && code.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+        if !is_valid_code {
+            return Err(Error::new(format!(
+                "invalid shortlink code {:?}, use up to {} letters, digits and dashes",
+                code, MAX_CODE_LENGTH
+            ))
+            .with_kind(ErrorKind::Validation));
+        }
+        if Self::by_code(db, &code).is_ok() {
+            return Err(Error::new(format!("shortlink {} already exists", code))
+                .with_kind(ErrorKind::Validation));
+        }
+
+        let target = target.trim();
+        let is_url = target.starts_with('/')
+            || url::Url::parse(target).is_ok_and(|url| matches!(url.scheme(), "http" | "https"));
+        if is_url {
+            return Self::insert(db, &code, None, Some(target));
+        }
+
+        let post = Post::by_slug(db, target)
+            .or_else(|_| Post::by_id(db, target))
+            .map_err(|_| {
+                Error::new(format!(
+                    "{:?} is neither a post nor a url starting with / or http(s)://",
+                    target
+                ))
+                .with_kind(ErrorKind::Validation)
+            })?;
+        Self::insert(db, &code, Some(&post.id), None)
+    }
+
+    fn insert(
+        db: &Database,
+        code: &str,
+        post_id: Option<&str>,
+        url: Option<&str>,
+    ) -> Result<Self, Error> {
+        db.query_one(
+            r#"
+                INSERT INTO shortlinks (code, post_id, url, created_at)
+                VALUES (?, ?, ?, unixepoch())
+                RETURNING code, post_id, url;
+            "#,
+            (code, post_id, url),
+            Shortlink::from_row,
+        )
+        .context("failed to insert shortlink into database")
+    }
+
+    pub fn remove(db: &Database, code: &str) -> Result<bool, Error> {
+        let removed = db
+            .query_mul(
+                "DELETE FROM shortlinks WHERE code = ? RETURNING code;",
+                [code.to_lowercase()],
+                |row| row.get::<_, String>(0),
+            )
+            .context("failed to delete shortlink from database")?;
+        Ok(!removed.is_empty())
+    }
+
+    pub fn by_code(db: &Database, code: &str) -> Result<Self, Error> {
+        db.query_one(
+            "SELECT code, post_id, url FROM shortlinks WHERE code = ?;",
+            [code.to_lowercase()],
+            Shortlink::from_row,
+        )
+        .context("failed to query shortlink from database")
+    }
+
+    pub fn by_post(db: &Database, post_id: &str) -> Result<Option<Self>, Error> {
+        db.query_mul(
+            r#"
+                SELECT code, post_id, url FROM shortlinks WHERE post_id = ?
+                ORDER BY created_at, code LIMIT 1;
+            "#,
+            [post_id],
+            Shortlink::from_row,
+        )
+        .context("failed to query shortlink from database")
+        .map(|shortlinks| shortlinks.into_iter().next())
+    }
+
+    pub fn get_all(db: &Database) -> Result<Vec<Self>, Error> {
+        db.query_mul(
+            r#"
+                SELECT code, post_id, url FROM shortlinks
+                ORDER BY url IS NULL, created_at DESC, code;
+            "#,
+            [],
+            Shortlink::from_row,
+        )
+        .context("failed to query shortlinks from database")
+    }
+
+    // what the code points at, for listings
+    pub fn target(&self) -> String {
+        match (&self.post_id, &self.url) {
+            (_, Some(url)) => url.clone(),
+            (Some(post_id), None) => routes::post(post_id),
+            (None, None) => String::new(),
+        }
+    }
+
+    fn random_code(length: usize) -> String {
+        (0..length)
+            .map(|_| ALPHABET[rand::random_range(0..ALPHABET.len())] as char)
+            .collect()
+    }
+}
+
+pub async fn get_shortlink(
+    ax::State(state): ax::State<Arc<AppState>>,
+    ax::Path(code): ax::Path<String>,
+    cookie: ax::CookieJar,
+) -> impl IntoResponse {
+    let db = &match state.lock_db().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let cfg = &state.config.lock().unwrap();
+    let user = User::from_cookie(db, &cookie).ok();
+
+    println!("GET shortlink {}, user = {:?}", code, user);
+
+    let Ok(shortlink) = Shortlink::by_code(db, &code) else {
+        return make_error(404, "Shortlink not found").into_response();
+    };
+
+    // hidden posts answer like their own url would, without giving away the slug
+    // links to urls can be edited, so browsers mustn't cache them for good like post links
+    let (status, location) = match (&shortlink.post_id, &shortlink.url) {
+        (_, Some(url)) => (ax::StatusCode::FOUND, url.clone()),
+        (Some(post_id), None) => match find_post(db, cfg, post_id, user.as_ref()) {
+            Ok(post) => (ax::StatusCode::MOVED_PERMANENTLY, post.url()),
+            Err((code, message)) => return make_error(code, message).into_response(),
+        },
+        (None, None) => return make_error(404, "Shortlink not found").into_response(),
+    };
+
+    (status, [(ax::header::LOCATION, location)]).into_response()
+}
+
+pub async fn get_shortlinks(
+    ax::State(state): ax::State<Arc<AppState>>,
+    RequireAdmin(user): RequireAdmin,
+    client: Client,
+    lite: Lite,
+) -> impl IntoResponse {
+    let db = &match state.lock_db().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let cfg = &state.config.lock().unwrap();
+
+    println!("GET shortlinks, user = {:?}", user);
+
+    let Ok(shortlinks) = Shortlink::get_all(db) else {
+        return make_error(500, "Failed to load shortlinks").into_response();
+    };
+
+    let content = html!(
+        form action=(routes::SHORTLINKS) method="post" {
+            (csrf_field())
+            input type="text" name="code" placeholder="code" required {}
+            " to "
+            input type="text" name="target" placeholder="post, /path or https://..." required {}
+            " "
+            input type="submit" value="Add shortlink" {}
+        }
+
+        table class="admin-shortlinks" {
+            @for shortlink in &shortlinks {
+                tr {
+                    td { code { (client.absolute_url(cfg, &routes::shortlink(&shortlink.code))) } }
+                    td { a href=(shortlink.target()) { (shortlink.target()) } }
+                    td {
+                        form action=(routes::delete_shortlink(&shortlink.code)) method="post" {
+                            (csrf_field())
+                            input type="submit" value="Delete" {}
+                        }
+                    }
+                }
+            }
+        }
+    );
+
+    let page = make_page(
+        PageMeta::new(Section::Admin).title("Shortlinks").lite(lite),
+        vec!["/styles/post.css"],
+        content,
+        Some(user),
+        false,
+    );
+
+    ax::Html::from(page.into_string()).into_response()
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ShortlinkForm {
+    code: String,
+    target: String,
+}
+
+pub async fn post_shortlink(
+    ax::State(state): ax::State<Arc<AppState>>,
+    RequireAdmin(user): RequireAdmin,
+    form: ax::Form<ShortlinkForm>,
+) -> impl IntoResponse {
+    let db = &match state.lock_writer().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+
+    println!(
+        "POST shortlink {} to {}, user = {:?}",
+        form.code, form.target, user
+    );
+
+    match Shortlink::add(db, form.code.trim(), &form.target) {
+        Ok(_) => ax::Redirect::to(routes::SHORTLINKS).into_response(),
+        Err(error) if error.kind() == ErrorKind::Validation => {
+            make_error(400, error.message()).into_response()
+        }
+        Err(_) => make_error(500, "Failed to add shortlink").into_response(),
+    }
+}
+
+// a post gets a new code on the next build if its own is deleted
+pub async fn post_delete_shortlink(
+    ax::State(state): ax::State<Arc<AppState>>,
+    ax::Path(code): ax::Path<String>,
+    RequireAdmin(user): RequireAdmin,
+) -> impl IntoResponse {
+    let db = &match state.lock_writer().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+
+    println!("POST delete shortlink {}, user = {:?}", code, user);
+
+    match Shortlink::remove(db, &code) {
+        Ok(true) => ax::Redirect::to(routes::SHORTLINKS).into_response(),
+        Ok(false) => make_error(404, "Shortlink not found").into_response(),
+        Err(_) => make_error(500, "Failed to delete shortlink").into_response(),
+    }
+}
@@ -0,0 +1,207 @@
+use crate::database::SqliteError;
+use crate::prelude::*;
+
+pub const NOW_SLUG: &str = "now";
+
+// Markdown pages that aren't posts. The intro at the top of the index has no title and no url of
+// its own, every page from `pages_path` is served at `/{slug}/` under its title.
+#[allow(dead_code)]
+pub struct StaticPage {
+    pub slug: String,
+    pub html: String,
+    pub title: Option<String>,
+}
+
+impl StaticPage {
+    pub fn setup(db: &Database) -> Result<(), Error> {
+        db.execute_batch(
+            r#"
+                CREATE TABLE IF NOT EXISTS pages (
+                    slug TEXT PRIMARY KEY NOT NULL,
+                    source TEXT NOT NULL,
+                    html TEXT NOT NULL
+                );
+            "#,
+        )
+        .context("failed to create pages table")?;
+
+        db.ensure_column("pages", "title", "TEXT NULL")
+    }
+
+    fn from_row(row: &Row) -> Result<Self, SqliteError> {
+        Ok(Self {
+            slug: row.get(0)?,
+            html: row.get(1)?,
+            title: row.get(2)?,
+        })
+    }
+
+    pub fn new(db: &Database, slug: &str, source_path: &Path) -> Result<Self, Error> {
+        let source = fs::read_to_string(source_path)
+            .context(format!("failed to read page file {:?}", source_path))?;
+        StaticPage::insert(db, slug, None, &source, &source)
+    }
+
+    // `body` is the part of `source` that is rendered, without the heading the title came from
+    fn insert(
+        db: &Database,
+        slug: &str,
+        title: Option<&str>,
+        source: &str,
+        body: &str,
+    ) -> Result<Self, Error> {
+        let html = markdown_to_html(body, &MarkdownContext::default())?;
+
+        db.query_one(
+            r#"
+                INSERT INTO pages (slug, source, html, title) VALUES (?, ?, ?, ?)
+                RETURNING slug, html, title;
+            "#,
+            (slug, source, &html, title),
+            StaticPage::from_row,
+        )
+        .context("failed to insert page into database")
+    }
+
+    pub fn by_slug(db: &Database, slug: &str) -> Result<Self, Error> {
+        db.query_one(
+            "SELECT slug, html, title FROM pages WHERE slug = ?;",
+            [slug],
+            StaticPage::from_row,
+        )
+        .context("failed to query page by slug from database")
+    }
+
+    // every `.md` file in `dir`, see `new_titled`
+    pub fn load_dir(db: &Database, dir: &Path) -> Result<(), Error> {
+        for entry in fs::read_dir(dir).context("failed to read pages directory")? {
+            let path = entry?.path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some("md") {
+                continue;
+            }
+
+            let slug = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .context("invalid page file name")?;
+            if slug.is_empty()
+                || !slug
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+                || slug == "intro"
+                || routes::is_taken(slug)
+            {
+                return Err(Error::new(format!(
+                    "page {:?} needs a lowercase name that isn't used by another url",
+                    path
+                ))
+                .with_kind(ErrorKind::Validation));
+            }
+
+            StaticPage::new_titled(db, slug, &path)?;
+        }
+
+        Ok(())
+    }
+
+    // a page with a url, titled by its leading heading or else its name
+    pub fn new_titled(db: &Database, slug: &str, source_path: &Path) -> Result<Self, Error> {
+        let source = fs::read_to_string(source_path)
+            .context(format!("failed to read page file {:?}", source_path))?;
+        let (title, body) = split_title(slug, &source);
+        StaticPage::insert(db, slug, Some(&title), &source, body)
+    }
+
+    // the pages with a url, i.e. everything but the intro
+    pub fn get_listed(db: &Database) -> Result<Vec<Self>, Error> {
+        db.query_mul(
+            "SELECT slug, html, title FROM pages WHERE title IS NOT NULL ORDER BY slug;",
+            [],
+            StaticPage::from_row,
+        )
+        .context("failed to query pages from database")
+    }
+
+    pub fn delete_all(db: &Database) -> Result<(), Error> {
+        db.execute("DELETE FROM pages", [])
+            .context("failed to delete all pages from database")
+    }
+}
+
+// a leading `# heading` is the title, shown above the page like the title of a post
+fn split_title<'a>(slug: &str, source: &'a str) -> (String, &'a str) {
+    let trimmed = source.trim_start();
+    if let Some(rest) = trimmed.strip_prefix("# ") {
+        let (title, body) = rest.split_once('\n').unwrap_or((rest, ""));
+        return (title.trim().to_string(), body);
+    }
+
+    let mut chars = slug.chars();
+    let title = chars
+        .next()
+        .map(|first| first.to_uppercase().chain(chars).collect())
+        .unwrap_or_default();
+    (title, source)
+}
+
+pub async fn get_page(
+    state: ax::State<Arc<AppState>>,
+    ax::Path(slug): ax::Path<String>,
+    uri: ax::Uri,
+    query: ax::Query<HashMap<String, String>>,
+    cookie: ax::CookieJar,
+    lite: Lite,
+) -> ax::Response {
+    serve_page(state, &slug, uri, query, cookie, lite).await
+}
+
+// the page from `now_path`, at the url other sites with a now page use
+pub async fn get_now(
+    state: ax::State<Arc<AppState>>,
+    uri: ax::Uri,
+    query: ax::Query<HashMap<String, String>>,
+    cookie: ax::CookieJar,
+    lite: Lite,
+) -> ax::Response {
+    serve_page(state, NOW_SLUG, uri, query, cookie, lite).await
+}
+
+async fn serve_page(
+    ax::State(state): ax::State<Arc<AppState>>,
+    slug: &str,
+    uri: ax::Uri,
+    query: ax::Query<HashMap<String, String>>,
+    cookie: ax::CookieJar,
+    lite: Lite,
+) -> ax::Response {
+    let page = {
+        let db = &match state.lock_db().await {
+            Ok(db) => db,
+            Err(response) => return response,
+        };
+        let user = User::from_cookie(db, &cookie).ok();
+
+        println!("GET page {}, user = {:?}", slug, user);
+
+        StaticPage::by_slug(db, slug)
+            .ok()
+            .and_then(|page| Some((page.title.clone()?, page, user)))
+    };
+
+    // anything else at the top level is a redirect from the old site or a 404
+    let Some((title, page, user)) = page else {
+        return get_not_found(ax::State(state), uri, query, cookie)
+            .await
+            .into_response();
+    };
+
+    let page = make_page(
+        PageMeta::new(Section::None).title(title).lite(lite),
+        vec!["/styles/post.css"],
+        PreEscaped(page.html),
+        user,
+        false,
+    );
+
+    ax::Html::from(page.into_string()).into_response()
+}
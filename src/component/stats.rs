@@ -0,0 +1,220 @@
+use crate::component::chart::{bar_chart, sparkline};
+use crate::prelude::*;
+
+// tags beyond this are too rare to say anything over time
+const MAX_TAGS: usize = 10;
+
+// Statistics over exactly the posts and photos the viewer is allowed to see, so the numbers never
+// hint at hidden content.
+pub struct Stats {
+    pub post_count: i64,
+    pub word_count: i64,
+    pub photo_count: i64,
+    pub years: Vec<String>,
+    pub posts_per_year: Vec<i64>,
+    pub words_per_year: Vec<i64>,
+    pub photos_per_year: Vec<i64>,
+    // most used tags first, with the number of posts per year
+    pub tags: Vec<(String, i64, Vec<i64>)>,
+}
+
+impl Stats {
+    pub fn collect(db: &Database, cfg: &Config, user: Option<&User>) -> Result<Self, Error> {
+        let posts = Post::get_all(db)?
+            .into_iter()
+            .filter(|post| post.visible_to(user, cfg.timezone()))
+            .map(|post| post.id)
+            .collect::<Vec<_>>();
+        let photos = Photo::get_all(db, None)?
+            .into_iter()
+            .filter(|photo| photo.visible_to(user))
+            .map(|photo| photo.id)
+            .collect::<Vec<_>>();
+
+        // ids are passed as json arrays, sqlite has no other way to bind a list
+        let posts = serde_json::to_string(&posts).context("failed to encode post ids")?;
+        let photos = serde_json::to_string(&photos).context("failed to encode photo ids")?;
+
+        let post_rows: Vec<(String, i64, i64)> = db
+            .query_mul(
+                r#"
+                    SELECT substr(date, 1, 4) AS year, COUNT(*), SUM(word_count) FROM posts
+                    WHERE id IN (SELECT value FROM json_each(?))
+                    GROUP BY year ORDER BY year;
+                "#,
+                [&posts],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .context("failed to query post statistics from database")?;
+
+        let photo_rows: Vec<(String, i64)> = db
+            .query_mul(
+                r#"
+                    SELECT substr(posts.date, 1, 4) AS year, COUNT(DISTINCT photos.id) FROM photos
+                    JOIN posts_photos ON photos.id = posts_photos.photo_id
+                    JOIN posts ON posts_photos.post_id = posts.id
+                    WHERE posts.id IN (SELECT value FROM json_each(?1))
+                        AND photos.id IN (SELECT value FROM json_each(?2))
+                    GROUP BY year ORDER BY year;
+                "#,
+                [&posts, &photos],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .context("failed to query photo statistics from database")?;
+
+        let photo_count: i64 = db
+            .query_one(
+                r#"
+                    SELECT COUNT(DISTINCT photos.id) FROM photos
+                    JOIN posts_photos ON photos.id = posts_photos.photo_id
+                    WHERE posts_photos.post_id IN (SELECT value FROM json_each(?1))
+                        AND photos.id IN (SELECT value FROM json_each(?2));
+                "#,
+                [&posts, &photos],
+                |row| row.get(0),
+            )
+            .context("failed to count photos in database")?;
+
+        let tag_rows: Vec<(String, String, i64)> = db
+            .query_mul(
+                r#"
+                    SELECT posts_tags.tag, substr(posts.date, 1, 4) AS year, COUNT(*) FROM posts_tags
+                    JOIN posts ON posts_tags.post_id = posts.id
+                    WHERE posts.id IN (SELECT value FROM json_each(?))
+                    GROUP BY posts_tags.tag, year;
+                "#,
+                [&posts],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .context("failed to query tag statistics from database")?;
+
+        // every year between the first and the last post, so quiet years show up as gaps
+        let years = match (post_rows.first(), post_rows.last()) {
+            (Some((first, ..)), Some((last, ..))) => match (first.parse(), last.parse()) {
+                (Ok(first), Ok(last)) => (first..=last).map(|year: i32| year.to_string()).collect(),
+                _ => post_rows.iter().map(|(year, ..)| year.clone()).collect(),
+            },
+            _ => vec![],
+        };
+
+        let per_year = |values: &[(&str, i64)]| {
+            years
+                .iter()
+                .map(|year| {
+                    values
+                        .iter()
+                        .filter(|(other, _)| other == year)
+                        .map(|(_, value)| value)
+                        .sum()
+                })
+                .collect::<Vec<i64>>()
+        };
+
+        let mut tags = HashMap::<&str, Vec<(&str, i64)>>::new();
+        for (tag, year, count) in &tag_rows {
+            tags.entry(tag).or_default().push((year, *count));
+        }
+        let mut tags = tags
+            .into_iter()
+            .map(|(tag, counts)| {
+                let total: i64 = counts.iter().map(|(_, count)| count).sum();
+                (tag.to_string(), total, per_year(&counts))
+            })
+            .collect::<Vec<_>>();
+        tags.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        tags.truncate(MAX_TAGS);
+
+        let posts_per_year = post_rows
+            .iter()
+            .map(|(year, count, _)| (year.as_str(), *count))
+            .collect::<Vec<_>>();
+        let words_per_year = post_rows
+            .iter()
+            .map(|(year, _, words)| (year.as_str(), *words))
+            .collect::<Vec<_>>();
+        let photos_per_year = photo_rows
+            .iter()
+            .map(|(year, count)| (year.as_str(), *count))
+            .collect::<Vec<_>>();
+
+        Ok(Self {
+            post_count: posts_per_year.iter().map(|(_, count)| count).sum(),
+            word_count: words_per_year.iter().map(|(_, words)| words).sum(),
+            photo_count,
+            posts_per_year: per_year(&posts_per_year),
+            words_per_year: per_year(&words_per_year),
+            photos_per_year: per_year(&photos_per_year),
+            years,
+            tags,
+        })
+    }
+}
+
+pub async fn get_stats(
+    ax::State(state): ax::State<Arc<AppState>>,
+    cookie: ax::CookieJar,
+    lite: Lite,
+) -> impl IntoResponse {
+    let db = &match state.lock_db().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let cfg = &state.config.lock().unwrap();
+    let user = User::from_cookie(db, &cookie).ok();
+
+    println!("GET stats, user = {:?}", user);
+
+    let stats = match Stats::collect(db, cfg, user.as_ref()) {
+        Ok(stats) => stats,
+        Err(_) => return make_error(500, "Failed to load statistics").into_response(),
+    };
+
+    let content = html!(
+        p class="stats-summary" {
+            (stats.post_count) " posts · " (stats.word_count) " words · " (stats.photo_count) " photos"
+        }
+
+        @if !stats.years.is_empty() {
+            h2 { "Posts per year" }
+            (bar_chart(&stats.years, &stats.posts_per_year))
+
+            h2 { "Words per year" }
+            (bar_chart(&stats.years, &stats.words_per_year))
+
+            h2 { "Photos per year" }
+            (bar_chart(&stats.years, &stats.photos_per_year))
+        }
+
+        @if !stats.tags.is_empty() {
+            h2 { "Tags over time" }
+            table class="stats-tags" {
+                @for (tag, total, per_year) in &stats.tags {
+                    tr {
+                        td { a href=(routes::posts_tagged(tag)) { (tag) } }
+                        td { (total) }
+                        td {
+                            @let title = stats
+                                .years
+                                .iter()
+                                .zip(per_year)
+                                .map(|(year, count)| format!("{}: {}", year, count))
+                                .collect::<Vec<_>>()
+                                .join(", ");
+                            (sparkline(&title, per_year))
+                        }
+                    }
+                }
+            }
+        }
+    );
+
+    let page = make_page(
+        PageMeta::new(Section::None).title("Statistics").lite(lite),
+        vec!["/styles/post.css"],
+        content,
+        user,
+        false,
+    );
+
+    ax::Html::from(page.into_string()).into_response()
+}
@@ -0,0 +1,126 @@
+use std::convert::Infallible;
+
+use axum::extract::{FromRequestParts, Request};
+use axum::http::request::Parts;
+use axum::middleware::Next;
+use axum::response::Response;
+use maud::Markup;
+
+use crate::prelude::*;
+
+// Pages follow the light or dark setting of the reader's system unless `?theme=light` or
+// `?theme=dark` picks one, which is remembered in a cookie until `?theme=auto`. The colors are
+// variables in `/styles/theme.css`, which a file of the same name in the files directory replaces.
+const THEME_COOKIE: &str = "theme";
+
+pub const THEME_STYLE_NAME: &str = "theme.css";
+pub const THEME_STYLE: &[u8] = include_bytes!("theme.css");
+
+tokio::task_local! {
+    static THEME: Theme;
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Theme {
+    #[default]
+    Auto,
+    Light,
+    Dark,
+}
+
+impl Theme {
+    const ALL: [Theme; 3] = [Theme::Auto, Theme::Light, Theme::Dark];
+
+    fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|theme| theme.name() == value)
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Theme::Auto => "auto",
+            Theme::Light => "light",
+            Theme::Dark => "dark",
+        }
+    }
+
+    // on the root element, overriding the system setting
+    pub fn class(self) -> Option<&'static str> {
+        match self {
+            Theme::Auto => None,
+            Theme::Light => Some("theme-light"),
+            Theme::Dark => Some("theme-dark"),
+        }
+    }
+
+    // the theme of the request being handled
+    pub fn current() -> Self {
+        THEME.try_with(|theme| *theme).unwrap_or_default()
+    }
+
+    // links to switch to the other themes
+    pub fn switcher() -> Markup {
+        let current = Self::current();
+        html! {
+            @for theme in Self::ALL.into_iter().filter(|theme| *theme != current) {
+                a href=(href_with_param("theme", theme.name())) { (theme.label()) }
+                " "
+            }
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Theme::Auto => "System theme",
+            Theme::Light => "Light theme",
+            Theme::Dark => "Dark theme",
+        }
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for Theme {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        if let Some(theme) = theme_param(&parts.uri) {
+            return Ok(theme);
+        }
+
+        let cookies = ax::CookieJar::from_headers(&parts.headers);
+        Ok(cookies
+            .get(THEME_COOKIE)
+            .and_then(|cookie| Theme::parse(cookie.value()))
+            .unwrap_or_default())
+    }
+}
+
+fn theme_param(uri: &ax::Uri) -> Option<Theme> {
+    uri.query()?
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("theme="))
+        .map(|value| Theme::parse(value).unwrap_or_default())
+}
+
+// makes the theme available to `make_page` and stores or clears the cookie whenever a request
+// sets `?theme=`
+pub async fn remember_theme(request: Request, next: Next) -> Response {
+    let (mut parts, body) = request.into_parts();
+    let Ok::<_, Infallible>(theme) = Theme::from_request_parts(&mut parts, &()).await;
+    let param = theme_param(&parts.uri);
+    let response = THEME
+        .scope(theme, next.run(Request::from_parts(parts, body)))
+        .await;
+
+    let cookies = match param {
+        Some(Theme::Auto) => {
+            ax::CookieJar::new().add(ax::Cookie::build(THEME_COOKIE).path("/").removal())
+        }
+        Some(theme) => ax::CookieJar::new().add(
+            ax::Cookie::build((THEME_COOKIE, theme.name()))
+                .path("/")
+                .permanent(),
+        ),
+        None => return response,
+    };
+
+    (cookies, response).into_response()
+}
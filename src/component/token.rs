@@ -0,0 +1,218 @@
+use axum::extract::{FromRequestParts, OptionalFromRequestParts};
+use axum::http::request::Parts;
+use sha2::{Digest, Sha256};
+
+use crate::database::SqliteError;
+use crate::prelude::*;
+
+// API tokens authenticate scripts, CI and mobile clients on write-capable endpoints, where a login
+// cookie doesn't work. Only the hash of a token is stored, the token itself is shown once when it
+// is minted. Each token only grants the scopes it was minted with.
+#[allow(dead_code)]
+pub struct ApiToken {
+    pub token_hash: String,
+    pub label: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<i64>,
+    pub created_at: i64,
+}
+
+impl ApiToken {
+    pub fn setup(db: &Database) -> Result<(), Error> {
+        db.execute_batch(
+            r#"
+                CREATE TABLE IF NOT EXISTS api_tokens (
+                    token_hash TEXT PRIMARY KEY NOT NULL,
+                    label TEXT NOT NULL UNIQUE,
+                    scopes TEXT NOT NULL,
+                    expires_at INTEGER NULL,
+                    created_at INTEGER NOT NULL
+                );
+            "#,
+        )
+        .context("failed to create api_tokens table")
+    }
+
+    fn from_row(row: &Row) -> Result<Self, SqliteError> {
+        let scopes: String = row.get(2)?;
+        Ok(Self {
+            token_hash: row.get(0)?,
+            label: row.get(1)?,
+            scopes: scopes.split_whitespace().map(str::to_string).collect(),
+            expires_at: row.get(3)?,
+            created_at: row.get(4)?,
+        })
+    }
+
+    // returns the token together with its secret, which can't be recovered later
+    pub fn mint(
+        db: &Database,
+        label: &str,
+        scopes: &[String],
+        expires_at: Option<i64>,
+    ) -> Result<(Self, String), Error> {
+        let secret = hex::encode(rand::random::<[u8; 32]>());
+
+        let token = db
+            .query_one(
+                r#"
+                    INSERT INTO api_tokens (token_hash, label, scopes, expires_at, created_at)
+                    VALUES (?, ?, ?, ?, unixepoch())
+                    RETURNING token_hash, label, scopes, expires_at, created_at;
+                "#,
+                (
+                    Self::token_hash(&secret),
+                    label,
+                    scopes.join(" "),
+                    expires_at,
+                ),
+                ApiToken::from_row,
+            )
+            .context(format!(
+                "failed to insert token into database, is the label {:?} already used?",
+                label
+            ))?;
+
+        Ok((token, secret))
+    }
+
+    // adds a token whose secret is already known, false if it was there already
+    pub fn import(
+        db: &Database,
+        label: &str,
+        secret: &str,
+        scopes: &[String],
+    ) -> Result<bool, Error> {
+        if Self::by_secret(db, secret).is_ok() {
+            return Ok(false);
+        }
+
+        db.execute(
+            r#"
+                INSERT INTO api_tokens (token_hash, label, scopes, expires_at, created_at)
+                VALUES (?, ?, ?, NULL, unixepoch());
+            "#,
+            (Self::token_hash(secret), label, scopes.join(" ")),
+        )
+        .context(format!(
+            "failed to import token into database, is the label {:?} already used?",
+            label
+        ))?;
+        Ok(true)
+    }
+
+    pub fn by_secret(db: &Database, secret: &str) -> Result<Self, Error> {
+        db.query_one(
+            "SELECT token_hash, label, scopes, expires_at, created_at FROM api_tokens WHERE token_hash = ?;",
+            [Self::token_hash(secret)],
+            ApiToken::from_row,
+        )
+        .context("failed to query token from database")
+    }
+
+    pub fn get_all(db: &Database) -> Result<Vec<Self>, Error> {
+        db.query_mul(
+            "SELECT token_hash, label, scopes, expires_at, created_at FROM api_tokens ORDER BY label;",
+            [],
+            ApiToken::from_row,
+        )
+        .context("failed to query tokens from database")
+    }
+
+    // false if there was no token with this label
+    pub fn revoke(db: &Database, label: &str) -> Result<bool, Error> {
+        let revoked = db
+            .query_mul(
+                "DELETE FROM api_tokens WHERE label = ? RETURNING label;",
+                [label],
+                |row| row.get::<_, String>(0),
+            )
+            .context("failed to delete token from database")?;
+        Ok(!revoked.is_empty())
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at <= chrono::Utc::now().timestamp())
+    }
+
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|other| other == scope)
+    }
+
+    // expired and unknown tokens are treated the same
+    pub fn authenticate(db: &Database, secret: &str) -> Option<Self> {
+        Self::by_secret(db, secret.trim())
+            .ok()
+            .filter(|token| !token.is_expired())
+    }
+
+    fn token_hash(secret: &str) -> String {
+        hex::encode(Sha256::digest(secret.as_bytes()))
+    }
+}
+
+impl std::fmt::Debug for ApiToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ApiToken(\"{}\")", self.label)
+    }
+}
+
+// errors in the shape OAuth clients expect
+pub fn api_error(code: u16, error: &str, description: &str) -> ax::Response {
+    (
+        ax::StatusCode::from_u16(code).unwrap_or(ax::StatusCode::INTERNAL_SERVER_ERROR),
+        [(ax::header::WWW_AUTHENTICATE, "Bearer")],
+        axum::Json(serde_json::json!({
+            "error": error,
+            "error_description": description,
+        })),
+    )
+        .into_response()
+}
+
+// A valid token from the `Authorization: Bearer` header. Handlers still have to check its scopes.
+// As `Option<Bearer>` a missing header is not an error, for endpoints that also accept the token
+// somewhere else.
+pub struct Bearer(pub ApiToken);
+
+fn bearer_secret(parts: &Parts) -> Option<&str> {
+    parts
+        .headers
+        .get(ax::header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+}
+
+impl OptionalFromRequestParts<Arc<AppState>> for Bearer {
+    type Rejection = ax::Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Option<Self>, Self::Rejection> {
+        let Some(secret) = bearer_secret(parts) else {
+            return Ok(None);
+        };
+
+        let db = state.lock_db().await?;
+        match ApiToken::authenticate(&db, secret) {
+            Some(token) => Ok(Some(Bearer(token))),
+            None => Err(api_error(403, "forbidden", "invalid access token")),
+        }
+    }
+}
+
+impl FromRequestParts<Arc<AppState>> for Bearer {
+    type Rejection = ax::Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        <Self as OptionalFromRequestParts<_>>::from_request_parts(parts, state)
+            .await?
+            .ok_or_else(|| api_error(401, "unauthorized", "missing access token"))
+    }
+}
@@ -0,0 +1,71 @@
+use crate::prelude::*;
+
+// ids, slugs and aliases of public posts that have been removed from the source tree, so their old
+// urls answer with 410 Gone instead of 404
+pub struct Tombstone;
+
+impl Tombstone {
+    pub fn setup(db: &Database) -> Result<(), Error> {
+        db.execute_batch(
+            r#"
+                CREATE TABLE IF NOT EXISTS tombstones (
+                    key TEXT PRIMARY KEY NOT NULL,
+                    deleted_at INTEGER NOT NULL
+                );
+            "#,
+        )
+        .context("failed to create tombstones table")
+    }
+
+    // `previous` are the posts and their aliases from before the rebuild. Only posts anonymous
+    // readers could see are buried, so a 410 never gives away that a private post existed.
+    pub fn bury_missing(
+        db: &Database,
+        previous: &[(Vec<String>, Post)],
+        tz: Tz,
+    ) -> Result<(), Error> {
+        for (aliases, post) in previous
+            .iter()
+            .filter(|(_, post)| post.visible_to(None, tz))
+        {
+            for key in [&post.id, &post.slug].into_iter().chain(aliases) {
+                db.execute(
+                    r#"
+                        INSERT OR IGNORE INTO tombstones (key, deleted_at)
+                        SELECT ?1, unixepoch()
+                        WHERE NOT EXISTS (SELECT 1 FROM posts WHERE id = ?1 OR slug = ?1)
+                        AND NOT EXISTS (SELECT 1 FROM post_aliases WHERE alias = ?1)
+                        AND NOT EXISTS (
+                            SELECT 1 FROM post_slugs
+                            WHERE slug = ?1 AND post_id IN (SELECT id FROM posts)
+                        );
+                    "#,
+                    [key],
+                )
+                .context("failed to insert tombstone into database")?;
+            }
+        }
+
+        // posts that came back are alive again
+        db.execute(
+            r#"
+                DELETE FROM tombstones WHERE key IN (
+                    SELECT id FROM posts UNION SELECT slug FROM posts
+                    UNION SELECT alias FROM post_aliases
+                    UNION SELECT slug FROM post_slugs WHERE post_id IN (SELECT id FROM posts)
+                );
+            "#,
+            [],
+        )
+        .context("failed to delete tombstones from database")
+    }
+
+    pub fn exists(db: &Database, key: &str) -> Result<bool, Error> {
+        db.query_one(
+            "SELECT EXISTS (SELECT 1 FROM tombstones WHERE key = ?);",
+            [key],
+            |row| row.get(0),
+        )
+        .context("failed to query tombstone from database")
+    }
+}
@@ -0,0 +1,31 @@
+// Maps of the GPX tracks on a post: the svg of each track is swapped for a Leaflet map with the
+// configured tiles. Without Leaflet, e.g. when it failed to load, the svg stays.
+(function () {
+    if (!window.L) return;
+
+    var maps = document.querySelectorAll(".track-map[data-points]");
+    for (var i = 0; i < maps.length; i++) {
+        var element = maps[i];
+        var points;
+        try {
+            points = JSON.parse(element.dataset.points);
+        } catch (e) {
+            continue;
+        }
+        if (points.length < 2) continue;
+
+        element.innerHTML = "";
+        element.style.height = "400px";
+
+        var map = L.map(element, { scrollWheelZoom: false });
+        L.tileLayer(element.dataset.tiles, {
+            attribution: element.dataset.attribution,
+            maxZoom: 18,
+        }).addTo(map);
+
+        var line = L.polyline(points, { weight: 4 }).addTo(map);
+        L.circleMarker(points[0], { radius: 5 }).addTo(map);
+        L.circleMarker(points[points.length - 1], { radius: 5 }).addTo(map);
+        map.fitBounds(line.getBounds(), { padding: [16, 16] });
+    }
+})();
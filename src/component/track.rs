@@ -0,0 +1,324 @@
+use quick_xml::events::Event as XmlEvent;
+use quick_xml::Reader;
+
+use crate::config::MapConfig;
+use crate::database::SqliteError;
+use crate::prelude::*;
+
+pub const TRACK_SCRIPT_NAME: &str = "track.js";
+pub const TRACK_SCRIPT: &[u8] = include_bytes!("track.js");
+
+// leaflet isn't shipped with the binary, the site hosts it in its files directory
+pub const LEAFLET_SCRIPT_NAME: &str = "leaflet.js";
+pub const LEAFLET_STYLE_NAME: &str = "leaflet.css";
+
+const EARTH_RADIUS: f64 = 6_371_000.0;
+// points closer than this to the simplified line are dropped, in meters
+const SIMPLIFY_TOLERANCE: f64 = 10.0;
+// climbs smaller than this are gps noise, in meters
+const ELEVATION_THRESHOLD: f64 = 2.0;
+
+const SVG_WIDTH: f64 = 600.0;
+const SVG_HEIGHT: f64 = 400.0;
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Point {
+    pub lat: f64,
+    pub lon: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ele: Option<f64>,
+}
+
+// A route from a `.gpx` file in a post's assets, shown below the post with its distance and
+// climb. Only the simplified line is kept, the stats are taken from every recorded point.
+pub struct Track {
+    pub name: String,
+    pub points: Vec<Point>,
+    // in meters
+    pub distance: f64,
+    pub elevation_gain: Option<f64>,
+}
+
+impl Track {
+    pub fn setup(db: &Database) -> Result<(), Error> {
+        db.execute_batch(
+            r#"
+                CREATE TABLE IF NOT EXISTS post_tracks (
+                    post_id TEXT NOT NULL,
+                    name TEXT NOT NULL,
+                    points TEXT NOT NULL,
+                    distance REAL NOT NULL,
+                    elevation_gain REAL NULL,
+                    PRIMARY KEY (post_id, name)
+                );
+            "#,
+        )
+        .context("failed to create post_tracks table")
+    }
+
+    fn from_row(row: &Row) -> Result<Self, SqliteError> {
+        let points: String = row.get(1)?;
+        Ok(Self {
+            name: row.get(0)?,
+            points: serde_json::from_str(&points).unwrap_or_default(),
+            distance: row.get(2)?,
+            elevation_gain: row.get(3)?,
+        })
+    }
+
+    pub fn new(db: &Database, post_id: &str, path: &Path) -> Result<Self, Error> {
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .context("invalid track path")?;
+        let gpx = fs::read_to_string(path).context("failed to read gpx file")?;
+        let points = parse_gpx(&gpx)
+            .context(format!("invalid gpx file {:?}", path))
+            .map_err(|error| error.with_kind(ErrorKind::Validation))?;
+
+        let distance = points
+            .windows(2)
+            .map(|pair| haversine(pair[0], pair[1]))
+            .sum::<f64>();
+        let simplified = simplify(&points);
+
+        db.query_one(
+            r#"
+                INSERT INTO post_tracks (post_id, name, points, distance, elevation_gain)
+                VALUES (?, ?, ?, ?, ?)
+                RETURNING name, points, distance, elevation_gain;
+            "#,
+            (
+                post_id,
+                name,
+                serde_json::to_string(&simplified)?,
+                distance,
+                elevation_gain(&points),
+            ),
+            Track::from_row,
+        )
+        .context("failed to insert track into database")
+    }
+
+    pub fn by_post(db: &Database, post_id: &str) -> Result<Vec<Self>, Error> {
+        db.query_mul(
+            r#"
+                SELECT name, points, distance, elevation_gain FROM post_tracks
+                WHERE post_id = ? ORDER BY name;
+            "#,
+            [post_id],
+            Track::from_row,
+        )
+        .context("failed to query tracks from database")
+    }
+
+    pub fn delete_all(db: &Database) -> Result<(), Error> {
+        db.execute("DELETE FROM post_tracks", [])
+            .context("failed to delete all tracks from database")
+    }
+
+    // The line as an svg, which `track.js` replaces with a map when the site hosts leaflet.
+    // Readers without javascript still see the shape of the route.
+    pub fn to_html(&self, map: Option<&MapConfig>) -> PreEscaped<String> {
+        let points = serde_json::to_string(
+            &self
+                .points
+                .iter()
+                .map(|point| [point.lat, point.lon])
+                .collect::<Vec<_>>(),
+        )
+        .unwrap_or_default();
+
+        html!(
+            figure class="track" {
+                div class="track-map"
+                    data-points=[map.map(|_| &points)]
+                    data-tiles=[map.map(|map| &map.tile_url)]
+                    data-attribution=[map.map(|map| &map.attribution)] {
+                    (self.to_svg())
+                }
+                figcaption {
+                    (self.name) " · " (format!("{:.1} km", self.distance / 1000.0))
+                    @if let Some(gain) = self.elevation_gain {
+                        " · " (format!("{:.0} m", gain)) " climb"
+                    }
+                }
+            }
+        )
+    }
+
+    fn to_svg(&self) -> PreEscaped<String> {
+        // flat projection, fine at the scale of a hike
+        let mean_lat = self.points.iter().map(|point| point.lat).sum::<f64>()
+            / self.points.len().max(1) as f64;
+        let scale_x = mean_lat.to_radians().cos();
+        let projected = self
+            .points
+            .iter()
+            .map(|point| (point.lon * scale_x, -point.lat))
+            .collect::<Vec<_>>();
+
+        let (min_x, max_x, min_y, max_y) = projected.iter().fold(
+            (f64::MAX, f64::MIN, f64::MAX, f64::MIN),
+            |(min_x, max_x, min_y, max_y), (x, y)| {
+                (min_x.min(*x), max_x.max(*x), min_y.min(*y), max_y.max(*y))
+            },
+        );
+        let scale = ((SVG_WIDTH - 20.0) / (max_x - min_x).max(f64::EPSILON))
+            .min((SVG_HEIGHT - 20.0) / (max_y - min_y).max(f64::EPSILON));
+
+        let line = projected
+            .iter()
+            .map(|(x, y)| {
+                format!(
+                    "{:.1},{:.1}",
+                    10.0 + (x - min_x) * scale,
+                    10.0 + (y - min_y) * scale
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        html!(
+            svg xmlns="http://www.w3.org/2000/svg" width="100%" viewBox=(format!("0 0 {} {}", SVG_WIDTH, SVG_HEIGHT)) role="img" aria-label=(format!("Map of {}", self.name)) {
+                polyline points=(line) fill="none" stroke="currentColor" stroke-width="3" stroke-linejoin="round" {}
+            }
+        )
+    }
+}
+
+// every track point, or route point if the file has no track
+fn parse_gpx(gpx: &str) -> Result<Vec<Point>, Error> {
+    let mut reader = Reader::from_str(gpx);
+    reader.config_mut().trim_text(true);
+
+    let mut track = vec![];
+    let mut route = vec![];
+    let mut in_ele = false;
+
+    loop {
+        match reader.read_event().context("failed to parse gpx")? {
+            XmlEvent::Start(tag) | XmlEvent::Empty(tag)
+                if matches!(tag.local_name().as_ref(), b"trkpt" | b"rtept") =>
+            {
+                let coordinate = |name: &str| -> Result<f64, Error> {
+                    tag.try_get_attribute(name)
+                        .context("invalid attribute")?
+                        .context(format!("point without {}", name))?
+                        .unescape_value()
+                        .context("invalid attribute")?
+                        .trim()
+                        .parse::<f64>()
+                        .context(format!("invalid {}", name))
+                };
+                let point = Point {
+                    lat: coordinate("lat")?,
+                    lon: coordinate("lon")?,
+                    ele: None,
+                };
+                match tag.local_name().as_ref() {
+                    b"trkpt" => track.push(point),
+                    _ => route.push(point),
+                }
+            }
+            XmlEvent::Start(tag) if tag.local_name().as_ref() == b"ele" => in_ele = true,
+            XmlEvent::End(tag) if tag.local_name().as_ref() == b"ele" => in_ele = false,
+            XmlEvent::Text(text) if in_ele => {
+                let ele = text.decode().ok().and_then(|ele| ele.trim().parse().ok());
+                let points = if track.is_empty() {
+                    &mut route
+                } else {
+                    &mut track
+                };
+                if let Some(point) = points.last_mut() {
+                    point.ele = ele;
+                }
+            }
+            XmlEvent::Eof => break,
+            _ => {}
+        }
+    }
+
+    let points = if track.is_empty() { route } else { track };
+    if points.len() < 2 {
+        return Err(Error::new("gpx file has fewer than two points"));
+    }
+    Ok(points)
+}
+
+fn haversine(a: Point, b: Point) -> f64 {
+    let (lat_a, lat_b) = (a.lat.to_radians(), b.lat.to_radians());
+    let d_lat = lat_b - lat_a;
+    let d_lon = (b.lon - a.lon).to_radians();
+    let h = (d_lat / 2.0).sin().powi(2) + lat_a.cos() * lat_b.cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * h.sqrt().asin()
+}
+
+// Sums the climbs, ignoring wiggles below the threshold. Nothing if the file has no elevation.
+fn elevation_gain(points: &[Point]) -> Option<f64> {
+    let mut elevations = points.iter().filter_map(|point| point.ele);
+    let mut low = elevations.next()?;
+    let mut gain = 0.0;
+    for ele in elevations {
+        if ele - low >= ELEVATION_THRESHOLD {
+            gain += ele - low;
+            low = ele;
+        } else if ele < low {
+            low = ele;
+        }
+    }
+    Some(gain)
+}
+
+// Ramer-Douglas-Peucker, measuring in meters on a flat projection around the first point
+fn simplify(points: &[Point]) -> Vec<Point> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+
+    let origin = points[0];
+    let scale_x = origin.lat.to_radians().cos() * EARTH_RADIUS.to_radians();
+    let scale_y = EARTH_RADIUS.to_radians();
+    let xy = |point: &Point| {
+        (
+            (point.lon - origin.lon) * scale_x,
+            (point.lat - origin.lat) * scale_y,
+        )
+    };
+
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+    let mut stack = vec![(0, points.len() - 1)];
+
+    while let Some((start, end)) = stack.pop() {
+        let (ax, ay) = xy(&points[start]);
+        let (bx, by) = xy(&points[end]);
+        let length = ((bx - ax).powi(2) + (by - ay).powi(2)).sqrt();
+
+        let farthest = (start + 1..end)
+            .map(|i| {
+                let (px, py) = xy(&points[i]);
+                let distance = match length {
+                    0.0 => ((px - ax).powi(2) + (py - ay).powi(2)).sqrt(),
+                    _ => ((bx - ax) * (ay - py) - (ax - px) * (by - ay)).abs() / length,
+                };
+                (i, distance)
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+
+        if let Some((i, distance)) = farthest
+            && distance > SIMPLIFY_TOLERANCE
+        {
+            keep[i] = true;
+            stack.push((start, i));
+            stack.push((i, end));
+        }
+    }
+
+    points
+        .iter()
+        .zip(keep)
+        .filter_map(|(point, keep)| keep.then_some(*point))
+        .collect()
+}
@@ -0,0 +1,103 @@
+use axum::extract::Request;
+use axum::middleware::Next;
+use tower::ServiceExt;
+
+use crate::prelude::*;
+
+// Pages are routed with a trailing slash (`/posts/`), files and endpoints without one. With
+// `add` the slash is part of the canonical url, with `strip` it isn't and pages are served
+// without it. Either way the other form of a url still works.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum TrailingSlash {
+    #[default]
+    Add,
+    Strip,
+}
+
+fn with_path(uri: &ax::Uri, path: &str) -> String {
+    match uri.query() {
+        Some(query) => format!("{}?{}", path, query),
+        None => path.to_string(),
+    }
+}
+
+fn is_file(path: &str) -> bool {
+    path.rsplit('/')
+        .next()
+        .is_some_and(|name| name.contains('.'))
+}
+
+fn is_read(request: &Request) -> bool {
+    request.method() == ax::Method::GET || request.method() == ax::Method::HEAD
+}
+
+fn permanent_redirect(location: String) -> ax::Response {
+    // 308 rather than 301, so a form posted to the other url is posted again
+    (
+        ax::StatusCode::PERMANENT_REDIRECT,
+        [(ax::header::LOCATION, location)],
+    )
+        .into_response()
+}
+
+// With `strip`, reads of a page url with the slash are sent to the url without it. Posts are
+// left alone, forms on the site still post to the routes with the slash.
+pub async fn strip_trailing_slash(
+    ax::State(state): ax::State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> ax::Response {
+    let path = request.uri().path();
+    if state.config.lock().unwrap().trailing_slash == TrailingSlash::Strip
+        && is_read(&request)
+        && path.len() > 1
+        && path.ends_with('/')
+    {
+        let path = match path.trim_end_matches('/') {
+            "" => "/",
+            path => path,
+        };
+        return permanent_redirect(with_path(request.uri(), path));
+    }
+
+    next.run(request).await
+}
+
+// Requests that didn't match a route try again with a trailing slash, on `routes` which end in
+// the real 404. The request is passed on whole, query and body included. With `add` reads are
+// redirected to the url with the slash instead of being answered.
+pub async fn normalize_trailing_slash(
+    routes: ax::Router,
+    state: Arc<AppState>,
+    request: Request,
+) -> ax::Response {
+    let path = request.uri().path().to_string();
+    let policy = state.config.lock().unwrap().trailing_slash;
+
+    let has_redirect = state.config.lock().unwrap().redirect(&path).is_some();
+    if path.ends_with('/') || is_file(&path) || has_redirect {
+        return routes.oneshot(request).await.into_response();
+    }
+
+    let with_slash = with_path(request.uri(), &format!("{}/", path));
+    let is_read = is_read(&request);
+    let (mut parts, body) = request.into_parts();
+    parts.uri = match with_slash.parse() {
+        Ok(uri) => uri,
+        Err(_) => return make_error(404, "Page not found").into_response(),
+    };
+
+    let response = routes
+        .oneshot(Request::from_parts(parts, body))
+        .await
+        .into_response();
+
+    match policy {
+        TrailingSlash::Add if is_read && response.status() != ax::StatusCode::NOT_FOUND => {
+            println!("redirecting with trailing slash");
+            permanent_redirect(with_slash)
+        }
+        _ => response,
+    }
+}
@@ -1,6 +1,8 @@
+use std::net::IpAddr;
+
 use crate::database::SqliteError;
 use crate::prelude::*;
-use std::hash::{Hash, Hasher};
+use sha2::{Digest, Sha256};
 
 #[allow(dead_code)]
 pub struct User {
@@ -18,7 +20,15 @@ impl User {
                 );
             "#,
         )
-        .context("failed to create users table")
+        .context("failed to create users table")?;
+
+        // feed readers can't log in, so each user gets a secret feed url instead
+        db.ensure_column("users", "feed_token", "TEXT NULL")
+            .context("failed to update users table")?;
+        db.execute_batch(
+            "CREATE UNIQUE INDEX IF NOT EXISTS users_feed_token_index ON users (feed_token);",
+        )
+        .context("failed to create users feed token index")
     }
 
     fn from_row(row: &Row) -> Result<Self, SqliteError> {
@@ -28,8 +38,8 @@ impl User {
         })
     }
 
-    pub fn new(db: &Database, key_hash: &str, group_name: &str) -> Result<Self, Error> {
-        let key_hash = Self::key_hash(key_hash);
+    pub fn new(db: &Database, key: &str, group_name: &str) -> Result<Self, Error> {
+        let key_hash = Self::key_hash(key);
 
         db.execute(
             "INSERT INTO users (key_hash, group_name) VALUES (?, ?)",
@@ -43,9 +53,29 @@ impl User {
         })
     }
 
+    // adds the user or moves it to the group, its feed token and sessions stay valid
+    pub fn upsert(db: &Database, key_hash: &str, group_name: &str) -> Result<Self, Error> {
+        db.execute(
+            r#"
+                INSERT INTO users (key_hash, group_name) VALUES (?, ?)
+                ON CONFLICT (key_hash) DO UPDATE SET group_name = excluded.group_name;
+            "#,
+            (&key_hash, group_name),
+        )
+        .context("failed to upsert user into database")?;
+
+        Ok(Self {
+            key_hash: key_hash.to_string(),
+            group_name: group_name.to_string(),
+        })
+    }
+
     pub fn from_cookie(db: &Database, cookies: &ax::CookieJar) -> Result<User, Error> {
-        let key = cookies.get("key").ok_or(Error::new("no key in cookies"))?;
-        Self::by_hash(db, key.value())
+        let secret = cookies
+            .get(SESSION_COOKIE)
+            .ok_or(Error::new("no session in cookies"))?;
+        let session = Session::by_secret(db, secret.value())?;
+        Self::by_hash(db, &session.key_hash)
     }
 
     pub fn by_hash(db: &Database, key_hash: &str) -> Result<User, Error> {
@@ -57,15 +87,90 @@ impl User {
         .context("failed to query user by key_hash from database")
     }
 
-    pub fn delete_all(db: &Database) -> Result<(), Error> {
-        db.execute("DELETE FROM users", [])
-            .context("failed to delete all users from database")
+    pub fn by_feed_token(db: &Database, feed_token: &str) -> Result<User, Error> {
+        db.query_one(
+            "SELECT key_hash, group_name FROM users WHERE feed_token = ?;",
+            [feed_token],
+            User::from_row,
+        )
+        .context("failed to query user by feed token from database")
+    }
+
+    // created the first time it's needed
+    pub fn feed_token(&self, db: &Database) -> Result<String, Error> {
+        let feed_token: Option<String> = db
+            .query_one(
+                "SELECT feed_token FROM users WHERE key_hash = ?;",
+                [&self.key_hash],
+                |row| row.get(0),
+            )
+            .context("failed to query feed token from database")?;
+
+        match feed_token {
+            Some(feed_token) => Ok(feed_token),
+            None => self.reset_feed_token(db),
+        }
+    }
+
+    // the old feed urls stop working, e.g. after one was shared by accident
+    pub fn reset_feed_token(&self, db: &Database) -> Result<String, Error> {
+        let feed_token = hex::encode(rand::random::<[u8; 16]>());
+        db.execute(
+            "UPDATE users SET feed_token = ? WHERE key_hash = ?;",
+            (&feed_token, &self.key_hash),
+        )
+        .context("failed to update feed token in database")?;
+        Ok(feed_token)
+    }
+
+    pub fn role(&self) -> Role {
+        Role::of_group(&self.group_name)
+    }
+
+    pub fn has_permission(&self, permission: Permission) -> bool {
+        self.role().has(permission)
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.has_permission(Permission::Administer)
+    }
+
+    // content shared with a group is for that group only, other private content for every member
+    pub fn can_see(&self, allowed_group: Option<&str>) -> bool {
+        match allowed_group {
+            Some(group) => {
+                group == self.group_name || self.has_permission(Permission::ViewAllGroups)
+            }
+            None => self.has_permission(Permission::ViewPrivate),
+        }
+    }
+
+    pub fn get_all(db: &Database) -> Result<Vec<User>, Error> {
+        db.query_mul(
+            "SELECT key_hash, group_name FROM users ORDER BY group_name;",
+            [],
+            User::from_row,
+        )
+        .context("failed to query users from database")
+    }
+
+    // the user with `key` and its sessions, false if there is none
+    pub fn delete(db: &Database, key: &str) -> Result<bool, Error> {
+        let key_hash = Self::key_hash(key);
+        db.execute("DELETE FROM sessions WHERE key_hash = ?;", [&key_hash])
+            .context("failed to delete sessions of user from database")?;
+        let deleted = db
+            .query_mul(
+                "DELETE FROM users WHERE key_hash = ? RETURNING key_hash;",
+                [&key_hash],
+                |row| row.get::<_, String>(0),
+            )
+            .context("failed to delete user from database")?;
+        Ok(!deleted.is_empty())
     }
 
-    fn key_hash(key: &str) -> String {
-        let mut hasher = std::hash::DefaultHasher::new();
-        key.hash(&mut hasher);
-        format!("{:016x}", hasher.finish())
+    pub(crate) fn key_hash(key: &str) -> String {
+        hex::encode(Sha256::digest(key.as_bytes()))
     }
 }
 
@@ -85,8 +190,12 @@ pub async fn get_login(
     ax::State(state): ax::State<Arc<AppState>>,
     ax::Query(params): ax::Query<HashMap<String, String>>,
     cookie: ax::CookieJar,
+    lite: Lite,
 ) -> impl IntoResponse {
-    let db = &state.db.lock().unwrap();
+    let db = &match state.lock_writer().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
     let user = User::from_cookie(db, &cookie).ok();
     let failed = if let Some(failed) = params.get("failed") {
         failed == "true"
@@ -96,20 +205,47 @@ pub async fn get_login(
 
     println!("GET login, failed = {}, user = {:?}", failed, user);
 
+    let feed_token = match user.as_ref().map(|user| user.feed_token(db)).transpose() {
+        Ok(feed_token) => feed_token,
+        Err(_) => return make_error(500, "Failed to load feeds").into_response(),
+    };
+    let site_url = state
+        .config
+        .lock()
+        .unwrap()
+        .site_url
+        .trim_end_matches('/')
+        .to_string();
+
     let content = html!(
         @if failed {
             p { "Invalid password, please try again." }
         }
 
-        form action="/login/" method="post" {
+        form action=(routes::LOGIN) method="post" {
+            (csrf_field())
             input type="password" name="key" placeholder="password" required {}
             input type="submit" value="Login" {}
         }
+
+        @if let Some(feed_token) = feed_token {
+            h2 { "Your feeds" }
+            p { "These feeds include everything you can see when logged in. Keep them to yourself." }
+            ul {
+                @for (name, path) in [("Posts", routes::POSTS_FEED), ("Photos", routes::PHOTOS_FEED), ("Calendar", routes::CALENDAR)] {
+                    @let url = format!("{}{}?token={}", site_url, path, feed_token);
+                    li { (name) ": " a href=(url) { code { (url) } } }
+                }
+            }
+            form action=(routes::RESET_FEEDS) method="post" {
+                (csrf_field())
+                input type="submit" value="Reset feed urls" {}
+            }
+        }
     );
 
     let page = make_page(
-        Some("Login"),
-        "Login page.",
+        PageMeta::new(Section::Login).title("Login").lite(lite),
         vec!["/styles/login.css"],
         content,
         user,
@@ -119,6 +255,32 @@ pub async fn get_login(
     ax::Html::from(page.into_string()).into_response()
 }
 
+// against guessing keys, a client with this many failed logins has to wait until the oldest is
+// older than the window
+const MAX_FAILED_LOGINS: usize = 10;
+const FAILED_LOGIN_WINDOW: i64 = 10 * 60;
+
+// times of recent failed logins by client address, since the server started
+#[derive(Default)]
+pub struct FailedLogins(Mutex<HashMap<IpAddr, Vec<i64>>>);
+
+impl FailedLogins {
+    fn is_blocked(&self, ip: IpAddr, now: i64) -> bool {
+        let mut failures = self.0.lock().unwrap();
+        failures.retain(|_, times| {
+            times.retain(|at| now - at < FAILED_LOGIN_WINDOW);
+            !times.is_empty()
+        });
+        failures
+            .get(&ip)
+            .is_some_and(|times| times.len() >= MAX_FAILED_LOGINS)
+    }
+
+    fn record(&self, ip: IpAddr, now: i64) {
+        self.0.lock().unwrap().entry(ip).or_default().push(now);
+    }
+}
+
 #[derive(Deserialize, Debug)]
 pub struct LoginForm {
     key: String,
@@ -126,31 +288,95 @@ pub struct LoginForm {
 
 pub async fn post_login(
     ax::State(state): ax::State<Arc<AppState>>,
+    client: Client,
     form: ax::Form<LoginForm>,
 ) -> impl IntoResponse {
-    let db = &state.db.lock().unwrap();
+    let now = chrono::Utc::now().timestamp();
+    // without an address every client would share the limit, so they aren't limited at all
+    if let Some(ip) = client.ip
+        && state.failed_logins.is_blocked(ip, now)
+    {
+        println!("POST login, too many failed logins from {}", ip);
+        return make_error(429, "Too many failed logins, please try again later").into_response();
+    }
+
+    let db = &match state.lock_writer().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let cfg = &state.config.lock().unwrap();
 
     let hash = User::key_hash(&form.key);
     let user = User::by_hash(db, &hash).ok();
 
     if let Some(user) = user {
         println!("POST login, user = {:?}", user);
+
+        // logins are rare enough to clean up old sessions on the way
+        let session =
+            Session::delete_expired(db).and_then(|_| Session::new(db, &hash, cfg.session_ttl));
+        let Ok((session, secret)) = session else {
+            return make_error(500, "Failed to log in").into_response();
+        };
+
         (
-            ax::CookieJar::new().add(ax::Cookie::build(("key", hash)).path("/")),
-            ax::Redirect::to("/"),
+            ax::CookieJar::new().add(Session::cookie(cfg, Some((&session, secret)))),
+            ax::Redirect::to(routes::INDEX),
         )
             .into_response()
     } else {
-        println!("POST login, invalid key");
-        ax::Redirect::to("/login/?failed=true").into_response()
+        println!("POST login, invalid key from {:?}", client.ip);
+        if let Some(ip) = client.ip {
+            state.failed_logins.record(ip, now);
+        }
+        ax::Redirect::to(&format!("{}?failed=true", routes::LOGIN)).into_response()
+    }
+}
+
+pub async fn post_reset_feeds(
+    ax::State(state): ax::State<Arc<AppState>>,
+    cookie: ax::CookieJar,
+) -> impl IntoResponse {
+    let db = &match state.lock_writer().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let user = User::from_cookie(db, &cookie).ok();
+
+    println!("POST reset feeds, user = {:?}", user);
+
+    let Some(user) = user else {
+        return ax::Redirect::to(routes::LOGIN).into_response();
+    };
+
+    if user.reset_feed_token(db).is_err() {
+        return make_error(500, "Failed to reset feeds").into_response();
     }
+
+    ax::Redirect::to(routes::LOGIN).into_response()
 }
 
-pub async fn post_logout(cookie: ax::CookieJar) -> impl IntoResponse {
+pub async fn post_logout(
+    ax::State(state): ax::State<Arc<AppState>>,
+    cookie: ax::CookieJar,
+) -> impl IntoResponse {
+    let db = &match state.lock_writer().await {
+        Ok(db) => db,
+        Err(response) => return response,
+    };
+    let cfg = &state.config.lock().unwrap();
+
     println!("POST logout");
+
+    if let Some(secret) = cookie.get(SESSION_COOKIE)
+        && Session::delete(db, secret.value()).is_err()
+    {
+        return make_error(500, "Failed to log out").into_response();
+    }
+
     (
-        cookie.add(ax::Cookie::build("key").path("/").removal().build()),
-        ax::Redirect::to("/"),
+        cookie.add(Session::cookie(cfg, None)),
+        ax::Redirect::to(routes::INDEX),
     )
         .into_response()
 }
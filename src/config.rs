@@ -1,36 +1,500 @@
+use std::net::IpAddr;
+
+use crate::component::trailing_slash::TrailingSlash;
+use crate::crypto;
 use crate::prelude::*;
 
+const ENCRYPTION_KEY_VAR: &str = "WEBSITE_ENCRYPTION_KEY";
+const GITHUB_TOKEN_VAR: &str = "WEBSITE_GITHUB_TOKEN";
+const SMTP_PASSWORD_VAR: &str = "WEBSITE_SMTP_PASSWORD";
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct LinkConfig {
+    pub text: String,
+    pub href: String,
+    #[serde(default)]
+    pub icon: Option<String>,
+    #[serde(default)]
+    pub rel_me: bool,
+}
+
+// push-to-deploy, see `post_github_hook`
+#[derive(Serialize, Deserialize, Clone)]
+pub struct GithubWebhookConfig {
+    // the secret set on the webhook in the repository settings
+    pub secret: String,
+    // git checkout of the content, pulled before rebuilding
+    pub repository_path: String,
+}
+
+// repositories on the projects page, fetched on every build, see `Project::sync`
+#[derive(Serialize, Deserialize, Clone)]
+pub struct GithubProjectsConfig {
+    pub user: String,
+    #[serde(default)]
+    pub repositories: GithubRepositories,
+    // needed for pinned repositories, `WEBSITE_GITHUB_TOKEN` wins
+    #[serde(default)]
+    pub token: Option<String>,
+    #[serde(default = "default_github_max_projects")]
+    pub max_projects: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum GithubRepositories {
+    // pinned on the profile
+    #[default]
+    Pinned,
+    // starred by the user
+    Starred,
+}
+
+// a project listed before the ones from GitHub, e.g. one hosted elsewhere
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ProjectConfig {
+    pub name: String,
+    pub url: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub language: Option<String>,
+}
+
+// the form at `/contact/`, messages are kept for the admins and mailed to `to` if `smtp` is set
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ContactConfig {
+    pub to: String,
+    #[serde(default)]
+    pub smtp: Option<SmtpConfig>,
+}
+
+// a relay that accepts mail for `to`, over STARTTLS
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SmtpConfig {
+    pub host: String,
+    #[serde(default = "default_smtp_port")]
+    pub port: u16,
+    pub from: String,
+    #[serde(default)]
+    pub username: Option<String>,
+    // `WEBSITE_SMTP_PASSWORD` wins
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+impl SmtpConfig {
+    pub fn password(&self) -> Option<String> {
+        std::env::var(SMTP_PASSWORD_VAR)
+            .ok()
+            .or_else(|| self.password.clone())
+    }
+}
+
+// Maps of the GPX tracks in posts and of the photos at `/photos/map/`. Leaflet isn't bundled, it
+// is served from the files directory as `scripts/leaflet.js` and `styles/leaflet.css`, and the
+// tiles come from `tile_url`.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MapConfig {
+    // e.g. `https://tile.openstreetmap.org/{z}/{x}/{y}.png`
+    pub tile_url: String,
+    #[serde(default = "default_map_attribution")]
+    pub attribution: String,
+}
+
+// Lets the blog be followed from Mastodon and other fediverse servers as `@username@host`, see
+// `component::activitypub`. The key signs what the blog sends, e.g. generated with
+// `openssl genpkey -algorithm RSA -pkeyopt rsa_keygen_bits:2048`.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ActivityPubConfig {
+    pub username: String,
+    // a PKCS#8 PEM file
+    pub private_key_path: String,
+    #[serde(default = "default_activitypub_name")]
+    pub name: String,
+    #[serde(default)]
+    pub summary: Option<String>,
+}
+
+// backups taken while serving, see `backup::schedule_backups`
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BackupConfig {
+    pub dir: String,
+    #[serde(default = "default_backup_interval_hours")]
+    pub interval_hours: u64,
+    // older backups in `dir` are deleted
+    #[serde(default = "default_backup_keep")]
+    pub keep: usize,
+}
+
+// A user kept in sync with the database on every build, see `sync_users`. Only the hash of the
+// key goes in here, from `website user hash`, so the config never holds a working key.
 #[derive(Serialize, Deserialize, Clone)]
 pub struct UserConfig {
-    pub key: String,
+    pub key_hash: String,
     pub group: String,
 }
 
+// where an old url went, a plain path redirects permanently
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum RedirectConfig {
+    Permanent(String),
+    Target {
+        to: String,
+        #[serde(default = "default_permanent")]
+        permanent: bool,
+    },
+}
+
+impl RedirectConfig {
+    pub fn to(&self) -> &str {
+        match self {
+            RedirectConfig::Permanent(to) | RedirectConfig::Target { to, .. } => to,
+        }
+    }
+
+    pub fn is_permanent(&self) -> bool {
+        match self {
+            RedirectConfig::Permanent(_) => true,
+            RedirectConfig::Target { permanent, .. } => *permanent,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Config {
     pub database_path: String,
     pub posts_path: String,
     pub files_path: String,
+    #[serde(default)]
+    pub intro_path: Option<String>,
+    // markdown pages like `about.md`, served at `/about/`
+    #[serde(default)]
+    pub pages_path: Option<String>,
+    // served at `/now/`, see `get_now`
+    #[serde(default)]
+    pub now_path: Option<String>,
+    // a JSON or TOML file served at `/cv/`, see `Cv`
+    #[serde(default)]
+    pub cv_path: Option<String>,
     pub post_content_path: String,
     pub post_metadata_path: String,
     pub post_assets_path: String,
     pub post_public_photos_path: String,
     pub post_private_photos_path: String,
+    // write the id of a post without one into its meta.json or frontmatter. Otherwise the build
+    // never changes the sources and the id is derived from the name of the post's directory, so
+    // renaming the directory changes it.
+    #[serde(default)]
+    pub write_post_ids: bool,
     pub photo_max_preview_size: u32,
     pub photo_quality: u8,
     pub server_host: String,
     pub server_port: u16,
+    // reverse proxies whose X-Forwarded-For and X-Forwarded-Proto headers are believed
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
+    // path of a unix socket to listen on instead of host and port, e.g. behind nginx
+    #[serde(default)]
+    pub server_socket: Option<String>,
+    // where the site is reachable, for feeds, mails and links shared elsewhere. Configs from
+    // before it existed get a local one, which `website doctor` complains about.
+    #[serde(default = "default_site_url")]
+    pub site_url: String,
     pub photos_per_page: u32,
+    #[serde(default = "default_feed_length")]
+    pub feed_length: u32,
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
+    #[serde(default)]
+    pub links: Vec<LinkConfig>,
+    #[serde(default)]
+    pub warm_posts: u32,
+    #[serde(default)]
+    pub encryption_key: Option<String>,
+    // progress bar and remembered reading position on posts
+    #[serde(default)]
+    pub reading_progress: bool,
+    // requests that can't get hold of the database within this time are answered with a 503
+    #[serde(default = "default_db_timeout_ms")]
+    pub db_timeout_ms: u64,
+    // from before api tokens, imported as the token `micropub-config` by `migrate` and `build`
+    #[serde(default)]
+    pub micropub_token: Option<String>,
+    // sqlite pragmas set on every connection, wal lets pages be read while a build writes
+    #[serde(default = "default_db_journal_mode")]
+    pub db_journal_mode: String,
+    #[serde(default = "default_db_synchronous")]
+    pub db_synchronous: String,
+    // enforces the foreign keys, so deleting a post also deletes its photos, tags, ...
+    #[serde(default = "default_db_foreign_keys")]
+    pub db_foreign_keys: bool,
+    // requests that take longer than this are answered with a 408
+    #[serde(default = "default_request_timeout_secs")]
+    pub request_timeout_secs: u64,
+    // in bytes, for routes without a limit of their own
+    #[serde(default = "default_max_body_size")]
+    pub max_body_size: usize,
+    // requests beyond this wait until one of the others is done
+    #[serde(default = "default_max_concurrent_requests")]
+    pub max_concurrent_requests: usize,
+    // `build --strict` fails when fewer photos and images have alt text, in percent
+    #[serde(default)]
+    pub alt_text_min_coverage: Option<f64>,
+    // a WebSub hub, e.g. `https://pubsubhubbub.appspot.com/`, announced in the feeds and pinged
+    // when a build changes public posts
+    #[serde(default)]
+    pub websub_hub: Option<String>,
+    // directories of `files/files` listed at `/files/`, with everything below them, e.g.
+    // `["downloads"]`, or `[""]` for all of it
+    #[serde(default)]
+    pub public_files: Vec<String>,
+    // files under `/files/` that browsers download instead of showing, directories like
+    // `downloads` with everything below them or extensions like `.zip`
+    #[serde(default)]
+    pub attachment_files: Vec<String>,
+    #[serde(default)]
+    pub github_webhook: Option<GithubWebhookConfig>,
+    #[serde(default)]
+    pub backup: Option<BackupConfig>,
+    #[serde(default)]
+    pub github_projects: Option<GithubProjectsConfig>,
+    #[serde(default)]
+    pub projects: Vec<ProjectConfig>,
+    #[serde(default)]
+    pub contact: Option<ContactConfig>,
+    #[serde(default)]
+    pub map: Option<MapConfig>,
+    #[serde(default)]
+    pub activitypub: Option<ActivityPubConfig>,
+    #[serde(default)]
     pub users: Vec<UserConfig>,
+    // how long a login lasts, in seconds
+    #[serde(default = "default_session_ttl")]
+    pub session_ttl: i64,
+    // how long a login link from the admin dashboard can be used, in seconds
+    #[serde(default = "default_login_link_ttl")]
+    pub login_link_ttl: i64,
+    // off for local development over plain http
+    #[serde(default = "default_secure_cookies")]
+    pub secure_cookies: bool,
+    // urls of the old site by path, checked before answering with a 404
+    #[serde(default)]
+    pub redirects: HashMap<String, RedirectConfig>,
+    // whether page urls end with a slash, see `TrailingSlash`
+    #[serde(default)]
+    pub trailing_slash: TrailingSlash,
+}
+
+pub const DEFAULT_SITE_URL: &str = "http://localhost";
+
+fn default_site_url() -> String {
+    DEFAULT_SITE_URL.to_string()
+}
+
+fn default_feed_length() -> u32 {
+    20
+}
+
+fn default_db_timeout_ms() -> u64 {
+    5000
+}
+
+fn default_backup_interval_hours() -> u64 {
+    24
+}
+
+fn default_backup_keep() -> usize {
+    7
+}
+
+fn default_map_attribution() -> String {
+    "© OpenStreetMap contributors".to_string()
+}
+
+fn default_activitypub_name() -> String {
+    "Kai".to_string()
+}
+
+fn default_smtp_port() -> u16 {
+    587
+}
+
+fn default_github_max_projects() -> usize {
+    6
+}
+
+fn default_db_journal_mode() -> String {
+    "wal".to_string()
+}
+
+fn default_db_synchronous() -> String {
+    "normal".to_string()
+}
+
+fn default_db_foreign_keys() -> bool {
+    true
+}
+
+fn default_session_ttl() -> i64 {
+    30 * 24 * 60 * 60
+}
+
+fn default_permanent() -> bool {
+    true
+}
+
+fn default_login_link_ttl() -> i64 {
+    7 * 24 * 60 * 60
+}
+
+fn default_secure_cookies() -> bool {
+    true
+}
+
+fn default_request_timeout_secs() -> u64 {
+    30
+}
+
+fn default_max_body_size() -> usize {
+    2 * 1024 * 1024
+}
+
+fn default_max_concurrent_requests() -> usize {
+    64
+}
+
+fn default_timezone() -> String {
+    "UTC".to_string()
 }
 
 impl Config {
     pub fn from_json_str(json_str: &str) -> Result<Config, Error> {
-        serde_json::from_str(json_str).context("failed to decode configuration")
+        let config: Config = serde_json::from_str(json_str)
+            .context("failed to decode configuration")
+            .map_err(|error| error.with_kind(ErrorKind::Config))?;
+
+        config
+            .timezone
+            .parse::<Tz>()
+            .context(format!("invalid timezone {:?}", config.timezone))
+            .map_err(|error| error.with_kind(ErrorKind::Config))?;
+
+        for (name, value, allowed) in [
+            (
+                "db_journal_mode",
+                &config.db_journal_mode,
+                &["delete", "truncate", "persist", "memory", "wal", "off"][..],
+            ),
+            (
+                "db_synchronous",
+                &config.db_synchronous,
+                &["off", "normal", "full", "extra"][..],
+            ),
+        ] {
+            if !allowed.contains(&value.to_lowercase().as_str()) {
+                return Err(Error::new(format!(
+                    "{} must be one of {}, not {:?}",
+                    name,
+                    allowed.join(", "),
+                    value
+                ))
+                .with_kind(ErrorKind::Config));
+            }
+        }
+
+        if let Some(path) = config.redirects.keys().find(|path| !path.starts_with('/')) {
+            return Err(
+                Error::new(format!("redirect from {:?} must start with a /", path))
+                    .with_kind(ErrorKind::Config),
+            );
+        }
+
+        config
+            .encryption_key()
+            .map_err(|error| error.with_kind(ErrorKind::Config))?;
+
+        if let Some(github) = &config.github_projects
+            && github.repositories == GithubRepositories::Pinned
+            && config.github_token().is_none()
+        {
+            return Err(Error::new(format!(
+                "pinned repositories of {} need a token, set github_projects.token or {}",
+                github.user, GITHUB_TOKEN_VAR
+            ))
+            .with_kind(ErrorKind::Config));
+        }
+
+        if let Some(contact) = &config.contact {
+            let from = contact.smtp.as_ref().map(|smtp| smtp.from.as_str());
+            for address in std::iter::once(contact.to.as_str()).chain(from) {
+                address
+                    .parse::<lettre::message::Mailbox>()
+                    .context(format!("invalid contact address {}", address))
+                    .map_err(|error| error.with_kind(ErrorKind::Config))?;
+            }
+        }
+
+        Ok(config)
     }
 
     pub fn from_json_file(path: &str) -> Result<Config, Error> {
-        let json_str = fs::read_to_string(path).context("failed to read configuration file")?;
+        let json_str = fs::read_to_string(path)
+            .context("failed to read configuration file")
+            .map_err(|error| error.with_kind(ErrorKind::Config))?;
         Config::from_json_str(&json_str)
     }
+
+    // the path as given, or with or without the trailing slash
+    pub fn redirect(&self, path: &str) -> Option<&RedirectConfig> {
+        let other = match path.strip_suffix('/') {
+            Some(path) => path.to_string(),
+            None => format!("{}/", path),
+        };
+        self.redirects
+            .get(path)
+            .or_else(|| self.redirects.get(&other))
+    }
+
+    pub fn timezone(&self) -> Tz {
+        self.timezone.parse().unwrap_or(Tz::UTC)
+    }
+
+    // the configured links and, instead of a mail address, the contact form
+    pub fn footer_links(&self) -> Vec<LinkConfig> {
+        let mut links = self.links.clone();
+        if self.contact.is_some() {
+            links.push(LinkConfig {
+                text: "Contact".to_string(),
+                href: routes::CONTACT.to_string(),
+                icon: None,
+                rel_me: false,
+            });
+        }
+        links
+    }
+
+    // like the encryption key, the environment variable wins
+    pub fn github_token(&self) -> Option<String> {
+        std::env::var(GITHUB_TOKEN_VAR).ok().or_else(|| {
+            self.github_projects
+                .as_ref()
+                .and_then(|github| github.token.clone())
+        })
+    }
+
+    // the environment variable wins, so the key doesn't have to live next to the database
+    pub fn encryption_key(&self) -> Result<Option<crypto::Key>, Error> {
+        match std::env::var(ENCRYPTION_KEY_VAR) {
+            Ok(key) => crypto::parse_key(&key)
+                .context(format!("invalid {}", ENCRYPTION_KEY_VAR))
+                .map(Some),
+            Err(_) => self
+                .encryption_key
+                .as_deref()
+                .map(|key| crypto::parse_key(key).context("invalid encryption_key"))
+                .transpose(),
+        }
+    }
 }
@@ -0,0 +1,51 @@
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+
+use crate::prelude::*;
+
+pub type Key = [u8; 32];
+
+const NONCE_LENGTH: usize = 24;
+// what encryption adds to the length of the data: the nonce and the authentication tag
+pub const OVERHEAD: usize = NONCE_LENGTH + 16;
+
+pub fn parse_key(hex_key: &str) -> Result<Key, Error> {
+    hex::decode(hex_key.trim())
+        .ok()
+        .and_then(|key| Key::try_from(key).ok())
+        .ok_or_else(|| Error::new("encryption key must be 64 hex characters (32 bytes)"))
+}
+
+// the output is the random nonce followed by the ciphertext. `context` is authenticated but not
+// stored, so a blob can only be decrypted for the row it was written to
+pub fn encrypt(key: &Key, data: &[u8], context: &[u8]) -> Result<Vec<u8>, Error> {
+    let nonce: [u8; NONCE_LENGTH] = rand::random();
+    let ciphertext = XChaCha20Poly1305::new(key.into())
+        .encrypt(
+            XNonce::from_slice(&nonce),
+            Payload {
+                msg: data,
+                aad: context,
+            },
+        )
+        .map_err(|_| Error::new("failed to encrypt data"))?;
+
+    Ok([nonce.as_slice(), &ciphertext].concat())
+}
+
+pub fn decrypt(key: &Key, data: &[u8], context: &[u8]) -> Result<Vec<u8>, Error> {
+    if data.len() < NONCE_LENGTH {
+        return Err(Error::new("encrypted data is too short"));
+    }
+
+    let (nonce, ciphertext) = data.split_at(NONCE_LENGTH);
+    XChaCha20Poly1305::new(key.into())
+        .decrypt(
+            XNonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: context,
+            },
+        )
+        .map_err(|_| Error::new("failed to decrypt data, is the encryption key correct?"))
+}
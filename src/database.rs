@@ -1,7 +1,16 @@
-use rusqlite::{Connection, Params};
+use std::time::Duration;
+
+use rusqlite::backup::{Backup, Progress};
+use rusqlite::hooks::{AuthAction, AuthContext, Authorization};
+use rusqlite::{Connection, OpenFlags, Params, MAIN_DB};
 pub use rusqlite::{Error as SqliteError, Row};
 
 use crate::prelude::*;
+use crate::schema::SERVER_TABLES;
+
+// copied between pauses, so a backup doesn't keep out other connections for long
+const BACKUP_PAGES_PER_STEP: std::ffi::c_int = 256;
+const BACKUP_PAUSE: Duration = Duration::from_millis(10);
 
 pub struct Database {
     connection: Connection,
@@ -14,6 +23,108 @@ impl Database {
         })
     }
 
+    // the database of the config, with its pragmas applied
+    pub fn open(config: &Config) -> Result<Self, Error> {
+        let db = Self::connect(&config.database_path)?;
+        db.set_busy_timeout(Duration::from_millis(config.db_timeout_ms))?;
+
+        // in-memory databases stay in memory whatever is asked for
+        let _: String = db
+            .connection
+            .pragma_update_and_check(None, "journal_mode", &config.db_journal_mode, |row| {
+                row.get(0)
+            })
+            .context("failed to set database journal mode")?;
+        db.connection
+            .pragma_update(None, "synchronous", &config.db_synchronous)
+            .context("failed to set database synchronous mode")?;
+        db.connection
+            .pragma_update(None, "foreign_keys", config.db_foreign_keys)
+            .context("failed to set database foreign keys")?;
+
+        Ok(db)
+    }
+
+    // For serving pages, which never write. A bug in a handler can't change what the build wrote.
+    pub fn open_read_only(config: &Config) -> Result<Self, Error> {
+        let db = Self {
+            connection: Connection::open_with_flags(
+                &config.database_path,
+                OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+            )
+            .context("failed to open database read-only")?,
+        };
+        db.set_busy_timeout(Duration::from_millis(config.db_timeout_ms))?;
+        Ok(db)
+    }
+
+    // For what the server writes itself, e.g. sessions and comments. Writes to any other table or
+    // changes to the schema are refused by sqlite before they run.
+    pub fn open_server_writer(config: &Config) -> Result<Self, Error> {
+        let db = Self::open(config)?;
+        db.connection
+            .authorizer(Some(authorize_server_write))
+            .context("failed to restrict database writes")?;
+        Ok(db)
+    }
+
+    // A consistent copy at `path`, also while other connections write. The copy is written next
+    // to it first, so `path` is never left half written.
+    pub fn backup(&self, path: &Path) -> Result<(), Error> {
+        let partial = path.with_extension("partial");
+        let mut destination = Connection::open(&partial).context("failed to create backup file")?;
+        Backup::new(&self.connection, &mut destination)
+            .and_then(|backup| backup.run_to_completion(BACKUP_PAGES_PER_STEP, BACKUP_PAUSE, None))
+            .context("failed to back up database")?;
+        drop(destination);
+
+        fs::rename(&partial, path).context("failed to move backup into place")
+    }
+
+    // replaces the whole database with the one at `path`
+    pub fn restore(&mut self, path: &Path) -> Result<(), Error> {
+        self.connection
+            .restore(MAIN_DB, path, None::<fn(Progress)>)
+            .context("failed to restore database")
+    }
+
+    pub fn set_busy_timeout(&self, timeout: Duration) -> Result<(), Error> {
+        self.connection
+            .busy_timeout(timeout)
+            .context("failed to set database busy timeout")
+    }
+
+    // fails if another connection keeps the database locked for longer than the busy timeout
+    pub fn check_available(&self) -> Result<(), Error> {
+        self.connection
+            .query_row("SELECT COUNT(*) FROM sqlite_master;", [], |_| Ok(()))
+            .context("database is busy")
+    }
+
+    // bytes used by the database file, without the journal
+    pub fn size(&self) -> Result<i64, Error> {
+        self.connection
+            .query_row(
+                "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size();",
+                [],
+                |row| row.get(0),
+            )
+            .context("failed to query database size")
+    }
+
+    // changes whenever this connection or another one writes to the database
+    pub fn version(&self) -> Result<(i64, u64), Error> {
+        let data_version = self
+            .connection
+            .query_row(
+                "SELECT data_version FROM pragma_data_version();",
+                [],
+                |row| row.get(0),
+            )
+            .context("failed to query database version")?;
+        Ok((data_version, self.connection.total_changes()))
+    }
+
     pub fn execute<P: Params>(&self, sql: &str, params: P) -> Result<(), Error> {
         self.connection
             .prepare(sql)
@@ -29,6 +140,43 @@ impl Database {
             .context("failed to execute batch SQL")
     }
 
+    // Runs `f` in a transaction that takes the write lock up front. Other connections keep seeing
+    // what was there before until it commits, and nothing of it is kept if `f` fails.
+    pub fn transaction<T>(&self, f: impl FnOnce() -> Result<T, Error>) -> Result<T, Error> {
+        self.execute_batch("BEGIN IMMEDIATE;")?;
+        match f() {
+            Ok(value) => {
+                self.execute_batch("COMMIT;")?;
+                Ok(value)
+            }
+            Err(error) => {
+                self.execute_batch("ROLLBACK;")?;
+                Err(error)
+            }
+        }
+    }
+
+    pub fn has_column(&self, table: &str, column: &str) -> Result<bool, Error> {
+        self.query_one(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info(?) WHERE name = ?;",
+            (table, column),
+            |row| row.get(0),
+        )
+        .context("failed to query table info")
+    }
+
+    pub fn ensure_column(&self, table: &str, column: &str, definition: &str) -> Result<(), Error> {
+        if !self.has_column(table, column)? {
+            self.execute_batch(&format!(
+                "ALTER TABLE {} ADD COLUMN {} {};",
+                table, column, definition
+            ))
+            .context("failed to add column")?;
+        }
+
+        Ok(())
+    }
+
     pub fn query_one<P: Params, F: FnMut(&Row<'_>) -> Result<T, SqliteError>, T>(
         &self,
         sql: &str,
@@ -56,3 +204,24 @@ impl Database {
             .collect()
     }
 }
+
+fn authorize_server_write(context: AuthContext<'_>) -> Authorization {
+    match context.action {
+        AuthAction::Insert { table_name }
+        | AuthAction::Update { table_name, .. }
+        | AuthAction::Delete { table_name } => match SERVER_TABLES.contains(&table_name) {
+            true => Authorization::Allow,
+            false => Authorization::Deny,
+        },
+        AuthAction::CreateIndex { .. }
+        | AuthAction::CreateTable { .. }
+        | AuthAction::CreateTrigger { .. }
+        | AuthAction::CreateView { .. }
+        | AuthAction::DropIndex { .. }
+        | AuthAction::DropTable { .. }
+        | AuthAction::DropTrigger { .. }
+        | AuthAction::DropView { .. }
+        | AuthAction::AlterTable { .. } => Authorization::Deny,
+        _ => Authorization::Allow,
+    }
+}
@@ -0,0 +1,212 @@
+use std::io::IsTerminal;
+use std::net::TcpListener;
+
+use crate::config::DEFAULT_SITE_URL;
+use crate::prelude::*;
+use crate::schema;
+
+// stylesheets and assets every page links to, relative to the files directory
+const REQUIRED_FILES: &[(&str, &str)] = &[
+    ("styles", "page.css"),
+    ("styles", "post.css"),
+    ("styles", "photo.css"),
+    ("styles", "login.css"),
+    ("styles", "error.css"),
+    ("assets", "logo.jpg"),
+];
+
+// one line per check, shared with `website check`
+pub(crate) struct Report {
+    color: bool,
+    passed: usize,
+    failed: usize,
+}
+
+impl Report {
+    pub(crate) fn new() -> Self {
+        Self {
+            color: std::io::stdout().is_terminal(),
+            passed: 0,
+            failed: 0,
+        }
+    }
+
+    pub(crate) fn check(&mut self, name: &str, result: Result<(), Error>) -> bool {
+        let (mark, color, detail) = match &result {
+            Ok(()) => ("✓", "32", String::new()),
+            Err(error) => ("✗", "31", format!(": {}", error.chain_message())),
+        };
+
+        if self.color {
+            println!("\x1b[{}m{} {}{}\x1b[0m", color, mark, name, detail);
+        } else {
+            println!("{} {}{}", mark, name, detail);
+        }
+
+        match result {
+            Ok(()) => self.passed += 1,
+            Err(_) => self.failed += 1,
+        }
+        detail.is_empty()
+    }
+}
+
+// Checks everything a deploy needs without building or serving anything, so a broken setup shows
+// up as a list of problems instead of one error at a time.
+pub async fn doctor(args: &[String]) -> Result<(), Error> {
+    if !args.is_empty() {
+        return crate::usage("doctor");
+    }
+
+    let mut report = Report::new();
+
+    let config = match Config::from_json_file("website.json") {
+        Ok(config) => {
+            report.check("configuration is valid", Ok(()));
+            config
+        }
+        Err(error) => {
+            report.check("configuration is valid", Err(error));
+            return finish(&report);
+        }
+    };
+
+    for (name, path) in [
+        ("posts directory", Some(&config.posts_path)),
+        ("files directory", Some(&config.files_path)),
+        ("intro page", config.intro_path.as_ref()),
+        ("pages directory", config.pages_path.as_ref()),
+        ("now page", config.now_path.as_ref()),
+        ("cv", config.cv_path.as_ref()),
+    ] {
+        if let Some(path) = path {
+            report.check(&format!("{} {} exists", name, path), check_exists(path));
+        }
+    }
+
+    check_database(&mut report, &config);
+
+    for (dir, name) in REQUIRED_FILES {
+        let path = Path::new(&config.files_path).join(dir).join(name);
+        report.check(
+            &format!("{}/{} is present", dir, name),
+            check_exists(&path.to_string_lossy()),
+        );
+    }
+
+    // leaflet for the maps of tracks isn't built in
+    if config.map.is_some() {
+        for (dir, name) in [
+            ("scripts", LEAFLET_SCRIPT_NAME),
+            ("styles", LEAFLET_STYLE_NAME),
+        ] {
+            let path = Path::new(&config.files_path).join(dir).join(name);
+            report.check(
+                &format!("{}/{} is present for maps", dir, name),
+                check_exists(&path.to_string_lossy()),
+            );
+        }
+    }
+
+    // deliveries to followers are signed with it
+    if let Some(activitypub) = &config.activitypub {
+        report.check(
+            "activitypub private key is readable",
+            crate::component::activitypub::private_key(activitypub).map(drop),
+        );
+    }
+
+    for link in &config.links {
+        if let Some(icon) = &link.icon {
+            let path = Path::new(&config.files_path).join("assets").join(icon);
+            report.check(
+                &format!("icon {} for link {} is present", icon, link.href),
+                check_exists(&path.to_string_lossy()),
+            );
+        }
+    }
+
+    report.check(
+        &format!("site_url {} is set", config.site_url),
+        match config.site_url.as_str() {
+            DEFAULT_SITE_URL => Err(Error::new(
+                "feeds and shared links would point at localhost, set site_url in website.json",
+            )
+            .with_kind(ErrorKind::Config)),
+            _ => Ok(()),
+        },
+    );
+
+    let address = format!("{}:{}", config.server_host, config.server_port);
+    report.check(
+        &format!("{} is available", address),
+        TcpListener::bind(&address)
+            .map(drop)
+            .context("can't bind, is the server already running?"),
+    );
+
+    report.check(
+        "database directory is writable for backups",
+        check_writable(&config.database_path),
+    );
+
+    finish(&report)
+}
+
+pub(crate) fn finish(report: &Report) -> Result<(), Error> {
+    println!("{} passed, {} failed", report.passed, report.failed);
+
+    match report.failed {
+        0 => Ok(()),
+        failed => Err(Error::new(format!("{} checks failed", failed))),
+    }
+}
+
+fn check_exists(path: &str) -> Result<(), Error> {
+    match Path::new(path).exists() {
+        true => Ok(()),
+        false => Err(Error::new("not found").with_kind(ErrorKind::Io)),
+    }
+}
+
+fn check_database(report: &mut Report, config: &Config) {
+    // connecting would create an empty database, which is exactly what doctor must not do
+    if !report.check(
+        &format!("database {} exists", config.database_path),
+        check_exists(&config.database_path).context("run `website build` to create it"),
+    ) {
+        return;
+    }
+
+    let db = Database::connect(&config.database_path);
+    let db = match db.and_then(|db| db.check_available().map(|_| db)) {
+        Ok(db) => {
+            report.check("database is accessible", Ok(()));
+            db
+        }
+        Err(error) => {
+            report.check("database is accessible", Err(error));
+            return;
+        }
+    };
+
+    report.check(
+        &format!("database schema is at version {}", schema::SCHEMA_VERSION),
+        schema::check_version(&db),
+    );
+}
+
+fn check_writable(database_path: &str) -> Result<(), Error> {
+    let dir = match Path::new(database_path).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let probe = dir.join(format!(".website-doctor-{}", std::process::id()));
+
+    fs::write(&probe, b"")
+        .context(format!("can't write to {}", dir.display()))
+        .map_err(|error| error.with_kind(ErrorKind::Io))?;
+    fs::remove_file(&probe).context(format!("can't remove {}", probe.display()))?;
+
+    Ok(())
+}
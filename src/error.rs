@@ -1,8 +1,44 @@
+use std::any::Any;
 use std::fmt::{Debug, Formatter};
 use std::panic::Location;
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    Other,
+    Usage,
+    Config,
+    Io,
+    Database,
+    Validation,
+}
+
+impl ErrorKind {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorKind::Other => 1,
+            ErrorKind::Usage => 2,
+            ErrorKind::Config => 3,
+            ErrorKind::Io => 4,
+            ErrorKind::Database => 5,
+            ErrorKind::Validation => 6,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorKind::Other => "other",
+            ErrorKind::Usage => "usage",
+            ErrorKind::Config => "config",
+            ErrorKind::Io => "io",
+            ErrorKind::Database => "database",
+            ErrorKind::Validation => "validation",
+        }
+    }
+}
+
 pub struct Error {
     message: String,
+    kind: Option<ErrorKind>,
     file: String,
     line: u32,
     column: u32,
@@ -15,6 +51,7 @@ impl Error {
         let location = Location::caller();
         Self {
             message: message.into(),
+            kind: None,
             file: location.file().to_string(),
             line: location.line(),
             column: location.column(),
@@ -27,12 +64,66 @@ impl Error {
         let location = Location::caller();
         Self {
             message: message.into(),
+            kind: None,
             file: location.file().to_string(),
             line: location.line(),
             column: location.column(),
             child: Some(Box::new(self)),
         }
     }
+
+    pub fn with_kind(mut self, kind: ErrorKind) -> Self {
+        self.kind = Some(kind);
+        self
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        let mut current = Some(self);
+        while let Some(error) = current {
+            if let Some(kind) = error.kind {
+                return kind;
+            }
+            current = error.child.as_deref();
+        }
+
+        ErrorKind::Other
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    // all messages from the outermost context down to the cause, without source locations
+    pub fn chain_message(&self) -> String {
+        let mut messages = vec![];
+        let mut current = Some(self);
+        while let Some(error) = current {
+            messages.push(error.message.as_str());
+            current = error.child.as_deref();
+        }
+        messages.join(": ")
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut trace = vec![];
+        let mut current = Some(self);
+        while let Some(error) = current {
+            trace.push(serde_json::json!({
+                "message": error.message,
+                "file": error.file,
+                "line": error.line,
+                "column": error.column,
+            }));
+            current = error.child.as_deref();
+        }
+
+        serde_json::json!({
+            "kind": self.kind().name(),
+            "exit_code": self.kind().exit_code(),
+            "message": self.message,
+            "trace": trace,
+        })
+    }
 }
 
 impl Debug for Error {
@@ -46,16 +137,30 @@ impl Debug for Error {
                 "{}:{}:{}: {}",
                 error.file, error.line, error.column, error.message
             )?;
-            current = error.child.as_ref().map(|e| &**e);
+            current = error.child.as_deref();
         }
 
         Ok(())
     }
 }
 
-impl<T: std::error::Error> From<T> for Error {
+impl<T: std::error::Error + 'static> From<T> for Error {
     fn from(value: T) -> Self {
-        Self::new(value.to_string())
+        let any = &value as &dyn Any;
+        let kind = if any.is::<std::io::Error>() {
+            Some(ErrorKind::Io)
+        } else if any.is::<rusqlite::Error>() {
+            Some(ErrorKind::Database)
+        } else if any.is::<serde_json::Error>() {
+            Some(ErrorKind::Validation)
+        } else {
+            None
+        };
+
+        Self {
+            kind,
+            ..Self::new(value.to_string())
+        }
     }
 }
 
@@ -63,7 +168,7 @@ pub trait WithContext<T, S: Into<String>> {
     fn context(self, message: S) -> Result<T, Error>;
 }
 
-impl<T, E: std::error::Error, S: Into<String>> WithContext<T, S> for Result<T, E> {
+impl<T, E: std::error::Error + 'static, S: Into<String>> WithContext<T, S> for Result<T, E> {
     #[track_caller]
     fn context(self, message: S) -> Result<T, Error> {
         // self.map_err(|e| Error::new(e.to_string()).context(message))
@@ -74,6 +179,7 @@ impl<T, E: std::error::Error, S: Into<String>> WithContext<T, S> for Result<T, E
                 let location = Location::caller();
                 Err(Error {
                     message: message.into(),
+                    kind: None,
                     file: location.file().to_string(),
                     line: location.line(),
                     column: location.column(),
@@ -95,6 +201,7 @@ impl<T, S: Into<String>> WithContext<T, S> for Option<T> {
                 let location = Location::caller();
                 Err(Error {
                     message: message.into(),
+                    kind: None,
                     file: location.file().to_string(),
                     line: location.line(),
                     column: location.column(),
@@ -116,6 +223,7 @@ impl<T, S: Into<String>> WithContext<T, S> for Result<T, Error> {
                 let location = Location::caller();
                 Err(Error {
                     message: message.into(),
+                    kind: None,
                     file: location.file().to_string(),
                     line: location.line(),
                     column: location.column(),
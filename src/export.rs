@@ -0,0 +1,227 @@
+use std::collections::VecDeque;
+
+use axum::body::Body;
+use axum::http::Request;
+use tower::ServiceExt;
+
+use crate::prelude::*;
+use crate::{make_router, schema, usage};
+
+const USAGE: &str = "export <dir>";
+
+// where every visitor starts, the rest is found by following links
+const START_PATHS: &[&str] = &[
+    routes::INDEX,
+    routes::POSTS,
+    routes::PHOTOS,
+    routes::PROJECTS,
+    routes::CV,
+    routes::STATS,
+    routes::POSTS_FEED,
+    routes::PHOTOS_FEED,
+    routes::CALENDAR,
+];
+
+// pages that only make sense on the server
+const SKIPPED_PREFIXES: &[&str] = &[
+    "/admin/",
+    "/login/",
+    "/logout/",
+    "/comments/",
+    "/micropub",
+    "/hooks/",
+];
+
+// file types whose links are followed and rewritten
+const TEXT_TYPES: &[&str] = &[
+    "text/html",
+    "text/css",
+    "application/xml",
+    "application/rss",
+];
+
+// Writes what an anonymous reader can see to `dir`, as plain files a static host can serve. Pages
+// are crawled through the router like a reader would, so private posts and photos can't end up in
+// the export. Urls with a query get a path of their own (`/posts/?tag=rust` is written to
+// `/posts/tag/rust/`) and links to them are rewritten. Comments, polls and logins don't work in
+// the export.
+pub async fn export(args: &[String]) -> Result<(), Error> {
+    let [dir] = args else {
+        return usage(USAGE);
+    };
+    let dir = Path::new(dir);
+    if dir
+        .read_dir()
+        .is_ok_and(|mut entries| entries.next().is_some())
+    {
+        return Err(
+            Error::new(format!("{} is not empty, remove it first", dir.display()))
+                .with_kind(ErrorKind::Validation),
+        );
+    }
+
+    let config = Config::from_json_file("website.json")?;
+    let db = Database::open(&config)?;
+    schema::check_version(&db)?;
+    set_links(config.links.clone());
+
+    let state = AppState::new(config)?;
+    let count = export_site(&make_router(state.clone()), &state, dir).await?;
+    println!("exported {} files to {}", count, dir.display());
+
+    Ok(())
+}
+
+pub async fn export_site(
+    router: &ax::Router,
+    state: &AppState,
+    dir: &Path,
+) -> Result<usize, Error> {
+    let mut queue = VecDeque::from_iter(START_PATHS.iter().map(|path| path.to_string()));
+    let site_url = {
+        let db = &state.db.lock().unwrap();
+        let cfg = &state.config.lock().unwrap();
+
+        for post in Post::get_all(db)? {
+            if post.visible_to(None, cfg.timezone()) {
+                queue.push_back(post.url());
+            }
+        }
+        for page in StaticPage::get_listed(db)? {
+            queue.push_back(routes::page(&page.slug));
+        }
+        for file in File::get_all(db)? {
+            queue.push_back(format!("/{}/{}", file.path, file.name));
+        }
+
+        cfg.site_url.trim_end_matches('/').to_string()
+    };
+
+    let mut seen = HashSet::<String>::from_iter(queue.iter().cloned());
+    let mut pages = vec![];
+    let mut count = 0;
+
+    while let Some(path) = queue.pop_front() {
+        let request = Request::get(&path)
+            .body(Body::empty())
+            .context("failed to build export request")?;
+        let Ok(response) = router.clone().oneshot(request).await;
+
+        if response.status() != ax::StatusCode::OK {
+            println!("skipping {}, returned {}", path, response.status());
+            continue;
+        }
+
+        let is_text = response
+            .headers()
+            .get(ax::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| TEXT_TYPES.iter().any(|kind| value.starts_with(kind)));
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .context("failed to read export response")?;
+
+        if is_text {
+            let text = String::from_utf8_lossy(&body).into_owned();
+            for link in find_links(&text, &site_url) {
+                if seen.insert(link.clone()) {
+                    queue.push_back(link);
+                }
+            }
+            pages.push((path, text));
+        } else {
+            write_file(dir, &path, &body)?;
+            count += 1;
+        }
+    }
+
+    // only now are all urls with a query known
+    let with_query = seen
+        .iter()
+        .filter(|path| path.contains('?'))
+        .collect::<Vec<_>>();
+    for (path, mut text) in pages {
+        for url in &with_query {
+            let escaped = url.replace('&', "&amp;");
+            let target = static_path(url);
+            for quote in ['"', '#'] {
+                text = text.replace(
+                    &format!("\"{}{}", escaped, quote),
+                    &format!("\"{}{}", target, quote),
+                );
+            }
+        }
+        write_file(dir, &path, text.as_bytes())?;
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+fn write_file(dir: &Path, path: &str, data: &[u8]) -> Result<(), Error> {
+    let path = static_path(path);
+    let file = match path.ends_with('/') {
+        true => dir.join(path.trim_start_matches('/')).join("index.html"),
+        false => dir.join(path.trim_start_matches('/')),
+    };
+
+    if let Some(parent) = file.parent() {
+        fs::create_dir_all(parent).context("failed to create export directory")?;
+    }
+    fs::write(&file, data).context(format!("failed to write {}", file.display()))
+}
+
+// Static hosts ignore the query, so it becomes part of the path: `key/value/` below pages and
+// `-value` after files, `/photos/1?size=small` is `/photos/1-small`.
+fn static_path(url: &str) -> String {
+    let Some((path, query)) = url.split_once('?') else {
+        return url.to_string();
+    };
+
+    let values = query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(key, value)| (key, value.trim_matches('/').replace('/', "-")));
+
+    let mut path = path.to_string();
+    for (key, value) in values {
+        match path.ends_with('/') {
+            true => path.push_str(&format!("{}/{}/", key, value)),
+            false => path.push_str(&format!("-{}", value)),
+        }
+    }
+    path
+}
+
+// local links in `href`, `src` and css `url()`, without fragments
+fn find_links(text: &str, site_url: &str) -> Vec<String> {
+    let mut links = vec![];
+
+    for (start, end) in [("href=\"", '"'), ("src=\"", '"'), ("url(", ')')] {
+        for (index, _) in text.match_indices(start) {
+            let rest = &text[index + start.len()..];
+            let Some(link) = rest.split(end).next() else {
+                continue;
+            };
+
+            let link = link.trim_matches(['\'', '"']).replace("&amp;", "&");
+            let link = link.strip_prefix(site_url).unwrap_or(&link);
+            let link = link.split('#').next().unwrap_or_default();
+            // static hosts ignore the query, the versioned url of a file works as it is
+            let link = link.split("?v=").next().unwrap_or_default();
+
+            if link.starts_with('/')
+                && !link.starts_with("//")
+                && !link.contains("lite=")
+                && !link.contains("theme=")
+                && !SKIPPED_PREFIXES
+                    .iter()
+                    .any(|prefix| link.starts_with(prefix))
+            {
+                links.push(link.to_string());
+            }
+        }
+    }
+
+    links
+}
@@ -0,0 +1,181 @@
+use crate::component::markdown::map_photo_shortcodes;
+use crate::import::Generator;
+use crate::prelude::*;
+use crate::{schema, take_flag, take_option, usage};
+
+const USAGE: &str = "export-content --to <hugo|jekyll> [--include-private] <dir>";
+
+#[derive(Serialize)]
+struct Frontmatter<'a> {
+    title: &'a str,
+    date: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<&'a str>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    // hugo
+    #[serde(skip_serializing_if = "Option::is_none")]
+    slug: Option<&'a str>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    aliases: Vec<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    draft: bool,
+    #[serde(rename = "expiryDate", skip_serializing_if = "Option::is_none")]
+    expiry_date: Option<String>,
+    // jekyll, redirects need the jekyll-redirect-from plugin
+    #[serde(skip_serializing_if = "Option::is_none")]
+    permalink: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    redirect_from: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    published: Option<bool>,
+}
+
+// Writes the posts of the built site as a hugo or jekyll site: frontmattered markdown with the
+// photos and assets each post shows, so the content can move to another generator. Posts keep
+// their urls and the old ones redirect. Only what anonymous readers can see is written unless
+// `--include-private` is given, then hidden posts become drafts.
+pub async fn export_content(args: &[String]) -> Result<(), Error> {
+    let mut args = args.to_vec();
+    let include_private = take_flag(&mut args, "--include-private");
+    let Some(generator) = take_option(&mut args, "--to")
+        .as_deref()
+        .and_then(Generator::parse)
+    else {
+        return usage(USAGE);
+    };
+    let [dir] = args.as_slice() else {
+        return usage(USAGE);
+    };
+    let dir = Path::new(dir);
+    if dir
+        .read_dir()
+        .is_ok_and(|mut entries| entries.next().is_some())
+    {
+        return Err(
+            Error::new(format!("{} is not empty, remove it first", dir.display()))
+                .with_kind(ErrorKind::Validation),
+        );
+    }
+
+    let config = Config::from_json_file("website.json")?;
+    let db = Database::open(&config)?;
+    schema::check_version(&db)?;
+
+    let count = export_content_site(&db, &config, generator, include_private, dir)?;
+    println!("exported {} posts to {}", count, dir.display());
+    Ok(())
+}
+
+pub(crate) fn export_content_site(
+    db: &Database,
+    config: &Config,
+    generator: Generator,
+    include_private: bool,
+    dir: &Path,
+) -> Result<usize, Error> {
+    let mut count = 0;
+    for post in Post::get_all(db)? {
+        let is_hidden = post.is_private || post.allowed_group.is_some();
+        if is_hidden && !include_private {
+            continue;
+        }
+        export_post(db, config, generator, include_private, &post, dir)
+            .context(format!("failed to export post {}", post.id))?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+fn export_post(
+    db: &Database,
+    config: &Config,
+    generator: Generator,
+    include_private: bool,
+    post: &Post,
+    dir: &Path,
+) -> Result<(), Error> {
+    // hugo keeps media next to the post in a page bundle, jekyll serves it from the site root
+    let (content_path, media_dir, media_url) = match generator {
+        Generator::Hugo => {
+            let bundle = dir.join("content/posts").join(&post.slug);
+            (bundle.join("index.md"), bundle, String::new())
+        }
+        Generator::Jekyll => (
+            dir.join("_posts")
+                .join(format!("{}-{}.md", &post.date[..10], post.slug)),
+            dir.join("assets").join(&post.slug),
+            format!("/assets/{}/", post.slug),
+        ),
+    };
+    fs::create_dir_all(content_path.parent().unwrap())
+        .context("failed to create post directory")?;
+    fs::create_dir_all(&media_dir).context("failed to create media directory")?;
+
+    let photos = Photo::get_all(db, Some(&post.id))?
+        .into_iter()
+        .filter(|photo| include_private || photo.visible_to(None))
+        .collect::<Vec<_>>();
+    for photo in &photos {
+        fs::copy(&photo.source_path, media_dir.join(photo.name()))
+            .context(format!("failed to copy photo {}", photo.source_path))?;
+    }
+    for asset in Asset::by_post(db, &post.id)? {
+        fs::write(media_dir.join(&asset.name), asset.get_data(db, config)?)
+            .context("failed to write asset")?;
+    }
+
+    // photos that weren't exported are left out like they are for readers who can't see them
+    let markdown = map_photo_shortcodes(&post.get_source(db)?, |name, caption| {
+        match photos.iter().any(|photo| photo.name() == name) {
+            true => format!("![{}]({}{})", caption.unwrap_or_default(), media_url, name),
+            false => String::new(),
+        }
+    });
+    let assets_prefix = format!("]({}/", config.post_assets_path.trim_end_matches('/'));
+    let markdown = markdown.replace(&assets_prefix, &format!("]({}", media_url));
+
+    let old_urls = post
+        .get_aliases(db)?
+        .into_iter()
+        .chain([post.id.clone()])
+        .filter(|alias| *alias != post.slug)
+        .map(|alias| routes::post(&alias))
+        .collect::<Vec<_>>();
+    let is_hidden = post.is_private || post.allowed_group.is_some();
+    let frontmatter = match generator {
+        Generator::Hugo => Frontmatter {
+            title: &post.title,
+            date: post.date.replacen(' ', "T", 1) + if post.date.len() > 10 { ":00" } else { "" },
+            description: post.description.as_deref(),
+            tags: post.get_tags(db)?,
+            slug: Some(&post.slug),
+            aliases: old_urls,
+            draft: is_hidden,
+            expiry_date: post.expires.clone(),
+            permalink: None,
+            redirect_from: vec![],
+            published: None,
+        },
+        Generator::Jekyll => Frontmatter {
+            title: &post.title,
+            date: post.date.replacen('T', " ", 1) + if post.date.len() > 10 { ":00" } else { "" },
+            description: post.description.as_deref(),
+            tags: post.get_tags(db)?,
+            slug: None,
+            aliases: vec![],
+            draft: false,
+            expiry_date: None,
+            permalink: Some(post.url()),
+            redirect_from: old_urls,
+            published: is_hidden.then_some(false),
+        },
+    };
+
+    let yaml = serde_yaml::to_string(&frontmatter).context("failed to serialize frontmatter")?;
+    fs::write(
+        content_path,
+        format!("---\n{}---\n\n{}\n", yaml, markdown.trim()),
+    )
+    .context("failed to write post")
+}
@@ -0,0 +1,434 @@
+use crate::component::post::{frontmatter_value, slugify, Permalinks, PostMetadata};
+use crate::prelude::*;
+use crate::{take_option, time, usage};
+
+const USAGE: &str = "import --from <hugo|jekyll> <dir>";
+
+// shown with the photo shortcode, any other file a post links to is kept as an asset
+const PHOTO_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "webp"];
+
+const MARKDOWN_EXTENSIONS: &[&str] = &["md", "markdown"];
+
+#[derive(Clone, Copy)]
+pub(crate) enum Generator {
+    Hugo,
+    Jekyll,
+}
+
+impl Generator {
+    pub(crate) fn parse(name: &str) -> Option<Self> {
+        match name {
+            "hugo" => Some(Generator::Hugo),
+            "jekyll" => Some(Generator::Jekyll),
+            _ => None,
+        }
+    }
+
+    // where the posts are, relative to the site
+    fn posts_dirs(self) -> &'static [&'static str] {
+        match self {
+            Generator::Hugo => &["content/posts", "content/post", "content/blog"],
+            Generator::Jekyll => &["_posts"],
+        }
+    }
+
+    // where absolute paths like `/images/a.jpg` are served from
+    fn static_dir(self) -> &'static str {
+        match self {
+            Generator::Hugo => "static",
+            Generator::Jekyll => "",
+        }
+    }
+}
+
+// Converts the posts of a hugo or jekyll site into post directories. The images they show are
+// copied into the post, the old urls become permalinks so they redirect to the new ones, and
+// drafts stay private. Shortcodes other than hugo's `figure` are left for editing by hand.
+pub async fn import(args: &[String]) -> Result<(), Error> {
+    let mut args = args.to_vec();
+    let Some(generator) = take_option(&mut args, "--from")
+        .as_deref()
+        .and_then(Generator::parse)
+    else {
+        return usage(USAGE);
+    };
+    let [dir] = args.as_slice() else {
+        return usage(USAGE);
+    };
+
+    let config = Config::from_json_file("website.json")?;
+    let imported = import_site(&config, generator, Path::new(dir))?;
+    println!(
+        "imported {} posts, run `build` to check them",
+        imported.len()
+    );
+    Ok(())
+}
+
+pub(crate) fn import_site(
+    config: &Config,
+    generator: Generator,
+    site: &Path,
+) -> Result<Vec<PathBuf>, Error> {
+    let mut sources = vec![];
+    for posts_dir in generator.posts_dirs() {
+        find_posts(&site.join(posts_dir), &mut sources)?;
+    }
+    if sources.is_empty() {
+        return Err(Error::new(format!("found no posts in {}", site.display()))
+            .with_kind(ErrorKind::Validation));
+    }
+    sources.sort();
+
+    let mut imported = vec![];
+    for source in sources {
+        let dir = import_post(config, generator, site, &source)
+            .context(format!("failed to import {}", source.display()))?;
+        println!("imported {} to {}", source.display(), dir.display());
+        imported.push(dir);
+    }
+    Ok(imported)
+}
+
+fn find_posts(dir: &Path, sources: &mut Vec<PathBuf>) -> Result<(), Error> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    for entry in fs::read_dir(dir).context("failed to read posts directory")? {
+        let path = entry?.path();
+        let is_markdown = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| MARKDOWN_EXTENSIONS.contains(&extension));
+        if path.is_dir() {
+            find_posts(&path, sources)?;
+        } else if is_markdown && !file_stem(&path).starts_with('_') {
+            // `_index.md` is a section page in hugo
+            sources.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn import_post(
+    config: &Config,
+    generator: Generator,
+    site: &Path,
+    source_path: &Path,
+) -> Result<PathBuf, Error> {
+    let source = fs::read_to_string(source_path).context("failed to read post")?;
+    let (frontmatter, body) = frontmatter_value(&source)?
+        .context("post has no frontmatter")
+        .map_err(|error| error.with_kind(ErrorKind::Validation))?;
+    let string = |key: &str| frontmatter.get(key).and_then(|value| value.as_str());
+
+    // a page bundle (`my-post/index.md`) is named by its directory, jekyll names start with
+    // the date (`2020-01-02-my-post.md`)
+    let stem = file_stem(source_path);
+    let bundle = stem == "index";
+    let file_name = match bundle {
+        true => source_path
+            .parent()
+            .and_then(|parent| parent.file_name())
+            .and_then(|name| name.to_str())
+            .unwrap_or_default(),
+        false => stem,
+    };
+    let (file_date, file_name) = match (file_name.get(..10), file_name.get(11..)) {
+        (Some(date), Some(rest)) if time::parse_date(date, config.timezone()).is_some() => {
+            (Some(date), rest)
+        }
+        _ => (None, file_name),
+    };
+    let name = string("slug").unwrap_or(file_name);
+
+    let title = string("title").unwrap_or(name).to_string();
+    let date = string("date")
+        .map(convert_date)
+        .or(file_date.map(str::to_string))
+        .filter(|date| time::parse_date(date, config.timezone()).is_some())
+        .context("post has no valid date")
+        .map_err(|error| error.with_kind(ErrorKind::Validation))?;
+
+    let dir = Path::new(&config.posts_path).join(slugify(name));
+    if dir.exists() {
+        return Err(Error::new(format!("{} already exists", dir.display()))
+            .with_kind(ErrorKind::Validation));
+    }
+
+    let mut metadata = PostMetadata::new(title, date);
+    metadata.description = ["description", "summary", "excerpt"]
+        .iter()
+        .find_map(|key| string(key))
+        .map(str::to_string);
+    // `draft: true` in hugo, `published: false` in jekyll
+    let flag = |key: &str| frontmatter.get(key).and_then(|value| value.as_bool());
+    metadata.private = flag("draft") == Some(true) || flag("published") == Some(false);
+
+    for key in ["tags", "categories"] {
+        let tags = match frontmatter.get(key) {
+            Some(serde_json::Value::Array(tags)) => {
+                tags.iter().filter_map(|tag| tag.as_str()).collect()
+            }
+            // jekyll separates them with spaces
+            Some(serde_json::Value::String(tags)) => tags.split_whitespace().collect(),
+            _ => vec![],
+        };
+        for tag in tags.into_iter().map(slugify) {
+            if !tag.is_empty() && !metadata.tags.contains(&tag) {
+                metadata.tags.push(tag);
+            }
+        }
+    }
+
+    // posts are only found by the last segment of their url, so that's what's kept of the old ones
+    let mut old_urls = vec![name];
+    old_urls.extend(["url", "permalink"].iter().filter_map(|key| string(key)));
+    // `redirect_from` is jekyll's plugin for them
+    for key in ["aliases", "redirect_from"] {
+        if let Some(serde_json::Value::Array(aliases)) = frontmatter.get(key) {
+            old_urls.extend(aliases.iter().filter_map(|alias| alias.as_str()));
+        }
+    }
+    let mut permalinks = vec![];
+    for url in old_urls {
+        let Some(segment) = url
+            .split('/')
+            .rfind(|segment| !segment.is_empty())
+            .map(|segment| segment.trim_end_matches(".html"))
+        else {
+            continue;
+        };
+        if segment != slugify(&metadata.title) && !permalinks.iter().any(|p| p == segment) {
+            permalinks.push(segment.to_string());
+        }
+    }
+    metadata.permalink = match permalinks.len() {
+        0 => None,
+        1 => Some(Permalinks::One(permalinks.remove(0))),
+        _ => Some(Permalinks::Many(permalinks)),
+    };
+
+    let mut images = Images {
+        config,
+        dir: &dir,
+        static_dir: site.join(generator.static_dir()),
+        relative_dir: source_path.parent().unwrap_or(site).to_path_buf(),
+        copied: HashMap::new(),
+    };
+    fs::create_dir_all(&dir).context("failed to create post directory")?;
+    let markdown = rewrite_images(&body.replace("<!--more-->", ""), &mut images)?;
+    if markdown.contains("{{<") || markdown.contains("{{%") || markdown.contains("{%") {
+        println!(
+            "warning: {} has shortcodes that need to be converted by hand",
+            source_path.display()
+        );
+    }
+
+    metadata.to_json_file(dir.join(&config.post_metadata_path).to_str().unwrap())?;
+    fs::write(
+        dir.join(&config.post_content_path),
+        format!("{}\n", markdown.trim()),
+    )
+    .context("failed to write post content file")?;
+
+    Ok(dir)
+}
+
+fn file_stem(path: &Path) -> &str {
+    path.file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or_default()
+}
+
+// `2020-01-02T10:30:00+01:00` is written in the local time of the site, the offset is dropped
+fn convert_date(date: &str) -> String {
+    let date = date.trim().replacen(' ', "T", 1);
+    match date.get(10..11) {
+        Some("T") if date.len() >= 16 => date[..16].to_string(),
+        _ => date.chars().take(10).collect(),
+    }
+}
+
+// copies the images a post shows into its directory
+struct Images<'a> {
+    config: &'a Config,
+    dir: &'a Path,
+    static_dir: PathBuf,
+    // relative paths start at the post, e.g. in a page bundle
+    relative_dir: PathBuf,
+    // by source path, the name each image was copied to
+    copied: HashMap<PathBuf, String>,
+}
+
+impl Images<'_> {
+    // the markdown showing the image, or nothing if it isn't a local file
+    fn convert(&mut self, src: &str, caption: &str) -> Result<Option<String>, Error> {
+        // `{{ site.baseurl }}/images/a.jpg` in jekyll
+        let src = match src.strip_prefix("{{") {
+            Some(rest) => rest.split_once("}}").map_or(src, |(_, path)| path),
+            None => src,
+        };
+        let src = src.split(['?', '#']).next().unwrap_or_default();
+        if src.is_empty() || src.contains("://") || src.starts_with("//") || src.contains("{{") {
+            return Ok(None);
+        }
+
+        let path = match src.strip_prefix('/') {
+            Some(path) => self.static_dir.join(path),
+            None => self.relative_dir.join(src),
+        };
+        if !path.is_file() {
+            println!("warning: image {} not found", path.display());
+            return Ok(None);
+        }
+
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+        let is_photo = PHOTO_EXTENSIONS.contains(&extension.as_str());
+
+        let name = match self.copied.get(&path) {
+            Some(name) => name.clone(),
+            None => {
+                let file_name = path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .context("invalid image name")?;
+                // images from different directories may share a name
+                let name = match self.copied.values().any(|name| name == file_name) {
+                    true => format!("{}-{}", self.copied.len() + 1, file_name),
+                    false => file_name.to_string(),
+                };
+                let target_dir = self.dir.join(match is_photo {
+                    true => &self.config.post_public_photos_path,
+                    false => &self.config.post_assets_path,
+                });
+                fs::create_dir_all(&target_dir).context("failed to create image directory")?;
+                fs::copy(&path, target_dir.join(&name))
+                    .context(format!("failed to copy image {}", path.display()))?;
+                self.copied.insert(path, name.clone());
+                name
+            }
+        };
+
+        Ok(Some(match (is_photo, caption.is_empty()) {
+            (true, true) => format!("![[photo:{}]]", name),
+            (true, false) => format!("![[photo:{}|{}]]", name, caption),
+            (false, _) => format!(
+                "![{}]({}/{})",
+                caption,
+                self.config.post_assets_path.trim_end_matches('/'),
+                name
+            ),
+        }))
+    }
+}
+
+// `![alt](src "title")` and hugo's `{{< figure src="..." caption="..." >}}`, outside of code blocks
+fn rewrite_images(markdown: &str, images: &mut Images) -> Result<String, Error> {
+    let mut output = String::with_capacity(markdown.len());
+    let mut fence: Option<&str> = None;
+
+    for line in markdown.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        let marker = ["```", "~~~"]
+            .into_iter()
+            .find(|marker| trimmed.starts_with(marker));
+        match (fence, marker) {
+            (None, Some(marker)) => fence = Some(marker),
+            (Some(open), Some(marker)) if open == marker => fence = None,
+            _ => {}
+        }
+        if fence.is_some() || marker.is_some() {
+            output.push_str(line);
+            continue;
+        }
+
+        output.push_str(&rewrite_figures(
+            &rewrite_markdown_images(line, images)?,
+            images,
+        )?);
+    }
+
+    Ok(output)
+}
+
+fn rewrite_markdown_images(line: &str, images: &mut Images) -> Result<String, Error> {
+    let mut output = String::with_capacity(line.len());
+    let mut rest = line;
+
+    while let Some(start) = rest.find("![") {
+        let after = &rest[start + 2..];
+        // photo shortcodes are already in our format
+        let parsed = match after.starts_with('[') {
+            true => None,
+            false => after.split_once("](").and_then(|(alt, target)| {
+                let end = target.find(')')?;
+                Some((alt, &target[..end], start + 2 + alt.len() + 2 + end + 1))
+            }),
+        };
+        let Some((alt, target, end)) = parsed else {
+            output.push_str(&rest[..start + 2]);
+            rest = after;
+            continue;
+        };
+
+        let src = target.split_whitespace().next().unwrap_or_default();
+        match images.convert(src.trim_matches(['<', '>']), alt)? {
+            Some(markdown) => {
+                output.push_str(&rest[..start]);
+                output.push_str(&markdown);
+            }
+            None => output.push_str(&rest[..end]),
+        }
+        rest = &rest[end..];
+    }
+
+    output.push_str(rest);
+    Ok(output)
+}
+
+fn rewrite_figures(line: &str, images: &mut Images) -> Result<String, Error> {
+    let mut output = String::with_capacity(line.len());
+    let mut rest = line;
+
+    while let Some(start) = ["{{<", "{{%"]
+        .iter()
+        .filter_map(|open| rest.find(open))
+        .min()
+    {
+        let Some(end) = rest[start..].find("}}").map(|end| start + end + 2) else {
+            break;
+        };
+        let shortcode = rest.get(start + 3..end - 3).unwrap_or_default().trim();
+        let converted = match shortcode.strip_prefix("figure ") {
+            Some(attributes) => {
+                let attribute = |name: &str| {
+                    let (_, value) = attributes.split_once(&format!("{}=\"", name))?;
+                    value.split_once('"').map(|(value, _)| value)
+                };
+                match attribute("src") {
+                    Some(src) => {
+                        let caption = attribute("caption")
+                            .or(attribute("alt"))
+                            .or(attribute("title"))
+                            .unwrap_or_default();
+                        images.convert(src, caption)?
+                    }
+                    None => None,
+                }
+            }
+            None => None,
+        };
+
+        output.push_str(&rest[..start]);
+        output.push_str(converted.as_deref().unwrap_or(&rest[start..end]));
+        rest = &rest[end..];
+    }
+
+    output.push_str(rest);
+    Ok(output)
+}
@@ -1,107 +1,691 @@
+mod backup;
+mod bench;
+mod check;
+mod check_links;
 mod component;
 mod config;
+mod crypto;
 mod database;
+mod doctor;
 mod error;
+mod export;
+mod export_content;
+mod import;
 mod prelude;
+mod review;
+mod routes;
+mod schema;
 mod state;
+mod time;
+mod warm;
+mod webmention;
+mod websub;
+
+#[cfg(test)]
+mod tests;
 
 use crate::prelude::*;
-use tokio::net::TcpListener;
+use std::net::SocketAddr;
+use std::os::unix::fs::FileTypeExt;
+use tokio::net::{TcpListener, UnixListener};
 
 #[tokio::main]
 async fn main() {
-    let args = std::env::args().collect::<Vec<String>>();
+    let mut args = std::env::args().collect::<Vec<String>>();
+    let json_errors = take_option(&mut args, "--format").as_deref() == Some("json");
+
+    let result = match args.get(1).map(|s| s.as_str()) {
+        Some("build") => build(&args[2..]).await,
+        Some("serve") => serve().await,
+        Some("migrate") => migrate().await,
+        Some("user") => user(&args[2..]).await,
+        Some("token") => token(&args[2..]).await,
+        Some("shortlink") => shortlink(&args[2..]).await,
+        Some("bench-serve") => bench::bench_serve(&args[2..]).await,
+        Some("backup") => backup::backup(&args[2..]).await,
+        Some("restore") => backup::restore(&args[2..]).await,
+        Some("export") => export::export(&args[2..]).await,
+        Some("check") => check::check(&args[2..]).await,
+        Some("check-links") => check_links::check_links(&args[2..]).await,
+        Some("doctor") => doctor::doctor(&args[2..]).await,
+        Some("year-review") => review::year_review(&args[2..]).await,
+        Some("import") => import::import(&args[2..]).await,
+        Some("export-content") => export_content::export_content(&args[2..]).await,
+        _ => usage(&format!(
+            "{} [build|serve|migrate|user|token|shortlink|backup|restore|bench-serve|export|check|check-links|doctor|year-review|import|export-content] [--format json]",
+            args[0]
+        )),
+    };
 
-    match args.get(1).map(|s| s.as_str()) {
-        Some("build") => build().await.unwrap(),
-        Some("serve") => serve().await.unwrap(),
-        _ => {
-            eprintln!("Usage: {} [build|serve]", args[0]);
-            std::process::exit(1);
+    if let Err(error) = result {
+        if json_errors {
+            eprintln!("{}", error.to_json());
+        } else if error.kind() == ErrorKind::Usage {
+            eprintln!("{}", error.message());
+        } else {
+            eprintln!("error: {}{:?}", error.message(), error);
         }
+        std::process::exit(error.kind().exit_code());
     }
 }
 
-async fn build() -> Result<(), Error> {
+fn usage(message: &str) -> Result<(), Error> {
+    Err(Error::new(format!("Usage: {}", message)).with_kind(ErrorKind::Usage))
+}
+
+fn take_flag(args: &mut Vec<String>, name: &str) -> bool {
+    let index = args.iter().position(|arg| arg == name);
+    index.map(|index| args.remove(index)).is_some()
+}
+
+fn take_option(args: &mut Vec<String>, name: &str) -> Option<String> {
+    let index = args.iter().position(|arg| arg == name)?;
+    args.remove(index);
+    (index < args.len()).then(|| args.remove(index))
+}
+
+async fn build(args: &[String]) -> Result<(), Error> {
+    let mut args = args.to_vec();
+    let no_ping = take_flag(&mut args, "--no-ping");
+    let strict = take_flag(&mut args, "--strict");
+    let json = take_flag(&mut args, "--json");
+    let keep_going = take_flag(&mut args, "--keep-going");
+    if !args.is_empty() {
+        return usage("build [--no-ping] [--strict] [--json] [--keep-going]");
+    }
+
     let config = Config::from_json_file("website.json")?;
-    let db = Database::connect(&config.database_path)?;
+    let db = Database::open(&config)?;
+
+    let report = run_build(&db, &config, !no_ping, strict, keep_going)?;
+    match json {
+        true => println!(
+            "{}",
+            serde_json::to_string(&report).context("failed to serialize build report")?
+        ),
+        false => print!("{}", report.to_table()),
+    }
+
+    match report.failures.len() {
+        0 => Ok(()),
+        count => Err(Error::new(format!(
+            "{} posts failed to build and were left out",
+            count
+        ))),
+    }
+}
+
+// The whole build, shared by `website build` and rebuilds triggered on the server. With
+// `keep_going` posts that fail to load are left out and listed in the report instead.
+fn run_build(
+    db: &Database,
+    config: &Config,
+    ping: bool,
+    strict: bool,
+    keep_going: bool,
+) -> Result<BuildReport, Error> {
+    let mut report = BuildReport::default();
+    report.phase("migrate", || schema::migrate(db))?;
+    let previous_sources = webmention::snapshot(db)?;
+    let snapshot = BuildSnapshot::take(db)?;
+
+    report.failures = report.phase("content", || build_content_with(db, config, keep_going))?;
+    Build::record(db, report.elapsed_ms("content") as i64)?;
+    report.compare(db, &snapshot)?;
+    report.phase("users", || sync_users(db, config))?;
+
+    report.alt_text_coverage = AltText::coverage(db)?;
+    if let Some(coverage) = report.alt_text_coverage
+        && let Some(min_coverage) = config.alt_text_min_coverage
+        && strict
+        && coverage < min_coverage
+    {
+        return Err(Error::new(format!(
+            "alt text coverage is {:.1}% but at least {:.1}% is required, add the missing alt text in the admin dashboard",
+            coverage, min_coverage
+        ))
+        .with_kind(ErrorKind::Validation));
+    }
+
+    if ping {
+        report.phase("webmentions", || {
+            webmention::send_webmentions(db, config, &previous_sources)
+        })?;
+        report.phase("websub", || websub::ping_hub(db, config, &previous_sources))?;
+        report.phase("activitypub", || deliver_posts(db, config))?;
+    }
+
+    Ok(report)
+}
+
+fn build_content(db: &Database, config: &Config) -> Result<(), Error> {
+    build_content_with(db, config, false).map(|_| ())
+}
+
+fn build_content_with(
+    db: &Database,
+    config: &Config,
+    keep_going: bool,
+) -> Result<Vec<BuildFailure>, Error> {
+    schema::migrate(db)?;
+    import_config_tokens(db, config)?;
+    // a failed build leaves the previous content as it was, also for the server meanwhile
+    db.transaction(|| load_content(db, config, keep_going))
+}
+
+fn load_content(
+    db: &Database,
+    config: &Config,
+    keep_going: bool,
+) -> Result<Vec<BuildFailure>, Error> {
+    let previous_posts = Post::get_all(db)?
+        .into_iter()
+        .map(|post| Ok((post.get_aliases(db)?, post.get_source_path(db).ok(), post)))
+        .collect::<Result<Vec<_>, Error>>()?;
+    schema::reset_content(db)?;
+
+    for parent in fs::read_dir(&config.files_path).context("failed to read files directory")? {
+        let root = parent?.path();
+        let mut dirs = vec![root.clone()];
+        while let Some(dir) = dirs.pop() {
+            for entry in fs::read_dir(&dir).context("failed to read files directory")? {
+                let entry = entry?;
+                let path = entry.path();
+                // links to directories aren't followed, so a link to a parent can't loop
+                if entry.file_type()?.is_dir() {
+                    dirs.push(path);
+                } else if path.is_file() {
+                    File::new(db, &root, &path)?;
+                }
+            }
+        }
+    }
 
-    Post::setup(&db)?;
-    Asset::setup(&db)?;
-    Photo::setup(&db)?;
-    File::setup(&db)?;
-    User::setup(&db)?;
+    File::add_builtin(db, "styles", THEME_STYLE_NAME, THEME_STYLE)?;
+    File::add_builtin(db, "styles", PROJECTS_STYLE_NAME, PROJECTS_STYLE)?;
+    File::add_builtin(db, "styles", CV_STYLE_NAME, CV_STYLE)?;
 
-    Post::delete_all(&db)?;
-    Photo::unmark_all(&db)?;
-    File::delete_all(&db)?;
-    Asset::delete_all(&db)?;
-    User::delete_all(&db)?;
+    if config.map.is_some() {
+        File::add_builtin(db, "scripts", TRACK_SCRIPT_NAME, TRACK_SCRIPT)?;
+        File::add_builtin(db, "scripts", PHOTO_MAP_SCRIPT_NAME, PHOTO_MAP_SCRIPT)?;
+    }
+
+    if config.reading_progress {
+        File::add_builtin(db, "scripts", PROGRESS_SCRIPT_NAME, PROGRESS_SCRIPT)?;
+    }
+
+    if let Some(intro_path) = &config.intro_path {
+        StaticPage::new(db, "intro", Path::new(intro_path))?;
+    }
+
+    if let Some(now_path) = &config.now_path {
+        StaticPage::new_titled(db, NOW_SLUG, Path::new(now_path))?;
+    }
+
+    if let Some(cv_path) = &config.cv_path {
+        Cv::load(db, Path::new(cv_path))?;
+    }
+
+    if let Some(pages_path) = &config.pages_path {
+        StaticPage::load_dir(db, Path::new(pages_path))?;
+
+        // without an intro of its own, the index starts with the about page
+        let about_path = Path::new(pages_path).join("about.md");
+        if config.intro_path.is_none() && about_path.is_file() {
+            StaticPage::new(db, "intro", &about_path)?;
+        }
+    }
+
+    let mut failures = vec![];
+    for post_path in fs::read_dir(&config.posts_path).context("failed to read posts directory")? {
+        let post_path = post_path?.path();
+        // a post that fails halfway mustn't leave half of itself behind
+        db.execute_batch("SAVEPOINT post;")?;
+        match Post::new(db, config, &post_path) {
+            Ok(_) => db.execute_batch("RELEASE post;")?,
+            Err(error) if keep_going => {
+                db.execute_batch("ROLLBACK TO post; RELEASE post;")?;
+                failures.push(BuildFailure {
+                    path: post_path.display().to_string(),
+                    error: error.chain_message(),
+                });
+            }
+            Err(error) => {
+                db.execute_batch("ROLLBACK TO post; RELEASE post;")?;
+                return Err(error.context(format!("failed to load post {:?}", post_path)));
+            }
+        }
+    }
 
+    // posts that failed are still there, their urls mustn't answer 410 until they are fixed
+    let previous_posts = previous_posts
+        .into_iter()
+        .filter(|(_, source_path, _)| {
+            !failures.iter().any(|failure| {
+                source_path
+                    .as_ref()
+                    .is_some_and(|path| path.display().to_string() == failure.path)
+            })
+        })
+        .map(|(aliases, _, post)| (aliases, post))
+        .collect::<Vec<_>>();
+
+    Post::assign_slugs(db)?;
+    Shortlink::assign(db)?;
+    Photo::delete_unmarked(db)?;
+    File::delete_unmarked(db)?;
+    Asset::delete_unmarked(db)?;
+    Blob::delete_unused(db)?;
+    Tombstone::bury_missing(db, &previous_posts, config.timezone())?;
+    Project::sync(db, config)?;
+
+    Ok(failures)
+}
+
+// Users are state: the build never deletes them. Users in the config are added or moved to their
+// group, everyone else (e.g. added with `website user add`) is left alone.
+fn sync_users(db: &Database, config: &Config) -> Result<(), Error> {
     for user in &config.users {
-        User::new(&db, &user.key, &user.group)?;
+        let is_hash =
+            user.key_hash.len() == 64 && user.key_hash.bytes().all(|b| b.is_ascii_hexdigit());
+        if !is_hash {
+            return Err(Error::new(format!(
+                "user in group {} needs a key_hash from `website user hash`, not a key",
+                user.group
+            ))
+            .with_kind(ErrorKind::Config));
+        }
+        User::upsert(db, &user.key_hash.to_lowercase(), &user.group)?;
+    }
+
+    if !config.users.is_empty() {
+        println!("synced {} users from config", config.users.len());
     }
 
-    for parent in fs::read_dir(&config.files_path).expect("failed to read files directory") {
-        let parent = parent?;
-        for entry in fs::read_dir(parent.path()).expect("failed to read files directory") {
-            File::new(&db, &parent.path(), &entry?.path())?;
+    Ok(())
+}
+
+const MICROPUB_CONFIG_TOKEN: &str = "micropub-config";
+
+// `micropub_token` from before api tokens keeps working as a token with the micropub scope
+fn import_config_tokens(db: &Database, config: &Config) -> Result<(), Error> {
+    let Some(secret) = &config.micropub_token else {
+        return Ok(());
+    };
+
+    let scopes = [component::micropub::MICROPUB_SCOPE.to_string()];
+    if ApiToken::import(db, MICROPUB_CONFIG_TOKEN, secret, &scopes)? {
+        println!(
+            "imported micropub_token as the api token {}",
+            MICROPUB_CONFIG_TOKEN
+        );
+    }
+    println!(
+        "warning: micropub_token in website.json is deprecated, remove it and manage the token with `website token`"
+    );
+    Ok(())
+}
+
+async fn migrate() -> Result<(), Error> {
+    let config = Config::from_json_file("website.json")?;
+    let db = Database::open(&config)?;
+
+    schema::migrate(&db)?;
+    import_config_tokens(&db, &config)?;
+    println!("database is at schema version {}", schema::SCHEMA_VERSION);
+
+    Ok(())
+}
+
+async fn user(args: &[String]) -> Result<(), Error> {
+    let config = Config::from_json_file("website.json")?;
+    let db = Database::open(&config)?;
+
+    schema::migrate(&db)?;
+
+    match (args.first().map(|s| s.as_str()), args.get(1)) {
+        (Some("add"), Some(group)) => {
+            let key = read_key(&format!("key for group {}", group))?;
+            User::new(&db, &key, group)?;
+            println!("added user to group {}", group);
         }
+        (Some("remove"), None) => {
+            let key = read_key("key to remove")?;
+            if !User::delete(&db, &key)? {
+                return Err(Error::new("no user with this key").with_kind(ErrorKind::Validation));
+            }
+            println!("removed user and its sessions");
+        }
+        (Some("sync"), None) => sync_users(&db, &config)?,
+        // for the `users` of the config
+        (Some("hash"), None) => println!("{}", User::key_hash(&read_key("key")?)),
+        (Some("list"), None) => {
+            for user in User::get_all(&db)? {
+                println!("{} {}", user.group_name, &user.key_hash[..16]);
+            }
+        }
+        _ => return usage("user [add <group>|remove|sync|hash|list]"),
     }
 
-    for post_path in fs::read_dir(&config.posts_path).expect("failed to read posts directory") {
-        Post::new(&db, &config, &post_path?.path())?;
+    Ok(())
+}
+
+// a key typed on stdin, so it doesn't end up in the shell history
+fn read_key(prompt: &str) -> Result<String, Error> {
+    eprint!("{}: ", prompt);
+    let mut key = String::new();
+    std::io::stdin()
+        .read_line(&mut key)
+        .context("failed to read key from stdin")?;
+
+    let key = key.trim_end_matches(['\r', '\n']);
+    if key.is_empty() {
+        return Err(Error::new("key must not be empty").with_kind(ErrorKind::Validation));
     }
+    Ok(key.to_string())
+}
+
+async fn token(args: &[String]) -> Result<(), Error> {
+    const USAGE: &str =
+        "token [mint <label> --scope <scope>... [--expires-days N]|revoke <label>|list]";
+
+    let config = Config::from_json_file("website.json")?;
+    let db = Database::open(&config)?;
+
+    schema::migrate(&db)?;
+
+    let mut args = args.to_vec();
+    let mut scopes = vec![];
+    while let Some(scope) = take_option(&mut args, "--scope") {
+        scopes.push(scope);
+    }
+    let expires_days = match take_option(&mut args, "--expires-days") {
+        Some(days) => match days.parse::<i64>() {
+            Ok(days) if days > 0 => Some(days),
+            _ => return usage(USAGE),
+        },
+        None => None,
+    };
+
+    match (args.first().map(|s| s.as_str()), args.get(1), args.len()) {
+        (Some("mint"), Some(label), 2) if !scopes.is_empty() => {
+            let expires_at =
+                expires_days.map(|days| chrono::Utc::now().timestamp() + days * 24 * 60 * 60);
+            let (token, secret) = ApiToken::mint(&db, label, &scopes, expires_at)?;
+            eprintln!(
+                "minted token {} with scopes {}, it won't be shown again:",
+                token.label,
+                token.scopes.join(" ")
+            );
+            println!("{}", secret);
+        }
+        (Some("revoke"), Some(label), 2) if scopes.is_empty() && expires_days.is_none() => {
+            if !ApiToken::revoke(&db, label)? {
+                return Err(Error::new(format!("no token with label {:?}", label))
+                    .with_kind(ErrorKind::Validation));
+            }
+            println!("revoked token {}", label);
+        }
+        (Some("list"), None, 1) if scopes.is_empty() && expires_days.is_none() => {
+            for token in ApiToken::get_all(&db)? {
+                let expires = match token.expires_at {
+                    Some(_) if token.is_expired() => "expired".to_string(),
+                    Some(expires_at) => {
+                        format!(
+                            "expires {}",
+                            time::display_timestamp(expires_at, config.timezone())
+                        )
+                    }
+                    None => "never expires".to_string(),
+                };
+                println!("{} [{}] {}", token.label, token.scopes.join(" "), expires);
+            }
+        }
+        _ => return usage(USAGE),
+    }
+
+    Ok(())
+}
+
+// Codes of posts are made by the build, these are the custom ones. Removing the code of a post
+// gives it a new one on the next build.
+async fn shortlink(args: &[String]) -> Result<(), Error> {
+    let config = Config::from_json_file("website.json")?;
+    let db = Database::open(&config)?;
 
-    Photo::delete_unmarked(&db)?;
+    schema::migrate(&db)?;
 
-    println!("all done!");
+    match (
+        args.first().map(|s| s.as_str()),
+        args.get(1),
+        args.get(2),
+        args.len(),
+    ) {
+        (Some("add"), Some(code), Some(target), 3) => {
+            let shortlink = Shortlink::add(&db, code, target)?;
+            println!(
+                "added {} to {}",
+                routes::shortlink(&shortlink.code),
+                shortlink.target()
+            );
+        }
+        (Some("remove"), Some(code), None, 2) => {
+            if !Shortlink::remove(&db, code)? {
+                return Err(Error::new(format!("no shortlink with code {:?}", code))
+                    .with_kind(ErrorKind::Validation));
+            }
+            println!("removed shortlink {}", code);
+        }
+        (Some("list"), None, None, 1) => {
+            for shortlink in Shortlink::get_all(&db)? {
+                println!("{} {}", shortlink.code, shortlink.target());
+            }
+        }
+        _ => return usage("shortlink [add <code> <post|/path|url>|remove <code>|list]"),
+    }
 
     Ok(())
 }
 
 async fn serve() -> Result<(), Error> {
     let config = Config::from_json_file("website.json")?;
-    let db = Database::connect(&config.database_path)?;
-
-    let state = Arc::new(AppState {
-        db: Arc::new(Mutex::new(db)),
-        config: Arc::new(Mutex::new(config.clone())),
-    });
-
-    let app = ax::Router::new()
-        .route("/", ax::routing::get(get_index))
-        .route("/posts/", ax::routing::get(get_posts))
-        .route("/posts/{id}/", ax::routing::get(get_post))
-        .route("/posts/{id}/assets/{name}", ax::routing::get(get_asset))
-        .route("/photos/", ax::routing::get(get_photos))
-        .route("/photos/{id}", ax::routing::get(get_photo))
-        .route("/projects/", ax::routing::get(get_projects))
-        .route("/files/{name}", ax::routing::get(get_file_file))
-        .route("/styles/{name}", ax::routing::get(get_file_style))
-        .route("/assets/{name}", ax::routing::get(get_file_asset))
-        .route("/login/", ax::routing::get(get_login))
-        .route("/login/", ax::routing::post(post_login))
-        .route("/logout/", ax::routing::post(post_logout))
-        .fallback(ax::routing::get(get_not_found))
-        .with_state(state);
-
-    let listener = TcpListener::bind(format!("{}:{}", config.server_host, config.server_port))
-        .await
-        .context("failed to bind server")?;
+    let db = Database::open(&config)?;
 
-    println!(
-        "Server running on http://{}:{}",
-        config.server_host, config.server_port
-    );
+    schema::check_version(&db)?;
+
+    for link in &config.links {
+        if let Some(icon) = &link.icon
+            && File::by_path_and_name(&db, "assets", icon).is_err()
+        {
+            println!(
+                "warning: icon asset {} for link {} not found",
+                icon, link.href
+            );
+        }
+    }
+
+    set_links(config.footer_links());
+
+    let state = AppState::new(config.clone())?;
+
+    let app = make_router(state.clone());
 
-    axum::serve(listener, app)
-        .await
-        .context("failed to start server")?;
+    backup::schedule_backups(&config);
+
+    if config.warm_posts > 0 {
+        warm::warm_cache(&app, &state, config.warm_posts as usize).await?;
+    }
+
+    match &config.server_socket {
+        Some(path) => {
+            let listener = bind_socket(path)?;
+            println!("Server running on unix:{}", path);
+            axum::serve(listener, app)
+                .await
+                .context("failed to start server")?;
+        }
+        None => {
+            let listener =
+                TcpListener::bind(format!("{}:{}", config.server_host, config.server_port))
+                    .await
+                    .context("failed to bind server")?;
+            println!(
+                "Server running on http://{}:{}",
+                config.server_host, config.server_port
+            );
+            // the address of the peer, see `Client`
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .await
+            .context("failed to start server")?;
+        }
+    }
 
     Ok(())
 }
 
-// fn make_redirect(path: &str) -> axum::routing::MethodRouter<Arc<AppState>> {
-//     ax::routing::get(async || ax::Redirect::to("/photos/"))
-// }
+// A socket left behind by a previous run would make binding fail, so it is removed first. Other
+// files at the path are left alone.
+fn bind_socket(path: &str) -> Result<UnixListener, Error> {
+    if fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        fs::remove_file(path).context("failed to remove old server socket")?;
+    }
+    UnixListener::bind(path).context("failed to bind server socket")
+}
+
+fn make_router(state: Arc<AppState>) -> ax::Router {
+    let routes = make_routes();
+    let not_found = routes
+        .clone()
+        .fallback(get_not_found)
+        .with_state(state.clone());
+
+    let router = routes
+        .fallback(
+            move |ax::State(state): ax::State<Arc<AppState>>, request: axum::extract::Request| {
+                normalize_trailing_slash(not_found.clone(), state, request)
+            },
+        )
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            strip_trailing_slash,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            version_files,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            cache_pages,
+        ))
+        .layer(axum::middleware::from_fn(remember_lite))
+        .layer(axum::middleware::from_fn(remember_theme))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            check_csrf,
+        ));
+
+    let router = apply_limits(router, &state.config.lock().unwrap());
+
+    router
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            record_errors,
+        ))
+        .with_state(state)
+}
+
+fn make_routes() -> ax::Router<Arc<AppState>> {
+    ax::Router::new()
+        .route(routes::INDEX, ax::routing::get(get_index))
+        .route(routes::POSTS, ax::routing::get(get_posts))
+        .route(routes::POSTS_FEED, ax::routing::get(get_posts_feed))
+        .route(routes::CALENDAR, ax::routing::get(get_calendar))
+        .route(routes::POST, ax::routing::get(get_post))
+        .route(routes::POST_MARKDOWN, ax::routing::get(get_post_markdown))
+        .route(routes::POST_TEXT, ax::routing::get(get_post_text))
+        .route(routes::POST_PDF, ax::routing::get(get_post_pdf))
+        .route(routes::POST_QR, ax::routing::get(get_post_qr))
+        .route(routes::POST_ASSET, ax::routing::get(get_asset))
+        .route(routes::POST_COMMENTS, ax::routing::post(post_comment))
+        .route(routes::POST_POLL, ax::routing::post(post_poll_vote))
+        .route(routes::COMMENTS, ax::routing::get(get_comments))
+        .route(
+            routes::APPROVE_COMMENT,
+            ax::routing::post(post_approve_comment),
+        )
+        .route(
+            routes::DELETE_COMMENT,
+            ax::routing::post(post_delete_comment),
+        )
+        .route(routes::PHOTOS, ax::routing::get(get_photos))
+        .route(routes::PHOTOS_FEED, ax::routing::get(get_photos_feed))
+        .route(routes::PHOTOS_MAP, ax::routing::get(get_photos_map))
+        // matched before `PHOTO` would take it as the id of a photo
+        .route(
+            routes::PHOTOS_MAP.trim_end_matches('/'),
+            ax::routing::get(get_photos_map),
+        )
+        .route(routes::PHOTO, ax::routing::get(get_photo))
+        .route(routes::PROJECTS, ax::routing::get(get_projects))
+        .route(routes::CONTACT, ax::routing::get(get_contact))
+        .route(routes::CONTACT, ax::routing::post(post_contact))
+        .route(routes::NOW, ax::routing::get(get_now))
+        .route(routes::CV, ax::routing::get(get_cv))
+        .route(routes::CV_PDF, ax::routing::get(get_cv_pdf))
+        .route(routes::STATS, ax::routing::get(get_stats))
+        .route(routes::ADMIN, ax::routing::get(get_admin))
+        .route(
+            routes::EDIT_POST,
+            ax::routing::get(get_edit_post).post(post_edit_post),
+        )
+        .route(
+            routes::UPLOAD_PHOTOS,
+            ax::routing::post(post_upload_photos).layer(axum::extract::DefaultBodyLimit::max(
+                component::editor::MAX_UPLOAD_SIZE,
+            )),
+        )
+        .route(routes::PREVIEW, ax::routing::post(post_preview))
+        .route(routes::ALT_TEXT, ax::routing::post(post_alt_text))
+        .route(routes::REBUILD, ax::routing::post(post_rebuild))
+        .route(routes::LOGIN_LINKS, ax::routing::post(post_mint_login_link))
+        .route(routes::MESSAGES, ax::routing::get(get_messages))
+        .route(
+            routes::SHORTLINKS,
+            ax::routing::get(get_shortlinks).post(post_shortlink),
+        )
+        .route(
+            routes::DELETE_SHORTLINK,
+            ax::routing::post(post_delete_shortlink),
+        )
+        .route(routes::GITHUB_HOOK, ax::routing::post(post_github_hook))
+        .route(
+            routes::MICROPUB,
+            ax::routing::get(get_micropub).post(post_micropub).layer(
+                axum::extract::DefaultBodyLimit::max(component::micropub::MAX_REQUEST_SIZE),
+            ),
+        )
+        .route(routes::WEBFINGER, ax::routing::get(get_webfinger))
+        .route(routes::ACTOR, ax::routing::get(get_actor))
+        .route(routes::INBOX, ax::routing::post(post_inbox))
+        .route(routes::OUTBOX, ax::routing::get(get_outbox))
+        .route(routes::FOLLOWERS, ax::routing::get(get_followers))
+        .route(routes::NOTE, ax::routing::get(get_note))
+        .route(routes::SHORTLINK, ax::routing::get(get_shortlink))
+        .route(routes::FILES, ax::routing::get(get_files))
+        .route(routes::FILE, ax::routing::get(get_file_file))
+        .route(routes::STYLE, ax::routing::get(get_file_style))
+        .route(routes::SCRIPT, ax::routing::get(get_file_script))
+        .route(routes::ASSET, ax::routing::get(get_file_asset))
+        .route(routes::LOGIN, ax::routing::get(get_login))
+        .route(routes::LOGIN, ax::routing::post(post_login))
+        .route(routes::RESET_FEEDS, ax::routing::post(post_reset_feeds))
+        .route(
+            routes::LOGIN_LINK,
+            ax::routing::get(get_login_link).post(post_login_link),
+        )
+        .route(routes::LOGOUT, ax::routing::post(post_logout))
+        .route(routes::PAGE, ax::routing::get(get_page))
+}
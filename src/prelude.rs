@@ -1,25 +1,28 @@
 pub use crate::component::prelude::*;
-pub use crate::config::Config;
+pub use crate::config::{Config, LinkConfig};
 pub use crate::database::{Database, Row};
-pub use crate::error::{Error, WithContext};
+pub use crate::error::{Error, ErrorKind, WithContext};
+pub(crate) use crate::routes;
+pub use crate::routes::PhotoSize;
 pub use crate::state::AppState;
+pub use crate::time::Tz;
 
 pub use axum::response::IntoResponse;
 pub use maud::{html, PreEscaped};
 pub use serde::{Deserialize, Serialize};
 // pub use sqlx::Row;
-pub use std::collections::HashMap;
+pub use std::collections::{HashMap, HashSet};
 pub use std::fs;
-pub use std::path::Path;
+pub use std::path::{Path, PathBuf};
 pub use std::sync::{Arc, Mutex};
 
 pub mod ax {
     pub use axum::extract::{Path, Query, State};
     pub use axum::http::header;
-    pub use axum::http::{HeaderMap, StatusCode, Uri};
-    pub use axum::response::{Html, Redirect};
+    pub use axum::http::{HeaderMap, Method, StatusCode, Uri};
+    pub use axum::response::{Html, Redirect, Response};
     pub use axum::routing;
     pub use axum::Form;
     pub use axum::Router;
-    pub use axum_extra::extract::cookie::{Cookie, CookieJar};
+    pub use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
 }
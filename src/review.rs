@@ -0,0 +1,124 @@
+use crate::component::post::PostMetadata;
+use crate::prelude::*;
+use crate::{schema, time, usage};
+
+const USAGE: &str = "year-review <year>";
+
+const TOP_TAGS: usize = 5;
+const SELECTED_PHOTOS: usize = 6;
+
+// Writes a draft post summarizing one year of public posts. The draft is private, so it only
+// shows up for logged-in users until it has been edited and `private` is removed.
+pub async fn year_review(args: &[String]) -> Result<(), Error> {
+    let [year] = args else {
+        return usage(USAGE);
+    };
+    let Ok(year) = year.parse::<i32>() else {
+        return usage(USAGE);
+    };
+
+    let config = Config::from_json_file("website.json")?;
+    let db = Database::open(&config)?;
+    schema::check_version(&db)?;
+
+    let tz = config.timezone();
+    let prefix = format!("{}-", year);
+    let posts = Post::get_all(&db)?
+        .into_iter()
+        .filter(|post| post.date.starts_with(&prefix) && post.visible_to(None, tz))
+        .rev()
+        .collect::<Vec<_>>();
+
+    if posts.is_empty() {
+        return Err(
+            Error::new(format!("there are no public posts from {}", year))
+                .with_kind(ErrorKind::Validation),
+        );
+    }
+
+    let dir = Path::new(&config.posts_path).join(format!("{}-in-review", year));
+    if dir.exists() {
+        return Err(Error::new(format!("{} already exists", dir.display()))
+            .with_kind(ErrorKind::Validation));
+    }
+
+    let mut tags = HashMap::<String, usize>::new();
+    let mut photos = vec![];
+    for post in &posts {
+        for tag in post.get_tags(&db)? {
+            *tags.entry(tag).or_default() += 1;
+        }
+        for photo in Photo::get_all(&db, Some(&post.id))? {
+            if photo.visible_to(None) {
+                photos.push((post, photo));
+            }
+        }
+    }
+
+    let mut tags = tags.into_iter().collect::<Vec<_>>();
+    tags.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    tags.truncate(TOP_TAGS);
+
+    // spread over the whole year instead of all coming from the first post
+    let step = photos.len().div_ceil(SELECTED_PHOTOS).max(1);
+    let selected = photos.iter().step_by(step).collect::<Vec<_>>();
+
+    let word_count = posts.iter().map(|post| post.word_count).sum::<i64>();
+    let mut markdown = format!(
+        "In {} I wrote {} posts with {} words in total and shared {} photos.\n",
+        year,
+        posts.len(),
+        word_count,
+        photos.len()
+    );
+
+    if !tags.is_empty() {
+        markdown.push_str("\n## Top tags\n\n");
+        for (tag, count) in &tags {
+            markdown.push_str(&format!("- [#{}](/posts/?tag={}) ({})\n", tag, tag, count));
+        }
+    }
+
+    markdown.push_str("\n## Posts\n\n");
+    for post in &posts {
+        markdown.push_str(&format!(
+            "- {} [{}]({})\n",
+            time::display_date(&post.date, tz),
+            post.title,
+            post.url()
+        ));
+    }
+
+    fs::create_dir_all(&dir).context("failed to create post directory")?;
+
+    if !selected.is_empty() {
+        markdown.push_str("\n## Photos\n");
+        let photos_dir = dir.join(&config.post_public_photos_path);
+        fs::create_dir_all(&photos_dir).context("failed to create photos directory")?;
+
+        for (index, (post, photo)) in selected.iter().enumerate() {
+            // photos of different posts may share a name
+            let name = format!("{}-{}", index + 1, photo.name());
+            fs::copy(&photo.source_path, photos_dir.join(&name))
+                .context(format!("failed to copy photo {}", photo.source_path))?;
+            markdown.push_str(&format!("\n![[photo:{}|From {}]]\n", name, post.title));
+        }
+    }
+
+    let mut metadata = PostMetadata::new(
+        format!("{} in review", year),
+        time::now(tz).format("%Y-%m-%d").to_string(),
+    );
+    metadata.private = true;
+
+    metadata.to_json_file(dir.join(&config.post_metadata_path).to_str().unwrap())?;
+    fs::write(dir.join(&config.post_content_path), markdown)
+        .context("failed to write post content file")?;
+
+    println!(
+        "wrote draft to {}, remove `private` from its metadata to publish it",
+        dir.display()
+    );
+
+    Ok(())
+}
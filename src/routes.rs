@@ -0,0 +1,236 @@
+// Every url of the site. The router is set up from the patterns and pages link with the functions
+// below them, which fill in the same patterns, so a url can't change in one place and be
+// forgotten in another.
+
+pub const INDEX: &str = "/";
+pub const POSTS: &str = "/posts/";
+pub const POSTS_FEED: &str = "/posts/feed.xml";
+pub const CALENDAR: &str = "/calendar.ics";
+pub const POST: &str = "/posts/{id}/";
+pub const POST_MARKDOWN: &str = "/posts/{id}/index.md";
+pub const POST_TEXT: &str = "/posts/{id}/index.txt";
+pub const POST_PDF: &str = "/posts/{id}/pdf";
+pub const POST_QR: &str = "/posts/{id}/qr.svg";
+pub const POST_ASSET: &str = "/posts/{id}/assets/{name}";
+pub const POST_COMMENTS: &str = "/posts/{id}/comments";
+pub const POST_POLL: &str = "/posts/{id}/polls/{poll}";
+pub const COMMENTS: &str = "/comments/";
+pub const APPROVE_COMMENT: &str = "/comments/{id}/approve";
+pub const DELETE_COMMENT: &str = "/comments/{id}/delete";
+pub const PHOTOS: &str = "/photos/";
+pub const PHOTOS_FEED: &str = "/photos/feed.xml";
+pub const PHOTOS_MAP: &str = "/photos/map/";
+pub const PHOTO: &str = "/photos/{id}";
+pub const PROJECTS: &str = "/projects/";
+pub const CONTACT: &str = "/contact/";
+pub const NOW: &str = "/now/";
+pub const CV: &str = "/cv/";
+pub const CV_PDF: &str = "/cv/pdf";
+pub const STATS: &str = "/stats/";
+pub const ADMIN: &str = "/admin/";
+pub const EDIT_POST: &str = "/admin/posts/{id}/edit";
+pub const UPLOAD_PHOTOS: &str = "/admin/posts/{id}/photos";
+pub const PREVIEW: &str = "/admin/preview";
+pub const ALT_TEXT: &str = "/admin/alt-text";
+pub const REBUILD: &str = "/admin/rebuild";
+pub const LOGIN_LINKS: &str = "/admin/login-links";
+pub const MESSAGES: &str = "/admin/messages/";
+pub const SHORTLINKS: &str = "/admin/shortlinks/";
+pub const DELETE_SHORTLINK: &str = "/admin/shortlinks/{code}/delete";
+pub const GITHUB_HOOK: &str = "/hooks/github";
+pub const MICROPUB: &str = "/micropub";
+pub const WEBFINGER: &str = "/.well-known/webfinger";
+pub const ACTOR: &str = "/activitypub/actor";
+pub const INBOX: &str = "/activitypub/inbox";
+pub const OUTBOX: &str = "/activitypub/outbox";
+pub const FOLLOWERS: &str = "/activitypub/followers";
+pub const NOTE: &str = "/activitypub/posts/{id}";
+pub const SHORTLINK: &str = "/s/{code}";
+pub const FILES: &str = "/files/";
+pub const FILE: &str = "/files/{*path}";
+pub const STYLE: &str = "/styles/{name}";
+pub const SCRIPT: &str = "/scripts/{name}";
+pub const ASSET: &str = "/assets/{name}";
+pub const LOGIN: &str = "/login/";
+pub const RESET_FEEDS: &str = "/login/feeds/reset";
+pub const LOGIN_LINK: &str = "/login/token/{secret}";
+pub const LOGOUT: &str = "/logout/";
+// markdown pages from `pages_path`, matched after everything above
+pub const PAGE: &str = "/{slug}/";
+
+// a page can't be named like the first segment of another url
+const TOP_LEVEL: &[&str] = &[
+    POSTS,
+    CALENDAR,
+    COMMENTS,
+    PHOTOS,
+    PROJECTS,
+    CONTACT,
+    NOW,
+    CV,
+    STATS,
+    ADMIN,
+    GITHUB_HOOK,
+    MICROPUB,
+    WEBFINGER,
+    ACTOR,
+    SHORTLINK,
+    FILE,
+    STYLE,
+    SCRIPT,
+    ASSET,
+    LOGIN,
+    LOGOUT,
+];
+
+pub fn is_taken(slug: &str) -> bool {
+    TOP_LEVEL
+        .iter()
+        .any(|pattern| pattern.trim_start_matches('/').split('/').next() == Some(slug))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PhotoSize {
+    Small,
+    Large,
+}
+
+impl PhotoSize {
+    // anything else is the full size
+    pub fn from_query(size: Option<&str>) -> Self {
+        match size {
+            Some("small") => Self::Small,
+            _ => Self::Large,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Small => "small",
+            Self::Large => "large",
+        }
+    }
+}
+
+// replaces the `{...}` segments of `pattern` with `values`, in order
+fn fill(pattern: &str, values: &[&str]) -> String {
+    let mut url = String::with_capacity(pattern.len());
+    let mut values = values.iter();
+    let mut rest = pattern;
+
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..]
+            .find('}')
+            .map_or(rest.len(), |end| start + end + 1);
+        url.push_str(&rest[..start]);
+        url.push_str(values.next().expect("missing value for url pattern"));
+        rest = &rest[end..];
+    }
+    url.push_str(rest);
+    url
+}
+
+pub fn page(slug: &str) -> String {
+    fill(PAGE, &[slug])
+}
+
+pub fn post(slug: &str) -> String {
+    fill(POST, &[slug])
+}
+
+pub fn note(id: &str) -> String {
+    fill(NOTE, &[id])
+}
+
+pub fn shortlink(code: &str) -> String {
+    fill(SHORTLINK, &[code])
+}
+
+pub fn delete_shortlink(code: &str) -> String {
+    fill(DELETE_SHORTLINK, &[code])
+}
+
+pub fn post_asset(id: &str, name: &str) -> String {
+    fill(POST_ASSET, &[id, name])
+}
+
+pub fn post_pdf(id: &str) -> String {
+    fill(POST_PDF, &[id])
+}
+
+pub fn post_qr(id: &str) -> String {
+    fill(POST_QR, &[id])
+}
+
+pub fn post_comments(id: &str) -> String {
+    fill(POST_COMMENTS, &[id])
+}
+
+pub fn post_poll(id: &str, poll: &str) -> String {
+    fill(POST_POLL, &[id, poll])
+}
+
+pub fn posts_tagged(tag: &str) -> String {
+    format!("{}?tag={}", POSTS, encode_segment(tag))
+}
+
+pub fn approve_comment(id: i64) -> String {
+    fill(APPROVE_COMMENT, &[&id.to_string()])
+}
+
+pub fn delete_comment(id: i64) -> String {
+    fill(DELETE_COMMENT, &[&id.to_string()])
+}
+
+pub fn photo(id: &str) -> String {
+    fill(PHOTO, &[id])
+}
+
+pub fn photo_sized(id: &str, size: PhotoSize) -> String {
+    format!("{}?size={}", photo(id), size.as_str())
+}
+
+pub fn photos_page(page: u32) -> String {
+    format!("{}?page={}", PHOTOS, page)
+}
+
+pub fn edit_post(id: &str) -> String {
+    fill(EDIT_POST, &[id])
+}
+
+pub fn upload_photos(id: &str) -> String {
+    fill(UPLOAD_PHOTOS, &[id])
+}
+
+// each segment of the path is encoded, so names with `#`, `?` or spaces still link to the file
+pub fn file(name: &str) -> String {
+    let path = name.split('/').map(encode_segment).collect::<Vec<_>>();
+    fill(FILE, &[&path.join("/")])
+}
+
+pub fn style(name: &str) -> String {
+    fill(STYLE, &[name])
+}
+
+pub fn script(name: &str) -> String {
+    fill(SCRIPT, &[name])
+}
+
+pub fn asset(name: &str) -> String {
+    fill(ASSET, &[name])
+}
+
+pub fn login_link(secret: &str) -> String {
+    fill(LOGIN_LINK, &[secret])
+}
+
+pub fn encode_segment(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|byte| match byte {
+            b'-' | b'.' | b'_' | b'~' => (byte as char).to_string(),
+            byte if byte.is_ascii_alphanumeric() => (byte as char).to_string(),
+            byte => format!("%{:02X}", byte),
+        })
+        .collect()
+}
@@ -0,0 +1,123 @@
+use crate::check_links::LinkCheck;
+use crate::prelude::*;
+
+// Bump this whenever a setup function changes the schema, so serve refuses to run against a
+// database that has not been migrated yet.
+pub const SCHEMA_VERSION: i64 = 35;
+
+// Content tables are derived from the source tree and can be rebuilt at any time. State tables
+// hold data created at runtime (users, sessions, ...) and must survive a rebuild.
+
+pub fn setup_content(db: &Database) -> Result<(), Error> {
+    // first, the tables below move their contents into it
+    Blob::setup(db)?;
+    Post::setup(db)?;
+    Asset::setup(db)?;
+    Photo::setup(db)?;
+    File::setup(db)?;
+    StaticPage::setup(db)?;
+    Poll::setup(db)?;
+    AltText::setup(db)?;
+    Event::setup(db)?;
+    Track::setup(db)?;
+    Project::setup(db)?;
+    Cv::setup(db)?;
+    PdfCache::setup(db)?;
+    Ok(())
+}
+
+pub fn setup_state(db: &Database) -> Result<(), Error> {
+    User::setup(db)?;
+    Tombstone::setup(db)?;
+    Post::setup_slugs(db)?;
+    Comment::setup(db)?;
+    ApiToken::setup(db)?;
+    Build::setup(db)?;
+    Session::setup(db)?;
+    LoginLink::setup(db)?;
+    Message::setup(db)?;
+    Follower::setup(db)?;
+    Shortlink::setup(db)?;
+    LinkCheck::setup(db)?;
+    Ok(())
+}
+
+// What the server itself may write, everything else is only written by builds. Poll votes and
+// alt texts are set up with their content but written by readers and editors, PDFs are cached
+// until the next build.
+pub const SERVER_TABLES: &[&str] = &[
+    "users",
+    "tombstones",
+    "comments",
+    "api_tokens",
+    "builds",
+    "sessions",
+    "login_links",
+    "messages",
+    "activitypub_followers",
+    "shortlinks",
+    "poll_votes",
+    "alt_texts",
+    "pdf_cache",
+];
+
+pub fn reset_content(db: &Database) -> Result<(), Error> {
+    Post::delete_all(db)?;
+    Photo::unmark_all(db)?;
+    File::unmark_all(db)?;
+    Asset::unmark_all(db)?;
+    StaticPage::delete_all(db)?;
+    Poll::delete_all(db)?;
+    AltText::delete_items(db)?;
+    Event::delete_all(db)?;
+    Track::delete_all(db)?;
+    Cv::delete_all(db)?;
+    PdfCache::delete_all(db)?;
+    Ok(())
+}
+
+pub fn setup_migrations(db: &Database) -> Result<(), Error> {
+    db.execute_batch(
+        r#"
+            CREATE TABLE IF NOT EXISTS migrations (
+                version INTEGER PRIMARY KEY,
+                applied_at INTEGER NOT NULL
+            );
+        "#,
+    )
+    .context("failed to create migrations table")
+}
+
+pub fn migrate(db: &Database) -> Result<(), Error> {
+    setup_migrations(db)?;
+    setup_content(db)?;
+    setup_state(db)?;
+
+    db.execute(
+        "INSERT OR IGNORE INTO migrations (version, applied_at) VALUES (?, unixepoch());",
+        [SCHEMA_VERSION],
+    )
+    .context("failed to record schema version")
+}
+
+pub fn version(db: &Database) -> Result<Option<i64>, Error> {
+    setup_migrations(db)?;
+    db.query_one("SELECT MAX(version) FROM migrations;", [], |row| row.get(0))
+        .context("failed to query schema version")
+}
+
+pub fn check_version(db: &Database) -> Result<(), Error> {
+    match version(db)? {
+        Some(SCHEMA_VERSION) => Ok(()),
+        Some(version) => Err(Error::new(format!(
+            "database schema is at version {} but this binary expects version {}, run `website migrate` or `website build` first",
+            version, SCHEMA_VERSION
+        ))
+        .with_kind(ErrorKind::Database)),
+        None => Err(Error::new(format!(
+            "database has no schema version (expected version {}), run `website migrate` or `website build` first",
+            SCHEMA_VERSION
+        ))
+        .with_kind(ErrorKind::Database)),
+    }
+}
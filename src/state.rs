@@ -1,6 +1,91 @@
+use std::sync::{MutexGuard, TryLockError};
+use std::time::{Duration, Instant};
+
 use crate::prelude::*;
 
+// how often a waiting request checks whether the database is free again
+const DB_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
 pub struct AppState {
+    // read-only, the content is only written by builds
     pub db: Arc<Mutex<Database>>,
+    // for sessions, comments, votes and the rest the server writes itself
+    pub writer: Arc<Mutex<Database>>,
     pub config: Arc<Mutex<Config>>,
+    pub recent_errors: RecentErrors,
+    pub rebuild: RebuildStatus,
+    pub failed_logins: FailedLogins,
+    pub page_cache: PageCache,
+    pub file_versions: FileVersions,
+    db_timeout: Duration,
+}
+
+impl AppState {
+    // Opens the connections to the database of the config, which has to be set up already. Both
+    // wait for locks held by other processes, e.g. a rebuild, up to `db_timeout_ms`.
+    pub fn new(config: Config) -> Result<Arc<Self>, Error> {
+        let db_timeout = Duration::from_millis(config.db_timeout_ms);
+        // first, a read-only connection can't create the journal files of a wal database
+        let writer = Database::open_server_writer(&config)?;
+        let db = Database::open_read_only(&config)?;
+
+        Ok(Arc::new(Self {
+            db: Arc::new(Mutex::new(db)),
+            writer: Arc::new(Mutex::new(writer)),
+            config: Arc::new(Mutex::new(config)),
+            recent_errors: RecentErrors::default(),
+            rebuild: RebuildStatus::default(),
+            failed_logins: FailedLogins::default(),
+            page_cache: PageCache::default(),
+            file_versions: FileVersions::default(),
+            db_timeout,
+        }))
+    }
+
+    // Waits for the database without blocking the runtime. If it stays busy for longer than
+    // `db_timeout_ms` the request is answered with a 503 instead of hanging.
+    pub async fn lock_db(&self) -> Result<MutexGuard<'_, Database>, ax::Response> {
+        self.lock(&self.db).await
+    }
+
+    // like `lock_db`, for handlers that write, see `Database::open_server_writer`
+    pub async fn lock_writer(&self) -> Result<MutexGuard<'_, Database>, ax::Response> {
+        self.lock(&self.writer).await
+    }
+
+    async fn lock<'a>(
+        &self,
+        db: &'a Mutex<Database>,
+    ) -> Result<MutexGuard<'a, Database>, ax::Response> {
+        let deadline = Instant::now() + self.db_timeout;
+
+        loop {
+            match db.try_lock() {
+                // another process (e.g. a rebuild) can still hold the database itself, sqlite
+                // waits for it up to the busy timeout
+                Ok(db) => match db.check_available() {
+                    Ok(()) => return Ok(db),
+                    Err(_) => return Err(self.busy()),
+                },
+                Err(TryLockError::Poisoned(error)) => panic!("{}", error),
+                Err(TryLockError::WouldBlock) => {}
+            }
+
+            if Instant::now() >= deadline {
+                return Err(self.busy());
+            }
+
+            tokio::time::sleep(DB_POLL_INTERVAL).await;
+        }
+    }
+
+    fn busy(&self) -> ax::Response {
+        println!("database busy, giving up after {:?}", self.db_timeout);
+        let retry_after = self.db_timeout.as_secs().max(1).to_string();
+        (
+            [(ax::header::RETRY_AFTER, retry_after)],
+            make_error(503, "The site is busy, please try again shortly"),
+        )
+            .into_response()
+    }
 }
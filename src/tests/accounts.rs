@@ -0,0 +1,521 @@
+use axum::body::Body;
+use axum::http::Request;
+use tower::ServiceExt;
+
+use super::site::*;
+use crate::config::UserConfig;
+use crate::prelude::*;
+use crate::{make_router, run_build};
+
+#[tokio::test]
+async fn admin_pages_are_only_for_admins() {
+    let site = make_site();
+    User::new(&site.db(), "admin-key", "admin").unwrap();
+    let friends = site.login(FRIENDS_KEY).await;
+    let admin = site.login("admin-key").await;
+
+    assert_eq!(site.get("/admin/", None).await.0, ax::StatusCode::NOT_FOUND);
+    let (status, body) = site.get("/admin/", Some(&friends)).await;
+    assert_eq!(status, ax::StatusCode::NOT_FOUND);
+    assert!(!site.get("/", Some(&friends)).await.1.contains("/admin/"));
+    assert!(!body.contains("Database size"));
+
+    let (status, body) = site.get("/admin/", Some(&admin)).await;
+    assert_eq!(status, ax::StatusCode::OK);
+    assert!(body.contains("Database size"));
+    assert!(site
+        .get("/", Some(&admin))
+        .await
+        .1
+        .contains("href=\"/admin/\""));
+}
+
+#[tokio::test]
+async fn editor_is_only_for_admins_and_keeps_broken_edits_out() {
+    let site = make_site();
+    User::new(&site.db(), "admin-key", "admin").unwrap();
+    let friends = site.login(FRIENDS_KEY).await;
+    let admin = site.login("admin-key").await;
+    let index_path =
+        Path::new(&site.state.config.lock().unwrap().posts_path).join("public/index.md");
+
+    for user in [None, Some(friends.as_str())] {
+        let (status, body) = site.get("/admin/posts/publicpost/edit", user).await;
+        assert_eq!(status, ax::StatusCode::NOT_FOUND);
+        assert!(!body.contains("Hello."));
+        assert_eq!(
+            site.post("/admin/posts/publicpost/edit", user, "markdown=Defaced.")
+                .await,
+            ax::StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            site.post("/admin/preview", user, "post=privatepost&markdown=x")
+                .await,
+            ax::StatusCode::NOT_FOUND
+        );
+    }
+    assert!(fs::read_to_string(&index_path)
+        .unwrap()
+        .starts_with("Hello."));
+
+    let (status, body) = site.get("/admin/posts/publicpost/edit", Some(&admin)).await;
+    assert_eq!(status, ax::StatusCode::OK);
+    assert!(body.contains("Hello."));
+    assert!(site
+        .get("/posts/public-post/", Some(&admin))
+        .await
+        .1
+        .contains("/admin/posts/publicpost/edit"));
+    assert!(!site
+        .get("/posts/public-post/", Some(&friends))
+        .await
+        .1
+        .contains("/admin/posts/"));
+
+    assert_eq!(
+        site.post(
+            "/admin/posts/publicpost/edit",
+            Some(&admin),
+            "markdown=Edited.%0D%0A"
+        )
+        .await,
+        ax::StatusCode::SEE_OTHER
+    );
+    assert_eq!(fs::read_to_string(&index_path).unwrap(), "Edited.\n");
+    assert!(site
+        .get("/posts/public-post/", None)
+        .await
+        .1
+        .contains("Edited."));
+
+    // an edit that doesn't load is reverted
+    assert_eq!(
+        site.post(
+            "/admin/posts/publicpost/edit",
+            Some(&admin),
+            "markdown=%7B%7Binclude+assets%2Fmissing.rs%7D%7D%0A"
+        )
+        .await,
+        ax::StatusCode::BAD_REQUEST
+    );
+    assert_eq!(fs::read_to_string(&index_path).unwrap(), "Edited.\n");
+    assert!(site
+        .get("/posts/public-post/", None)
+        .await
+        .1
+        .contains("Edited."));
+}
+
+#[tokio::test]
+async fn only_admins_add_missing_alt_text() {
+    let site = make_site();
+    User::new(&site.db(), "admin-key", "admin").unwrap();
+    let friends = site.login(FRIENDS_KEY).await;
+    let admin = site.login("admin-key").await;
+    let form = "post_id=privatepost&name=inner.jpg&alt_text=A+cat";
+
+    // captioned photos have alt text, the other three photos don't
+    let coverage = || AltText::coverage(&site.db()).unwrap();
+    assert_eq!(coverage(), Some(40.0));
+
+    assert_eq!(
+        site.post("/admin/alt-text", Some(&friends), form).await,
+        ax::StatusCode::NOT_FOUND
+    );
+    assert_eq!(coverage(), Some(40.0));
+
+    let (_, body) = site.get("/admin/", Some(&admin)).await;
+    assert!(body.contains("value=\"inner.jpg\""));
+
+    assert_eq!(
+        site.post("/admin/alt-text", Some(&admin), form).await,
+        ax::StatusCode::SEE_OTHER
+    );
+    assert_eq!(coverage(), Some(60.0));
+    assert!(!site
+        .get("/admin/", Some(&admin))
+        .await
+        .1
+        .contains("value=\"inner.jpg\""));
+    assert!(site
+        .get("/posts/private-post/", Some(&friends))
+        .await
+        .1
+        .contains("alt=\"A cat\""));
+}
+
+#[tokio::test]
+async fn only_admins_upload_photos() {
+    let site = make_site();
+    User::new(&site.db(), "admin-key", "admin").unwrap();
+    let friends = site.login(FRIENDS_KEY).await;
+    let admin = site.login("admin-key").await;
+
+    let mut photo = std::io::Cursor::new(vec![]);
+    image::RgbImage::from_pixel(8, 8, image::Rgb([0, 0, 0]))
+        .write_to(&mut photo, image::ImageFormat::Jpeg)
+        .unwrap();
+    let photo = photo.into_inner();
+    let photos_path =
+        Path::new(&site.state.config.lock().unwrap().posts_path).join("public/photos");
+
+    for user in [None, Some(friends.as_str())] {
+        assert_eq!(
+            site.upload("/admin/posts/publicpost/photos", user, "cat.jpg", &photo)
+                .await,
+            ax::StatusCode::NOT_FOUND
+        );
+    }
+    assert_eq!(
+        site.upload(
+            "/admin/posts/publicpost/photos",
+            Some(&admin),
+            "cat.jpg",
+            b"not a photo"
+        )
+        .await,
+        ax::StatusCode::BAD_REQUEST
+    );
+    assert!(!photos_path.join("cat.jpg").exists());
+
+    // the name can't escape the photos directory and doesn't replace an existing photo
+    for file_name in ["../../cat.jpg", "public.jpg"] {
+        assert_eq!(
+            site.upload(
+                "/admin/posts/publicpost/photos",
+                Some(&admin),
+                file_name,
+                &photo
+            )
+            .await,
+            ax::StatusCode::SEE_OTHER
+        );
+    }
+    assert!(photos_path.join("cat.jpg").exists());
+    assert!(photos_path.join("2-public.jpg").exists());
+
+    let (_, body) = site.get("/posts/public-post/", None).await;
+    assert!(body.contains(&site.photo_id("cat.jpg")));
+    assert!(body.contains(&site.photo_id("public.jpg")));
+}
+
+#[tokio::test]
+async fn rebuilds_keep_users_and_sync_the_configured_ones() {
+    let site = make_site_with(|config| {
+        config.users = vec![UserConfig {
+            key_hash: User::key_hash("admin-key"),
+            group: "admin".to_string(),
+        }];
+    });
+    let friends = site.login(FRIENDS_KEY).await;
+    let token = {
+        let db = site.db();
+        let user = user_of(&db, &friends);
+        user.feed_token(&db).unwrap()
+    };
+
+    for _ in 0..2 {
+        let db = site.db();
+        run_build(&db, &site.state.config.lock().unwrap(), false, false, false).unwrap();
+        assert_eq!(User::get_all(&db).unwrap().len(), 3);
+    }
+
+    let admin = site.login("admin-key").await;
+    assert_eq!(
+        site.get("/admin/", Some(&admin)).await.0,
+        ax::StatusCode::OK
+    );
+    assert!(site
+        .get("/posts/private-post/", Some(&friends))
+        .await
+        .1
+        .contains("Members only."));
+    assert_eq!(
+        site.get(&format!("/posts/feed.xml?token={}", token), None)
+            .await
+            .0,
+        ax::StatusCode::OK
+    );
+
+    // moving a configured user to another group keeps its feed token
+    site.state.config.lock().unwrap().users[0].group = "friends".to_string();
+    {
+        let db = site.db();
+        let token = user_of(&db, &admin).feed_token(&db).unwrap();
+        run_build(&db, &site.state.config.lock().unwrap(), false, false, false).unwrap();
+        assert_eq!(
+            User::by_feed_token(&db, &token).unwrap().group_name,
+            "friends"
+        );
+    }
+    assert_eq!(
+        site.get("/admin/", Some(&admin)).await.0,
+        ax::StatusCode::NOT_FOUND
+    );
+
+    // a plain key in the config is refused rather than stored
+    site.state.config.lock().unwrap().users[0].key_hash = "admin-key".to_string();
+    let db = site.db();
+    let cfg = site.state.config.lock().unwrap().clone();
+    assert!(run_build(&db, &cfg, false, false, false).is_err());
+}
+
+#[tokio::test]
+async fn forms_need_the_csrf_token_of_the_browser() {
+    let site = make_site();
+    let friends = site.login(FRIENDS_KEY).await;
+
+    async fn post_raw(site: &Site, path: &str, cookie: &str, form: &str) -> ax::StatusCode {
+        let request = Request::post(path)
+            .header(
+                ax::header::CONTENT_TYPE,
+                "application/x-www-form-urlencoded",
+            )
+            .header(ax::header::COOKIE, cookie)
+            .body(Body::from(form.to_string()))
+            .unwrap();
+        make_router(site.state.clone())
+            .oneshot(request)
+            .await
+            .unwrap()
+            .status()
+    }
+
+    let key = format!("key={}", FRIENDS_KEY);
+    assert_eq!(
+        post_raw(&site, "/login/", "", &key).await,
+        ax::StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        post_raw(
+            &site,
+            "/login/",
+            "csrf=mine",
+            &format!("{}&csrf=theirs", key)
+        )
+        .await,
+        ax::StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        post_raw(&site, "/logout/", &friends, "").await,
+        ax::StatusCode::FORBIDDEN
+    );
+    let comment = "name=spam&body=spam";
+    assert_eq!(
+        post_raw(&site, "/posts/public-post/comments", &friends, comment).await,
+        ax::StatusCode::FORBIDDEN
+    );
+
+    // a new browser gets a token with the page and the form carries it
+    let response = make_router(site.state.clone())
+        .oneshot(Request::get("/login/").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let cookie = response
+        .headers()
+        .get(ax::header::SET_COOKIE)
+        .expect("no csrf cookie")
+        .to_str()
+        .unwrap()
+        .split(';')
+        .next()
+        .unwrap()
+        .to_string();
+    let token = cookie.strip_prefix("csrf=").unwrap().to_string();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    assert!(String::from_utf8_lossy(&body).contains(&format!("value=\"{}\"", token)));
+    assert_eq!(
+        post_raw(
+            &site,
+            "/login/",
+            &cookie,
+            &format!("{}&csrf={}", key, token)
+        )
+        .await,
+        ax::StatusCode::SEE_OTHER
+    );
+}
+
+#[tokio::test]
+async fn sessions_end_on_logout_and_expiry() {
+    let site = make_site();
+    let secret = format!("/photos/{}", site.photo_id("secret.jpg"));
+
+    let response = make_router(site.state.clone())
+        .oneshot(
+            Request::post("/login/")
+                .header(
+                    ax::header::CONTENT_TYPE,
+                    "application/x-www-form-urlencoded",
+                )
+                .header(ax::header::COOKIE, browser_cookie(None))
+                .body(Body::from(format!(
+                    "key={}&csrf={}",
+                    FRIENDS_KEY, CSRF_TOKEN
+                )))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let set_cookie = response.headers()[ax::header::SET_COOKIE].to_str().unwrap();
+    for attribute in ["HttpOnly", "Secure", "SameSite=Lax", "Max-Age=2592000"] {
+        assert!(set_cookie.contains(attribute), "{}", set_cookie);
+    }
+    assert!(!set_cookie.contains(&User::get_all(&site.db()).unwrap()[0].key_hash));
+
+    let friends = site.login(FRIENDS_KEY).await;
+    let other_browser = site.login(FRIENDS_KEY).await;
+    assert_eq!(
+        site.get(&secret, Some(&friends)).await.0,
+        ax::StatusCode::OK
+    );
+    assert_eq!(
+        site.post("/logout/", Some(&friends), "").await,
+        ax::StatusCode::SEE_OTHER
+    );
+    // a copy of the cookie is useless after logging out, other logins stay
+    assert_eq!(
+        site.get(&secret, Some(&friends)).await.0,
+        ax::StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        site.get(&secret, Some(&other_browser)).await.0,
+        ax::StatusCode::OK
+    );
+
+    site.state.config.lock().unwrap().session_ttl = 0;
+    let expired = site.login(FRIENDS_KEY).await;
+    assert_eq!(
+        site.get(&secret, Some(&expired)).await.0,
+        ax::StatusCode::FORBIDDEN
+    );
+}
+
+#[tokio::test]
+async fn removing_a_user_ends_only_its_sessions() {
+    let site = make_site();
+    let secret = format!("/photos/{}", site.photo_id("secret.jpg"));
+    let friends = site.login(FRIENDS_KEY).await;
+    let family = site.login(FAMILY_KEY).await;
+
+    assert!(User::delete(&site.db(), FRIENDS_KEY).unwrap());
+    assert!(!User::delete(&site.db(), FRIENDS_KEY).unwrap());
+    assert_eq!(
+        site.get(&secret, Some(&friends)).await.0,
+        ax::StatusCode::FORBIDDEN
+    );
+    assert_eq!(site.get(&secret, Some(&family)).await.0, ax::StatusCode::OK);
+}
+
+#[tokio::test]
+async fn roles_decide_what_logged_in_users_can_do() {
+    let site = make_site();
+    {
+        let db = site.db();
+        User::new(&db, "admin-key", "admin").unwrap();
+        User::new(&db, "guest-key", "guest").unwrap();
+    }
+    let admin = site.login("admin-key").await;
+    let guest = site.login("guest-key").await;
+    let friends = site.login(FRIENDS_KEY).await;
+
+    // guests only get what is shared with their group
+    let (_, body) = site.get("/posts/", Some(&guest)).await;
+    assert!(body.contains("Public post"));
+    assert!(!body.contains("Private post"));
+    assert!(!body.contains("Family post"));
+    let secret = format!("/photos/{}", site.photo_id("secret.jpg"));
+    assert_eq!(
+        site.get(&secret, Some(&guest)).await.0,
+        ax::StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        site.get("/comments/", Some(&guest)).await.0,
+        ax::StatusCode::NOT_FOUND
+    );
+
+    // members moderate but don't administer
+    assert_eq!(
+        site.get("/comments/", Some(&friends)).await.0,
+        ax::StatusCode::OK
+    );
+    assert_eq!(
+        site.get("/admin/posts/publicpost/edit", Some(&friends))
+            .await
+            .0,
+        ax::StatusCode::NOT_FOUND
+    );
+
+    // admins see every group
+    let (_, body) = site.get("/posts/", Some(&admin)).await;
+    assert!(body.contains("Private post"));
+    assert!(body.contains("Family post"));
+    let family = format!("/photos/{}", site.photo_id("family.jpg"));
+    assert_eq!(site.get(&family, Some(&admin)).await.0, ax::StatusCode::OK);
+}
+
+#[tokio::test]
+async fn login_links_work_once_and_only_admins_mint_them() {
+    let site = make_site();
+    User::new(&site.db(), "admin-key", "admin").unwrap();
+    let admin = site.login("admin-key").await;
+    let friends = site.login(FRIENDS_KEY).await;
+
+    assert_eq!(
+        site.post("/admin/login-links", Some(&friends), "group=family")
+            .await,
+        ax::StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        site.post(
+            "/admin/login-links",
+            Some(&admin),
+            "group=family&expires_days=2"
+        )
+        .await,
+        ax::StatusCode::OK
+    );
+
+    let secret = {
+        let db = site.db();
+        let (link, secret) = LoginLink::mint(&db, "family", 60).unwrap();
+        assert_eq!(link.group_name, "family");
+        let (_, expired) = LoginLink::mint(&db, "family", 0).unwrap();
+        assert!(LoginLink::by_secret(&db, &expired).is_err());
+        secret
+    };
+    let path = format!("/login/token/{}", secret);
+
+    // opening the link doesn't use it up
+    assert_eq!(site.get(&path, None).await.0, ax::StatusCode::OK);
+    assert_eq!(site.get(&path, None).await.0, ax::StatusCode::OK);
+
+    let response = make_router(site.state.clone())
+        .oneshot(
+            Request::post(&path)
+                .header(
+                    ax::header::CONTENT_TYPE,
+                    "application/x-www-form-urlencoded",
+                )
+                .header(ax::header::COOKIE, browser_cookie(None))
+                .body(Body::from(format!("csrf={}", CSRF_TOKEN)))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), ax::StatusCode::SEE_OTHER);
+    let family = response.headers()[ax::header::SET_COOKIE]
+        .to_str()
+        .unwrap()
+        .split(';')
+        .next()
+        .unwrap()
+        .to_string();
+    let photo = format!("/photos/{}", site.photo_id("family.jpg"));
+    assert_eq!(site.get(&photo, Some(&family)).await.0, ax::StatusCode::OK);
+
+    assert_eq!(site.get(&path, None).await.0, ax::StatusCode::NOT_FOUND);
+    assert_eq!(site.post(&path, None, "").await, ax::StatusCode::NOT_FOUND);
+}
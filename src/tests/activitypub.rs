@@ -0,0 +1,168 @@
+use axum::body::Body;
+use axum::http::Request;
+use tower::ServiceExt;
+
+use super::site::*;
+use super::TempDir;
+use crate::config::ActivityPubConfig;
+use crate::prelude::*;
+use crate::{build_content, make_router};
+
+const ACTIVITYPUB_KEY: &str = include_str!("activitypub_key.pem");
+
+#[tokio::test(flavor = "multi_thread")]
+async fn fediverse_followers_are_verified_and_get_new_posts() {
+    use crate::component::activitypub::sign;
+    use rsa::pkcs8::{DecodePrivateKey, EncodePublicKey, LineEnding};
+
+    let keys = TempDir::new();
+    let key_path = keys.path().join("key.pem");
+    fs::write(&key_path, ACTIVITYPUB_KEY).unwrap();
+    let site = make_site_with(|config| {
+        config.activitypub = Some(ActivityPubConfig {
+            username: "kai".to_string(),
+            private_key_path: key_path.to_str().unwrap().to_string(),
+            name: "Kai".to_string(),
+            summary: None,
+        })
+    });
+
+    let (status, body) = site
+        .get("/.well-known/webfinger?resource=acct:kai@localhost", None)
+        .await;
+    assert_eq!(status, ax::StatusCode::OK);
+    assert!(body.contains("\"href\":\"http://localhost/activitypub/actor\""));
+    let (_, body) = site.get("/activitypub/actor", None).await;
+    assert!(body.contains("BEGIN PUBLIC KEY") && body.contains("/activitypub/inbox"));
+    let (_, body) = site.get("/activitypub/outbox", None).await;
+    assert!(body.contains("Public post") && !body.contains("Private post"));
+    let (status, _) = site.get("/activitypub/posts/privatepost", None).await;
+    assert_eq!(status, ax::StatusCode::NOT_FOUND);
+
+    // another server, with an account that signs with the same key for simplicity
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let remote = format!("http://{}", listener.local_addr().unwrap());
+    let key = rsa::RsaPrivateKey::from_pkcs8_pem(ACTIVITYPUB_KEY).unwrap();
+    let actor = format!("{}/actor", remote);
+    let key_id = format!("{}#key", actor);
+    let document = serde_json::json!({
+        "id": actor,
+        "type": "Person",
+        "inbox": format!("{}/inbox", remote),
+        "publicKey": {
+            "id": key_id,
+            "owner": actor,
+            "publicKeyPem": rsa::RsaPublicKey::from(&key).to_public_key_pem(LineEnding::LF).unwrap(),
+        },
+    });
+    let received = Arc::new(Mutex::new(Vec::<String>::new()));
+    let inbox = received.clone();
+    let fetches = Arc::new(Mutex::new(0));
+    let fetched = fetches.clone();
+    let router = ax::Router::new()
+        .route(
+            "/actor",
+            ax::routing::get(move || {
+                *fetched.lock().unwrap() += 1;
+                let document = document.clone();
+                async move { axum::Json(document) }
+            }),
+        )
+        .route(
+            "/inbox",
+            ax::routing::post(move |body: String| {
+                inbox.lock().unwrap().push(body);
+                async { ax::StatusCode::ACCEPTED }
+            }),
+        );
+    tokio::spawn(axum::serve(listener, router).into_future());
+
+    let send = |activity: serde_json::Value, signed_body: Option<String>| {
+        let body = activity.to_string();
+        let signed_body = signed_body.unwrap_or(body.clone());
+        let headers = sign(
+            &key,
+            &key_id,
+            "http://localhost/activitypub/inbox",
+            signed_body.as_bytes(),
+        )
+        .unwrap();
+        let state = site.state.clone();
+        async move {
+            let mut request = Request::post("/activitypub/inbox");
+            for (name, value) in headers {
+                request = request.header(name, value);
+            }
+            make_router(state)
+                .oneshot(request.body(Body::from(body)).unwrap())
+                .await
+                .unwrap()
+                .status()
+        }
+    };
+    let follow = serde_json::json!({
+        "id": format!("{}/follows/1", remote),
+        "type": "Follow",
+        "actor": actor,
+        "object": "http://localhost/activitypub/actor",
+    });
+
+    // signed for another body, refused before the actor is fetched
+    assert_eq!(
+        send(follow.clone(), Some("{}".to_string())).await,
+        ax::StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(*fetches.lock().unwrap(), 0);
+    assert_eq!(send(follow.clone(), None).await, ax::StatusCode::ACCEPTED);
+    let (_, body) = site.get("/activitypub/followers", None).await;
+    assert!(body.contains("\"totalItems\":1"));
+
+    let wait_for = |needle: &'static str| {
+        let received = received.clone();
+        async move {
+            for _ in 0..100 {
+                if received
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .any(|body| body.contains(needle))
+                {
+                    return;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            }
+            panic!("nothing with {} was delivered", needle);
+        }
+    };
+    wait_for("\"type\":\"Accept\"").await;
+
+    // the first delivery only takes note of the posts that are already there
+    let cfg = site.state.config.lock().unwrap().clone();
+    let deliver = |cfg: Config| {
+        tokio::task::spawn_blocking(move || {
+            deliver_posts(&Database::open(&cfg).unwrap(), &cfg).unwrap()
+        })
+    };
+    deliver(cfg.clone()).await.unwrap();
+    write_post(
+        site._dir.path(),
+        "fresh",
+        serde_json::json!({"id": "freshpost", "title": "Fresh post", "date": "2024-02-01", "tags": []}),
+        "New.\n",
+    );
+    build_content(&site.db(), &cfg).unwrap();
+    deliver(cfg.clone()).await.unwrap();
+    wait_for("Fresh post").await;
+    let received_posts = received.lock().unwrap().clone();
+    assert!(!received_posts
+        .iter()
+        .any(|body| body.contains("Public post")));
+    assert!(received_posts
+        .iter()
+        .any(|body| body.contains("\"type\":\"Create\"")));
+
+    let undo = serde_json::json!({"type": "Undo", "actor": actor, "object": follow});
+    assert_eq!(send(undo, None).await, ax::StatusCode::ACCEPTED);
+    let (_, body) = site.get("/activitypub/followers", None).await;
+    assert!(body.contains("\"totalItems\":0"));
+}
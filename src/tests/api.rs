@@ -0,0 +1,219 @@
+use axum::body::Body;
+use axum::http::Request;
+use tower::ServiceExt;
+
+use super::site::*;
+use crate::prelude::*;
+use crate::{build_content, make_router};
+
+#[tokio::test]
+async fn a_micropub_token_from_the_config_keeps_working() {
+    let site = make_site_with(|config| config.micropub_token = Some("old-secret".to_string()));
+    let token = ApiToken::authenticate(&site.db(), "old-secret").unwrap();
+    assert!(token.has_scope("micropub"));
+
+    // imported once, a rebuild leaves it alone
+    build_content(&site.db(), &site.state.config.lock().unwrap()).unwrap();
+    assert_eq!(ApiToken::get_all(&site.db()).unwrap().len(), 1);
+
+    assert_eq!(
+        site.post(
+            "/micropub",
+            None,
+            "h=entry&content=Still+works&access_token=old-secret"
+        )
+        .await,
+        ax::StatusCode::CREATED
+    );
+}
+
+#[tokio::test]
+async fn micropub_only_publishes_with_a_valid_token() {
+    let site = make_site();
+    let (phone, ci, expired) = {
+        let db = site.db();
+        let scopes = ["micropub".to_string()];
+        let yesterday = chrono::Utc::now().timestamp() - 24 * 60 * 60;
+        (
+            ApiToken::mint(&db, "phone", &scopes, None).unwrap().1,
+            ApiToken::mint(&db, "ci", &["rebuild".to_string()], None)
+                .unwrap()
+                .1,
+            ApiToken::mint(&db, "old", &scopes, Some(yesterday))
+                .unwrap()
+                .1,
+        )
+    };
+    let form = "h=entry&content=Hello+from+my+phone&category[]=notes";
+
+    assert_eq!(
+        site.post("/micropub", None, form).await,
+        ax::StatusCode::UNAUTHORIZED
+    );
+    for token in ["wrong", &ci, &expired] {
+        assert_eq!(
+            site.post(
+                "/micropub",
+                None,
+                &format!("{}&access_token={}", form, token)
+            )
+            .await,
+            ax::StatusCode::FORBIDDEN
+        );
+    }
+    assert!(!site
+        .get("/posts/", None)
+        .await
+        .1
+        .contains("Hello from my phone"));
+
+    assert_eq!(
+        site.post(
+            "/micropub",
+            None,
+            &format!("{}&access_token={}", form, phone)
+        )
+        .await,
+        ax::StatusCode::CREATED
+    );
+    let (_, body) = site.get("/posts/hello-from-my-phone/", None).await;
+    assert!(body.contains("Hello from my phone"));
+    assert!(body.contains("#notes"));
+}
+
+#[tokio::test]
+async fn rebuilds_need_an_admin_or_a_rebuild_token() {
+    let site = make_site();
+    User::new(&site.db(), "admin-key", "admin").unwrap();
+    let friends = site.login(FRIENDS_KEY).await;
+    let admin = site.login("admin-key").await;
+    let (ci, phone) = {
+        let db = site.db();
+        (
+            ApiToken::mint(&db, "ci", &["rebuild".to_string()], None)
+                .unwrap()
+                .1,
+            ApiToken::mint(&db, "phone", &["micropub".to_string()], None)
+                .unwrap()
+                .1,
+        )
+    };
+
+    assert_eq!(
+        site.post("/admin/rebuild", None, "").await,
+        ax::StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        site.post("/admin/rebuild", Some(&friends), "").await,
+        ax::StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        site.post_with_token("/admin/rebuild", &phone).await,
+        ax::StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        site.post_with_token("/admin/rebuild", "wrong").await,
+        ax::StatusCode::FORBIDDEN
+    );
+    assert!(site
+        .get("/admin/", Some(&admin))
+        .await
+        .1
+        .contains("No rebuilds since"));
+
+    assert_eq!(
+        site.post_with_token("/admin/rebuild", &ci).await,
+        ax::StatusCode::ACCEPTED
+    );
+
+    let mut body = String::new();
+    for _ in 0..200 {
+        body = site.get("/admin/", Some(&admin)).await.1;
+        if body.contains("Last rebuild") {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    assert!(body.contains("Last rebuild succeeded"));
+    assert!(site
+        .get("/posts/public-post/", None)
+        .await
+        .1
+        .contains("Hello."));
+
+    // a failed rebuild leaves the site as it was
+    fs::write(
+        site._dir.path().join("posts/family/meta.json"),
+        "{ not json",
+    )
+    .unwrap();
+    assert_eq!(
+        site.post_with_token("/admin/rebuild", &ci).await,
+        ax::StatusCode::ACCEPTED
+    );
+    for _ in 0..200 {
+        body = site.get("/admin/", Some(&admin)).await.1;
+        if body.contains("Last rebuild failed") {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    assert!(body.contains("Last rebuild failed"));
+    assert_eq!(Post::count_all(&site.db()).unwrap(), 4);
+    assert!(site
+        .get("/posts/public-post/", None)
+        .await
+        .1
+        .contains("Hello."));
+}
+
+#[tokio::test]
+async fn github_hooks_need_a_valid_signature() {
+    use hmac::{Hmac, Mac};
+
+    async fn deliver(site: &Site, event: &str, signature: &str) -> ax::StatusCode {
+        let request = Request::post("/hooks/github")
+            .header("x-github-event", event)
+            .header("x-hub-signature-256", signature)
+            .body(Body::from("{}"))
+            .unwrap();
+        make_router(site.state.clone())
+            .oneshot(request)
+            .await
+            .unwrap()
+            .status()
+    }
+
+    let mut mac = Hmac::<sha2::Sha256>::new_from_slice(b"hook-secret").unwrap();
+    mac.update(b"{}");
+    let signature = format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+
+    let site = make_site();
+    assert_eq!(
+        deliver(&site, "ping", &signature).await,
+        ax::StatusCode::NOT_FOUND
+    );
+
+    let site = make_site_with(|config| {
+        config.github_webhook = Some(crate::config::GithubWebhookConfig {
+            secret: "hook-secret".to_string(),
+            repository_path: config.posts_path.clone(),
+        })
+    });
+    for signature in ["", "sha256=00", "sha1=abc"] {
+        assert_eq!(
+            deliver(&site, "push", signature).await,
+            ax::StatusCode::UNAUTHORIZED
+        );
+    }
+    assert_eq!(deliver(&site, "ping", &signature).await, ax::StatusCode::OK);
+    assert_eq!(
+        deliver(&site, "issues", &signature).await,
+        ax::StatusCode::ACCEPTED
+    );
+    // the posts directory is not a git checkout, so there is nothing to pull
+    assert_eq!(
+        deliver(&site, "push", &signature).await,
+        ax::StatusCode::INTERNAL_SERVER_ERROR
+    );
+}
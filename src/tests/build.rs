@@ -0,0 +1,379 @@
+use super::site::*;
+use super::TempDir;
+use crate::prelude::*;
+use crate::{build_content, run_build};
+
+#[test]
+fn deleting_a_post_deletes_what_belongs_to_it() {
+    let site = make_site();
+    let db = site.db();
+    let count = |table: &str| -> i64 {
+        db.query_one(
+            &format!(
+                "SELECT COUNT(*) FROM {} WHERE post_id = 'publicpost';",
+                table
+            ),
+            [],
+            |row| row.get(0),
+        )
+        .unwrap()
+    };
+    assert!(count("posts_tags") > 0);
+    assert!(count("posts_photos") > 0);
+
+    db.execute("DELETE FROM posts WHERE id = 'publicpost';", [])
+        .unwrap();
+    assert_eq!(count("posts_tags"), 0);
+    assert_eq!(count("posts_photos"), 0);
+}
+
+#[test]
+fn backups_can_be_restored_and_old_ones_are_pruned() {
+    let site = make_site();
+    let dir = TempDir::new();
+    let source = dir.path().join("source.sqlite");
+    site.db().backup(&source).unwrap();
+
+    let mut config = site.state.config.lock().unwrap().clone();
+    config.database_path = source.to_string_lossy().into_owned();
+    let backup = crate::config::BackupConfig {
+        dir: dir.path().join("backups").to_string_lossy().into_owned(),
+        interval_hours: 24,
+        keep: 2,
+    };
+    fs::create_dir_all(&backup.dir).unwrap();
+    for name in [
+        "website-20200101-000000.sqlite",
+        "website-20210101-000000.sqlite",
+    ] {
+        fs::write(Path::new(&backup.dir).join(name), "old").unwrap();
+    }
+
+    let path = crate::backup::run_scheduled_backup(&config, &backup).unwrap();
+    let mut left = fs::read_dir(&backup.dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    left.sort();
+    assert_eq!(left[0], "website-20210101-000000.sqlite");
+    assert_eq!(left.len(), 2);
+
+    let mut restored = Database::connect(":memory:").unwrap();
+    restored.restore(&path).unwrap();
+    assert_eq!(
+        Post::get_all(&restored).unwrap().len(),
+        Post::get_all(&site.db()).unwrap().len()
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn websub_hub_is_announced_and_pinged_when_public_posts_change() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let hub = format!("http://{}/", listener.local_addr().unwrap());
+    let pings = Arc::new(Mutex::new(Vec::<String>::new()));
+    let received = pings.clone();
+    let router = ax::Router::new().route(
+        "/",
+        ax::routing::post(move |body: String| {
+            received.lock().unwrap().push(body);
+            async { ax::StatusCode::NO_CONTENT }
+        }),
+    );
+    tokio::spawn(axum::serve(listener, router).into_future());
+
+    let site = make_site_with(|config| config.websub_hub = Some(hub.clone()));
+    let (_, body) = site.get("/posts/feed.xml", None).await;
+    assert!(body.contains(&format!(r#"<atom:link rel="hub" href="{}">"#, hub)));
+    assert!(body.contains(
+        r#"rel="self" type="application/rss+xml" href="http://localhost/posts/feed.xml""#
+    ));
+
+    let cfg = site.state.config.lock().unwrap().clone();
+    let ping = |cfg: Config| {
+        tokio::task::spawn_blocking(move || {
+            let db = Database::open(&cfg).unwrap();
+            let previous = crate::webmention::snapshot(&db).unwrap();
+            build_content(&db, &cfg).unwrap();
+            crate::websub::ping_hub(&db, &cfg, &previous).unwrap();
+        })
+    };
+
+    // nothing changed
+    ping(cfg.clone()).await.unwrap();
+    assert!(pings.lock().unwrap().is_empty());
+
+    let post_dir = site._dir.path().join("posts/public");
+    fs::write(post_dir.join("index.md"), "Hello again.\n").unwrap();
+    ping(cfg).await.unwrap();
+    let pings = pings.lock().unwrap().clone();
+    assert_eq!(pings.len(), 2);
+    assert!(pings[0].contains("hub.mode=publish"));
+    assert!(pings[0].contains("hub.url=http%3A%2F%2Flocalhost%2Fposts%2Ffeed.xml"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn check_links_reports_broken_links_and_caches_working_ones() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let remote = format!("http://{}", listener.local_addr().unwrap());
+    let hits = Arc::new(Mutex::new(0));
+    let counted = hits.clone();
+    let router = ax::Router::new().route(
+        "/ok",
+        ax::routing::get(move || {
+            *counted.lock().unwrap() += 1;
+            async { "fine" }
+        }),
+    );
+    tokio::spawn(axum::serve(listener, router).into_future());
+
+    let site = make_site();
+    write_post(
+        site._dir.path(),
+        "links",
+        serde_json::json!({"title": "Links", "date": "2024-03-01", "tags": []}),
+        &format!(
+            "[ok]({0}/ok), [gone]({0}/gone), [own](http://localhost/posts/) and [local](/now/)\n",
+            remote
+        ),
+    );
+    let cfg = site.state.config.lock().unwrap().clone();
+    build_content(&site.db(), &cfg).unwrap();
+
+    let check = |recheck: bool| {
+        let cfg = cfg.clone();
+        tokio::task::spawn_blocking(move || {
+            let db = Database::open(&cfg).unwrap();
+            crate::check_links::check_links_site(&db, &cfg, recheck, 2).unwrap()
+        })
+    };
+
+    let broken = check(false).await.unwrap();
+    assert_eq!(broken.len(), 1);
+    assert_eq!(broken[0].0.url, format!("{}/gone", remote));
+    assert_eq!(broken[0].0.status, Some(404));
+    assert_eq!(broken[0].1, vec!["links".to_string()]);
+    assert_eq!(*hits.lock().unwrap(), 1);
+
+    // the working link is cached, the broken one asked again
+    assert_eq!(check(false).await.unwrap().len(), 1);
+    assert_eq!(*hits.lock().unwrap(), 1);
+    check(true).await.unwrap();
+    assert_eq!(*hits.lock().unwrap(), 2);
+}
+
+#[tokio::test]
+async fn build_report_counts_what_changed() {
+    let site = make_site();
+    let cfg = site.state.config.lock().unwrap().clone();
+    let db = site.db();
+
+    let report = run_build(&db, &cfg, false, false, false).unwrap();
+    assert_eq!(
+        (
+            report.posts_added,
+            report.posts_updated,
+            report.posts_unchanged
+        ),
+        (0, 0, 4)
+    );
+    assert_eq!((report.photos_encoded, report.photos_skipped), (0, 5));
+    assert_eq!(report.bytes_written, 0);
+
+    let dir = site._dir.path();
+    fs::write(dir.join("posts/public/index.md"), "Changed.\n").unwrap();
+    fs::remove_dir_all(dir.join("posts/expired")).unwrap();
+    let new_post = write_post(
+        dir,
+        "new",
+        serde_json::json!({"title": "New post", "date": "2024-03-01", "tags": []}),
+        "New.\n",
+    );
+    write_photo(&new_post.join("photos/new.jpg"));
+    // the photo looks like the others, so only the asset is new data
+    fs::create_dir_all(new_post.join("assets")).unwrap();
+    fs::write(new_post.join("assets/notes.txt"), "new data").unwrap();
+
+    let report = run_build(&db, &cfg, false, false, false).unwrap();
+    assert_eq!(
+        (
+            report.posts_added,
+            report.posts_updated,
+            report.posts_unchanged,
+            report.posts_removed
+        ),
+        (1, 1, 2, 1)
+    );
+    assert_eq!((report.photos_encoded, report.photos_skipped), (1, 4));
+    assert_eq!(report.bytes_written, 8);
+    assert!(report.elapsed_ms("content") <= report.phases.iter().map(|p| p.elapsed_ms).sum());
+
+    let json = serde_json::to_value(&report).unwrap();
+    assert_eq!(json["posts_added"], 1);
+    assert_eq!(json["phases"][1]["name"], "content");
+    assert!(report
+        .to_table()
+        .contains("1 added, 1 updated, 2 unchanged, 1 removed"));
+}
+
+#[tokio::test]
+async fn failed_builds_keep_the_previous_content() {
+    let site = make_site();
+    let cfg = site.state.config.lock().unwrap().clone();
+    let db = site.db();
+    let files = File::count_all(&db).unwrap();
+    assert_eq!(Post::count_all(&db).unwrap(), 4);
+
+    fs::write(
+        site._dir.path().join("posts/public/meta.json"),
+        "{ not json",
+    )
+    .unwrap();
+    assert!(run_build(&db, &cfg, false, false, false).is_err());
+
+    assert_eq!(Post::count_all(&db).unwrap(), 4);
+    assert_eq!(File::count_all(&db).unwrap(), files);
+    let (status, body) = site.get("/posts/public-post/", None).await;
+    assert_eq!(status, ax::StatusCode::OK);
+    assert!(body.contains("Hello."));
+}
+
+#[tokio::test]
+async fn keep_going_leaves_broken_posts_out_and_reports_them() {
+    let site = make_site();
+    let cfg = site.state.config.lock().unwrap().clone();
+    let db = site.db();
+
+    let dir = site._dir.path();
+    fs::write(dir.join("posts/public/meta.json"), "{ not json").unwrap();
+    // fails after the post itself is inserted
+    let half = write_post(
+        dir,
+        "half",
+        serde_json::json!({"id": "halfpost", "title": "Half", "date": "2024-03-01", "tags": []}),
+        "Half.\n",
+    );
+    fs::create_dir_all(half.join("photos")).unwrap();
+    fs::write(half.join("photos/broken.jpg"), "not a photo").unwrap();
+
+    let Err(error) = run_build(&db, &cfg, false, false, false) else {
+        panic!("a broken post didn't fail the build");
+    };
+    assert!(error.chain_message().contains("failed to load post"));
+
+    let report = run_build(&db, &cfg, false, false, true).unwrap();
+    let mut failed = report
+        .failures
+        .iter()
+        .map(|failure| failure.path.clone())
+        .collect::<Vec<_>>();
+    failed.sort();
+    assert_eq!(
+        failed,
+        vec![
+            half.display().to_string(),
+            dir.join("posts/public").display().to_string()
+        ]
+    );
+    assert!(report.to_table().contains("2 posts failed"));
+    assert!(Post::by_id(&db, "halfpost").is_err());
+    assert!(Post::by_id(&db, "privatepost").is_ok());
+
+    // a broken post isn't gone for good
+    assert_eq!(
+        site.get("/posts/public-post/", None).await.0,
+        ax::StatusCode::NOT_FOUND
+    );
+}
+
+#[tokio::test]
+async fn colliding_ids_and_permalinks_name_both_posts() {
+    let site = make_site();
+    let cfg = site.state.config.lock().unwrap().clone();
+    let db = site.db();
+    let dir = site._dir.path();
+
+    let copy = write_post(
+        dir,
+        "copy",
+        serde_json::json!({"id": "publicpost", "title": "Copy", "date": "2024-03-01", "tags": []}),
+        "Copied.\n",
+    );
+    let message = build_content(&db, &cfg).unwrap_err().chain_message();
+    assert!(message.contains(r#"post id "publicpost""#));
+    assert!(message.contains(&copy.display().to_string()));
+    assert!(message.contains(&dir.join("posts/public").display().to_string()));
+
+    write_post(
+        dir,
+        "copy",
+        serde_json::json!({"title": "Copy", "date": "2024-03-01", "tags": [], "permalink": "hello"}),
+        "Copied.\n",
+    );
+    write_post(
+        dir,
+        "other",
+        serde_json::json!({"title": "Other", "date": "2024-03-02", "tags": [], "permalink": ["hello"]}),
+        "Other.\n",
+    );
+    let message = build_content(&db, &cfg).unwrap_err().chain_message();
+    assert!(message.contains(r#"post permalink "hello""#));
+    assert!(message.contains(&copy.display().to_string()));
+    assert!(message.contains(&dir.join("posts/other").display().to_string()));
+}
+
+#[tokio::test]
+async fn builds_only_write_post_ids_when_asked() {
+    let site = make_site();
+    let mut cfg = site.state.config.lock().unwrap().clone();
+    let db = site.db();
+    let dir = site._dir.path();
+
+    let meta = "{\n  \"title\": \"Notes\",\n  \"tags\": [\"Travel Notes\"],\n  \"date\": \"2024-03-01\"\n}\n";
+    fs::create_dir_all(dir.join("posts/notes")).unwrap();
+    fs::write(dir.join("posts/notes/meta.json"), meta).unwrap();
+    fs::write(dir.join("posts/notes/index.md"), "Notes.\n").unwrap();
+    let frontmatter = "---\ntitle: Trip\ndate: 2024-03-02\ntags: [Trip]\n---\nWent.\n";
+    fs::create_dir_all(dir.join("posts/trip")).unwrap();
+    fs::write(dir.join("posts/trip/index.md"), frontmatter).unwrap();
+
+    let notes_id = |db: &Database| {
+        Post::get_all(db)
+            .unwrap()
+            .into_iter()
+            .find(|post| post.title == "Notes")
+            .unwrap()
+            .id
+    };
+
+    build_content(&db, &cfg).unwrap();
+    let id = notes_id(&db);
+    build_content(&db, &cfg).unwrap();
+    assert_eq!(notes_id(&db), id);
+    assert_eq!(
+        Post::by_id(&db, &id).unwrap().get_tags(&db).unwrap(),
+        vec!["travel_notes".to_string()]
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("posts/notes/meta.json")).unwrap(),
+        meta
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("posts/trip/index.md")).unwrap(),
+        frontmatter
+    );
+
+    cfg.write_post_ids = true;
+    build_content(&db, &cfg).unwrap();
+    let id = notes_id(&db);
+    assert_eq!(
+        fs::read_to_string(dir.join("posts/notes/meta.json")).unwrap(),
+        meta.replacen("{\n", &format!("{{\n  \"id\": \"{}\",\n", id), 1)
+    );
+    let trip = fs::read_to_string(dir.join("posts/trip/index.md")).unwrap();
+    assert!(trip.starts_with("---\nid: \""));
+    assert!(trip.ends_with("title: Trip\ndate: 2024-03-02\ntags: [Trip]\n---\nWent.\n"));
+
+    // the written ids are kept
+    build_content(&db, &cfg).unwrap();
+    assert_eq!(notes_id(&db), id);
+}
@@ -0,0 +1,323 @@
+use axum::body::Body;
+use axum::http::Request;
+use tower::ServiceExt;
+
+use super::site::*;
+use crate::prelude::*;
+use crate::{build_content, make_router};
+
+#[tokio::test]
+async fn stylesheets_are_versioned_by_their_contents() {
+    let site = make_site();
+    let stylesheet = |body: &str| {
+        let start = body.find("/styles/page.css?v=").unwrap();
+        body[start..].split('"').next().unwrap().to_string()
+    };
+    let cache_control = |path: String| {
+        let router = make_router(site.state.clone());
+        async move {
+            let request = Request::get(path).body(Body::empty()).unwrap();
+            router
+                .oneshot(request)
+                .await
+                .unwrap()
+                .headers()
+                .get(ax::header::CACHE_CONTROL)
+                .map(|value| value.to_str().unwrap().to_string())
+        }
+    };
+
+    let (_, body) = site.get("/posts/", None).await;
+    let first = stylesheet(&body);
+    assert!(cache_control(first.clone())
+        .await
+        .unwrap()
+        .contains("immutable"));
+    assert_eq!(cache_control("/styles/page.css".to_string()).await, None);
+
+    fs::write(
+        site._dir.path().join("files/styles/page.css"),
+        "body { margin: 0 }",
+    )
+    .unwrap();
+    build_content(&site.db(), &site.state.config.lock().unwrap()).unwrap();
+
+    let (_, body) = site.get("/posts/", None).await;
+    let second = stylesheet(&body);
+    assert_ne!(first, second);
+    assert_eq!(cache_control(first).await, None);
+}
+
+#[test]
+fn identical_contents_are_stored_once() {
+    let site = make_site();
+    let db = site.db();
+    let count = |sql: &str| -> i64 { db.query_one(sql, [], |row| row.get(0)).unwrap() };
+
+    // every photo in the fixture is the same image
+    assert!(count("SELECT COUNT(*) FROM photos;") > 1);
+    assert_eq!(
+        count("SELECT COUNT(DISTINCT image_small_hash) FROM photos;"),
+        1
+    );
+    assert_eq!(
+        count("SELECT COUNT(*) FROM blobs;"),
+        count(
+            "SELECT COUNT(*) FROM (SELECT data_hash FROM files UNION SELECT image_large_hash FROM photos UNION SELECT image_small_hash FROM photos);"
+        )
+    );
+    assert!(Blob::find_corrupted(&db).unwrap().is_empty());
+
+    db.execute(
+        "UPDATE blobs SET data = x'00' WHERE hash IN (SELECT data_hash FROM files WHERE name = 'page.css');",
+        [],
+    )
+    .unwrap();
+    assert_eq!(Blob::find_corrupted(&db).unwrap().len(), 1);
+}
+
+#[test]
+fn unchanged_files_and_assets_are_kept_across_builds() {
+    let site = make_site();
+    let post_dir = write_post(
+        site._dir.path(),
+        "notes",
+        serde_json::json!({
+            "id": "notespost",
+            "title": "Notes",
+            "date": "2024-01-05",
+            "tags": [],
+        }),
+        "![diagram](assets/diagram.svg)\n",
+    );
+    fs::create_dir_all(post_dir.join("assets")).unwrap();
+    fs::write(post_dir.join("assets/diagram.svg"), "<svg></svg>").unwrap();
+    fs::write(site._dir.path().join("files/styles/extra.css"), "p {}").unwrap();
+    let cfg = site.state.config.lock().unwrap().clone();
+    build_content(&site.db(), &cfg).unwrap();
+
+    let db = site.db();
+    let file_id = |name: &str| -> Option<i64> {
+        db.query_mul("SELECT id FROM files WHERE name = ?;", [name], |row| {
+            row.get(0)
+        })
+        .unwrap()
+        .pop()
+    };
+    let asset_id = || -> i64 {
+        db.query_one(
+            "SELECT id FROM styles WHERE name = 'diagram.svg';",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap()
+    };
+    let (page, extra, diagram) = (file_id("page.css"), file_id("extra.css"), asset_id());
+
+    build_content(&db, &cfg).unwrap();
+    assert_eq!(file_id("page.css"), page);
+    assert_eq!(file_id("extra.css"), extra);
+    assert_eq!(asset_id(), diagram);
+    assert!(Asset::by_post_and_name(&db, "notespost", "diagram.svg").is_ok());
+
+    fs::write(
+        site._dir.path().join("files/styles/page.css"),
+        "body { margin: 0 }",
+    )
+    .unwrap();
+    fs::remove_file(site._dir.path().join("files/styles/extra.css")).unwrap();
+    build_content(&db, &cfg).unwrap();
+    assert_ne!(file_id("page.css"), page);
+    assert_eq!(
+        File::by_path_and_name(&db, "styles", "page.css")
+            .unwrap()
+            .get_data(&db)
+            .unwrap(),
+        b"body { margin: 0 }"
+    );
+    assert_eq!(file_id("extra.css"), None);
+    assert_eq!(asset_id(), diagram);
+}
+
+#[tokio::test]
+async fn files_in_subdirectories_are_served_under_their_path() {
+    let site = make_site();
+    let fonts = site._dir.path().join("files/files/downloads/fonts");
+    fs::create_dir_all(&fonts).unwrap();
+    fs::write(fonts.join("serif.woff2"), "font").unwrap();
+    fs::write(site._dir.path().join("files/files/notes.txt"), "notes").unwrap();
+    // linked files are served, linked directories aren't walked into
+    std::os::unix::fs::symlink(
+        site._dir.path().join("files/files/notes.txt"),
+        fonts.join("notes.txt"),
+    )
+    .unwrap();
+    std::os::unix::fs::symlink(site._dir.path().join("files"), fonts.join("loop")).unwrap();
+    build_content(&site.db(), &site.state.config.lock().unwrap()).unwrap();
+
+    let (status, body) = site.get("/files/downloads/fonts/notes.txt", None).await;
+    assert_eq!(status, ax::StatusCode::OK);
+    assert_eq!(body, "notes");
+    let (status, _) = site
+        .get("/files/downloads/fonts/loop/files/notes.txt", None)
+        .await;
+    assert_eq!(status, ax::StatusCode::NOT_FOUND);
+    let (status, body) = site.get("/files/downloads/fonts/serif.woff2", None).await;
+    assert_eq!(status, ax::StatusCode::OK);
+    assert_eq!(body, "font");
+    let (status, body) = site.get("/files/notes.txt", None).await;
+    assert_eq!(status, ax::StatusCode::OK);
+    assert_eq!(body, "notes");
+    let (status, _) = site.get("/files/downloads/serif.woff2", None).await;
+    assert_eq!(status, ax::StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn only_public_file_directories_are_listed() {
+    let site = make_site();
+    let (status, _) = site.get("/files/", None).await;
+    assert_eq!(status, ax::StatusCode::NOT_FOUND);
+
+    let files = site._dir.path().join("files/files");
+    fs::create_dir_all(files.join("downloads/fonts")).unwrap();
+    fs::create_dir_all(files.join("private")).unwrap();
+    fs::write(files.join("downloads/slides.pdf"), "0123456789").unwrap();
+    fs::write(files.join("downloads/fonts/serif.woff2"), "font").unwrap();
+    fs::write(files.join("downloads/a b#1%?.txt"), "odd").unwrap();
+    fs::write(files.join("private/taxes.pdf"), "taxes").unwrap();
+    let cfg = {
+        let mut cfg = site.state.config.lock().unwrap();
+        cfg.public_files = vec!["downloads".to_string()];
+        cfg.clone()
+    };
+    build_content(&site.db(), &cfg).unwrap();
+
+    let (status, body) = site.get("/files/", None).await;
+    assert_eq!(status, ax::StatusCode::OK);
+    assert!(body.contains("<h2>/files/downloads/</h2>"));
+    assert!(body.contains("<h2>/files/downloads/fonts/</h2>"));
+    assert!(body.contains("href=\"/files/downloads/slides.pdf\">slides.pdf</a>"));
+    assert!(body.contains("10 B"));
+    assert!(body.contains("/files/downloads/fonts/serif.woff2"));
+    assert!(!body.contains("taxes"));
+    assert!(body.find("slides.pdf").unwrap() < body.find("serif.woff2").unwrap());
+    assert!(body.contains("href=\"/files/downloads/a%20b%231%25%3F.txt\">a b#1%?.txt</a>"));
+    let (status, body) = site.get("/files/downloads/a%20b%231%25%3F.txt", None).await;
+    assert_eq!(status, ax::StatusCode::OK);
+    assert_eq!(body, "odd");
+}
+
+#[tokio::test]
+async fn downloads_are_served_as_attachments_under_their_name() {
+    let site = make_site();
+    let files = site._dir.path().join("files/files");
+    fs::create_dir_all(files.join("downloads")).unwrap();
+    fs::write(files.join("downloads/résumé.pdf"), "0123456789").unwrap();
+    fs::write(files.join("archive.ZIP"), "zip").unwrap();
+    fs::write(files.join("notes.txt"), "notes").unwrap();
+    let cfg = {
+        let mut cfg = site.state.config.lock().unwrap();
+        cfg.attachment_files = vec!["downloads".to_string(), ".zip".to_string()];
+        cfg.clone()
+    };
+    build_content(&site.db(), &cfg).unwrap();
+
+    let headers = |path: &'static str| {
+        let router = make_router(site.state.clone());
+        async move {
+            let request = Request::get(path).body(Body::empty()).unwrap();
+            let response = router.oneshot(request).await.unwrap();
+            let header = |name| {
+                response
+                    .headers()
+                    .get(name)
+                    .map(|value| value.to_str().unwrap().to_string())
+            };
+            (
+                header(ax::header::CONTENT_DISPOSITION),
+                header(ax::header::CONTENT_LENGTH),
+            )
+        }
+    };
+
+    let (disposition, length) = headers("/files/downloads/r%C3%A9sum%C3%A9.pdf").await;
+    assert_eq!(
+        disposition.as_deref(),
+        Some("attachment; filename=\"r_sum_.pdf\"; filename*=UTF-8''r%C3%A9sum%C3%A9.pdf")
+    );
+    assert_eq!(length.as_deref(), Some("10"));
+    let (disposition, _) = headers("/files/archive.ZIP").await;
+    assert!(disposition
+        .unwrap()
+        .starts_with("attachment; filename=\"archive.ZIP\""));
+    let (disposition, length) = headers("/files/notes.txt").await;
+    assert!(disposition
+        .unwrap()
+        .starts_with("inline; filename=\"notes.txt\""));
+    assert_eq!(length.as_deref(), Some("5"));
+    let (disposition, _) = headers("/styles/page.css").await;
+    assert_eq!(disposition, None);
+}
+
+#[tokio::test]
+async fn files_can_be_downloaded_in_parts() {
+    let site = make_site();
+    let files = site._dir.path().join("files/files");
+    fs::create_dir_all(&files).unwrap();
+    fs::write(files.join("video.mp4"), "0123456789").unwrap();
+    build_content(&site.db(), &site.state.config.lock().unwrap()).unwrap();
+
+    let get = |range: &'static str| {
+        let router = make_router(site.state.clone());
+        async move {
+            let request = Request::get("/files/video.mp4")
+                .header(ax::header::RANGE, range)
+                .body(Body::empty())
+                .unwrap();
+            let response = router.oneshot(request).await.unwrap();
+            let status = response.status();
+            let header = |name| {
+                response
+                    .headers()
+                    .get(name)
+                    .map(|value| value.to_str().unwrap().to_string())
+            };
+            let headers = (
+                header(ax::header::CONTENT_RANGE),
+                header(ax::header::CONTENT_LENGTH),
+                header(ax::header::CONTENT_DISPOSITION).is_some(),
+            );
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            (status, headers, String::from_utf8_lossy(&body).to_string())
+        }
+    };
+
+    let (status, (range, length, disposition), body) = get("bytes=2-5").await;
+    assert_eq!(status, ax::StatusCode::PARTIAL_CONTENT);
+    assert_eq!(range.as_deref(), Some("bytes 2-5/10"));
+    assert_eq!(length.as_deref(), Some("4"));
+    assert!(disposition);
+    assert_eq!(body, "2345");
+
+    let (status, _, body) = get("bytes=7-").await;
+    assert_eq!(status, ax::StatusCode::PARTIAL_CONTENT);
+    assert_eq!(body, "789");
+
+    let (status, (range, _, _), _) = get("bytes=20-").await;
+    assert_eq!(status, ax::StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(range.as_deref(), Some("bytes */10"));
+
+    let (status, (_, length, _), body) = get("bytes=0-1,4-5").await;
+    assert_eq!(status, ax::StatusCode::OK);
+    assert_eq!(length.as_deref(), Some("10"));
+    assert_eq!(body, "0123456789");
+
+    let (status, _, body) = get("bytes=5-3").await;
+    assert_eq!(status, ax::StatusCode::OK);
+    assert_eq!(body, "0123456789");
+
+    let (status, body) = site.get("/styles/page.css", None).await;
+    assert_eq!((status, body.as_str()), (ax::StatusCode::OK, "body {}"));
+}
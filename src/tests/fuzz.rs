@@ -0,0 +1,209 @@
+use proptest::prelude::*;
+
+use super::{test_config, TempDir};
+use crate::component::chart;
+use crate::component::post::PostMetadata;
+use crate::prelude::*;
+use crate::{schema, time};
+
+// pieces of markdown and shortcode syntax that tend to interact badly when mixed together
+const FRAGMENTS: &[&str] = &[
+    "![[photo:",
+    "]]",
+    "|",
+    "a.jpg",
+    "b.jpg",
+    "{{include ",
+    "{{poll poll}}",
+    "}}",
+    "assets/main.rs",
+    "~~~",
+    "$",
+    "$$",
+    "\\frac{",
+    "}",
+    "^",
+    "```math",
+    "---\n",
+    "+++\n",
+    "## ",
+    "### ",
+    "# ",
+    "\n",
+    "\n\n",
+    "    ",
+    "> ",
+    "- ",
+    "1. ",
+    "```",
+    "`",
+    "*",
+    "_",
+    "[",
+    "](",
+    "https://example.com/",
+    ")",
+    "<div>",
+    "</div>",
+    "<!--",
+    "|---|",
+    "é",
+    "🦀",
+    "\u{0}",
+    "\r",
+    "text",
+];
+
+fn markdown() -> impl Strategy<Value = String> {
+    prop_oneof![
+        any::<String>(),
+        prop::collection::vec(prop::sample::select(FRAGMENTS), 0..64)
+            .prop_map(|fragments| fragments.concat()),
+    ]
+}
+
+fn photos() -> Vec<Photo> {
+    ["a.jpg", "b.jpg"]
+        .iter()
+        .enumerate()
+        .map(|(i, name)| Photo {
+            id: format!("{:016x}", i),
+            mark: true,
+            is_private: false,
+            source_path: format!("posts/test/photos/{}", name),
+            source_time: 0,
+            allowed_group: None,
+            is_encrypted: false,
+        })
+        .collect()
+}
+
+fn metadata_json() -> impl Strategy<Value = String> {
+    let value = prop_oneof![
+        Just(serde_json::Value::Null),
+        any::<bool>().prop_map(serde_json::Value::from),
+        any::<i64>().prop_map(serde_json::Value::from),
+        any::<String>().prop_map(serde_json::Value::from),
+        prop::collection::vec(any::<String>(), 0..4).prop_map(serde_json::Value::from),
+    ];
+    let keys = prop::sample::select(
+        &[
+            "id",
+            "title",
+            "description",
+            "date",
+            "tags",
+            "permalink",
+            "private",
+            "allowed_group",
+            "math",
+            "expires",
+            "featured",
+            "featured_order",
+            "styles",
+            "scripts",
+            "polls",
+            "links_appendix",
+            "event",
+        ][..],
+    );
+
+    prop_oneof![
+        any::<String>(),
+        prop::collection::btree_map(keys.clone(), value.clone(), 0..8)
+            .prop_map(|fields| serde_json::json!(fields).to_string()),
+        // mostly valid metadata, so the rest of the build gets exercised too
+        prop::collection::btree_map(keys, value, 0..4).prop_map(|mut fields| {
+            fields.entry("title").or_insert("title".into());
+            fields.entry("date").or_insert("2024-01-01".into());
+            fields.entry("tags").or_insert(serde_json::json!([]));
+            serde_json::json!(fields).to_string()
+        }),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn markdown_never_panics(markdown in markdown()) {
+        let photos = photos();
+        let ctx = MarkdownContext {
+            photos: photos.iter().collect(),
+            math: true,
+            lite: true,
+            polls: HashMap::from([("poll".to_string(), "<div></div>".to_string())]),
+            links_appendix: true,
+            alt_texts: HashMap::from([("a.jpg".to_string(), "alt".to_string())]),
+            post_id: Some("post"),
+            assets_path: "assets",
+        };
+        markdown_to_html(&markdown, &ctx).unwrap();
+        markdown_to_html(&markdown, &MarkdownContext::default()).unwrap();
+    }
+
+    #[test]
+    fn links_appendix_lists_each_external_link_once(markdown in markdown()) {
+        let ctx = MarkdownContext { links_appendix: true, ..Default::default() };
+        let html = markdown_to_html(&markdown, &ctx).unwrap();
+        let external = markdown_links(&markdown)
+            .into_iter()
+            .filter(|link| link.starts_with("http://") || link.starts_with("https://"))
+            .count();
+        let listed = html
+            .split_once("<section class=\"links-appendix\">")
+            .map_or(0, |(_, appendix)| appendix.matches("<li>").count());
+        prop_assert_eq!(listed, external);
+    }
+
+    #[test]
+    fn shortcode_names_are_trimmed(markdown in markdown()) {
+        for name in photo_shortcode_names(&markdown) {
+            prop_assert_eq!(name, name.trim());
+        }
+    }
+
+    #[test]
+    fn metadata_never_panics(json in metadata_json()) {
+        let _ = PostMetadata::from_json_str(&json);
+    }
+
+    #[test]
+    fn dates_never_panic(date in any::<String>(), tz in prop::sample::select(&["UTC", "Asia/Tokyo", "America/Los_Angeles"][..])) {
+        let _ = time::parse_date(&date, tz.parse().unwrap());
+    }
+
+    #[test]
+    fn charts_never_panic(values in prop::collection::vec(any::<i64>(), 0..16)) {
+        let labels = values.iter().map(|value| value.to_string()).collect::<Vec<_>>();
+        chart::bar_chart(&labels, &values);
+        chart::sparkline("values", &values);
+    }
+}
+
+proptest! {
+    // every case builds a real post, so keep the count low
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn posts_never_panic(json in metadata_json(), markdown in markdown(), frontmatter in any::<bool>()) {
+        let temp = TempDir::new();
+        let post_path = temp.path().join("posts/test");
+        fs::create_dir_all(&post_path).unwrap();
+
+        // json is valid yaml, so the same metadata also works as frontmatter
+        if frontmatter {
+            fs::write(post_path.join("index.md"), format!("---\n{}\n---\n{}", json, markdown)).unwrap();
+        } else {
+            fs::write(post_path.join("meta.json"), &json).unwrap();
+            fs::write(post_path.join("index.md"), &markdown).unwrap();
+        }
+
+        let config = test_config(temp.path());
+        let db = Database::open(&config).unwrap();
+        schema::migrate(&db).unwrap();
+
+        if let Ok(post) = Post::new(&db, &config, &post_path) {
+            let source = post.get_source(&db).unwrap();
+            markdown_to_html(&source, &MarkdownContext::default()).unwrap();
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use super::site::*;
+use super::TempDir;
+use crate::build_content;
+use crate::prelude::*;
+
+#[tokio::test]
+async fn hugo_posts_are_imported_with_their_images_and_old_urls() {
+    let site = make_site();
+    let hugo = TempDir::new();
+    let bundle = hugo.path().join("content/posts/trip");
+    write_photo(&bundle.join("beach.jpg"));
+    fs::write(
+        bundle.join("index.md"),
+        "---\ntitle: A trip\ndate: 2020-05-01T10:30:00+02:00\ntags: [Travel, Photos]\naliases: [/2020/05/trip.html]\n---\nWe went.\n\n![The beach](beach.jpg)\n\n{{< figure src=\"beach.jpg\" caption=\"Again\" >}}\n\n```\n![kept](beach.jpg)\n```\n",
+    )
+    .unwrap();
+    fs::create_dir_all(hugo.path().join("static/images")).unwrap();
+    fs::write(hugo.path().join("static/images/plan.svg"), "<svg></svg>").unwrap();
+    fs::write(
+        hugo.path().join("content/posts/notes.md"),
+        "+++\ntitle = \"Notes\"\ndate = 2021-03-04\ndraft = true\ncategories = [\"misc\"]\n+++\nSee ![the plan](/images/plan.svg).\n",
+    )
+    .unwrap();
+    fs::write(
+        hugo.path().join("content/posts/_index.md"),
+        "---\ntitle: Posts\n---\n",
+    )
+    .unwrap();
+
+    let cfg = site.state.config.lock().unwrap().clone();
+    let imported =
+        crate::import::import_site(&cfg, crate::import::Generator::Hugo, hugo.path()).unwrap();
+    assert_eq!(imported.len(), 2);
+
+    let trip = fs::read_to_string(site._dir.path().join("posts/trip/index.md")).unwrap();
+    assert!(trip.contains("![[photo:beach.jpg|The beach]]"));
+    assert!(trip.contains("![[photo:beach.jpg|Again]]"));
+    assert!(trip.contains("![kept](beach.jpg)"));
+    let meta: serde_json::Value = serde_json::from_str(
+        &fs::read_to_string(site._dir.path().join("posts/trip/meta.json")).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(meta["date"], "2020-05-01T10:30");
+    assert_eq!(meta["tags"], serde_json::json!(["travel", "photos"]));
+    assert_eq!(meta["permalink"], serde_json::json!("trip"));
+
+    let notes = site._dir.path().join("posts/notes");
+    assert!(notes.join("assets/plan.svg").exists());
+    assert!(fs::read_to_string(notes.join("index.md"))
+        .unwrap()
+        .contains("![the plan](assets/plan.svg)"));
+
+    // importing twice doesn't overwrite the first import
+    assert!(crate::import::import_site(&cfg, crate::import::Generator::Hugo, hugo.path()).is_err());
+
+    build_content(&site.db(), &site.state.config.lock().unwrap()).unwrap();
+    let (status, _) = site.get("/posts/trip/", None).await;
+    assert_eq!(status, ax::StatusCode::MOVED_PERMANENTLY);
+    let (status, body) = site.get("/posts/a-trip/", None).await;
+    assert_eq!(status, ax::StatusCode::OK);
+    assert!(body.contains("We went."));
+    let (status, _) = site.get("/posts/notes/", None).await;
+    assert_eq!(status, ax::StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn content_exports_to_hugo_leave_out_hidden_content_and_import_again() {
+    use crate::import::Generator;
+
+    let site = make_site();
+    let cfg = site.state.config.lock().unwrap().clone();
+    let out = TempDir::new();
+    crate::export_content::export_content_site(
+        &site.db(),
+        &cfg,
+        Generator::Hugo,
+        false,
+        out.path(),
+    )
+    .unwrap();
+
+    let bundle = out.path().join("content/posts/public-post");
+    let post = fs::read_to_string(bundle.join("index.md")).unwrap();
+    assert!(post.starts_with("---\ntitle: Public post\ndate: 2024-01-01\n"));
+    assert!(post.contains("slug: public-post"));
+    assert!(post.contains("- /posts/publicpost/"));
+    assert!(post.contains("![public](public.jpg)"));
+    assert!(!post.contains("secret"));
+    assert!(bundle.join("public.jpg").exists());
+    assert!(!bundle.join("secret.jpg").exists());
+    for name in ["private-post", "family-post"] {
+        assert!(
+            !out.path().join("content/posts").join(name).exists(),
+            "{}",
+            name
+        );
+    }
+
+    let all = TempDir::new();
+    crate::export_content::export_content_site(
+        &site.db(),
+        &cfg,
+        Generator::Jekyll,
+        true,
+        all.path(),
+    )
+    .unwrap();
+    let private = fs::read_to_string(all.path().join("_posts/2024-01-02-private-post.md")).unwrap();
+    assert!(private.contains("published: false"));
+    assert!(private.contains("permalink: /posts/private-post/"));
+    assert!(all.path().join("assets/private-post/inner.jpg").exists());
+
+    // the export is a site `import` understands
+    let other = make_site();
+    let mut other_cfg = other.state.config.lock().unwrap().clone();
+    other_cfg.posts_path = other
+        ._dir
+        .path()
+        .join("imported")
+        .to_str()
+        .unwrap()
+        .to_string();
+    crate::import::import_site(&other_cfg, Generator::Hugo, out.path()).unwrap();
+    let meta =
+        fs::read_to_string(other._dir.path().join("imported/public-post/meta.json")).unwrap();
+    assert!(meta.contains("\"title\": \"Public post\""));
+    assert!(meta.contains("\"publicpost\""));
+    let markdown =
+        fs::read_to_string(other._dir.path().join("imported/public-post/index.md")).unwrap();
+    assert!(markdown.contains("![[photo:public.jpg|public]]"));
+}
@@ -0,0 +1,313 @@
+use axum::body::Body;
+use axum::http::Request;
+use base64::Engine;
+use tower::ServiceExt;
+
+use super::site::*;
+use crate::config::MapConfig;
+use crate::prelude::*;
+use crate::{build_content, make_router};
+
+#[tokio::test]
+async fn post_pdfs_are_rendered_once_and_respect_privacy() {
+    let site = make_site();
+
+    let (status, body) = site.get("/posts/public-post/pdf", None).await;
+    assert_eq!(status, ax::StatusCode::OK);
+    assert!(body.starts_with("%PDF"));
+    assert!(body.contains("(Public post)"));
+    assert!(site
+        .get("/posts/public-post/", None)
+        .await
+        .1
+        .contains("/posts/public-post/pdf"));
+
+    assert_eq!(
+        site.get("/posts/private-post/pdf", None).await.0,
+        ax::StatusCode::NOT_FOUND
+    );
+    let friends = site.login(FRIENDS_KEY).await;
+    assert_eq!(
+        site.get("/posts/private-post/pdf", Some(&friends)).await.0,
+        ax::StatusCode::OK
+    );
+
+    // stored in the background
+    let count = || {
+        site.db()
+            .query_one("SELECT COUNT(*) FROM pdf_cache;", [], |row| {
+                row.get::<_, i64>(0)
+            })
+            .unwrap()
+    };
+    for _ in 0..50 {
+        if count() == 2 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    assert_eq!(count(), 2);
+    assert_eq!(site.get("/posts/public-post/pdf", None).await.1, body);
+    assert_eq!(count(), 2);
+}
+
+#[tokio::test]
+async fn episodes_play_their_audio_and_attach_it_to_the_feed() {
+    let site = make_site();
+    let post_dir = write_post(
+        site._dir.path(),
+        "episode",
+        serde_json::json!({
+            "id": "episodepost",
+            "title": "Episode",
+            "date": "2024-01-06",
+            "tags": [],
+            "audio": "episode.mp3",
+        }),
+        "Show notes.\n",
+    );
+    fs::create_dir_all(post_dir.join("assets")).unwrap();
+    fs::write(post_dir.join("assets/episode.mp3"), b"0123456789").unwrap();
+    build_content(&site.db(), &site.state.config.lock().unwrap()).unwrap();
+
+    let (_, body) = site.get("/posts/episode/", None).await;
+    assert!(body.contains("src=\"/posts/episodepost/assets/episode.mp3\""));
+
+    let (_, feed) = site.get("/posts/feed.xml", None).await;
+    assert!(
+        feed.contains("/posts/episodepost/assets/episode.mp3\" length=\"10\" type=\"audio/mpeg\"")
+    );
+
+    let range = |range: &'static str| {
+        let request = Request::get("/posts/episodepost/assets/episode.mp3")
+            .header(ax::header::RANGE, range)
+            .body(Body::empty())
+            .unwrap();
+        let router = make_router(site.state.clone());
+        async move {
+            let response = router.oneshot(request).await.unwrap();
+            let status = response.status();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            (status, String::from_utf8_lossy(&body).to_string())
+        }
+    };
+    assert_eq!(
+        range("bytes=2-4").await,
+        (ax::StatusCode::PARTIAL_CONTENT, "234".to_string())
+    );
+    assert_eq!(
+        range("bytes=-3").await,
+        (ax::StatusCode::PARTIAL_CONTENT, "789".to_string())
+    );
+    assert_eq!(
+        range("bytes=20-").await.0,
+        ax::StatusCode::RANGE_NOT_SATISFIABLE
+    );
+
+    // the audio has to be one of the assets
+    fs::remove_file(post_dir.join("assets/episode.mp3")).unwrap();
+    assert!(build_content(&site.db(), &site.state.config.lock().unwrap()).is_err());
+}
+
+#[tokio::test]
+async fn gpx_tracks_are_shown_with_their_stats() {
+    let site = make_site_with(|config| {
+        config.map = Some(MapConfig {
+            tile_url: "https://tiles.example.com/{z}/{x}/{y}.png".to_string(),
+            attribution: "Example".to_string(),
+        })
+    });
+    let post_dir = write_post(
+        site._dir.path(),
+        "hike",
+        serde_json::json!({
+            "id": "hikepost",
+            "title": "Hike",
+            "date": "2024-01-07",
+            "tags": [],
+        }),
+        "Up and down.\n",
+    );
+    fs::create_dir_all(post_dir.join("assets")).unwrap();
+    // about 1.1 km north and back east, climbing 100 m, with a point in the middle of the line
+    fs::write(
+        post_dir.join("assets/hike.gpx"),
+        r#"<?xml version="1.0"?>
+        <gpx version="1.1" xmlns="http://www.topografix.com/GPX/1/1">
+            <trk><trkseg>
+                <trkpt lat="46.000" lon="7.000"><ele>1000</ele></trkpt>
+                <trkpt lat="46.005" lon="7.000"><ele>1050</ele></trkpt>
+                <trkpt lat="46.010" lon="7.000"><ele>1100</ele></trkpt>
+                <trkpt lat="46.010" lon="7.010"><ele>1090</ele></trkpt>
+            </trkseg></trk>
+        </gpx>"#,
+    )
+    .unwrap();
+    build_content(&site.db(), &site.state.config.lock().unwrap()).unwrap();
+
+    let (_, body) = site.get("/posts/hike/", None).await;
+    assert!(body.contains("hike.gpx · 1.9 km · 100 m climb"));
+    assert!(body.contains("<polyline"));
+    assert!(body.contains("data-tiles=\"https://tiles.example.com/{z}/{x}/{y}.png\""));
+    // the middle point is on the line and dropped
+    assert!(body.contains("data-points=\"[[46.0,7.0],[46.01,7.0],[46.01,7.01]]\""));
+    assert!(body.contains("/scripts/track.js"));
+
+    // lite pages keep the svg without scripts
+    let (_, lite) = site.get("/posts/hike/?lite=1", None).await;
+    assert!(lite.contains("<polyline"));
+    assert!(!lite.contains("data-points"));
+
+    fs::write(post_dir.join("assets/hike.gpx"), "<gpx></gpx>").unwrap();
+    assert!(build_content(&site.db(), &site.state.config.lock().unwrap()).is_err());
+}
+
+#[tokio::test]
+async fn photo_map_shows_geotagged_photos_only_to_who_may_see_them() {
+    let site = make_site_with(|config| {
+        config.map = Some(MapConfig {
+            tile_url: "https://tiles.example.com/{z}/{x}/{y}.png".to_string(),
+            attribution: "Example".to_string(),
+        })
+    });
+    let public_post = site._dir.path().join("posts/public");
+    geotag_photo(&public_post.join("photos/public.jpg"), 46.5, -7.25);
+    geotag_photo(&public_post.join("private/secret.jpg"), -33.9, 151.2);
+    build_content(&site.db(), &site.state.config.lock().unwrap()).unwrap();
+
+    let public = site.photo_id("public.jpg");
+    let secret = site.photo_id("secret.jpg");
+
+    let (status, body) = site.get("/photos/map/", None).await;
+    assert_eq!(status, ax::StatusCode::OK);
+    assert!(body.contains("(46.5000, -7.2500)"));
+    assert!(body.contains(&public));
+    assert!(!body.contains(&secret));
+    assert!(body.contains("/scripts/photo-map.js"));
+
+    let friends = site.login(FRIENDS_KEY).await;
+    let (_, body) = site.get("/photos/map/", Some(&friends)).await;
+    assert!(body.contains(&secret));
+    assert!(body.contains("(-33.9000, 151.2000)"));
+
+    // not taken for the id of a photo without the slash
+    let (status, _) = site.get("/photos/map", None).await;
+    assert_ne!(status, ax::StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn notebooks_are_rendered_with_their_outputs() {
+    let site = make_site();
+    let post_dir = write_post(
+        site._dir.path(),
+        "notebook",
+        serde_json::json!({
+            "id": "notebookpost",
+            "title": "Notebook",
+            "date": "2024-01-08",
+            "tags": [],
+        }),
+        "",
+    );
+    fs::remove_file(post_dir.join("index.md")).unwrap();
+
+    let mut png = std::io::Cursor::new(vec![]);
+    image::RgbImage::from_pixel(4, 4, image::Rgb([0, 0, 0]))
+        .write_to(&mut png, image::ImageFormat::Png)
+        .unwrap();
+    let png = base64::engine::general_purpose::STANDARD.encode(png.into_inner());
+    let notebook = serde_json::json!({
+        "nbformat": 4,
+        "nbformat_minor": 5,
+        "metadata": {"language_info": {"name": "python"}},
+        "cells": [
+            {"cell_type": "markdown", "metadata": {}, "source": ["# Reads\n", "Counting reads."]},
+            {
+                "cell_type": "code",
+                "metadata": {},
+                "execution_count": 1,
+                "source": "print(len(reads))",
+                "outputs": [
+                    {"output_type": "stream", "name": "stdout", "text": ["1204\n"]},
+                    {"output_type": "display_data", "metadata": {}, "data": {"image/png": png, "text/plain": "<Figure>"}},
+                    {"output_type": "error", "ename": "KeyError", "evalue": "'x'", "traceback": ["\u{1b}[0;31mKeyError\u{1b}[0m: 'x'"]},
+                ],
+            },
+        ],
+    });
+    fs::write(post_dir.join("index.ipynb"), notebook.to_string()).unwrap();
+    build_content(&site.db(), &site.state.config.lock().unwrap()).unwrap();
+
+    let (status, body) = site.get("/posts/notebook/", None).await;
+    assert_eq!(status, ax::StatusCode::OK);
+    assert!(body.contains("Counting reads."));
+    assert!(body.contains("language-python"));
+    assert!(body.contains("print(len(reads))"));
+    assert!(body.contains("1204"));
+    assert!(body.contains("KeyError: 'x'"));
+    assert!(!body.contains("&lt;Figure&gt;"));
+
+    let image = body
+        .split("src=\"/posts/notebookpost/assets/")
+        .nth(1)
+        .and_then(|rest| rest.split('"').next())
+        .unwrap()
+        .to_string();
+    let (status, _) = site
+        .get(&format!("/posts/notebookpost/assets/{}", image), None)
+        .await;
+    assert_eq!(status, ax::StatusCode::OK);
+}
+
+#[tokio::test]
+async fn org_and_asciidoc_posts_are_rendered_like_markdown() {
+    let site = make_site();
+    for (name, file, source) in [
+        (
+            "org",
+            "index.org",
+            "#+TITLE: ignored\n* Results :lab:\nReads were *counted* with /care/, see [[https://example.com][the docs]] and =wc -l=.\n+ first\n+ second\n#+BEGIN_SRC python\n  print(1)\n#+END_SRC\n",
+        ),
+        (
+            "adoc",
+            "index.adoc",
+            ":toc:\n== Results\nReads were *counted* with _care_, see https://example.com[the docs] and `wc -l`.\n* first\n** nested\n\n[source,python]\n----\nprint(1)\n----\nNOTE: keep the raw reads.\n",
+        ),
+    ] {
+        let post_dir = write_post(
+            site._dir.path(),
+            name,
+            serde_json::json!({"id": format!("{}post", name), "title": name, "date": "2024-01-09", "tags": []}),
+            "",
+        );
+        fs::remove_file(post_dir.join("index.md")).unwrap();
+        fs::write(post_dir.join(file), source).unwrap();
+    }
+    build_content(&site.db(), &site.state.config.lock().unwrap()).unwrap();
+
+    for name in ["org", "adoc"] {
+        let (status, body) = site.get(&format!("/posts/{}/", name), None).await;
+        assert_eq!(status, ax::StatusCode::OK, "{}", name);
+        assert!(body.contains("<h2 id=\"results\""), "{}", name);
+        assert!(body.contains("<strong>counted</strong>"), "{}", name);
+        assert!(body.contains("<em>care</em>"), "{}", name);
+        assert!(
+            body.contains("<a href=\"https://example.com\">the docs</a>"),
+            "{}",
+            name
+        );
+        assert!(body.contains("<code>wc -l</code>"), "{}", name);
+        assert!(
+            body.contains("<li>first</li>") || body.contains("<li>first"),
+            "{}",
+            name
+        );
+        assert!(body.contains("language-python"), "{}", name);
+        assert!(!body.contains("ignored") && !body.contains(":toc:") && !body.contains(":lab:"));
+    }
+
+    let (_, body) = site.get("/posts/adoc/", None).await;
+    assert!(body.contains("<strong>Note:</strong> keep the raw reads."));
+}
@@ -0,0 +1,62 @@
+mod accounts;
+mod activitypub;
+mod api;
+mod build;
+mod files;
+mod fuzz;
+mod import_export;
+mod media;
+mod pages;
+mod posts;
+mod privacy;
+mod server;
+mod site;
+
+use std::path::PathBuf;
+
+use crate::prelude::*;
+
+// a scratch directory that is removed again when the test finishes
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new() -> Self {
+        let path =
+            std::env::temp_dir().join(format!("website-test-{:016x}", rand::random::<u64>()));
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn test_config(dir: &Path) -> Config {
+    Config::from_json_str(
+        &serde_json::json!({
+            "database_path": dir.join("database.sqlite"),
+            "posts_path": dir.join("posts"),
+            "files_path": dir.join("files"),
+            "post_content_path": "index.md",
+            "post_metadata_path": "meta.json",
+            "post_assets_path": "assets",
+            "post_public_photos_path": "photos",
+            "post_private_photos_path": "private",
+            "photo_max_preview_size": 16,
+            "photo_quality": 50,
+            "server_host": "127.0.0.1",
+            "server_port": 0,
+            // left out like in configs from before it existed, the default is the same
+            "photos_per_page": 100,
+        })
+        .to_string(),
+    )
+    .unwrap()
+}
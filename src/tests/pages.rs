@@ -0,0 +1,245 @@
+use axum::body::Body;
+use axum::http::Request;
+use tower::ServiceExt;
+
+use super::site::*;
+use super::test_config;
+use crate::prelude::*;
+use crate::{build_content, make_router};
+
+#[tokio::test]
+async fn themes_are_remembered_and_cached_apart() {
+    let site = make_site();
+
+    let (_, body) = site.get("/posts/", None).await;
+    assert!(body.contains("<html>"));
+    assert!(body.contains("/styles/theme.css?v="));
+    let (_, body) = site.get("/posts/", Some("theme=dark")).await;
+    assert!(body.contains("<html class=\"theme-dark\">"));
+    let (_, body) = site.get("/posts/", None).await;
+    assert!(body.contains("<html>"));
+
+    let request = Request::get("/posts/?theme=light")
+        .body(Body::empty())
+        .unwrap();
+    let response = make_router(site.state.clone())
+        .oneshot(request)
+        .await
+        .unwrap();
+    let set_cookie = response.headers().get(ax::header::SET_COOKIE).unwrap();
+    assert!(set_cookie.to_str().unwrap().starts_with("theme=light"));
+
+    let (status, css) = site.get("/styles/theme.css", None).await;
+    assert_eq!(status, ax::StatusCode::OK);
+    assert!(css.contains("prefers-color-scheme: dark"));
+}
+
+#[tokio::test]
+async fn configured_projects_are_listed_as_cards() {
+    let site = make_site();
+    let (_, body) = site.get("/projects/", None).await;
+    assert!(!body.contains("class=\"project\""));
+
+    let site = make_site_with(|config| {
+        config.projects = vec![
+            crate::config::ProjectConfig {
+                name: "Website".to_string(),
+                url: "https://example.com/website".to_string(),
+                description: "This site".to_string(),
+                language: Some("Rust".to_string()),
+            },
+            crate::config::ProjectConfig {
+                name: "Dotfiles".to_string(),
+                url: "https://example.com/dotfiles".to_string(),
+                description: String::new(),
+                language: None,
+            },
+        ]
+    });
+    let (status, body) = site.get("/projects/", None).await;
+    assert_eq!(status, ax::StatusCode::OK);
+    assert!(body.contains("href=\"https://example.com/website\""));
+    assert!(body.contains("Rust"));
+    assert!(body.find("Website").unwrap() < body.find("Dotfiles").unwrap());
+    assert!(body.contains("/styles/projects.css"));
+}
+
+#[test]
+fn pinned_projects_need_a_token() {
+    let mut config: serde_json::Value =
+        serde_json::from_str(&serde_json::to_string(&test_config(Path::new("/tmp"))).unwrap())
+            .unwrap();
+    config["github_projects"] = serde_json::json!({ "user": "someone" });
+    assert!(Config::from_json_str(&config.to_string()).is_err());
+
+    config["github_projects"]["repositories"] = serde_json::json!("starred");
+    assert!(Config::from_json_str(&config.to_string()).is_ok());
+}
+
+#[tokio::test]
+async fn contact_messages_are_kept_for_admins() {
+    let site = make_site();
+    assert_eq!(
+        site.get("/contact/", None).await.0,
+        ax::StatusCode::NOT_FOUND
+    );
+
+    let site = make_site_with(|config| {
+        config.contact = Some(crate::config::ContactConfig {
+            to: "me@example.com".to_string(),
+            smtp: None,
+        })
+    });
+    User::new(&site.db(), "admin-key", "admin").unwrap();
+    let friends = site.login(FRIENDS_KEY).await;
+    let admin = site.login("admin-key").await;
+
+    let (status, body) = site.get("/contact/", None).await;
+    assert_eq!(status, ax::StatusCode::OK);
+    assert!(body.contains("name=\"email\""));
+
+    let started = chrono::Utc::now().timestamp() - 60;
+    let message = format!(
+        "name=Ann&email=ann%40example.com&body=Hello+there&started={}",
+        started
+    );
+    assert_eq!(
+        site.post("/contact/", None, &format!("{}&website=spam", message))
+            .await,
+        ax::StatusCode::BAD_REQUEST
+    );
+    assert_eq!(
+        site.post("/contact/", None, &message.replace("ann%40", "ann"))
+            .await,
+        ax::StatusCode::BAD_REQUEST
+    );
+    assert_eq!(
+        site.post("/contact/", None, &message).await,
+        ax::StatusCode::SEE_OTHER
+    );
+
+    for cookie in [None, Some(friends.as_str())] {
+        let (status, body) = site.get("/admin/messages/", cookie).await;
+        assert_eq!(status, ax::StatusCode::NOT_FOUND);
+        assert!(!body.contains("Hello there"));
+    }
+    let (status, body) = site.get("/admin/messages/", Some(&admin)).await;
+    assert_eq!(status, ax::StatusCode::OK);
+    assert!(body.contains("Hello there"));
+    assert!(body.contains("ann@example.com"));
+    assert!(body.contains("not mailed"));
+}
+
+#[tokio::test]
+async fn markdown_pages_are_served_by_name() {
+    let site = make_site_with(|config| {
+        let pages = Path::new(&config.posts_path)
+            .parent()
+            .unwrap()
+            .join("pages");
+        fs::create_dir_all(&pages).unwrap();
+        fs::write(pages.join("about.md"), "# About me\n\nI write code.\n").unwrap();
+        fs::write(pages.join("uses.md"), "A keyboard.\n").unwrap();
+        config.pages_path = Some(pages.to_string_lossy().to_string());
+    });
+
+    let (status, body) = site.get("/about/", None).await;
+    assert_eq!(status, ax::StatusCode::OK);
+    assert!(body.contains("<title>Kai - About me</title>"));
+    assert_eq!(body.matches("About me").count(), 2);
+    assert!(body.contains("I write code."));
+
+    let (status, body) = site.get("/uses/", None).await;
+    assert_eq!(status, ax::StatusCode::OK);
+    assert!(body.contains("<h1>Uses</h1>"));
+
+    let (_, body) = site.get("/", None).await;
+    assert_eq!(body.matches("I write code.").count(), 1);
+
+    // an intro of its own replaces the about page on the index
+    let intro = site._dir.path().join("intro.md");
+    fs::write(&intro, "Hi there.\n").unwrap();
+    let mut cfg = site.state.config.lock().unwrap().clone();
+    cfg.intro_path = Some(intro.to_string_lossy().to_string());
+    build_content(&site.db(), &cfg).unwrap();
+    let (_, body) = site.get("/", None).await;
+    assert!(body.contains("Hi there."));
+    assert!(!body.contains("I write code."));
+    assert_eq!(
+        site.get("/nothing/", None).await.0,
+        ax::StatusCode::NOT_FOUND
+    );
+
+    let pages = site._dir.path().join("pages");
+    fs::write(pages.join("posts.md"), "Not a page.\n").unwrap();
+    assert!(build_content(&site.db(), &site.state.config.lock().unwrap()).is_err());
+}
+
+#[tokio::test]
+async fn now_and_cv_pages_come_from_their_sources() {
+    let site = make_site();
+    assert_eq!(site.get("/now/", None).await.0, ax::StatusCode::NOT_FOUND);
+    assert_eq!(site.get("/cv/", None).await.0, ax::StatusCode::NOT_FOUND);
+
+    let site = make_site_with(|config| {
+        let dir = Path::new(&config.posts_path)
+            .parent()
+            .unwrap()
+            .to_path_buf();
+        fs::write(dir.join("now.md"), "Learning Japanese.\n").unwrap();
+        fs::write(
+            dir.join("cv.toml"),
+            r#"
+                name = "Kai"
+                headline = "Software engineer"
+
+                [[work]]
+                title = "Engineer"
+                place = "Somewhere Inc."
+                start = "2022"
+                highlights = ["Built things"]
+
+                [[skills]]
+                name = "Languages"
+                items = ["Rust", "Python"]
+            "#,
+        )
+        .unwrap();
+        config.now_path = Some(dir.join("now.md").to_string_lossy().to_string());
+        config.cv_path = Some(dir.join("cv.toml").to_string_lossy().to_string());
+    });
+
+    let (status, body) = site.get("/now/", None).await;
+    assert_eq!(status, ax::StatusCode::OK);
+    assert!(body.contains("<h1>Now</h1>"));
+    assert!(body.contains("Learning Japanese."));
+
+    let (status, body) = site.get("/cv/", None).await;
+    assert_eq!(status, ax::StatusCode::OK);
+    assert!(body.contains("Somewhere Inc."));
+    assert!(body.contains("2022 – present"));
+    assert!(body.contains("Rust, Python"));
+    assert!(body.contains("/styles/cv.css"));
+
+    fs::write(site._dir.path().join("cv.toml"), "name = 1\n").unwrap();
+    assert!(build_content(&site.db(), &site.state.config.lock().unwrap()).is_err());
+}
+
+#[tokio::test]
+async fn lite_switch_links_keep_the_rest_of_the_query() {
+    let site = make_site();
+
+    let (_, body) = site.get("/posts/", None).await;
+    assert!(body.contains("href=\"?lite=1\""));
+
+    let (_, body) = site.get("/posts/?tag=project&page=1", None).await;
+    assert!(body.contains("href=\"?tag=project&amp;page=1&amp;lite=1\""));
+
+    let (_, body) = site.get("/posts/?lite=1&tag=project", None).await;
+    assert!(body.contains("href=\"?tag=project&amp;lite=0\""));
+
+    // the theme switch too
+    let (_, body) = site.get("/posts/?tag=project&theme=dark", None).await;
+    assert!(body.contains("href=\"?tag=project&amp;theme=light\""));
+    assert!(body.contains("href=\"?tag=project&amp;theme=dark&amp;lite=1\""));
+}
@@ -0,0 +1,510 @@
+use axum::body::Body;
+use axum::http::Request;
+use tower::ServiceExt;
+
+use super::site::*;
+use crate::prelude::*;
+use crate::{build_content, make_router};
+
+#[tokio::test]
+async fn ids_only_redirect_to_slugs_of_readable_posts() {
+    let site = make_site();
+    let friends = site.login(FRIENDS_KEY).await;
+
+    let redirect = |path: &'static str, cookie: Option<String>| {
+        let state = site.state.clone();
+        async move {
+            let mut request = Request::get(path);
+            if let Some(cookie) = cookie {
+                request = request.header(ax::header::COOKIE, cookie);
+            }
+            let response = make_router(state)
+                .oneshot(request.body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            let location = response
+                .headers()
+                .get(ax::header::LOCATION)
+                .map(|location| location.to_str().unwrap().to_string());
+            (response.status(), location)
+        }
+    };
+
+    assert_eq!(
+        redirect("/posts/publicpost/", None).await,
+        (
+            ax::StatusCode::MOVED_PERMANENTLY,
+            Some("/posts/public-post/".to_string())
+        )
+    );
+    assert_eq!(
+        redirect("/posts/privatepost/", None).await,
+        (ax::StatusCode::NOT_FOUND, None)
+    );
+    assert_eq!(
+        redirect("/posts/privatepost/", Some(friends)).await,
+        (
+            ax::StatusCode::MOVED_PERMANENTLY,
+            Some("/posts/private-post/".to_string())
+        )
+    );
+}
+
+#[tokio::test]
+async fn slugs_stay_put_across_renames_and_backdated_posts() {
+    let site = make_site();
+    let dir = site._dir.path();
+    let cfg = site.state.config.lock().unwrap().clone();
+
+    // an older post with the same title doesn't take the slug
+    write_post(
+        dir,
+        "older",
+        serde_json::json!({
+            "id": "olderpost",
+            "title": "Public post",
+            "date": "2023-01-01",
+            "tags": [],
+        }),
+        "Older.\n",
+    );
+    build_content(&site.db(), &cfg).unwrap();
+    let slug = |id: &str| Post::by_id(&site.db(), id).unwrap().slug;
+    assert_eq!(slug("publicpost"), "public-post");
+    assert_eq!(slug("olderpost"), "public-post-2");
+
+    // a renamed post moves, its old url redirects instead of being gone
+    let meta = dir.join("posts/public/meta.json");
+    let renamed = fs::read_to_string(&meta)
+        .unwrap()
+        .replace("\"Public post\"", "\"Renamed post\"");
+    fs::write(&meta, renamed).unwrap();
+    build_content(&site.db(), &cfg).unwrap();
+    assert_eq!(slug("publicpost"), "renamed-post");
+    assert_eq!(slug("olderpost"), "public-post-2");
+    assert!(!Tombstone::exists(&site.db(), "public-post").unwrap());
+
+    let request = Request::get("/posts/public-post/")
+        .body(Body::empty())
+        .unwrap();
+    let response = make_router(site.state.clone())
+        .oneshot(request)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), ax::StatusCode::MOVED_PERMANENTLY);
+    assert_eq!(
+        response.headers().get(ax::header::LOCATION).unwrap(),
+        "/posts/renamed-post/"
+    );
+}
+
+#[tokio::test]
+async fn comments_are_only_shown_once_approved_by_a_reader_of_the_post() {
+    let site = make_site();
+    let friends = site.login(FRIENDS_KEY).await;
+    let family = site.login(FAMILY_KEY).await;
+    let started = chrono::Utc::now().timestamp() - 60;
+
+    let form = format!("name=Ann&body=Family+comment&started={}", started);
+    assert_eq!(
+        site.post("/posts/family-post/comments", None, &form).await,
+        ax::StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        site.post("/posts/family-post/comments", Some(&family), &form)
+            .await,
+        ax::StatusCode::SEE_OTHER
+    );
+
+    let (_, body) = site.get("/posts/family-post/", Some(&family)).await;
+    assert!(!body.contains("Family comment"));
+
+    // friends can't read the post, so they can't see or approve its comments
+    assert_eq!(
+        site.get("/comments/", None).await.0,
+        ax::StatusCode::NOT_FOUND
+    );
+    let (_, body) = site.get("/comments/", Some(&friends)).await;
+    assert!(!body.contains("Family comment"));
+    assert_eq!(
+        site.post("/comments/1/approve", Some(&friends), "").await,
+        ax::StatusCode::NOT_FOUND
+    );
+
+    let (_, body) = site.get("/comments/", Some(&family)).await;
+    assert!(body.contains("Family comment"));
+    assert_eq!(
+        site.post("/comments/1/approve", Some(&family), "").await,
+        ax::StatusCode::SEE_OTHER
+    );
+
+    let (_, body) = site.get("/posts/family-post/", Some(&family)).await;
+    assert!(body.contains("Family comment"));
+}
+
+#[tokio::test]
+async fn polls_count_one_vote_per_voter_on_readable_posts() {
+    let site = make_site();
+    let voter = "voter=0123456789abcdef";
+
+    assert_eq!(
+        site.post("/posts/private-post/polls/lunch", Some(voter), "option=0")
+            .await,
+        ax::StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        site.post("/posts/public-post/polls/lunch", Some(voter), "option=2")
+            .await,
+        ax::StatusCode::BAD_REQUEST
+    );
+
+    let (_, body) = site.get("/posts/public-post/", Some(voter)).await;
+    assert!(body.contains("Lunch?"));
+    assert!(body.contains("0 votes so far"));
+
+    for option in ["option=1", "option=0"] {
+        assert_eq!(
+            site.post("/posts/public-post/polls/lunch", Some(voter), option)
+                .await,
+            ax::StatusCode::SEE_OTHER
+        );
+    }
+
+    let (_, body) = site.get("/posts/public-post/", Some(voter)).await;
+    assert!(body.contains("bread · 1 (100%)"));
+    assert!(body.contains("1 votes"));
+}
+
+#[tokio::test]
+async fn relative_asset_links_point_at_the_asset_route() {
+    let site = make_site();
+    let post_dir = write_post(
+        site._dir.path(),
+        "notes",
+        serde_json::json!({
+            "id": "notespost",
+            "title": "Notes",
+            "date": "2024-01-05",
+            "tags": [],
+        }),
+        "![diagram](assets/diagram.svg)\n\n[source](./assets/diagram.svg) [other](other/diagram.svg)\n",
+    );
+    fs::create_dir_all(post_dir.join("assets")).unwrap();
+    fs::write(post_dir.join("assets/diagram.svg"), "<svg></svg>").unwrap();
+    build_content(&site.db(), &site.state.config.lock().unwrap()).unwrap();
+
+    let (_, body) = site.get("/posts/notes/", None).await;
+    assert_eq!(
+        body.matches("\"/posts/notespost/assets/diagram.svg\"")
+            .count(),
+        2
+    );
+    assert!(body.contains("\"other/diagram.svg\""));
+    let (status, _) = site.get("/posts/notespost/assets/diagram.svg", None).await;
+    assert_eq!(status, ax::StatusCode::OK);
+}
+
+#[tokio::test]
+async fn old_urls_redirect_before_the_404() {
+    let site = make_site_with(|config| {
+        config.redirects = serde_json::from_value(serde_json::json!({
+            "/blog/hello.html": "/posts/public-post/",
+            "/gallery/": {"to": "/photos/", "permanent": false},
+        }))
+        .unwrap();
+    });
+
+    let redirect = |path: &'static str| {
+        let site = &site;
+        async move {
+            let response = make_router(site.state.clone())
+                .oneshot(Request::get(path).body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            let location = response
+                .headers()
+                .get(ax::header::LOCATION)
+                .map(|location| location.to_str().unwrap().to_string());
+            (response.status(), location)
+        }
+    };
+
+    assert_eq!(
+        redirect("/blog/hello.html").await,
+        (
+            ax::StatusCode::MOVED_PERMANENTLY,
+            Some("/posts/public-post/".to_string())
+        )
+    );
+    assert_eq!(
+        redirect("/gallery").await,
+        (ax::StatusCode::FOUND, Some("/photos/".to_string()))
+    );
+    // routes of the site itself win
+    assert_eq!(redirect("/photos/").await.0, ax::StatusCode::OK);
+    assert_eq!(
+        site.get("/blog/other/", None).await.0,
+        ax::StatusCode::NOT_FOUND
+    );
+}
+
+#[tokio::test]
+async fn trailing_slashes_are_normalized_keeping_queries_and_forms() {
+    async fn request(site: &Site, method: &str, path: &str) -> (ax::StatusCode, Option<String>) {
+        let request = Request::builder()
+            .method(method)
+            .uri(path)
+            .header(
+                ax::header::CONTENT_TYPE,
+                "application/x-www-form-urlencoded",
+            )
+            .header(ax::header::COOKIE, browser_cookie(None))
+            .body(Body::from(format!(
+                "key={}&csrf={}",
+                FRIENDS_KEY, CSRF_TOKEN
+            )))
+            .unwrap();
+        let response = make_router(site.state.clone())
+            .oneshot(request)
+            .await
+            .unwrap();
+        let location = response
+            .headers()
+            .get(ax::header::LOCATION)
+            .map(|location| location.to_str().unwrap().to_string());
+        (response.status(), location)
+    }
+
+    let site = make_site();
+    assert_eq!(
+        request(&site, "GET", "/posts?tag=project").await,
+        (
+            ax::StatusCode::PERMANENT_REDIRECT,
+            Some("/posts/?tag=project".to_string())
+        )
+    );
+    assert_eq!(
+        request(&site, "GET", "/nothing").await.0,
+        ax::StatusCode::NOT_FOUND
+    );
+    let photo = format!("/photos/{}", site.photo_id("public.jpg"));
+    assert_eq!(request(&site, "GET", &photo).await.0, ax::StatusCode::OK);
+    // the form still logs in
+    assert_eq!(
+        request(&site, "POST", "/login").await,
+        (ax::StatusCode::SEE_OTHER, Some("/".to_string()))
+    );
+
+    let site = make_site_with(|config| {
+        config.trailing_slash = serde_json::from_str("\"strip\"").unwrap();
+    });
+    assert_eq!(
+        request(&site, "GET", "/posts/?tag=project").await,
+        (
+            ax::StatusCode::PERMANENT_REDIRECT,
+            Some("/posts?tag=project".to_string())
+        )
+    );
+    let (status, _) = site.get("/posts?tag=project", None).await;
+    assert_eq!(status, ax::StatusCode::OK);
+    assert_eq!(request(&site, "GET", "/").await.0, ax::StatusCode::OK);
+    assert_eq!(
+        request(&site, "POST", "/login/").await.0,
+        ax::StatusCode::SEE_OTHER
+    );
+}
+
+#[tokio::test]
+async fn shortlinks_redirect_like_the_posts_they_point_at() {
+    let site = make_site();
+    User::new(&site.db(), "admin-key", "admin").unwrap();
+    let friends = site.login(FRIENDS_KEY).await;
+    let admin = site.login("admin-key").await;
+
+    let code_of = |post_id: &str| {
+        Shortlink::get_all(&site.db())
+            .unwrap()
+            .into_iter()
+            .find(|shortlink| shortlink.post_id.as_deref() == Some(post_id))
+            .unwrap()
+            .code
+    };
+    let public = code_of("publicpost");
+    let private = code_of("privatepost");
+    assert_eq!(public.len(), 4);
+
+    let redirect = |code: String, cookie: Option<String>| {
+        let state = site.state.clone();
+        async move {
+            let mut request = Request::get(routes::shortlink(&code));
+            if let Some(cookie) = cookie {
+                request = request.header(ax::header::COOKIE, cookie);
+            }
+            let response = make_router(state)
+                .oneshot(request.body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            let location = response
+                .headers()
+                .get(ax::header::LOCATION)
+                .map(|location| location.to_str().unwrap().to_string());
+            (response.status(), location)
+        }
+    };
+
+    let to_public = (
+        ax::StatusCode::MOVED_PERMANENTLY,
+        Some("/posts/public-post/".to_string()),
+    );
+    assert_eq!(redirect(public.clone(), None).await, to_public);
+    assert_eq!(redirect(public.to_uppercase(), None).await, to_public);
+    assert_eq!(
+        redirect(private.clone(), None).await,
+        (ax::StatusCode::NOT_FOUND, None)
+    );
+    assert_eq!(
+        redirect(private.clone(), Some(friends.clone())).await,
+        (
+            ax::StatusCode::MOVED_PERMANENTLY,
+            Some("/posts/private-post/".to_string())
+        )
+    );
+
+    // codes survive rebuilds
+    build_content(&site.db(), &site.state.config.lock().unwrap()).unwrap();
+    assert_eq!(code_of("publicpost"), public);
+
+    // custom ones are added by admins only
+    let form = "code=Talk&target=https%3A%2F%2Fexample.com%2Fslides";
+    assert_eq!(
+        site.post("/admin/shortlinks/", Some(&friends), form).await,
+        ax::StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        site.post("/admin/shortlinks/", Some(&admin), form).await,
+        ax::StatusCode::SEE_OTHER
+    );
+    assert_eq!(
+        site.post(
+            "/admin/shortlinks/",
+            Some(&admin),
+            "code=talk&target=nowhere"
+        )
+        .await,
+        ax::StatusCode::BAD_REQUEST
+    );
+    assert_eq!(
+        redirect("talk".to_string(), None).await,
+        (
+            ax::StatusCode::FOUND,
+            Some("https://example.com/slides".to_string())
+        )
+    );
+    let (status, body) = site.get("/admin/shortlinks/", Some(&admin)).await;
+    assert_eq!(status, ax::StatusCode::OK);
+    assert!(body.contains("https://example.com/slides"));
+
+    assert_eq!(
+        site.post("/admin/shortlinks/talk/delete", Some(&admin), "")
+            .await,
+        ax::StatusCode::SEE_OTHER
+    );
+    assert_eq!(
+        redirect("talk".to_string(), None).await,
+        (ax::StatusCode::NOT_FOUND, None)
+    );
+}
+
+#[tokio::test]
+async fn qr_codes_are_only_made_for_readable_posts() {
+    let site = make_site();
+    let friends = site.login(FRIENDS_KEY).await;
+
+    let (status, body) = site.get("/posts/public-post/", None).await;
+    assert_eq!(status, ax::StatusCode::OK);
+    assert!(body.contains(r#"src="/posts/public-post/qr.svg""#));
+    assert!(body.contains("http://localhost/s/"));
+
+    let (status, body) = site.get("/posts/public-post/qr.svg", None).await;
+    assert_eq!(status, ax::StatusCode::OK);
+    assert!(body.contains("<svg"));
+    assert_eq!(
+        site.get("/posts/publicpost/qr.svg", None).await.0,
+        ax::StatusCode::MOVED_PERMANENTLY
+    );
+
+    assert_eq!(
+        site.get("/posts/private-post/qr.svg", None).await.0,
+        ax::StatusCode::NOT_FOUND
+    );
+    let (status, body) = site.get("/posts/private-post/qr.svg", Some(&friends)).await;
+    assert_eq!(status, ax::StatusCode::OK);
+    assert!(body.contains("<svg"));
+}
+
+#[tokio::test]
+async fn posts_without_a_description_are_described_by_their_text() {
+    let site = make_site();
+    let dir = site._dir.path();
+    let cfg = site.state.config.lock().unwrap().clone();
+
+    write_post(
+        dir,
+        "long",
+        serde_json::json!({
+            "id": "longpost",
+            "title": "Long post",
+            "date": "2024-01-03",
+            "tags": [],
+        }),
+        &format!(
+            "# Heading\n\nSome `code` and\n*more*.\n\n{}\n",
+            "word ".repeat(50)
+        ),
+    );
+    build_content(&site.db(), &cfg).unwrap();
+
+    let (_, body) = site.get("/posts/long-post/", None).await;
+    let description = body
+        .split("<meta name=\"description\" content=\"")
+        .nth(1)
+        .unwrap()
+        .split('"')
+        .next()
+        .unwrap();
+    assert!(description.starts_with("Some code and more. word word"));
+    assert!(description.ends_with("word…"));
+    assert!(description.chars().count() <= 160);
+
+    let (_, body) = site.get("/posts/public-post/", None).await;
+    assert!(body.contains("<meta name=\"description\" content=\"Hello.\">"));
+}
+
+#[tokio::test]
+async fn tag_links_survive_tags_with_url_characters() {
+    let site = make_site();
+    let dir = site._dir.path();
+    let cfg = site.state.config.lock().unwrap().clone();
+
+    write_post(
+        dir,
+        "tagged",
+        serde_json::json!({
+            "id": "taggedpost",
+            "title": "Tagged post",
+            "date": "2024-01-05",
+            "tags": ["c++ & go"],
+        }),
+        "Tagged.\n",
+    );
+    build_content(&site.db(), &cfg).unwrap();
+
+    // spaces in tags become underscores, the rest is percent-encoded
+    let (_, body) = site.get("/posts/", None).await;
+    assert!(body.contains("href=\"/posts/?tag=c%2B%2B_%26_go\""));
+
+    let (status, body) = site.get("/posts/?tag=c%2B%2B_%26_go", None).await;
+    assert_eq!(status, ax::StatusCode::OK);
+    assert!(body.contains("Tagged post"));
+    assert!(!body.contains("Public post"));
+}
@@ -0,0 +1,622 @@
+use axum::body::Body;
+use axum::http::Request;
+use tower::ServiceExt;
+
+use super::site::*;
+use super::TempDir;
+use crate::prelude::*;
+use crate::{build_content, make_router};
+
+#[tokio::test]
+async fn post_assets_are_as_private_as_their_post() {
+    let site = make_site();
+    for post in ["public", "private", "family", "expired"] {
+        let assets = site._dir.path().join("posts").join(post).join("assets");
+        fs::create_dir_all(&assets).unwrap();
+        fs::write(assets.join("notes.txt"), post).unwrap();
+    }
+    build_content(&site.db(), &site.state.config.lock().unwrap()).unwrap();
+
+    let fetch = |path: String, cookie: Option<String>| {
+        let router = make_router(site.state.clone());
+        async move {
+            let request = Request::get(&path)
+                .header(ax::header::COOKIE, browser_cookie(cookie.as_deref()))
+                .body(Body::empty())
+                .unwrap();
+            let response = router.oneshot(request).await.unwrap();
+            let cache_control = response
+                .headers()
+                .get(ax::header::CACHE_CONTROL)
+                .map(|value| value.to_str().unwrap().to_string());
+            (response.status(), cache_control)
+        }
+    };
+    let url = |post: &str| {
+        let hash: String = site
+            .db()
+            .query_one(
+                r#"
+                    SELECT substr(styles.data_hash, 1, 10) FROM styles
+                    JOIN posts_assets ON posts_assets.asset_id = styles.id
+                    WHERE posts_assets.post_id = ?;
+                "#,
+                [post],
+                |row| row.get(0),
+            )
+            .unwrap();
+        format!("/posts/{}/assets/notes.txt?v={}", post, hash)
+    };
+
+    for (post, status) in [
+        ("privatepost", ax::StatusCode::NOT_FOUND),
+        ("familypost", ax::StatusCode::NOT_FOUND),
+        ("expiredpost", ax::StatusCode::GONE),
+    ] {
+        assert_eq!(fetch(url(post), None).await.0, status, "{}", post);
+    }
+
+    let (status, cache_control) = fetch(url("publicpost"), None).await;
+    assert_eq!(status, ax::StatusCode::OK);
+    assert!(cache_control.unwrap().contains("immutable"));
+
+    let friends = site.login(FRIENDS_KEY).await;
+    let (status, cache_control) = fetch(url("privatepost"), Some(friends.clone())).await;
+    assert_eq!(status, ax::StatusCode::OK);
+    assert_eq!(cache_control.as_deref(), Some("private"));
+    let (status, _) = fetch(url("familypost"), Some(friends)).await;
+    assert_eq!(status, ax::StatusCode::NOT_FOUND);
+}
+
+// every page an anonymous visitor can reach without knowing a photo id
+const LISTING_PATHS: &[&str] = &[
+    "/",
+    "/posts/",
+    "/posts/?tag=project",
+    "/projects/",
+    "/photos/",
+    "/photos/feed.xml",
+    "/posts/feed.xml",
+    "/posts/public-post/",
+    "/stats/",
+];
+
+#[tokio::test]
+async fn anonymous_users_cannot_fetch_restricted_photos() {
+    let site = make_site();
+
+    for name in ["secret.jpg", "inner.jpg", "family.jpg"] {
+        let id = site.photo_id(name);
+        for query in ["", "?size=small", "?size=large"] {
+            let (status, _) = site.get(&format!("/photos/{}{}", id, query), None).await;
+            assert_eq!(status, ax::StatusCode::FORBIDDEN, "{} {}", name, query);
+        }
+    }
+
+    let (status, _) = site
+        .get(&format!("/photos/{}", site.photo_id("public.jpg")), None)
+        .await;
+    assert_eq!(status, ax::StatusCode::OK);
+}
+
+#[tokio::test]
+async fn anonymous_pages_never_reference_restricted_photos() {
+    let site = make_site();
+    let restricted = ["secret.jpg", "inner.jpg", "family.jpg"].map(|name| site.photo_id(name));
+
+    for path in LISTING_PATHS {
+        let (status, body) = site.get(path, None).await;
+        assert_eq!(status, ax::StatusCode::OK, "{}", path);
+        for id in &restricted {
+            assert!(!body.contains(id.as_str()), "{} leaks photo {}", path, id);
+        }
+    }
+
+    let (_, body) = site.get("/posts/public-post/", None).await;
+    assert!(body.contains(&site.photo_id("public.jpg")));
+}
+
+#[tokio::test]
+async fn photo_links_match_the_photo_route() {
+    let site = make_site();
+    let id = site.photo_id("public.jpg");
+    let (_, body) = site.get("/posts/public-post/", None).await;
+
+    for size in [PhotoSize::Small, PhotoSize::Large] {
+        let url = routes::photo_sized(&id, size);
+        assert!(body.contains(&format!("\"{}\"", url)), "{}", url);
+        let (status, _) = site.get(&url, None).await;
+        assert_eq!(status, ax::StatusCode::OK, "{}", url);
+    }
+}
+
+#[tokio::test]
+async fn anonymous_users_cannot_see_restricted_posts() {
+    let site = make_site();
+
+    for id in ["privatepost", "familypost", "private-post", "family-post"] {
+        let (status, body) = site.get(&format!("/posts/{}/", id), None).await;
+        assert_eq!(status, ax::StatusCode::NOT_FOUND, "{}", id);
+        assert!(!body.contains("only."));
+    }
+
+    for path in LISTING_PATHS {
+        let (_, body) = site.get(path, None).await;
+        assert!(
+            !body.contains("Private post"),
+            "{} lists private post",
+            path
+        );
+        assert!(!body.contains("Family post"), "{} lists family post", path);
+    }
+}
+
+#[tokio::test]
+async fn logged_in_users_only_see_their_groups() {
+    let site = make_site();
+    let friends = site.login(FRIENDS_KEY).await;
+    let family = site.login(FAMILY_KEY).await;
+
+    let secret = format!("/photos/{}", site.photo_id("secret.jpg"));
+    let family_photo = format!("/photos/{}", site.photo_id("family.jpg"));
+
+    assert_eq!(
+        site.get(&secret, Some(&friends)).await.0,
+        ax::StatusCode::OK
+    );
+    assert_eq!(
+        site.get(&family_photo, Some(&friends)).await.0,
+        ax::StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        site.get(&family_photo, Some(&family)).await.0,
+        ax::StatusCode::OK
+    );
+
+    assert_eq!(
+        site.get("/posts/private-post/", Some(&friends)).await.0,
+        ax::StatusCode::OK
+    );
+    assert_eq!(
+        site.get("/posts/familypost/", Some(&friends)).await.0,
+        ax::StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        site.get("/posts/family-post/", Some(&family)).await.0,
+        ax::StatusCode::OK
+    );
+
+    let (_, body) = site.get("/photos/", Some(&friends)).await;
+    assert!(body.contains(&site.photo_id("secret.jpg")));
+    assert!(!body.contains(&site.photo_id("family.jpg")));
+}
+
+#[tokio::test]
+async fn feeds_never_include_restricted_photos_even_when_logged_in() {
+    let site = make_site();
+    let family = site.login(FAMILY_KEY).await;
+
+    let (_, body) = site.get("/photos/feed.xml", Some(&family)).await;
+    for name in ["secret.jpg", "inner.jpg", "family.jpg"] {
+        assert!(!body.contains(&site.photo_id(name)), "feed leaks {}", name);
+    }
+}
+
+#[tokio::test]
+async fn public_photos_of_group_posts_stay_within_the_group() {
+    let site = make_site();
+    let photo = site._dir.path().join("posts/family/photos/beach.jpg");
+    fs::create_dir_all(photo.parent().unwrap()).unwrap();
+    image::RgbImage::from_pixel(32, 24, image::Rgb([10, 200, 250]))
+        .save(&photo)
+        .unwrap();
+    build_content(&site.db(), &site.state.config.lock().unwrap()).unwrap();
+    let beach = site.photo_id("beach.jpg");
+
+    let friends = site.login(FRIENDS_KEY).await;
+    let family = site.login(FAMILY_KEY).await;
+    for cookie in [None, Some(friends.as_str())] {
+        for path in ["/photos/", "/photos/feed.xml"] {
+            let (_, body) = site.get(path, cookie).await;
+            assert!(!body.contains(&beach), "{} leaks the photo", path);
+        }
+    }
+    assert!(site.get("/photos/", Some(&family)).await.1.contains(&beach));
+}
+
+#[tokio::test]
+async fn invalid_cookies_are_treated_as_anonymous() {
+    let site = make_site();
+    let secret = format!("/photos/{}", site.photo_id("secret.jpg"));
+
+    let friends = site.login(FRIENDS_KEY).await;
+    let key_hash = user_of(&site.db(), &friends).key_hash;
+    for cookie in [
+        "session=".to_string(),
+        "session=invalid".to_string(),
+        "session=friends-key".to_string(),
+        format!("session={}", key_hash),
+        format!("key={}", key_hash),
+    ] {
+        assert_eq!(
+            site.get(&secret, Some(&cookie)).await.0,
+            ax::StatusCode::FORBIDDEN,
+            "{}",
+            cookie
+        );
+    }
+}
+
+#[tokio::test]
+async fn private_photos_are_encrypted_at_rest() {
+    let site = make_site_with(|config| config.encryption_key = Some("ab".repeat(32)));
+    let friends = site.login(FRIENDS_KEY).await;
+
+    let blob = |name: &str| -> Vec<u8> {
+        let id = site.photo_id(name);
+        let db = site.db();
+        db.query_one(
+            "SELECT data FROM blobs JOIN photos ON hash = image_large_hash WHERE id = ?;",
+            [id],
+            |row| row.get(0),
+        )
+        .unwrap()
+    };
+
+    // jpegs start with ff d8
+    assert!(blob("public.jpg").starts_with(&[0xff, 0xd8]));
+    for name in ["secret.jpg", "inner.jpg", "family.jpg"] {
+        assert!(
+            !blob(name).starts_with(&[0xff, 0xd8]),
+            "{} is stored in plain",
+            name
+        );
+    }
+
+    // the blob only decrypts with the configured key, so a 200 means it was decrypted
+    let (status, _) = site
+        .get(
+            &format!("/photos/{}", site.photo_id("secret.jpg")),
+            Some(&friends),
+        )
+        .await;
+    assert_eq!(status, ax::StatusCode::OK);
+}
+
+#[tokio::test]
+async fn assets_of_restricted_posts_are_encrypted_at_rest() {
+    let site = make_site_with(|config| config.encryption_key = Some("ab".repeat(32)));
+    for post in ["public", "private", "family"] {
+        let assets = site._dir.path().join("posts").join(post).join("assets");
+        fs::create_dir_all(&assets).unwrap();
+        fs::write(assets.join("notes.txt"), format!("notes of {}", post)).unwrap();
+    }
+    build_content(&site.db(), &site.state.config.lock().unwrap()).unwrap();
+
+    let stored = |post: &str| -> Vec<u8> {
+        site.db()
+            .query_one(
+                r#"
+                    SELECT blobs.data FROM blobs
+                    JOIN styles ON styles.data_hash = blobs.hash
+                    JOIN posts_assets ON posts_assets.asset_id = styles.id
+                    WHERE posts_assets.post_id = ?;
+                "#,
+                [post],
+                |row| row.get(0),
+            )
+            .unwrap()
+    };
+    assert_eq!(stored("publicpost"), b"notes of public");
+    for (post, name) in [("privatepost", "private"), ("familypost", "family")] {
+        let data = stored(post);
+        assert!(
+            !String::from_utf8_lossy(&data).contains("notes of"),
+            "{} is stored in plain",
+            post
+        );
+        assert_eq!(
+            data.len(),
+            format!("notes of {}", name).len() + crate::crypto::OVERHEAD
+        );
+    }
+
+    // unchanged, the encrypted asset is kept as it is
+    let before = stored("privatepost");
+    build_content(&site.db(), &site.state.config.lock().unwrap()).unwrap();
+    assert_eq!(stored("privatepost"), before);
+
+    let friends = site.login(FRIENDS_KEY).await;
+    let (status, body) = site
+        .get("/posts/private-post/assets/notes.txt", Some(&friends))
+        .await;
+    assert_eq!(status, ax::StatusCode::OK);
+    assert_eq!(body, "notes of private");
+}
+
+#[tokio::test]
+async fn expired_posts_are_gone_and_unlisted() {
+    let site = make_site();
+    let family = site.login(FAMILY_KEY).await;
+
+    for cookie in [None, Some(family.as_str())] {
+        let (status, body) = site.get("/posts/expiredpost/", cookie).await;
+        assert_eq!(status, ax::StatusCode::GONE);
+        assert!(!body.contains("Gone now."));
+
+        for path in LISTING_PATHS {
+            let (_, body) = site.get(path, cookie).await;
+            assert!(
+                !body.contains("Expired post"),
+                "{} lists expired post",
+                path
+            );
+            assert!(!body.contains(&site.photo_id("expired.jpg")));
+        }
+
+        let (status, _) = site
+            .get(&format!("/photos/{}", site.photo_id("expired.jpg")), cookie)
+            .await;
+        assert_eq!(status, ax::StatusCode::GONE);
+    }
+}
+
+#[tokio::test]
+async fn source_views_only_mention_visible_photos() {
+    let site = make_site();
+    let friends = site.login(FRIENDS_KEY).await;
+
+    for file in ["index.md", "index.txt"] {
+        let (status, body) = site
+            .get(&format!("/posts/public-post/{}", file), None)
+            .await;
+        assert_eq!(status, ax::StatusCode::OK, "{}", file);
+        assert!(body.contains("public"), "{}", file);
+        assert!(!body.contains("secret"), "{} leaks a hidden photo", file);
+
+        let (_, body) = site
+            .get(&format!("/posts/public-post/{}", file), Some(&friends))
+            .await;
+        assert!(body.contains("secret"), "{}", file);
+
+        let (status, _) = site
+            .get(&format!("/posts/private-post/{}", file), None)
+            .await;
+        assert_eq!(status, ax::StatusCode::NOT_FOUND, "{}", file);
+    }
+}
+
+#[tokio::test]
+async fn stats_only_count_visible_posts_and_photos() {
+    let site = make_site();
+    let friends = site.login(FRIENDS_KEY).await;
+
+    let (_, body) = site.get("/stats/", None).await;
+    assert!(body.contains(">1 posts · "));
+    assert!(body.contains(" · 1 photos<"));
+    assert!(!body.contains("Private post"));
+
+    let (_, body) = site.get("/stats/", Some(&friends)).await;
+    assert!(body.contains(">2 posts · "));
+    assert!(body.contains(" · 3 photos<"));
+}
+
+#[tokio::test]
+async fn feed_tokens_only_show_what_their_user_can_see() {
+    let site = make_site();
+    let friends = site.login(FRIENDS_KEY).await;
+    let token = {
+        let db = site.db();
+        User::by_feed_token(&db, "nope").unwrap_err();
+        let user = User::get_all(&db)
+            .unwrap()
+            .into_iter()
+            .find(|user| user.group_name == "friends")
+            .unwrap();
+        user.feed_token(&db).unwrap()
+    };
+    assert!(site.get("/login/", Some(&friends)).await.1.contains(&token));
+
+    let (_, body) = site.get("/posts/feed.xml", None).await;
+    assert!(body.contains("Public post"));
+    assert!(!body.contains("Private post"));
+
+    let (_, body) = site
+        .get(&format!("/posts/feed.xml?token={}", token), None)
+        .await;
+    assert!(body.contains("Private post"));
+    assert!(!body.contains("Family post"));
+
+    let (_, body) = site
+        .get(&format!("/photos/feed.xml?token={}", token), None)
+        .await;
+    assert!(body.contains(&site.photo_id("inner.jpg")));
+    assert!(!body.contains(&site.photo_id("family.jpg")));
+
+    assert_eq!(
+        site.post("/login/feeds/reset", Some(&friends), "").await,
+        ax::StatusCode::SEE_OTHER
+    );
+    for path in ["/posts/feed.xml", "/photos/feed.xml"] {
+        let (status, body) = site.get(&format!("{}?token={}", path, token), None).await;
+        assert_eq!(status, ax::StatusCode::NOT_FOUND);
+        assert!(!body.contains("Private post"));
+    }
+}
+
+#[tokio::test]
+async fn calendars_only_show_events_of_readable_posts() {
+    let site = make_site();
+    let dir = site._dir.path();
+    write_post(
+        dir,
+        "meetup",
+        serde_json::json!({
+            "id": "meetuppost",
+            "title": "Public meetup",
+            "date": "2024-01-05",
+            "tags": [],
+            "event": {"start": "2030-05-01", "location": "Hall, room 1"},
+        }),
+        "Come along.\n",
+    );
+    write_post(
+        dir,
+        "club",
+        serde_json::json!({
+            "id": "clubpost",
+            "title": "Club night",
+            "date": "2024-01-06",
+            "tags": [],
+            "private": true,
+            "event": {"start": "2030-05-02 19:00", "end": "2030-05-02 22:00"},
+        }),
+        "Members only.\n",
+    );
+    write_post(
+        dir,
+        "scheduled",
+        serde_json::json!({
+            "id": "scheduledpost",
+            "title": "Upcoming post",
+            "date": "2999-01-01",
+            "tags": [],
+        }),
+        "Not yet.\n",
+    );
+    {
+        let db = site.db();
+        build_content(&db, &site.state.config.lock().unwrap()).unwrap();
+        User::new(&db, "admin-key", "admin").unwrap();
+    }
+    let friends = site.login(FRIENDS_KEY).await;
+    let admin = site.login("admin-key").await;
+
+    let (status, body) = site.get("/calendar.ics", None).await;
+    assert_eq!(status, ax::StatusCode::OK);
+    assert!(body.starts_with("BEGIN:VCALENDAR\r\n"));
+    assert!(body.contains("SUMMARY:Public meetup"));
+    assert!(body.contains("DTSTART;VALUE=DATE:20300501"));
+    assert!(body.contains("DTEND;VALUE=DATE:20300502"));
+    assert!(body.contains("LOCATION:Hall\\, room 1"));
+    assert!(!body.contains("Club night"));
+    assert!(!body.contains("Upcoming post"));
+
+    let (_, body) = site.get("/calendar.ics", Some(&friends)).await;
+    assert!(body.contains("Club night"));
+    assert!(!body.contains("Upcoming post"));
+
+    let (_, body) = site.get("/calendar.ics", Some(&admin)).await;
+    assert!(body.contains("SUMMARY:Publish: Upcoming post"));
+    assert!(!body.contains("Publish: Public post"));
+
+    let (status, body) = site.get("/calendar.ics?token=nope", None).await;
+    assert_eq!(status, ax::StatusCode::NOT_FOUND);
+    assert!(!body.contains("Public meetup"));
+}
+
+#[tokio::test]
+async fn not_found_pages_only_suggest_readable_posts() {
+    let site = make_site();
+    let friends = site.login(FRIENDS_KEY).await;
+
+    let (status, body) = site.get("/posts/publik-post/", None).await;
+    assert_eq!(status, ax::StatusCode::NOT_FOUND);
+    assert!(body.contains("Did you mean"));
+    assert!(body.contains("href=\"/posts/public-post/\""));
+    assert!(body.contains("Recent posts"));
+
+    let (status, body) = site.get("/posts/privat-post/", None).await;
+    assert_eq!(status, ax::StatusCode::NOT_FOUND);
+    assert!(!body.contains("Private post"));
+    assert!(!body.contains("/posts/private-post/"));
+
+    let (_, body) = site.get("/posts/privat-post/", Some(&friends)).await;
+    assert!(body.contains("href=\"/posts/private-post/\""));
+
+    let secret = site.photo_id("secret.jpg");
+    let (_, body) = site.get(&format!("/photos/{}x/", secret), None).await;
+    assert!(!body.contains(&secret));
+}
+
+#[tokio::test]
+async fn static_exports_only_contain_public_content() {
+    let site = make_site();
+    let out = TempDir::new();
+    let router = make_router(site.state.clone());
+    crate::export::export_site(&router, &site.state, out.path())
+        .await
+        .unwrap();
+
+    let read = |path: &str| fs::read_to_string(out.path().join(path)).unwrap();
+    assert!(read("posts/public-post/index.html").contains("Hello."));
+    assert!(read("styles/page.css").contains("body"));
+    assert!(out.path().join("posts/feed.xml").exists());
+
+    // the tag page gets a path of its own and links to it follow
+    assert!(read("posts/tag/project/index.html").contains("Only showing posts tagged"));
+    assert!(read("posts/public-post/index.html").contains("href=\"/posts/tag/project/\""));
+    let public = site.photo_id("public.jpg");
+    assert!(out.path().join(format!("photos/{}-small", public)).exists());
+
+    for name in ["private-post", "family-post", "expired-post"] {
+        assert!(!out.path().join("posts").join(name).exists(), "{}", name);
+    }
+    for name in ["secret.jpg", "inner.jpg", "family.jpg", "expired.jpg"] {
+        let id = site.photo_id(name);
+        for entry in fs::read_dir(out.path().join("photos")).unwrap() {
+            let file_name = entry.unwrap().file_name();
+            assert!(!file_name.to_string_lossy().starts_with(&id), "{}", name);
+        }
+    }
+}
+
+#[tokio::test]
+async fn cached_pages_are_never_shared_beyond_anonymous_readers() {
+    let site = make_site();
+    let friends = site.login(FRIENDS_KEY).await;
+
+    // forms carry the token of whoever is reading
+    let (_, first) = site
+        .get("/posts/public-post/", Some("csrf=firsttoken"))
+        .await;
+    let (_, second) = site
+        .get("/posts/public-post/", Some("csrf=secondtoken"))
+        .await;
+    assert!(first.contains("value=\"firsttoken\""));
+    assert_eq!(first.replace("firsttoken", "secondtoken"), second);
+
+    let (_, body) = site.get("/posts/", None).await;
+    assert!(!body.contains("Private post"));
+    let (_, body) = site.get("/posts/", Some(&friends)).await;
+    assert!(body.contains("Private post"));
+
+    // anything written to the database shows up right away, also by another connection
+    site.db()
+        .execute(
+            "UPDATE posts SET title = 'Renamed post' WHERE id = 'publicpost';",
+            [],
+        )
+        .unwrap();
+    let (_, body) = site.get("/posts/", Some("csrf=firsttoken")).await;
+    assert!(body.contains("Renamed post"));
+}
+
+#[test]
+fn the_server_can_only_write_its_own_tables() {
+    let site = make_site();
+    let rename = "UPDATE posts SET title = 'Renamed post' WHERE id = 'publicpost';";
+
+    assert!(site.state.db.lock().unwrap().execute(rename, []).is_err());
+    let writer = site.state.writer.lock().unwrap();
+    assert!(writer.execute(rename, []).is_err());
+    assert!(writer.execute("DROP TABLE posts;", []).is_err());
+    writer
+        .execute("DELETE FROM sessions WHERE expires_at <= unixepoch();", [])
+        .unwrap();
+
+    assert_eq!(
+        Post::by_id(&site.db(), "publicpost").unwrap().title,
+        "Public post"
+    );
+}
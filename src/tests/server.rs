@@ -0,0 +1,149 @@
+use axum::body::Body;
+use axum::http::Request;
+use tower::ServiceExt;
+
+use super::site::*;
+use crate::prelude::*;
+use crate::{bind_socket, make_router};
+
+#[tokio::test]
+async fn oversized_bodies_are_rejected() {
+    let site = make_site_with(|config| config.max_body_size = 256);
+    let friends = site.login(FRIENDS_KEY).await;
+    let started = chrono::Utc::now().timestamp() - 60;
+
+    let comment = format!("name=someone&body={}&started={}", "a".repeat(1024), started);
+    assert_eq!(
+        site.post("/posts/public-post/comments", Some(&friends), &comment)
+            .await,
+        ax::StatusCode::PAYLOAD_TOO_LARGE
+    );
+    assert_eq!(
+        site.post(
+            "/posts/public-post/comments",
+            Some(&friends),
+            &format!("name=someone&body=short&started={}", started)
+        )
+        .await,
+        ax::StatusCode::SEE_OTHER
+    );
+    // uploads keep their own, larger limit
+    let mut photo = std::io::Cursor::new(vec![]);
+    image::RgbImage::from_pixel(64, 64, image::Rgb([1, 2, 3]))
+        .write_to(&mut photo, image::ImageFormat::Png)
+        .unwrap();
+    User::new(&site.db(), "admin-key", "admin").unwrap();
+    let admin = site.login("admin-key").await;
+    assert_eq!(
+        site.upload(
+            "/admin/posts/publicpost/photos",
+            Some(&admin),
+            "big.png",
+            photo.get_ref()
+        )
+        .await,
+        ax::StatusCode::SEE_OTHER
+    );
+}
+
+#[tokio::test]
+async fn the_server_can_listen_on_a_unix_socket() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let site = make_site();
+    let path = site._dir.path().join("website.sock");
+    let path = path.to_str().unwrap();
+
+    // a socket left over from an earlier run doesn't stop the server from starting
+    drop(bind_socket(path).unwrap());
+    let listener = bind_socket(path).unwrap();
+    tokio::spawn(axum::serve(listener, make_router(site.state.clone())).into_future());
+
+    for (page, status) in [
+        ("/posts/public-post/", "200 OK"),
+        ("/posts/private-post/", "404 Not Found"),
+    ] {
+        let mut stream = tokio::net::UnixStream::connect(path).await.unwrap();
+        let request = format!(
+            "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+            page
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(
+            response.starts_with(&format!("HTTP/1.1 {}", status)),
+            "{}",
+            page
+        );
+    }
+
+    // anything else at the path is left alone
+    let file = site._dir.path().join("not-a-socket");
+    fs::write(&file, "data").unwrap();
+    assert!(bind_socket(file.to_str().unwrap()).is_err());
+    assert_eq!(fs::read_to_string(&file).unwrap(), "data");
+}
+
+#[tokio::test]
+async fn forwarded_headers_are_only_believed_from_trusted_proxies() {
+    let proxy: std::net::IpAddr = "10.0.0.1".parse().unwrap();
+    let site = make_site_with(|config| config.trusted_proxies = vec![proxy]);
+
+    let login = |peer: &str, forwarded_for: &str| {
+        let peer: std::net::SocketAddr = format!("{}:4000", peer).parse().unwrap();
+        let mut request = Request::post("/login/")
+            .header(
+                ax::header::CONTENT_TYPE,
+                "application/x-www-form-urlencoded",
+            )
+            .header(ax::header::COOKIE, browser_cookie(None))
+            .header("x-forwarded-for", forwarded_for)
+            .body(Body::from(format!("key=wrong&csrf={}", CSRF_TOKEN)))
+            .unwrap();
+        request
+            .extensions_mut()
+            .insert(axum::extract::ConnectInfo(peer));
+        make_router(site.state.clone()).oneshot(request)
+    };
+
+    // failed logins are counted for the client behind the proxy, not the proxy
+    for _ in 0..10 {
+        let response = login("10.0.0.1", "203.0.113.5, 10.0.0.1").await.unwrap();
+        assert_eq!(response.status(), ax::StatusCode::SEE_OTHER);
+    }
+    let response = login("10.0.0.1", "203.0.113.5").await.unwrap();
+    assert_eq!(response.status(), ax::StatusCode::TOO_MANY_REQUESTS);
+    let response = login("10.0.0.1", "203.0.113.6").await.unwrap();
+    assert_eq!(response.status(), ax::StatusCode::SEE_OTHER);
+
+    // anyone else claiming to be another client is still the address they connect from
+    for _ in 0..10 {
+        login("198.51.100.7", "203.0.113.7").await.unwrap();
+    }
+    let response = login("198.51.100.7", "203.0.113.8").await.unwrap();
+    assert_eq!(response.status(), ax::StatusCode::TOO_MANY_REQUESTS);
+
+    let cfg = site.state.config.lock().unwrap();
+    let mut headers = ax::HeaderMap::new();
+    headers.insert("x-forwarded-proto", "https".parse().unwrap());
+    let mut extensions = axum::http::Extensions::new();
+    extensions.insert(axum::extract::ConnectInfo(std::net::SocketAddr::from((
+        proxy, 4000,
+    ))));
+    let client = Client::from_parts(&headers, &extensions, &cfg);
+    assert_eq!(
+        client.absolute_url(&cfg, "/feed/"),
+        "https://localhost/feed/"
+    );
+
+    extensions.insert(axum::extract::ConnectInfo(std::net::SocketAddr::from((
+        [198, 51, 100, 7],
+        4000,
+    ))));
+    let client = Client::from_parts(&headers, &extensions, &cfg);
+    assert_eq!(
+        client.absolute_url(&cfg, "/feed/"),
+        "http://localhost/feed/"
+    );
+}
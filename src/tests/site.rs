@@ -0,0 +1,307 @@
+// A site with a post of every visibility, each with photos, and the requests a browser would make
+// to it. Shared by the end-to-end tests of every feature.
+
+use axum::body::Body;
+use axum::http::Request;
+use tower::ServiceExt;
+
+use super::{test_config, TempDir};
+use crate::prelude::*;
+use crate::{build_content, make_router};
+
+pub(super) const FRIENDS_KEY: &str = "friends-key";
+pub(super) const FAMILY_KEY: &str = "family-key";
+// the csrf cookie of the test browser, sent along with every form
+pub(super) const CSRF_TOKEN: &str = "test-csrf-token";
+
+pub(super) fn browser_cookie(cookie: Option<&str>) -> String {
+    match cookie {
+        Some(cookie) => format!("csrf={}; {}", CSRF_TOKEN, cookie),
+        None => format!("csrf={}", CSRF_TOKEN),
+    }
+}
+
+pub(super) fn user_of(db: &Database, cookie: &str) -> User {
+    let jar = ax::CookieJar::new().add(ax::Cookie::parse(cookie.to_string()).unwrap());
+    User::from_cookie(db, &jar).unwrap()
+}
+
+pub(super) struct Site {
+    pub(super) _dir: TempDir,
+    pub(super) state: Arc<AppState>,
+}
+
+impl Site {
+    // a connection like the build's, the server's own can't write content
+    pub(super) fn db(&self) -> Database {
+        Database::open(&self.state.config.lock().unwrap()).unwrap()
+    }
+
+    pub(super) fn photo_id(&self, name: &str) -> String {
+        let db = self.state.db.lock().unwrap();
+        Photo::get_all(&db, None)
+            .unwrap()
+            .into_iter()
+            .find(|photo| photo.name() == name)
+            .map(|photo| photo.id)
+            .unwrap()
+    }
+
+    pub(super) async fn get(&self, path: &str, cookie: Option<&str>) -> (ax::StatusCode, String) {
+        let mut request = Request::get(path);
+        if let Some(cookie) = cookie {
+            request = request.header(ax::header::COOKIE, cookie);
+        }
+
+        let response = make_router(self.state.clone())
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8_lossy(&body).to_string())
+    }
+
+    pub(super) async fn post(
+        &self,
+        path: &str,
+        cookie: Option<&str>,
+        form: &str,
+    ) -> ax::StatusCode {
+        let request = Request::post(path)
+            .header(
+                ax::header::CONTENT_TYPE,
+                "application/x-www-form-urlencoded",
+            )
+            .header(ax::header::COOKIE, browser_cookie(cookie));
+        let form = match form {
+            "" => format!("csrf={}", CSRF_TOKEN),
+            form => format!("{}&csrf={}", form, CSRF_TOKEN),
+        };
+
+        make_router(self.state.clone())
+            .oneshot(request.body(Body::from(form)).unwrap())
+            .await
+            .unwrap()
+            .status()
+    }
+
+    pub(super) async fn post_with_token(&self, path: &str, token: &str) -> ax::StatusCode {
+        let request = Request::post(path)
+            .header(ax::header::AUTHORIZATION, format!("Bearer {}", token))
+            .body(Body::empty())
+            .unwrap();
+
+        make_router(self.state.clone())
+            .oneshot(request)
+            .await
+            .unwrap()
+            .status()
+    }
+
+    pub(super) async fn upload(
+        &self,
+        path: &str,
+        cookie: Option<&str>,
+        file_name: &str,
+        data: &[u8],
+    ) -> ax::StatusCode {
+        let boundary = "upload-boundary";
+        let mut body = format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"photo\"; filename=\"{}\"\r\nContent-Type: application/octet-stream\r\n\r\n",
+            boundary, file_name
+        )
+        .into_bytes();
+        body.extend_from_slice(data);
+        body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+
+        let request = Request::post(path)
+            .header(
+                ax::header::CONTENT_TYPE,
+                format!("multipart/form-data; boundary={}", boundary),
+            )
+            .header(ax::header::COOKIE, browser_cookie(cookie))
+            .header("x-csrf-token", CSRF_TOKEN);
+
+        make_router(self.state.clone())
+            .oneshot(request.body(Body::from(body)).unwrap())
+            .await
+            .unwrap()
+            .status()
+    }
+
+    pub(super) async fn login(&self, key: &str) -> String {
+        let request = Request::post("/login/")
+            .header(
+                ax::header::CONTENT_TYPE,
+                "application/x-www-form-urlencoded",
+            )
+            .header(ax::header::COOKIE, browser_cookie(None))
+            .body(Body::from(format!("key={}&csrf={}", key, CSRF_TOKEN)))
+            .unwrap();
+
+        let response = make_router(self.state.clone())
+            .oneshot(request)
+            .await
+            .unwrap();
+
+        let cookie = response
+            .headers()
+            .get(ax::header::SET_COOKIE)
+            .expect("login did not set a cookie")
+            .to_str()
+            .unwrap();
+        cookie.split(';').next().unwrap().to_string()
+    }
+}
+
+pub(super) fn write_photo(path: &Path) {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    image::RgbImage::from_pixel(32, 24, image::Rgb([200, 100, 50]))
+        .save(path)
+        .unwrap();
+}
+
+// adds the GPS tags a phone would write to a photo from `write_photo`
+pub(super) fn geotag_photo(path: &Path, latitude: f64, longitude: f64) {
+    use exif::{Field, In, Rational, Tag, Value};
+
+    let dms = |value: f64| {
+        let minutes = value.abs().fract() * 60.0;
+        Value::Rational(vec![
+            Rational::from((value.abs() as u32, 1)),
+            Rational::from((minutes as u32, 1)),
+            Rational::from(((minutes.fract() * 6000.0).round() as u32, 100)),
+        ])
+    };
+    let reference = |value: f64, positive: &str, negative: &str| {
+        let reference = if value < 0.0 { negative } else { positive };
+        Value::Ascii(vec![reference.as_bytes().to_vec()])
+    };
+    let fields = [
+        (Tag::GPSLatitudeRef, reference(latitude, "N", "S")),
+        (Tag::GPSLatitude, dms(latitude)),
+        (Tag::GPSLongitudeRef, reference(longitude, "E", "W")),
+        (Tag::GPSLongitude, dms(longitude)),
+    ]
+    .map(|(tag, value)| Field {
+        tag,
+        ifd_num: In::PRIMARY,
+        value,
+    });
+
+    let mut writer = exif::experimental::Writer::new();
+    for field in &fields {
+        writer.push_field(field);
+    }
+    let mut tiff = std::io::Cursor::new(vec![]);
+    writer.write(&mut tiff, false).unwrap();
+    let tiff = tiff.into_inner();
+
+    // an APP1 segment right after the start of the jpeg
+    let jpeg = fs::read(path).unwrap();
+    let mut tagged = jpeg[..2].to_vec();
+    tagged.extend([0xff, 0xe1]);
+    tagged.extend(((tiff.len() + 8) as u16).to_be_bytes());
+    tagged.extend(b"Exif\0\0");
+    tagged.extend(tiff);
+    tagged.extend(&jpeg[2..]);
+    fs::write(path, tagged).unwrap();
+}
+
+pub(super) fn write_post(
+    dir: &Path,
+    name: &str,
+    metadata: serde_json::Value,
+    markdown: &str,
+) -> std::path::PathBuf {
+    let post_dir = dir.join("posts").join(name);
+    fs::create_dir_all(&post_dir).unwrap();
+    fs::write(post_dir.join("meta.json"), metadata.to_string()).unwrap();
+    fs::write(post_dir.join("index.md"), markdown).unwrap();
+    post_dir
+}
+
+pub(super) fn make_site() -> Site {
+    make_site_with(|_| {})
+}
+
+pub(super) fn make_site_with(configure: impl FnOnce(&mut Config)) -> Site {
+    let temp = TempDir::new();
+    let dir = temp.path();
+    fs::create_dir_all(dir.join("files/styles")).unwrap();
+    fs::write(dir.join("files/styles/page.css"), "body {}").unwrap();
+
+    let public_post = write_post(
+        dir,
+        "public",
+        serde_json::json!({
+            "id": "publicpost",
+            "title": "Public post",
+            "date": "2024-01-01",
+            "tags": ["project"],
+            "polls": [{"id": "lunch", "question": "Lunch?", "options": ["rice", "bread"]}],
+        }),
+        "Hello.\n\n![[photo:public.jpg|public]]\n\n![[photo:secret.jpg|secret]]\n\n{{poll lunch}}\n",
+    );
+    write_photo(&public_post.join("photos/public.jpg"));
+    write_photo(&public_post.join("private/secret.jpg"));
+
+    let private_post = write_post(
+        dir,
+        "private",
+        serde_json::json!({
+            "id": "privatepost",
+            "title": "Private post",
+            "date": "2024-01-02",
+            "tags": ["project"],
+            "private": true,
+            "polls": [{"id": "lunch", "question": "Lunch?", "options": ["rice", "bread"]}],
+        }),
+        "Members only.\n",
+    );
+    write_photo(&private_post.join("photos/inner.jpg"));
+
+    let family_post = write_post(
+        dir,
+        "family",
+        serde_json::json!({
+            "id": "familypost",
+            "title": "Family post",
+            "date": "2024-01-03",
+            "tags": [],
+            "allowed_group": "family",
+        }),
+        "Family only.\n",
+    );
+    write_photo(&family_post.join("private/family.jpg"));
+
+    let expired_post = write_post(
+        dir,
+        "expired",
+        serde_json::json!({
+            "id": "expiredpost",
+            "title": "Expired post",
+            "date": "2024-01-04",
+            "tags": ["project"],
+            "expires": "2024-02-01",
+        }),
+        "Gone now.\n",
+    );
+    write_photo(&expired_post.join("photos/expired.jpg"));
+
+    let mut config = test_config(dir);
+    configure(&mut config);
+
+    let db = Database::open(&config).unwrap();
+    build_content(&db, &config).unwrap();
+    User::new(&db, FRIENDS_KEY, "friends").unwrap();
+    User::new(&db, FAMILY_KEY, "family").unwrap();
+
+    let state = AppState::new(config).unwrap();
+
+    Site { _dir: temp, state }
+}
@@ -0,0 +1,69 @@
+use chrono::{DateTime, LocalResult, NaiveDate, NaiveDateTime, TimeDelta, TimeZone};
+pub use chrono_tz::Tz;
+
+// Content dates are written in local time, either as a plain date or as a date with a time.
+pub fn parse_date(date: &str, tz: Tz) -> Option<DateTime<Tz>> {
+    let naive = NaiveDateTime::parse_from_str(date, "%Y-%m-%dT%H:%M")
+        .or_else(|_| NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M"))
+        .or_else(|_| {
+            NaiveDate::parse_from_str(date, "%Y-%m-%d").map(|d| d.and_time(Default::default()))
+        })
+        .ok()?;
+
+    match tz.from_local_datetime(&naive) {
+        // skipped when the clocks go forward, so it's the first minute after the gap
+        LocalResult::None => (1..=24 * 60).find_map(|minutes| {
+            tz.from_local_datetime(&(naive + TimeDelta::minutes(minutes)))
+                .earliest()
+        }),
+        result => result.earliest(),
+    }
+}
+
+pub fn now(tz: Tz) -> DateTime<Tz> {
+    chrono::Utc::now().with_timezone(&tz)
+}
+
+pub fn display_date(date: &str, tz: Tz) -> String {
+    match parse_date(date, tz) {
+        Some(date) => date.format("%Y-%m-%d").to_string(),
+        None => date.to_string(),
+    }
+}
+
+pub fn display_timestamp(timestamp: i64, tz: Tz) -> String {
+    match DateTime::from_timestamp(timestamp, 0) {
+        Some(date) => date.with_timezone(&tz).format("%Y-%m-%d").to_string(),
+        None => timestamp.to_string(),
+    }
+}
+
+pub fn display_timestamp_time(timestamp: i64, tz: Tz) -> String {
+    match DateTime::from_timestamp(timestamp, 0) {
+        Some(date) => date.with_timezone(&tz).format("%Y-%m-%d %H:%M").to_string(),
+        None => timestamp.to_string(),
+    }
+}
+
+pub fn rfc822_date(date: &str, tz: Tz) -> Option<String> {
+    parse_date(date, tz).map(|date| date.to_rfc2822())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn times_skipped_by_summer_time_move_to_the_end_of_the_gap() {
+        let date = parse_date("2024-03-31T02:30", Tz::Europe__Berlin).unwrap();
+        assert_eq!(date.to_rfc3339(), "2024-03-31T03:00:00+02:00");
+
+        // some zones switched at midnight, skipping the start of the day
+        let date = parse_date("2018-11-04", Tz::America__Sao_Paulo).unwrap();
+        assert_eq!(date.to_rfc3339(), "2018-11-04T01:00:00-02:00");
+
+        // a repeated hour when the clocks go back is the first of the two
+        let date = parse_date("2024-10-27 02:30", Tz::Europe__Berlin).unwrap();
+        assert_eq!(date.to_rfc3339(), "2024-10-27T02:30:00+02:00");
+    }
+}
@@ -0,0 +1,63 @@
+use std::time::Instant;
+
+use axum::body::Body;
+use axum::http::Request;
+use tower::ServiceExt;
+
+use crate::prelude::*;
+
+// requests the pages a first visitor is most likely to hit, so the database pages and photo blobs
+// they need are already cached by the time the server accepts connections
+pub async fn warm_cache(
+    router: &ax::Router,
+    state: &AppState,
+    post_count: usize,
+) -> Result<(), Error> {
+    let start = Instant::now();
+    let mut paths = vec![
+        routes::INDEX.to_string(),
+        routes::POSTS.to_string(),
+        routes::PHOTOS.to_string(),
+    ];
+
+    {
+        let db = &state.db.lock().unwrap();
+        let cfg = &state.config.lock().unwrap();
+
+        let posts = Post::get_all(db)?
+            .into_iter()
+            .filter(|post| post.visible_to(None, cfg.timezone()))
+            .take(post_count);
+
+        for post in posts {
+            paths.push(post.url());
+            for photo in Photo::get_all(db, Some(&post.id))? {
+                if photo.visible_to(None) {
+                    paths.push(routes::photo_sized(&photo.id, PhotoSize::Small));
+                }
+            }
+        }
+    }
+
+    for path in &paths {
+        let request = Request::get(path)
+            .body(Body::empty())
+            .context("failed to build warm-up request")?;
+        let Ok(response) = router.clone().oneshot(request).await;
+
+        if !response.status().is_success() {
+            println!("warning: warming {} returned {}", path, response.status());
+        }
+        axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .context("failed to read warm-up response")?;
+    }
+
+    println!(
+        "warmed {} routes in {:.2}s",
+        paths.len(),
+        start.elapsed().as_secs_f64()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,174 @@
+use std::io::Read;
+use std::time::Duration;
+
+use url::Url;
+
+use crate::prelude::*;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+// endpoints are announced in the head, so there is no need to read huge pages to the end
+const MAX_PAGE_SIZE: u64 = 1024 * 1024;
+
+// sources of all posts before a rebuild, so only new and changed posts send webmentions
+pub fn snapshot(db: &Database) -> Result<HashMap<String, String>, Error> {
+    let mut sources = HashMap::new();
+    for post in Post::get_all(db)? {
+        sources.insert(post.id.clone(), post.get_source(db)?);
+    }
+    Ok(sources)
+}
+
+// Notifies every external page linked from a new or changed public post. Failures are only
+// warnings, a slow or broken site on the other end must not fail the build.
+pub fn send_webmentions(
+    db: &Database,
+    cfg: &Config,
+    previous: &HashMap<String, String>,
+) -> Result<(), Error> {
+    let agent = ureq::AgentBuilder::new()
+        .timeout(TIMEOUT)
+        .user_agent(concat!(
+            "website/",
+            env!("CARGO_PKG_VERSION"),
+            " (webmention)"
+        ))
+        .build();
+    let site_url = cfg.site_url.trim_end_matches('/');
+
+    for post in Post::get_all(db)? {
+        // private posts must never tell anyone about themselves
+        if !post.visible_to(None, cfg.timezone()) {
+            continue;
+        }
+
+        let source = post.get_source(db)?;
+        if previous.get(&post.id) == Some(&source) {
+            continue;
+        }
+
+        let post_url = format!("{}{}", site_url, post.url());
+        for target in markdown_links(&source) {
+            if !(target.starts_with("http://") || target.starts_with("https://"))
+                || target.starts_with(site_url)
+            {
+                continue;
+            }
+
+            match send(&agent, &post_url, &target) {
+                Ok(true) => println!("sent webmention for {} to {}", post_url, target),
+                Ok(false) => {}
+                Err(error) => println!(
+                    "warning: failed to send webmention to {}: {}",
+                    target,
+                    error.message()
+                ),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// false if the target doesn't accept webmentions
+fn send(agent: &ureq::Agent, source: &str, target: &str) -> Result<bool, Error> {
+    let Some(endpoint) = discover_endpoint(agent, target)? else {
+        return Ok(false);
+    };
+
+    agent
+        .post(endpoint.as_str())
+        .send_form(&[("source", source), ("target", target)])
+        .context(format!("endpoint {} rejected the webmention", endpoint))?;
+    Ok(true)
+}
+
+fn discover_endpoint(agent: &ureq::Agent, target: &str) -> Result<Option<Url>, Error> {
+    let response = agent.get(target).call().context("failed to fetch target")?;
+    let base = Url::parse(response.get_url()).context("invalid target url")?;
+
+    // the http header wins over links in the document
+    for header in response.all("link") {
+        for link in header.split(',') {
+            let Some((href, params)) = link.trim().split_once(';') else {
+                continue;
+            };
+            let href = href.trim().trim_start_matches('<').trim_end_matches('>');
+            if params
+                .split(';')
+                .filter_map(|param| param.trim().strip_prefix("rel="))
+                .any(|rel| has_webmention_rel(rel.trim_matches('"')))
+            {
+                return base
+                    .join(href)
+                    .map(Some)
+                    .context("invalid webmention endpoint");
+            }
+        }
+    }
+
+    if !response.content_type().contains("html") {
+        return Ok(None);
+    }
+
+    let mut html = String::new();
+    response
+        .into_reader()
+        .take(MAX_PAGE_SIZE)
+        .read_to_string(&mut html)
+        .context("failed to read target")?;
+
+    for tag in html.split('<').skip(1) {
+        let tag = tag.split('>').next().unwrap_or_default();
+        let name = tag.split_whitespace().next().unwrap_or_default();
+        if !(name.eq_ignore_ascii_case("link") || name.eq_ignore_ascii_case("a")) {
+            continue;
+        }
+
+        if tag_attribute(tag, "rel").is_some_and(|rel| has_webmention_rel(&rel)) {
+            // an empty href points at the target itself
+            let href = tag_attribute(tag, "href").unwrap_or_default();
+            return base
+                .join(&href)
+                .map(Some)
+                .context("invalid webmention endpoint");
+        }
+    }
+
+    Ok(None)
+}
+
+fn has_webmention_rel(rel: &str) -> bool {
+    rel.split_whitespace()
+        .any(|rel| rel.eq_ignore_ascii_case("webmention"))
+}
+
+// value of `name="value"`, `name='value'` or `name=value` in the inside of a tag
+fn tag_attribute(tag: &str, name: &str) -> Option<String> {
+    let mut rest = tag;
+
+    while let Some(index) = rest.find('=') {
+        let key = rest[..index]
+            .rsplit(|c: char| c.is_whitespace())
+            .next()
+            .unwrap_or_default();
+        let value = rest[index + 1..].trim_start();
+
+        let (value, remaining) = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => match value[1..].find(quote) {
+                Some(end) => (&value[1..end + 1], &value[end + 2..]),
+                None => (&value[1..], ""),
+            },
+            _ => {
+                let end = value.find(char::is_whitespace).unwrap_or(value.len());
+                (&value[..end], &value[end..])
+            }
+        };
+
+        if key.eq_ignore_ascii_case(name) {
+            return Some(value.to_string());
+        }
+        rest = remaining;
+    }
+
+    None
+}
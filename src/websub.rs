@@ -0,0 +1,50 @@
+use std::time::Duration;
+
+use crate::prelude::*;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+// Tells the WebSub hub (https://www.w3.org/TR/websub/) that the feeds changed, so subscribers
+// fetch them right away instead of polling. Only pinged when a public post was added, changed or
+// removed since `previous`, the sources from `webmention::snapshot`. A failing hub is a warning.
+pub fn ping_hub(
+    db: &Database,
+    cfg: &Config,
+    previous: &HashMap<String, String>,
+) -> Result<(), Error> {
+    let Some(hub) = &cfg.websub_hub else {
+        return Ok(());
+    };
+
+    let posts = Post::get_all(db)?;
+    let mut changed = previous
+        .keys()
+        .any(|id| !posts.iter().any(|post| &post.id == id));
+    for post in &posts {
+        if !changed && post.visible_to(None, cfg.timezone()) {
+            changed = previous.get(&post.id) != Some(&post.get_source(db)?);
+        }
+    }
+    if !changed {
+        return Ok(());
+    }
+
+    let agent = ureq::AgentBuilder::new()
+        .timeout(TIMEOUT)
+        .user_agent(concat!("website/", env!("CARGO_PKG_VERSION"), " (websub)"))
+        .build();
+    let site_url = cfg.site_url.trim_end_matches('/');
+
+    for feed in [routes::POSTS_FEED, routes::PHOTOS_FEED] {
+        let url = format!("{}{}", site_url, feed);
+        match agent
+            .post(hub)
+            .send_form(&[("hub.mode", "publish"), ("hub.url", &url)])
+        {
+            Ok(_) => println!("pinged websub hub for {}", url),
+            Err(error) => println!("warning: websub hub rejected {}: {}", url, error),
+        }
+    }
+
+    Ok(())
+}